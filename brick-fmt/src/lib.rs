@@ -2,7 +2,10 @@ use std::cmp::Ordering;
 
 use brick::{
     parse_file,
-    parser::{AstNode, AstNodeValue, BinOp, UnaryOp, UnionDeclarationVariant},
+    parser::{
+        AstNode, AstNodeValue, BinOp, UnaryOp, UnionDeclarationVariant, BOOLEAN_NOT, CAST, COMPARE,
+        NEGATE, NULL_CHAINING, REFERENCE,
+    },
     CompileError,
 };
 use typed_arena::Arena;
@@ -28,9 +31,9 @@ pub fn format(nodes: &mut [AstNode]) -> String {
 fn rewrite(nodes: &mut [AstNode]) {
     // Move imports to the top of the block
     nodes.sort_by(|a, b| match (&a.value, &b.value) {
-        (AstNodeValue::Import(a_path), AstNodeValue::Import(b_path)) => a_path.cmp(b_path),
-        (AstNodeValue::Import(_), _) => Ordering::Less,
-        (_, AstNodeValue::Import(_)) => Ordering::Greater,
+        (AstNodeValue::Import(a_path, ..), AstNodeValue::Import(b_path, ..)) => a_path.cmp(b_path),
+        (AstNodeValue::Import(..), _) => Ordering::Less,
+        (_, AstNodeValue::Import(..)) => Ordering::Greater,
         (_, _) => Ordering::Equal,
     });
 }
@@ -38,6 +41,9 @@ fn rewrite(nodes: &mut [AstNode]) {
 fn write_node(node: &AstNode, result: &mut String, indent: u32) {
     match &node.value {
         AstNodeValue::FunctionDeclaration(func) => {
+            if func.is_exported {
+                result.push_str("export ");
+            }
             write_function_header(
                 result,
                 &func.name,
@@ -47,12 +53,19 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 func.returns.as_deref(),
                 func.is_extern,
                 func.is_coroutine,
+                func.is_const,
             );
             result.push(' ');
             write_node(func.body, result, indent);
             result.push('\n');
         }
-        AstNodeValue::ExternFunctionBinding(func) => {
+        AstNodeValue::ExternFunctionBinding {
+            header: func,
+            is_exported,
+        } => {
+            if *is_exported {
+                result.push_str("export ");
+            }
             write_function_header(
                 result,
                 func.name.as_str(),
@@ -62,10 +75,14 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 func.returns.as_deref(),
                 true,
                 false,
+                false,
             );
             result.push_str(";\n");
         }
         AstNodeValue::StructDeclaration(decl) => {
+            if decl.is_exported {
+                result.push_str("export ");
+            }
             result.push_str("struct ");
             result.push_str(decl.name.as_str());
             if !decl.properties.is_empty() {
@@ -84,6 +101,14 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 result.push_str(field.name.as_str());
                 result.push_str(": ");
                 write_node(field.ty, result, indent + 1);
+                if let Some((_, default)) = decl
+                    .field_defaults
+                    .iter()
+                    .find(|(name, _)| *name == field.name)
+                {
+                    result.push_str(" = ");
+                    write_node(default, result, indent + 1);
+                }
                 result.push_str(",\n");
             }
             if !decl.associated_functions.is_empty() {
@@ -95,6 +120,9 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             result.push_str("}\n");
         }
         AstNodeValue::UnionDeclaration(decl) => {
+            if decl.is_exported {
+                result.push_str("export ");
+            }
             result.push_str("union ");
             result.push_str(decl.name.as_str());
             if !decl.properties.is_empty() {
@@ -126,6 +154,9 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             result.push_str("}\n");
         }
         AstNodeValue::InterfaceDeclaration(decl) => {
+            if decl.is_exported {
+                result.push_str("export ");
+            }
             result.push_str("interface ");
             result.push_str(decl.name.as_str());
             result.push_str(" {\n");
@@ -135,6 +166,33 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             }
             result.push_str("}\n");
         }
+        AstNodeValue::ImplBlock(decl) => {
+            result.push_str("impl ");
+            if let Some((interface, _)) = &decl.interface {
+                result.push_str(interface.as_str());
+                result.push_str(" for ");
+            }
+            result.push_str(decl.name.as_str());
+            result.push_str(" {\n");
+            for func in decl.associated_functions.iter() {
+                write_node(func, result, indent + 1);
+            }
+            result.push_str("}\n");
+        }
+        AstNodeValue::TypeAliasDeclaration {
+            name,
+            ty,
+            is_exported,
+        } => {
+            if *is_exported {
+                result.push_str("export ");
+            }
+            result.push_str("type ");
+            result.push_str(name.as_str());
+            result.push_str(" = ");
+            write_node(ty, result, indent);
+            result.push_str(";\n");
+        }
         AstNodeValue::RequiredFunction(func) => {
             write_function_header(
                 result,
@@ -145,6 +203,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 func.returns.as_deref(),
                 false,
                 false,
+                false,
             );
             result.push_str(",\n");
         }
@@ -155,8 +214,10 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 result.push_str(": ");
                 write_node(ty, result, indent);
             }
-            result.push_str(" = ");
-            write_node(value, result, indent);
+            if let Some(value) = value {
+                result.push_str(" = ");
+                write_node(value, result, indent);
+            }
             result.push(';');
         }
         AstNodeValue::ConstDeclaration {
@@ -182,7 +243,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             write_node(value, result, indent);
             result.push(';');
         }
-        AstNodeValue::Import(path) => {
+        AstNodeValue::Import(path, names, alias) => {
             result.push_str("import ");
             for (idx, entry) in path.iter().enumerate() {
                 result.push_str(entry);
@@ -190,6 +251,15 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                     result.push('.');
                 }
             }
+            if let Some(names) = names {
+                result.push_str(".{");
+                result.push_str(&names.join(", "));
+                result.push('}');
+            }
+            if let Some(alias) = alias {
+                result.push_str(" as ");
+                result.push_str(alias);
+            }
             result.push(';');
         }
         AstNodeValue::Return(inner) => {
@@ -201,6 +271,31 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 result.push(';');
             }
         }
+        AstNodeValue::Break(inner, label) => {
+            result.push_str("break");
+            if let Some(label) = label {
+                result.push(' ');
+                result.push_str(&label.name);
+            }
+            if let Some(inner) = inner {
+                result.push(' ');
+                write_node(inner, result, indent);
+            }
+            result.push(';');
+        }
+        AstNodeValue::Continue(label) => {
+            result.push_str("continue");
+            if let Some(label) = label {
+                result.push(' ');
+                result.push_str(&label.name);
+            }
+            result.push(';');
+        }
+        AstNodeValue::Defer(inner) => {
+            result.push_str("defer ");
+            write_node(inner, result, indent);
+            result.push(';');
+        }
         AstNodeValue::Yield(inner) => {
             result.push_str("yield");
             if let Some(inner) = inner {
@@ -226,16 +321,37 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             result.push('\'');
         }
         AstNodeValue::StringLiteral(string) => {
-            result.push('\"');
-            result.push_str(string);
-            result.push('\"');
+            // Strings with quotes, backslashes or newlines are only preserved as raw strings
+            let needs_raw = string.contains(['"', '\\', '\n']);
+            if needs_raw && !string.contains("\"\"\"") && !string.ends_with('"') {
+                result.push_str("\"\"\"");
+                result.push_str(string);
+                result.push_str("\"\"\"");
+            } else {
+                result.push('\"');
+                result.push_str(string);
+                result.push('\"');
+            }
         }
         AstNodeValue::Null => result.push_str("null"),
-        AstNodeValue::UnaryExpr(op, operand) => {
-            match op {
-                UnaryOp::BooleanNot => result.push('!'),
-            }
-            write_node(operand, result, indent);
+        AstNodeValue::UnaryExpr(UnaryOp::BooleanNot, operand) => {
+            result.push('!');
+            write_operand(operand, BOOLEAN_NOT, result, indent);
+        }
+        AstNodeValue::UnaryExpr(
+            op @ (UnaryOp::Negate | UnaryOp::BitNot | UnaryOp::Plus),
+            operand,
+        ) => {
+            result.push(match op {
+                UnaryOp::Negate => '-',
+                UnaryOp::BitNot => '~',
+                _ => '+',
+            });
+            write_operand(operand, NEGATE, result, indent);
+        }
+        AstNodeValue::UnaryExpr(UnaryOp::NullableUnwrap, operand) => {
+            write_operand(operand, NULL_CHAINING, result, indent);
+            result.push('!');
         }
         AstNodeValue::BinExpr(BinOp::Index, lhs, rhs) => {
             write_node(lhs, result, indent);
@@ -243,6 +359,14 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             write_node(rhs, result, indent);
             result.push(']');
         }
+        AstNodeValue::ArraySlice(collection, start, end) => {
+            write_node(collection, result, indent);
+            result.push('[');
+            write_node(start, result, indent);
+            result.push_str("..");
+            write_node(end, result, indent);
+            result.push(']');
+        }
         AstNodeValue::BinExpr(BinOp::Dot, lhs, rhs) => {
             write_node(lhs, result, indent);
             result.push('.');
@@ -257,6 +381,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
         AstNodeValue::BinExpr(op, lhs, rhs) => {
             let paren_left = if let AstNodeValue::BinExpr(lhs_op, _, _) = &lhs.value {
                 op.binding_power() > lhs_op.binding_power()
+                    || (op.binding_power() == lhs_op.binding_power() && !op.is_left_associative())
             } else {
                 false
             };
@@ -293,6 +418,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             result.push(' ');
             let paren_right = if let AstNodeValue::BinExpr(rhs_op, _, _) = &rhs.value {
                 op.binding_power() > rhs_op.binding_power()
+                    || (op.binding_power() == rhs_op.binding_power() && !op.is_right_associative())
             } else {
                 false
             };
@@ -314,15 +440,40 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
                 write_node(else_branch, result, indent);
             }
         }
-        AstNodeValue::While(cond, body) => {
+        AstNodeValue::IfLet(decl) => {
+            result.push_str("if let ");
+            result.push_str(decl.pattern.name.as_str());
+            if !decl.pattern.bindings.is_empty() {
+                result.push('(');
+                result.push_str(decl.pattern.bindings.join(", ").as_str());
+                result.push(')');
+            }
+            result.push_str(" = ");
+            write_node(decl.value, result, indent);
+            write_node(decl.if_branch, result, indent);
+            if let Some(else_branch) = decl.else_branch.as_ref() {
+                result.push_str(" else ");
+                write_node(else_branch, result, indent);
+            }
+        }
+        AstNodeValue::While(cond, step, body) => {
             result.push_str("while ");
             write_node(cond, result, indent);
+            if let Some(step) = step {
+                result.push_str("; ");
+                write_node(step, result, indent);
+            }
             write_node(body, result, indent);
         }
         AstNodeValue::Loop(body) => {
             result.push_str("loop ");
             write_node(body, result, indent);
         }
+        AstNodeValue::LabeledLoop(label, inner) => {
+            result.push_str(label);
+            result.push_str(": ");
+            write_node(inner, result, indent);
+        }
         AstNodeValue::Call(func, args) => {
             write_node(func, result, indent);
             result.push('(');
@@ -403,7 +554,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
         }
         AstNodeValue::Deref(inner) => {
             result.push('*');
-            write_node(inner, result, indent);
+            write_operand(inner, REFERENCE, result, indent);
         }
         AstNodeValue::Match(decl) => {
             result.push_str("case ");
@@ -436,14 +587,24 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             }
             result.push('}');
         }
+        AstNodeValue::IsVariant(value, variant) => {
+            write_node(value, result, indent);
+            result.push_str(" is ");
+            result.push_str(variant.as_str());
+        }
+        AstNodeValue::Downcast(value, name) => {
+            write_operand(value, CAST, result, indent);
+            result.push_str(" as ");
+            result.push_str(name.as_str());
+        }
         AstNodeValue::VoidType => result.push_str("void"),
         AstNodeValue::TakeUnique(inner) | AstNodeValue::UniqueType(inner) => {
             result.push_str("unique ");
-            write_node(inner, result, indent);
+            write_operand(inner, REFERENCE, result, indent);
         }
         AstNodeValue::TakeRef(inner) | AstNodeValue::SharedType(inner) => {
             result.push_str("ref ");
-            write_node(inner, result, indent);
+            write_operand(inner, REFERENCE, result, indent);
         }
         AstNodeValue::ArrayType(inner) => {
             result.push_str("list[");
@@ -451,7 +612,7 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             result.push(']');
         }
         AstNodeValue::DictType(key_ty, value_ty) => {
-            result.push_str("list[");
+            result.push_str("dict[");
             write_node(key_ty, result, indent);
             result.push_str(", ");
             write_node(value_ty, result, indent);
@@ -478,6 +639,20 @@ fn write_node(node: &AstNode, result: &mut String, indent: u32) {
             write_node(param_ty, result, indent);
             result.push(']');
         }
+        AstNodeValue::FunctionType { params, returns } => {
+            result.push_str("fn(");
+            for (idx, param) in params.iter().enumerate() {
+                if idx > 0 {
+                    result.push_str(", ");
+                }
+                write_node(param, result, indent);
+            }
+            result.push(')');
+            if let Some(returns) = returns {
+                result.push_str(": ");
+                write_node(returns, result, indent);
+            }
+        }
     }
 }
 
@@ -488,10 +663,14 @@ fn write_function_header<'iter, 'node: 'iter>(
     returns: Option<&AstNode>,
     is_extern: bool,
     is_coroutine: bool,
+    is_const: bool,
 ) {
     if is_extern {
         result.push_str("extern ");
     }
+    if is_const {
+        result.push_str("const ");
+    }
     if is_coroutine {
         result.push_str("gen ");
     }
@@ -514,6 +693,26 @@ fn write_function_header<'iter, 'node: 'iter>(
     }
 }
 
+/**
+ * Write the operand of a unary operator, parenthesizing it if the operator binds more tightly
+ */
+fn write_operand(operand: &AstNode, binding_power: u8, result: &mut String, indent: u32) {
+    let operand_binding_power = match &operand.value {
+        AstNodeValue::BinExpr(op, _, _) => op.binding_power(),
+        AstNodeValue::IsVariant(_, _) => COMPARE,
+        AstNodeValue::Downcast(_, _) => CAST,
+        _ => u8::MAX,
+    };
+    let parenthesize = operand_binding_power < binding_power;
+    if parenthesize {
+        result.push('(');
+    }
+    write_node(operand, result, indent);
+    if parenthesize {
+        result.push(')');
+    }
+}
+
 fn do_indent(string: &mut String, count: u32) {
     for _ in 0..count {
         string.push_str("    ");
@@ -0,0 +1,167 @@
+use brick::{SourceMarker, SourceRange};
+use lsp_types::{Position, Range};
+
+/*
+ * Brick source locations count lines and characters from 1, and a range ends on its last
+ * character. LSP counts both from 0, and a range ends just after its last character. These are
+ * the only places that should have to care about the difference.
+ */
+
+pub fn source_range_to_lsp_range(provenance: &SourceRange) -> Range {
+    Range {
+        start: Position {
+            line: provenance.start_line.saturating_sub(1),
+            character: provenance.start_offset.saturating_sub(1),
+        },
+        end: Position {
+            line: provenance.end_line.saturating_sub(1),
+            // The last character's 1-based column is the 0-based column just past it
+            character: provenance.end_offset,
+        },
+    }
+}
+
+/**
+ * An empty range (like a cursor) becomes the single character just after it
+ */
+pub fn lsp_range_to_source_range(
+    range: &Range,
+    source_name: &'static str,
+    source_text: &'static str,
+) -> SourceRange {
+    let (start_line, start_offset) = lsp_position_to_source_position(range.start);
+    let start = SourceMarker::new(source_name, source_text, start_line, start_offset);
+    let end = if range.end == range.start {
+        start
+    } else {
+        // A range that ends at the start of a line has no column 0 to end on, so it ends on the
+        // first character of that line instead
+        SourceMarker::new(
+            source_name,
+            source_text,
+            range.end.line + 1,
+            range.end.character.max(1),
+        )
+    };
+
+    SourceRange::new(start, end)
+}
+
+/**
+ * The line and character of the source character at the given LSP position
+ */
+pub fn lsp_position_to_source_position(position: Position) -> (u32, u32) {
+    (position.line + 1, position.character + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Columns:           1234567890
+    const SOURCE: &str = "let x = 5;\nlet y = x;\n";
+
+    fn source_range(start: (u32, u32), end: (u32, u32)) -> SourceRange {
+        SourceRange::new(
+            SourceMarker::new("test.brick", SOURCE, start.0, start.1),
+            SourceMarker::new("test.brick", SOURCE, end.0, end.1),
+        )
+    }
+
+    fn lsp_range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range {
+            start: Position {
+                line: start.0,
+                character: start.1,
+            },
+            end: Position {
+                line: end.0,
+                character: end.1,
+            },
+        }
+    }
+
+    #[test]
+    fn first_character() {
+        let provenance = source_range((1, 1), (1, 1));
+        let range = source_range_to_lsp_range(&provenance);
+        assert_eq!(range, lsp_range((0, 0), (0, 1)));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            provenance
+        );
+    }
+
+    #[test]
+    fn last_character_of_line() {
+        let provenance = source_range((1, 10), (1, 10));
+        let range = source_range_to_lsp_range(&provenance);
+        assert_eq!(range, lsp_range((0, 9), (0, 10)));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            provenance
+        );
+    }
+
+    #[test]
+    fn multiple_characters() {
+        let provenance = source_range((1, 5), (1, 9));
+        let range = source_range_to_lsp_range(&provenance);
+        assert_eq!(range, lsp_range((0, 4), (0, 9)));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            provenance
+        );
+    }
+
+    #[test]
+    fn start_of_later_line() {
+        let provenance = source_range((2, 1), (2, 3));
+        let range = source_range_to_lsp_range(&provenance);
+        assert_eq!(range, lsp_range((1, 0), (1, 3)));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            provenance
+        );
+    }
+
+    #[test]
+    fn spanning_lines() {
+        let provenance = source_range((1, 5), (2, 5));
+        let range = source_range_to_lsp_range(&provenance);
+        assert_eq!(range, lsp_range((0, 4), (1, 5)));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            provenance
+        );
+    }
+
+    #[test]
+    fn end_at_start_of_line() {
+        let range = lsp_range((0, 4), (1, 0));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            source_range((1, 5), (2, 1))
+        );
+    }
+
+    #[test]
+    fn empty_range_is_character_after_it() {
+        let range = lsp_range((1, 0), (1, 0));
+        assert_eq!(
+            lsp_range_to_source_range(&range, "test.brick", SOURCE),
+            source_range((2, 1), (2, 1))
+        );
+    }
+
+    #[test]
+    fn position_at_origin() {
+        let position = Position {
+            line: 0,
+            character: 0,
+        };
+        assert_eq!(lsp_position_to_source_position(position), (1, 1));
+        let (line, character) = lsp_position_to_source_position(position);
+        assert!(source_range((1, 1), (1, 3)).contains(line, character));
+    }
+}
@@ -1,18 +1,29 @@
 // Adapating from the example from the lsp-server repo
 
+use std::collections::HashMap;
 use std::path::Path;
 
 use brick::id::AnyID;
 use brick::CompilationResults;
+use brick::CompileError;
+use brick::DeclarationContext;
 use brick::HirNodeValue;
 use brick::SourceFile;
 use brick::SourceRange;
+use brick_lsp::{lsp_position_to_source_position, source_range_to_lsp_range};
+use lsp_types::notification::{
+    DidOpenTextDocument, DidSaveTextDocument, Notification as _, PublishDiagnostics,
+};
 use lsp_types::{
     request::GotoDefinition, GotoDefinitionResponse, InitializeParams, ServerCapabilities,
 };
-use lsp_types::{GotoDefinitionParams, Location, OneOf, Position, Range};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, GotoDefinitionParams, Location, OneOf,
+    PublishDiagnosticsParams, TextDocumentSyncCapability, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url,
+};
 
-use lsp_server::{Connection, ExtractError, Message, Request, RequestId, Response};
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
 
 fn main() -> anyhow::Result<()> {
     eprintln!("brick-lsp booting up");
@@ -20,6 +31,13 @@ fn main() -> anyhow::Result<()> {
     let (connection, io_threads) = Connection::stdio();
     let server_capabilities = serde_json::to_value(ServerCapabilities {
         definition_provider: Some(OneOf::Left(true)),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
         ..Default::default()
     })?;
     let initialization_params = match connection.initialize(server_capabilities) {
@@ -40,6 +58,7 @@ fn main() -> anyhow::Result<()> {
 
 fn main_loop(connection: Connection, params: serde_json::Value) -> anyhow::Result<()> {
     let _params: InitializeParams = serde_json::from_value(params)?;
+    let mut checked = HashMap::new();
     for msg in &connection.receiver {
         eprintln!("got msg: {msg:?}");
         match msg {
@@ -51,7 +70,7 @@ fn main_loop(connection: Connection, params: serde_json::Value) -> anyhow::Resul
                 match cast::<GotoDefinition>(req) {
                     Ok((id, params)) => {
                         eprintln!("got gotoDefinition request #{id}: {params:?}\n");
-                        let provenance = find_definition(&params)?;
+                        let provenance = find_definition(&params, &mut checked)?;
                         let result = provenance.map(|provenance| {
                             GotoDefinitionResponse::Scalar(Location {
                                 uri: params
@@ -59,16 +78,7 @@ fn main_loop(connection: Connection, params: serde_json::Value) -> anyhow::Resul
                                     .text_document
                                     .uri
                                     .clone(),
-                                range: Range {
-                                    start: Position {
-                                        line: provenance.start_line - 1,
-                                        character: provenance.start_offset - 1,
-                                    },
-                                    end: Position {
-                                        line: provenance.end_line - 1,
-                                        character: provenance.end_offset - 1,
-                                    },
-                                },
+                                range: source_range_to_lsp_range(&provenance),
                             })
                         });
                         let result = serde_json::to_value(&result)?;
@@ -90,6 +100,31 @@ fn main_loop(connection: Connection, params: serde_json::Value) -> anyhow::Resul
             }
             Message::Notification(not) => {
                 eprintln!("got notification: {not:?}");
+                let uri = match not.method.as_str() {
+                    DidOpenTextDocument::METHOD => {
+                        let params: lsp_types::DidOpenTextDocumentParams =
+                            serde_json::from_value(not.params)?;
+                        params.text_document.uri
+                    }
+                    DidSaveTextDocument::METHOD => {
+                        let params: lsp_types::DidSaveTextDocumentParams =
+                            serde_json::from_value(not.params)?;
+                        params.text_document.uri
+                    }
+                    _ => continue,
+                };
+                let diagnostics = find_diagnostics(&uri, &mut checked)?;
+                let params = PublishDiagnosticsParams {
+                    uri,
+                    diagnostics,
+                    version: None,
+                };
+                connection
+                    .sender
+                    .send(Message::Notification(Notification::new(
+                        PublishDiagnostics::METHOD.to_string(),
+                        params,
+                    )))?;
             }
         }
     }
@@ -104,7 +139,29 @@ where
     req.extract(R::METHOD)
 }
 
-fn find_definition(params: &GotoDefinitionParams) -> anyhow::Result<Option<SourceRange>> {
+/**
+ * Check a document, declaring only its own module again if it was checked before. Whoever calls
+ * this puts the declarations back once they're done with the results.
+ */
+fn check_file(
+    uri: &Url,
+    file: SourceFile,
+    checked: &mut HashMap<Url, DeclarationContext>,
+) -> Result<CompilationResults, CompileError> {
+    match checked.remove(uri) {
+        Some(declarations) => {
+            let module_name = file.module_name;
+            brick::recheck_types(vec![file], module_name, declarations)
+        }
+        None => brick::check_types(vec![file]),
+    }
+}
+
+fn find_definition(
+    params: &GotoDefinitionParams,
+    checked: &mut HashMap<Url, DeclarationContext>,
+) -> anyhow::Result<Option<SourceRange>> {
+    let uri = &params.text_document_position_params.text_document.uri;
     let path = Path::new(
         params
             .text_document_position_params
@@ -120,7 +177,8 @@ fn find_definition(params: &GotoDefinitionParams) -> anyhow::Result<Option<Sourc
     let CompilationResults {
         modules,
         declarations,
-    } = brick::check_types(vec![file])?;
+        ..
+    } = check_file(uri, file, checked)?;
 
     let mut found = None;
     for module in modules.values() {
@@ -134,13 +192,14 @@ fn find_definition(params: &GotoDefinitionParams) -> anyhow::Result<Option<Sourc
             let Some(provenance) = &node.provenance else {
                 return;
             };
-            if provenance.contains(position.line + 1, position.character + 1) {
+            let (line, character) = lsp_position_to_source_position(position);
+            if provenance.contains(line, character) {
                 found = Some(*id);
             }
         });
     }
 
-    Ok(match found {
+    let definition = match found {
         Some(id) => match id {
             AnyID::Function(fn_id) => declarations.id_to_func[&fn_id].provenance.clone(),
             AnyID::Type(_) => todo!(),
@@ -148,5 +207,39 @@ fn find_definition(params: &GotoDefinitionParams) -> anyhow::Result<Option<Sourc
             AnyID::Constant(_) => todo!(),
         },
         None => None,
-    })
+    };
+    checked.insert(uri.clone(), declarations);
+
+    Ok(definition)
+}
+
+/**
+ * Warnings are surfaced as hints. Compile errors aren't reported yet, so a file that doesn't
+ * compile has no diagnostics.
+ */
+fn find_diagnostics(
+    uri: &Url,
+    checked: &mut HashMap<Url, DeclarationContext>,
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let file = SourceFile::from_filename(uri.path().to_string().leak() as &'static str)?;
+
+    let Ok(CompilationResults {
+        warnings,
+        declarations,
+        ..
+    }) = check_file(uri, file, checked)
+    else {
+        return Ok(Vec::new());
+    };
+    checked.insert(uri.clone(), declarations);
+
+    Ok(warnings
+        .iter()
+        .map(|warning| Diagnostic {
+            range: source_range_to_lsp_range(warning.provenance()),
+            severity: Some(DiagnosticSeverity::HINT),
+            message: warning.to_string(),
+            ..Default::default()
+        })
+        .collect())
 }
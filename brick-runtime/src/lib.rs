@@ -107,3 +107,10 @@ pub unsafe extern "C" fn brick_memcpy(dest: *mut u8, src: *const u8, len: usize)
     let src_slice = core::slice::from_raw_parts(src, len);
     dest_slice.copy_from_slice(src_slice);
 }
+
+/**
+ * Called right before a program traps, with the code of the compiler's `AbortReason`. There's
+ * nowhere to report it from here, so this does nothing and the trap that follows stops the program.
+ */
+#[no_mangle]
+pub extern "C" fn brick_runtime_abort(_reason: i32) {}
@@ -2,13 +2,13 @@ use std::{collections::HashMap, rc::Rc, sync::RwLock};
 
 use brick::{
     id::{FunctionID, RegisterID, VariableID},
-    ArithmeticOp, BinaryLogicalOp, ComparisonOp, DeclaredTypeLayout, LinearFunction, LinearNode,
-    LinearNodeValue, PhysicalCollection, PhysicalPrimitive, PhysicalType, RuntimeFunction, TypeID,
-    TypeLayoutValue, UnaryLogicalOp,
+    AbortReason, ArithmeticOp, BinaryLogicalOp, ComparisonOp, DeclaredTypeLayout, LinearFunction,
+    LinearNode, LinearNodeValue, MathFunction, PhysicalCollection, PhysicalPrimitive, PhysicalType,
+    RuntimeFunction, SourceRange, TypeID, TypeLayoutValue, UnaryLogicalOp,
 };
 use wasm_encoder::{BlockType, Function, Instruction, MemArg, ValType};
 
-use crate::{WASM_BOOL_SIZE, WASM_USIZE};
+use crate::{source_map::SourceMapEntry, WASM_BOOL_SIZE, WASM_USIZE};
 
 pub struct FunctionEncoder<'a> {
     pub function_id_to_fn_idx: &'a HashMap<FunctionID, u32>,
@@ -18,13 +18,18 @@ pub struct FunctionEncoder<'a> {
     pub stackptr_global_idx: u32,
     pub allocptr_global_idx: u32,
     pub linear_function_to_id: &'a HashMap<RuntimeFunction, u32>,
+    pub abort_fn_idx: u32,
     pub constant_data_start: i32,
     pub indirect_call_table: u32,
     pub indirect_function_id_to_table: &'a HashMap<FunctionID, u32>,
 }
 
 impl FunctionEncoder<'_> {
-    pub fn encode(&self, func: &LinearFunction) -> Function {
+    /**
+     * Encode the function, along with where its instructions came from. The source map entries
+     * count from the start of the function body.
+     */
+    pub fn encode(&self, func: &LinearFunction) -> (Function, Vec<SourceMapEntry>) {
         let mut parameter_start_idx = 0;
         let parameter_starts = func
             .params
@@ -38,8 +43,18 @@ impl FunctionEncoder<'_> {
             })
             .collect();
         let mut ctx = FunctionContext::new(self, func, parameter_starts, parameter_start_idx);
-        for node in func.body.iter() {
-            encode_node(&mut ctx, node, None);
+        if func.body.iter().any(|node| {
+            let mut has_goto = false;
+            node.visit(|node| has_goto |= matches!(node.value, LinearNodeValue::Goto(_)));
+            has_goto
+        }) {
+            ctx.goto_local = Some(ctx.alloc_local(ValType::I32));
+        }
+        encode_sequence(&mut ctx, &func.body, None);
+        // When every path through the body returns on its own, nothing is left on the stack for
+        // the end of the function to return
+        if func.returns.is_some() && func.body.iter().any(always_returns) {
+            ctx.instructions.push(Instruction::Unreachable);
         }
         let locals = ctx.locals.iter().cloned();
         let mut f = Function::new(locals);
@@ -48,17 +63,42 @@ impl FunctionEncoder<'_> {
         f.instruction(&Instruction::I32Const(ctx.stack_size));
         f.instruction(&Instruction::I32Sub);
         f.instruction(&Instruction::GlobalSet(ctx.stackptr_global_idx));
-        for instr in ctx.instructions.iter() {
+        let mut source_map: Vec<SourceMapEntry> = Vec::new();
+        let mut provenance_changes = ctx.provenance_changes.iter().peekable();
+        for (idx, instr) in ctx.instructions.iter().enumerate() {
+            let mut provenance = None;
+            while let Some((_, changed)) = provenance_changes.next_if(|(start, _)| *start <= idx) {
+                provenance = Some(changed);
+            }
+            if let Some(provenance) = provenance {
+                let offset = f.byte_len() as u32;
+                match source_map.last_mut() {
+                    Some(last) if last.offset == offset => last.provenance = provenance.clone(),
+                    _ => source_map.push(SourceMapEntry {
+                        offset,
+                        provenance: provenance.clone(),
+                    }),
+                }
+            }
             if matches!(instr, Instruction::Return) {
                 contract_stack(&mut f, ctx.stackptr_global_idx, ctx.stack_size);
             }
             f.instruction(instr);
         }
         // Contract stack
+        if source_map
+            .last()
+            .is_some_and(|entry| entry.provenance.is_some())
+        {
+            source_map.push(SourceMapEntry {
+                offset: f.byte_len() as u32,
+                provenance: None,
+            });
+        }
         contract_stack(&mut f, ctx.stackptr_global_idx, ctx.stack_size);
         f.instruction(&Instruction::End);
 
-        f
+        (f, source_map)
     }
 }
 
@@ -72,12 +112,15 @@ struct FunctionContext<'a> {
     declarations: &'a HashMap<TypeID, DeclaredTypeLayout>,
     function_return_types: &'a HashMap<FunctionID, Option<PhysicalType>>,
     linear_function_to_id: &'a HashMap<RuntimeFunction, u32>,
+    abort_fn_idx: u32,
     parameter_starts: Vec<u32>,
     constant_data_start: i32,
     indirect_call_table: u32,
     indirect_function_id_to_table: &'a HashMap<FunctionID, u32>,
     // Result
     instructions: Vec<Instruction<'a>>,
+    /// The index of each instruction where the source the instructions came from changes
+    provenance_changes: Vec<(usize, Option<SourceRange>)>,
     // Temp
     register_to_local: HashMap<RegisterID, u32>,
     locals: Vec<(u32, ValType)>,
@@ -85,6 +128,12 @@ struct FunctionContext<'a> {
     variable_locations: HashMap<VariableID, i32>,
     stack_size: i32,
     last_loop_depth: u32,
+    /// What last_loop_depth was in each of the loops outside of the innermost one, innermost last
+    outer_loop_depths: Vec<u32>,
+    /// Where the innermost node being encoded that knows its source came from
+    provenance: Option<SourceRange>,
+    /// The label a goto is looking for, or 0 when there isn't one, if the function has gotos
+    goto_local: Option<u32>,
 }
 
 impl<'a> FunctionContext<'a> {
@@ -97,6 +146,7 @@ impl<'a> FunctionContext<'a> {
             stackptr_global_idx,
             allocptr_global_idx,
             linear_function_to_id,
+            abort_fn_idx,
             constant_data_start,
             indirect_call_table,
             indirect_function_id_to_table,
@@ -111,6 +161,7 @@ impl<'a> FunctionContext<'a> {
             stackptr_global_idx: *stackptr_global_idx,
             allocptr_global_idx: *allocptr_global_idx,
             linear_function_to_id,
+            abort_fn_idx: *abort_fn_idx,
             parameter_starts,
             constant_data_start: *constant_data_start,
             function_return_types,
@@ -120,6 +171,7 @@ impl<'a> FunctionContext<'a> {
             function_id_to_ty_idx,
 
             instructions: Vec::new(),
+            provenance_changes: Vec::new(),
 
             register_to_local: HashMap::new(),
             locals: Vec::new(),
@@ -127,6 +179,9 @@ impl<'a> FunctionContext<'a> {
             variable_locations: HashMap::new(),
             stack_size: 0,
             last_loop_depth: 0,
+            outer_loop_depths: Vec::new(),
+            provenance: None,
+            goto_local: None,
         }
     }
 
@@ -143,6 +198,18 @@ impl<'a> FunctionContext<'a> {
         self.local_index += 1;
         local_idx
     }
+
+    /**
+     * Attribute the instructions pushed from here on to the source, returning the one they were
+     * attributed to before
+     */
+    fn set_provenance(&mut self, provenance: Option<SourceRange>) -> Option<SourceRange> {
+        if provenance != self.provenance {
+            self.provenance_changes
+                .push((self.instructions.len(), provenance.clone()));
+        }
+        std::mem::replace(&mut self.provenance, provenance)
+    }
 }
 
 type PrimitiveCallback<'a> = Option<&'a dyn Fn(&mut FunctionContext<'_>, ValType, usize)>;
@@ -188,6 +255,11 @@ fn encode_node(
     node: &LinearNode,
     callbacks: Option<&Callbacks<'_>>,
 ) {
+    // Nodes the compiler made up belong to whichever node they were made for
+    let outer_provenance = node
+        .provenance
+        .clone()
+        .map(|provenance| ctx.set_provenance(Some(provenance)));
     let node_ty = node.ty(ctx.function_return_types);
     if let Some(PhysicalType::Primitive(prim)) = node_ty {
         if let Some(inner_callbacks) = callbacks {
@@ -198,6 +270,8 @@ fn encode_node(
         LinearNodeValue::Parameter(_, idx) => {
             let mut start = ctx.parameter_starts[*idx];
             let ty = ctx.func.params[*idx].clone();
+            // Primitive parameters get their callbacks after the match, like any other primitive
+            let callbacks = callbacks.filter(|_| !matches!(ty, PhysicalType::Primitive(_)));
             walk_vals_read_order(ctx.declarations, &ty, 0, &mut |val, _| {
                 if let Some(callbacks) = callbacks {
                     callbacks.call_before(ctx, val);
@@ -386,18 +460,44 @@ fn encode_node(
             ctx.instructions.push(Instruction::Return);
         }
         LinearNodeValue::If(cond, then_branch, else_branch, ty) => {
-            encode_node(ctx, cond, None);
+            let then_labels = goto_labels(then_branch);
+            let else_labels = else_branch.as_deref().map(goto_labels).unwrap_or_default();
+            match ctx.goto_local {
+                // While a goto is looking for its label, take whichever branch has it instead
+                Some(goto_local) if !then_labels.is_empty() || !else_labels.is_empty() => {
+                    ctx.instructions.push(Instruction::LocalGet(goto_local));
+                    ctx.instructions.push(Instruction::I32Eqz);
+                    ctx.instructions
+                        .push(Instruction::If(BlockType::Result(ValType::I32)));
+                    ctx.last_loop_depth += 1;
+                    encode_node(ctx, cond, None);
+                    ctx.instructions.push(Instruction::Else);
+                    encode_goto_target_check(ctx, goto_local, &then_labels);
+                    ctx.instructions.push(Instruction::End);
+                    ctx.last_loop_depth -= 1;
+                }
+                _ => encode_node(ctx, cond, None),
+            }
             ctx.last_loop_depth += 1;
-            // TODO: BlockType for branches
+            // Wider results leave the block through locals instead of the stack, unless the
+            // callbacks are already consuming them value by value
+            let mut result_locals = Vec::new();
             let block_type = match ty {
                 None => BlockType::Empty,
                 Some(PhysicalType::Primitive(p)) => BlockType::Result(primitive_to_val_type(*p)),
-                _ => todo!(),
+                Some(_) if callbacks.is_some() => BlockType::Empty,
+                Some(ty) => {
+                    walk_vals_write_order(ctx.declarations, ty, 0, &mut |val, _| {
+                        result_locals.push((val, ctx.alloc_local(val)));
+                    });
+                    BlockType::Empty
+                }
             };
             ctx.instructions.push(Instruction::If(block_type));
             let pre_then_idx = callbacks.map(|c| c.index());
-            for node in then_branch.iter() {
-                encode_node(ctx, node, callbacks);
+            encode_sequence(ctx, then_branch, callbacks);
+            for (_, local) in result_locals.iter() {
+                ctx.instructions.push(Instruction::LocalSet(*local));
             }
             if let Some(else_branch) = else_branch {
                 // Reset the callback index so the else branch gets the right offset
@@ -405,39 +505,66 @@ fn encode_node(
                     callbacks.set_index(pre_then_idx.unwrap());
                 }
                 ctx.instructions.push(Instruction::Else);
-                for node in else_branch.iter() {
-                    encode_node(ctx, node, callbacks);
+                encode_sequence(ctx, else_branch, callbacks);
+                for (_, local) in result_locals.iter() {
+                    ctx.instructions.push(Instruction::LocalSet(*local));
                 }
             }
             ctx.instructions.push(Instruction::End);
+            for (_, local) in result_locals.iter().rev() {
+                ctx.instructions.push(Instruction::LocalGet(*local));
+            }
             ctx.last_loop_depth -= 1;
         }
         LinearNodeValue::Loop(inner) => {
             ctx.instructions.push(Instruction::Block(BlockType::Empty));
             ctx.instructions.push(Instruction::Loop(BlockType::Empty));
-            let prev_loop_depth = ctx.last_loop_depth;
+            ctx.outer_loop_depths.push(ctx.last_loop_depth);
             ctx.last_loop_depth = 1;
-            for node in inner.iter() {
-                encode_node(ctx, node, callbacks);
-            }
+            encode_sequence(ctx, inner, callbacks);
             ctx.instructions.push(Instruction::Br(0));
             ctx.instructions.push(Instruction::End);
             ctx.instructions.push(Instruction::End);
-            ctx.last_loop_depth = prev_loop_depth;
+            ctx.last_loop_depth = ctx.outer_loop_depths.pop().unwrap();
         }
-        LinearNodeValue::Break => {
-            ctx.instructions.push(Instruction::Br(ctx.last_loop_depth));
+        LinearNodeValue::Break(depth) => {
+            ctx.instructions
+                .push(Instruction::Br(loop_block_depth(ctx, *depth)));
         }
-        LinearNodeValue::Abort => {
-            ctx.instructions.push(Instruction::Unreachable);
+        // The loop sits just inside of the block that breaks target
+        LinearNodeValue::Continue(depth) => {
+            ctx.instructions
+                .push(Instruction::Br(loop_block_depth(ctx, *depth) - 1));
         }
-        LinearNodeValue::Goto(_) => { /* TODO */ }
-        LinearNodeValue::GotoLabel(_) => { /* TODO */ }
-        LinearNodeValue::Sequence(nodes) => {
-            for node in nodes.iter() {
-                encode_node(ctx, node, callbacks);
+        LinearNodeValue::Abort(reason) => {
+            encode_abort(ctx, *reason);
+        }
+        // There's no way to report the assertion message yet, so only the reason is passed along
+        LinearNodeValue::AssertionFailed(_) => {
+            encode_abort(ctx, AbortReason::AssertionFailed);
+        }
+        LinearNodeValue::Goto(label) => {
+            encode_node(ctx, label, None);
+            let goto_local = ctx
+                .goto_local
+                .expect("functions with gotos have a goto local");
+            ctx.instructions.push(Instruction::LocalSet(goto_local));
+        }
+        LinearNodeValue::GotoLabel(label) => {
+            // Landing on the label the goto was looking for lets the nodes after it run again
+            if let Some(goto_local) = ctx.goto_local {
+                ctx.instructions.push(Instruction::LocalGet(goto_local));
+                ctx.instructions.push(Instruction::I32Const(*label as i32));
+                ctx.instructions.push(Instruction::I32Eq);
+                ctx.instructions.push(Instruction::If(BlockType::Empty));
+                ctx.instructions.push(Instruction::I32Const(0));
+                ctx.instructions.push(Instruction::LocalSet(goto_local));
+                ctx.instructions.push(Instruction::End);
             }
         }
+        LinearNodeValue::Sequence(nodes) => {
+            encode_sequence(ctx, nodes, callbacks);
+        }
         LinearNodeValue::WriteRegister(reg_id, value) => {
             encode_node(ctx, value, None);
             write_register(ctx, reg_id);
@@ -478,15 +605,29 @@ fn encode_node(
                 (ArithmeticOp::Divide, PhysicalPrimitive::Float32) => Instruction::F32Div,
                 (ArithmeticOp::Divide, PhysicalPrimitive::Int64) => Instruction::I64DivS,
                 (ArithmeticOp::Divide, PhysicalPrimitive::Float64) => Instruction::F64Div,
+                (ArithmeticOp::BitwiseXor, PhysicalPrimitive::Byte)
+                | (ArithmeticOp::BitwiseXor, PhysicalPrimitive::PointerSize)
+                | (ArithmeticOp::BitwiseXor, PhysicalPrimitive::Int32) => Instruction::I32Xor,
+                (ArithmeticOp::BitwiseXor, PhysicalPrimitive::Int64) => Instruction::I64Xor,
+                (
+                    ArithmeticOp::BitwiseXor,
+                    PhysicalPrimitive::Float32 | PhysicalPrimitive::Float64,
+                ) => unreachable!("xor of floats"),
             });
         }
+        LinearNodeValue::Math(func, prim, args) => {
+            for arg in args.iter() {
+                encode_node(ctx, arg, None);
+            }
+            encode_math(ctx, *func, *prim);
+        }
         LinearNodeValue::Comparison(operator, prim, lhs, rhs) => {
             encode_node(ctx, lhs, None);
             encode_node(ctx, rhs, None);
             match (operator, prim) {
                 (_, PhysicalPrimitive::FunctionPointer) => unreachable!(),
                 (ComparisonOp::LessThan, PhysicalPrimitive::Byte) => {
-                    ctx.instructions.push(Instruction::I32LtS);
+                    ctx.instructions.push(Instruction::I32LtU);
                 }
                 (ComparisonOp::LessThan, PhysicalPrimitive::Int32) => {
                     ctx.instructions.push(Instruction::I32LtS);
@@ -504,7 +645,7 @@ fn encode_node(
                     ctx.instructions.push(Instruction::I32LtS);
                 }
                 (ComparisonOp::GreaterThan, PhysicalPrimitive::Byte) => {
-                    ctx.instructions.push(Instruction::I32GtS);
+                    ctx.instructions.push(Instruction::I32GtU);
                 }
                 (ComparisonOp::GreaterThan, PhysicalPrimitive::Int32) => {
                     ctx.instructions.push(Instruction::I32GtS);
@@ -522,7 +663,7 @@ fn encode_node(
                     ctx.instructions.push(Instruction::I32GtS);
                 }
                 (ComparisonOp::LessEqualThan, PhysicalPrimitive::Byte) => {
-                    ctx.instructions.push(Instruction::I32LeS);
+                    ctx.instructions.push(Instruction::I32LeU);
                 }
                 (ComparisonOp::LessEqualThan, PhysicalPrimitive::Int32) => {
                     ctx.instructions.push(Instruction::I32LeS);
@@ -540,7 +681,7 @@ fn encode_node(
                     ctx.instructions.push(Instruction::I32LeS);
                 }
                 (ComparisonOp::GreaterEqualThan, PhysicalPrimitive::Byte) => {
-                    ctx.instructions.push(Instruction::I32GeS);
+                    ctx.instructions.push(Instruction::I32GeU);
                 }
                 (ComparisonOp::GreaterEqualThan, PhysicalPrimitive::Int32) => {
                     ctx.instructions.push(Instruction::I32GeS);
@@ -618,6 +759,8 @@ fn encode_node(
         }
         LinearNodeValue::Cast { value, from, to } => {
             encode_node(ctx, value, None);
+            // Floats saturate when converted to out-of-range integers instead of trapping, to
+            // match the interpreter. Sizes are unsigned, so negative floats become 0.
             match (from, to) {
                 (_, PhysicalPrimitive::FunctionPointer) => unreachable!(),
                 (PhysicalPrimitive::FunctionPointer, _) => unreachable!(),
@@ -648,7 +791,7 @@ fn encode_node(
                     | PhysicalPrimitive::PointerSize,
                     PhysicalPrimitive::Int64,
                 ) => {
-                    ctx.instructions.push(Instruction::I64Extend32S);
+                    ctx.instructions.push(Instruction::I64ExtendI32S);
                 }
                 (
                     PhysicalPrimitive::Byte
@@ -660,14 +803,15 @@ fn encode_node(
                 }
                 (
                     PhysicalPrimitive::Float32,
-                    PhysicalPrimitive::Byte
-                    | PhysicalPrimitive::Int32
-                    | PhysicalPrimitive::PointerSize,
+                    PhysicalPrimitive::Byte | PhysicalPrimitive::Int32,
                 ) => {
-                    ctx.instructions.push(Instruction::I32TruncF32S);
+                    ctx.instructions.push(Instruction::I32TruncSatF32S);
+                }
+                (PhysicalPrimitive::Float32, PhysicalPrimitive::PointerSize) => {
+                    ctx.instructions.push(Instruction::I32TruncSatF32U);
                 }
                 (PhysicalPrimitive::Float32, PhysicalPrimitive::Int64) => {
-                    ctx.instructions.push(Instruction::I64TruncF32S);
+                    ctx.instructions.push(Instruction::I64TruncSatF32S);
                 }
                 (PhysicalPrimitive::Float32, PhysicalPrimitive::Float64) => {
                     ctx.instructions.push(Instruction::F64PromoteF32);
@@ -688,17 +832,18 @@ fn encode_node(
                 }
                 (
                     PhysicalPrimitive::Float64,
-                    PhysicalPrimitive::Byte
-                    | PhysicalPrimitive::Int32
-                    | PhysicalPrimitive::PointerSize,
+                    PhysicalPrimitive::Byte | PhysicalPrimitive::Int32,
                 ) => {
-                    ctx.instructions.push(Instruction::I32TruncF64S);
+                    ctx.instructions.push(Instruction::I32TruncSatF64S);
+                }
+                (PhysicalPrimitive::Float64, PhysicalPrimitive::PointerSize) => {
+                    ctx.instructions.push(Instruction::I32TruncSatF64U);
                 }
                 (PhysicalPrimitive::Float64, PhysicalPrimitive::Float32) => {
                     ctx.instructions.push(Instruction::F32DemoteF64);
                 }
                 (PhysicalPrimitive::Float64, PhysicalPrimitive::Int64) => {
-                    ctx.instructions.push(Instruction::I64TruncF64S);
+                    ctx.instructions.push(Instruction::I64TruncSatF64S);
                 }
             }
         }
@@ -744,7 +889,11 @@ fn encode_node(
             ctx.instructions.push(Instruction::End);
             let initial_idx = callbacks.map(|c| c.index());
             for (i, case) in cases.iter().enumerate() {
+                // Breaks inside of a case have to jump past the case blocks that are still open
+                let open_blocks = block_count - i as u32;
+                ctx.last_loop_depth += open_blocks;
                 encode_node(ctx, case, callbacks);
+                ctx.last_loop_depth -= open_blocks;
                 ctx.instructions
                     .push(Instruction::Br(block_count - 1 - i as u32));
                 ctx.instructions.push(Instruction::End);
@@ -784,6 +933,9 @@ fn encode_node(
             callbacks.call_after(ctx, primitive_to_val_type(prim));
         }
     }
+    if let Some(outer_provenance) = outer_provenance {
+        ctx.set_provenance(outer_provenance);
+    }
 }
 
 fn write_register(ctx: &mut FunctionContext<'_>, reg_id: &RegisterID) {
@@ -795,6 +947,65 @@ fn write_register(ctx: &mut FunctionContext<'_>, reg_id: &RegisterID) {
     ctx.instructions.push(Instruction::LocalSet(local_idx));
 }
 
+/**
+ * Whether the node never finishes, because every path through it returns or aborts
+ */
+fn always_returns(node: &LinearNode) -> bool {
+    match &node.value {
+        LinearNodeValue::Return(_)
+        | LinearNodeValue::Abort(_)
+        | LinearNodeValue::AssertionFailed(_) => true,
+        LinearNodeValue::Sequence(nodes) => nodes.iter().any(always_returns),
+        LinearNodeValue::If(_, if_branch, Some(else_branch), _) => {
+            if_branch.iter().any(always_returns) && else_branch.iter().any(always_returns)
+        }
+        LinearNodeValue::Switch { cases, .. } => {
+            !cases.is_empty() && cases.iter().all(always_returns)
+        }
+        LinearNodeValue::Loop(body) => !body.iter().any(|node| leaves_loop(node, 0)),
+        _ => false,
+    }
+}
+
+/**
+ * Whether the node may jump out of the loop it's in, with loops_inside being how many loops deeper
+ * than that loop it is
+ */
+fn leaves_loop(node: &LinearNode, loops_inside: u32) -> bool {
+    match &node.value {
+        LinearNodeValue::Break(loops_out) => *loops_out >= loops_inside,
+        // Gotos can land anywhere, so assume the worst
+        LinearNodeValue::Goto(_) => true,
+        LinearNodeValue::Loop(body) => body.iter().any(|node| leaves_loop(node, loops_inside + 1)),
+        _ => {
+            let mut leaves = false;
+            node.children(|child| leaves = leaves || leaves_loop(child, loops_inside));
+            leaves
+        }
+    }
+}
+
+/**
+ * How many blocks out the block around a loop is, counting loops outwards from the innermost one.
+ * Each loop further out is past the block and loop of the one inside of it.
+ */
+fn loop_block_depth(ctx: &FunctionContext, loops_out: u32) -> u32 {
+    ctx.outer_loop_depths
+        .iter()
+        .rev()
+        .take(loops_out as usize)
+        .fold(ctx.last_loop_depth, |depth, outer_depth| {
+            depth + 1 + outer_depth
+        })
+}
+
+// Tell the host why we're stopping, then trap in case it returns
+fn encode_abort(ctx: &mut FunctionContext<'_>, reason: AbortReason) {
+    ctx.instructions.push(Instruction::I32Const(reason.code()));
+    ctx.instructions.push(Instruction::Call(ctx.abort_fn_idx));
+    ctx.instructions.push(Instruction::Unreachable);
+}
+
 pub fn walk_vals_read_order(
     declarations: &HashMap<TypeID, DeclaredTypeLayout>,
     ty: &PhysicalType,
@@ -813,7 +1024,10 @@ pub fn walk_vals_read_order(
                     }
                 }
                 TypeLayoutValue::Interface(fields) => {
+                    // The tag of the struct the interface was made from is last
                     let mut location = offset + (ty.size as u64) - 4;
+                    callback(ValType::I32, location);
+                    location -= 4;
                     for _ in fields.iter().rev() {
                         callback(ValType::I32, location);
                         location -= 4;
@@ -831,9 +1045,9 @@ pub fn walk_vals_read_order(
                                 .unwrap_or(0) as u64
                         })
                         .max();
-                    // largest variant
+                    // largest variant, last word first like a union literal pushes it
                     if let Some(size) = size {
-                        for i in 1..=(size / 4) {
+                        for i in (1..=(size / 4)).rev() {
                             callback(ValType::I32, offset + i * 4);
                         }
                     }
@@ -880,13 +1094,16 @@ fn read_primitive(ctx: &mut FunctionContext<'_>, ty: ValType, offset: u64) {
 }
 
 /// Reverse results of function call and handle callback
+///
+/// Void callees leave nothing on the stack, so the callbacks (e.g. a Discard's drop) must not fire
 fn handle_function_call_results(
     ctx: &mut FunctionContext<'_>,
     callbacks: Option<&Callbacks<'_>>,
     ty: Option<&PhysicalType>,
 ) {
     if let Some(callback) = callbacks {
-        if let Some(ty) = ty {
+        // Primitive results get their callbacks from encode_node, like any other primitive node
+        if let Some(ty) = ty.filter(|ty| !matches!(ty, PhysicalType::Primitive(_))) {
             let mut locals = Vec::new();
             walk_vals_write_order(ctx.declarations, ty, 0, &mut |val, _| {
                 let local = ctx.alloc_local(val);
@@ -926,6 +1143,7 @@ pub fn walk_vals_write_order(
                     callback(ValType::I32, offset);
                     offset += 4;
                 }
+                callback(ValType::I32, offset);
             }
             TypeLayoutValue::Union(variants) => {
                 let size = variants
@@ -963,6 +1181,76 @@ pub fn walk_vals_write_order(
     }
 }
 
+/**
+ * Encode nodes that run one after the other. WASM can only jump to the edges of blocks, so a goto
+ * instead records the label it's looking for and everything up to that label is skipped, apart
+ * from the nodes that contain it.
+ */
+fn encode_sequence(
+    ctx: &mut FunctionContext<'_>,
+    nodes: &[LinearNode],
+    callbacks: Option<&Callbacks<'_>>,
+) {
+    let last_label = ctx.goto_local.and_then(|_| {
+        nodes
+            .iter()
+            .rposition(|node| !goto_labels(std::slice::from_ref(node)).is_empty())
+    });
+    for (idx, node) in nodes.iter().enumerate() {
+        let Some(goto_local) = ctx
+            .goto_local
+            .filter(|_| last_label.is_some_and(|last| idx < last))
+        else {
+            // Nothing after the last label can be skipped, because the goto has landed by then
+            encode_node(ctx, node, callbacks);
+            continue;
+        };
+        if matches!(node.value, LinearNodeValue::GotoLabel(_)) {
+            encode_node(ctx, node, callbacks);
+            continue;
+        }
+        // Run the node if no goto is in progress, or if the label the goto wants is inside it
+        ctx.instructions.push(Instruction::LocalGet(goto_local));
+        ctx.instructions.push(Instruction::I32Eqz);
+        encode_goto_target_check(ctx, goto_local, &goto_labels(std::slice::from_ref(node)));
+        ctx.instructions.push(Instruction::I32Or);
+        ctx.instructions.push(Instruction::If(BlockType::Empty));
+        ctx.last_loop_depth += 1;
+        encode_node(ctx, node, callbacks);
+        ctx.instructions.push(Instruction::End);
+        ctx.last_loop_depth -= 1;
+    }
+}
+
+/**
+ * Push whether the label the goto is looking for is one of the labels
+ */
+fn encode_goto_target_check(ctx: &mut FunctionContext<'_>, goto_local: u32, labels: &[usize]) {
+    ctx.instructions.push(Instruction::I32Const(0));
+    for label in labels.iter() {
+        ctx.instructions.push(Instruction::LocalGet(goto_local));
+        ctx.instructions.push(Instruction::I32Const(*label as i32));
+        ctx.instructions.push(Instruction::I32Eq);
+        ctx.instructions.push(Instruction::I32Or);
+    }
+}
+
+/**
+ * Every label a goto could land on somewhere inside of the nodes
+ */
+fn goto_labels(nodes: &[LinearNode]) -> Vec<usize> {
+    let mut labels = Vec::new();
+    for node in nodes.iter() {
+        node.visit(|node| {
+            if let LinearNodeValue::GotoLabel(label) = node.value {
+                labels.push(label);
+            }
+        });
+    }
+
+    labels
+}
+
 fn contract_stack(f: &mut Function, stackptr_global_idx: u32, stack_size: i32) {
     f.instruction(&Instruction::GlobalGet(stackptr_global_idx));
     f.instruction(&Instruction::I32Const(stack_size));
@@ -970,6 +1258,131 @@ fn contract_stack(f: &mut Function, stackptr_global_idx: u32, stack_size: i32) {
     f.instruction(&Instruction::GlobalSet(stackptr_global_idx));
 }
 
+/**
+ * Expects the math function's arguments to already be on the stack
+ */
+fn encode_math(ctx: &mut FunctionContext<'_>, func: MathFunction, prim: PhysicalPrimitive) {
+    let val_ty = primitive_to_val_type(prim);
+    match (func, prim) {
+        (_, PhysicalPrimitive::FunctionPointer | PhysicalPrimitive::Byte) => unreachable!(),
+        (MathFunction::Sqrt, PhysicalPrimitive::Float32) => {
+            ctx.instructions.push(Instruction::F32Sqrt);
+        }
+        (MathFunction::Sqrt, PhysicalPrimitive::Float64) => {
+            ctx.instructions.push(Instruction::F64Sqrt);
+        }
+        (MathFunction::Sqrt, _) => unreachable!("sqrt of integer"),
+        (MathFunction::Abs, PhysicalPrimitive::Float32) => {
+            ctx.instructions.push(Instruction::F32Abs);
+        }
+        (MathFunction::Abs, PhysicalPrimitive::Float64) => {
+            ctx.instructions.push(Instruction::F64Abs);
+        }
+        (MathFunction::Abs, PhysicalPrimitive::PointerSize) => {
+            // Pointer sizes are unsigned
+        }
+        (MathFunction::Abs, PhysicalPrimitive::Int32 | PhysicalPrimitive::Int64) => {
+            let value = ctx.alloc_local(val_ty);
+            ctx.instructions.push(Instruction::LocalSet(value));
+            // select(-x, x, x < 0)
+            if prim == PhysicalPrimitive::Int32 {
+                ctx.instructions.push(Instruction::I32Const(0));
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::I32Sub);
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::I32Const(0));
+                ctx.instructions.push(Instruction::I32LtS);
+            } else {
+                ctx.instructions.push(Instruction::I64Const(0));
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::I64Sub);
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::LocalGet(value));
+                ctx.instructions.push(Instruction::I64Const(0));
+                ctx.instructions.push(Instruction::I64LtS);
+            }
+            ctx.instructions.push(Instruction::Select);
+        }
+        (MathFunction::Min, PhysicalPrimitive::Float32) => {
+            ctx.instructions.push(Instruction::F32Min);
+        }
+        (MathFunction::Min, PhysicalPrimitive::Float64) => {
+            ctx.instructions.push(Instruction::F64Min);
+        }
+        (MathFunction::Max, PhysicalPrimitive::Float32) => {
+            ctx.instructions.push(Instruction::F32Max);
+        }
+        (MathFunction::Max, PhysicalPrimitive::Float64) => {
+            ctx.instructions.push(Instruction::F64Max);
+        }
+        (MathFunction::Min | MathFunction::Max, _) => {
+            let right = ctx.alloc_local(val_ty);
+            let left = ctx.alloc_local(val_ty);
+            ctx.instructions.push(Instruction::LocalSet(right));
+            ctx.instructions.push(Instruction::LocalSet(left));
+            // select(left, right, left < right) for min, left > right for max
+            ctx.instructions.push(Instruction::LocalGet(left));
+            ctx.instructions.push(Instruction::LocalGet(right));
+            ctx.instructions.push(Instruction::LocalGet(left));
+            ctx.instructions.push(Instruction::LocalGet(right));
+            ctx.instructions.push(match (func, prim) {
+                (MathFunction::Min, PhysicalPrimitive::Int32) => Instruction::I32LtS,
+                (MathFunction::Min, PhysicalPrimitive::Int64) => Instruction::I64LtS,
+                (MathFunction::Min, _) => Instruction::I32LtU,
+                (_, PhysicalPrimitive::Int32) => Instruction::I32GtS,
+                (_, PhysicalPrimitive::Int64) => Instruction::I64GtS,
+                _ => Instruction::I32GtU,
+            });
+            ctx.instructions.push(Instruction::Select);
+        }
+        (MathFunction::Pow, _) => {
+            let exponent = ctx.alloc_local(ValType::I32);
+            let base = ctx.alloc_local(val_ty);
+            let result = ctx.alloc_local(val_ty);
+            ctx.instructions.push(Instruction::LocalSet(exponent));
+            ctx.instructions.push(Instruction::LocalSet(base));
+            // Negative exponents abort
+            ctx.instructions.push(Instruction::LocalGet(exponent));
+            ctx.instructions.push(Instruction::I32Const(0));
+            ctx.instructions.push(Instruction::I32LtS);
+            ctx.instructions.push(Instruction::If(BlockType::Empty));
+            encode_abort(ctx, AbortReason::NegativeExponent);
+            ctx.instructions.push(Instruction::End);
+
+            let (one, multiply) = match prim {
+                PhysicalPrimitive::Int32 | PhysicalPrimitive::PointerSize => {
+                    (Instruction::I32Const(1), Instruction::I32Mul)
+                }
+                PhysicalPrimitive::Int64 => (Instruction::I64Const(1), Instruction::I64Mul),
+                PhysicalPrimitive::Float32 => (Instruction::F32Const(1.0), Instruction::F32Mul),
+                PhysicalPrimitive::Float64 => (Instruction::F64Const(1.0), Instruction::F64Mul),
+                PhysicalPrimitive::Byte | PhysicalPrimitive::FunctionPointer => unreachable!(),
+            };
+            ctx.instructions.push(one);
+            ctx.instructions.push(Instruction::LocalSet(result));
+            // Multiply the result by the base until the exponent runs out
+            ctx.instructions.push(Instruction::Block(BlockType::Empty));
+            ctx.instructions.push(Instruction::Loop(BlockType::Empty));
+            ctx.instructions.push(Instruction::LocalGet(exponent));
+            ctx.instructions.push(Instruction::I32Eqz);
+            ctx.instructions.push(Instruction::BrIf(1));
+            ctx.instructions.push(Instruction::LocalGet(result));
+            ctx.instructions.push(Instruction::LocalGet(base));
+            ctx.instructions.push(multiply);
+            ctx.instructions.push(Instruction::LocalSet(result));
+            ctx.instructions.push(Instruction::LocalGet(exponent));
+            ctx.instructions.push(Instruction::I32Const(1));
+            ctx.instructions.push(Instruction::I32Sub);
+            ctx.instructions.push(Instruction::LocalSet(exponent));
+            ctx.instructions.push(Instruction::Br(0));
+            ctx.instructions.push(Instruction::End);
+            ctx.instructions.push(Instruction::End);
+            ctx.instructions.push(Instruction::LocalGet(result));
+        }
+    }
+}
+
 fn primitive_to_val_type(primitive: PhysicalPrimitive) -> ValType {
     match primitive {
         PhysicalPrimitive::Byte
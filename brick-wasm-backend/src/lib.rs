@@ -2,9 +2,10 @@ use std::collections::HashMap;
 
 use brick::{
     expr_ty_to_physical, lower_code, CompileError, ExpressionType, LinearFunction, LowerResults,
-    SourceFile,
+    SourceFile, TargetConfig,
 };
 use function_bodies::{walk_vals_write_order, FunctionEncoder};
+use source_map::FunctionSourceMap;
 use wasm_encoder::{
     CodeSection, ConstExpr, DataSection, DataSegment, DataSegmentMode, ElementSection, Elements,
     EntityType, ExportKind, ExportSection, FunctionSection, GlobalSection, GlobalType,
@@ -16,6 +17,9 @@ mod function_bodies;
 mod function_headers;
 mod indirect_function_table;
 mod runtime;
+mod source_map;
+
+pub use source_map::{SourceMap, SourceMapEntry};
 
 /**
  * Note: currently in WASM, there is only a 0-memory. However, the spec is forwards-compatible with
@@ -27,12 +31,48 @@ const HEAP_MINIMUM_PAGES: u64 = 48;
 const MEMORY_MINIMUM_PAGES: u64 = STACK_PAGES + HEAP_MINIMUM_PAGES;
 const MAXIMUM_MEMORY: u64 = 16_384;
 
-const WASM_BOOL_SIZE: usize = 4;
-const WASM_USIZE: usize = 4;
+// The generated code only handles 32-bit pointers so far
+const WASM_BOOL_SIZE: usize = TargetConfig::WASM32.byte_size;
+const WASM_USIZE: usize = TargetConfig::WASM32.pointer_size;
 const HEAP_SIZE: i32 = 1024 * 1024 * 2;
 const STACK_SIZE: i32 = 1024;
 
 pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Module, CompileError> {
+    compile_for_target(sources, is_start_function, &TargetConfig::WASM32)
+}
+
+/**
+ * Compile for a particular WASM target. Instructions are only generated for 32-bit pointers so far,
+ * so any other target is an error.
+ */
+pub fn compile_for_target(
+    sources: Vec<SourceFile>,
+    is_start_function: bool,
+    target: &TargetConfig,
+) -> Result<Module, CompileError> {
+    let (module, _source_map) = compile_module(sources, is_start_function, target)?;
+
+    Ok(module)
+}
+
+/**
+ * Compile, and also map the instructions in the module back to the source they came from
+ */
+pub fn compile_with_source_map(
+    sources: Vec<SourceFile>,
+    is_start_function: bool,
+) -> Result<(Module, SourceMap), CompileError> {
+    compile_module(sources, is_start_function, &TargetConfig::WASM32)
+}
+
+fn compile_module(
+    sources: Vec<SourceFile>,
+    is_start_function: bool,
+    target: &TargetConfig,
+) -> Result<(Module, SourceMap), CompileError> {
+    if target != &TargetConfig::WASM32 {
+        return Err(CompileError::UnsupportedTarget(target.clone()));
+    }
     let LowerResults {
         statements,
         statements_ty,
@@ -40,10 +80,14 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         declarations,
         type_layouts,
         constant_data,
-    } = lower_code(sources, WASM_BOOL_SIZE, WASM_USIZE)?;
+    } = lower_code(sources, target)?;
 
     let mut function_return_types = HashMap::new();
     for func in declarations.id_to_func.values() {
+        // Generic intrinsics are always inlined
+        if func.type_param_count > 0 {
+            continue;
+        }
         function_return_types.insert(
             func.id,
             if func.returns == ExpressionType::Void || func.returns == ExpressionType::Unreachable {
@@ -110,11 +154,23 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         },
         &ConstExpr::i32_const(constant_data_offset + 4),
     );
+    let rng_state = 2;
+    globals.global(
+        GlobalType {
+            val_type: ValType::I64,
+            mutable: true,
+            shared: false,
+        },
+        &ConstExpr::i64_const(brick::RNG_DEFAULT_STATE as i64),
+    );
 
     let mut function_id_to_fn_idx = HashMap::new();
     let mut function_id_to_ty_idx = HashMap::new();
     // Imports
-    let linear_function_to_id = runtime::add_runtime_imports(&mut import_section, &mut ty_section);
+    let mut linear_function_to_id =
+        runtime::add_runtime_imports(&mut import_section, &mut ty_section);
+    let abort_fn_idx = fn_section.len() + import_section.len();
+    runtime::add_abort_import(&mut import_section, &mut ty_section);
     for (name, fn_id) in declarations.extern_function_bindings.iter() {
         function_id_to_fn_idx.insert(*fn_id, fn_section.len() + import_section.len());
         function_id_to_ty_idx.insert(*fn_id, ty_section.len());
@@ -125,8 +181,17 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
     let runtime_init_idx = fn_section.len() + import_section.len();
     runtime::add_init_import(&mut import_section, &mut ty_section);
 
+    // Functions emitted into the module, which have to come before any headers or bodies
+    linear_function_to_id.extend(runtime::add_rng_functions(
+        &mut fn_section,
+        &mut ty_section,
+        &mut codes,
+        import_section.len(),
+        rng_state,
+    ));
+
     // Function headers
-    let main_index = ty_section.len();
+    let main_index = fn_section.len() + import_section.len();
     for function in functions.iter() {
         if function_id_to_ty_idx.contains_key(&function.id) {
             continue;
@@ -136,7 +201,9 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         function_id_to_ty_idx.insert(function.id, ty_section.len());
         function_headers::encode_linear(&type_layouts, function, &mut ty_section);
     }
-    for function in declarations.id_to_func.values() {
+    let mut declared_functions: Vec<_> = declarations.id_to_func.values().collect();
+    declared_functions.sort_by_key(|function| function.id);
+    for function in declared_functions {
         // Skip intrinsics with generics
         if function.type_param_count > 0 || function_id_to_ty_idx.contains_key(&function.id) {
             continue;
@@ -150,7 +217,7 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         indirect_function_table::encode(
             &mut indirect_function_id_to_table,
             &mut indirect_functions_for_table,
-            &function_id_to_ty_idx,
+            &function_id_to_fn_idx,
             function,
         );
     }
@@ -177,12 +244,20 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         stackptr_global_idx: stack_pointer,
         allocptr_global_idx: alloc_pointer,
         linear_function_to_id: &linear_function_to_id,
+        abort_fn_idx,
         constant_data_start,
         indirect_call_table,
         indirect_function_id_to_table: &indirect_function_id_to_table,
     };
+    let mut function_source_maps = Vec::new();
     for function in functions.iter() {
-        codes.function(&context.encode(function));
+        let (body, entries) = context.encode(function);
+        function_source_maps.push(FunctionSourceMap {
+            code_offset: codes.byte_len(),
+            body_len: body.byte_len(),
+            entries,
+        });
+        codes.function(&body);
     }
 
     // Start section
@@ -224,8 +299,39 @@ pub fn compile(sources: Vec<SourceFile>, is_start_function: bool) -> Result<Modu
         });
     }
     module.section(&elem_section);
+    let source_map =
+        SourceMap::from_functions(module.as_slice().len(), &codes, function_source_maps);
     module.section(&codes);
     module.section(&data_section);
 
-    Ok(module)
+    Ok((module, source_map))
+}
+
+/**
+ * Render a compiled module in the WebAssembly text format, for eyeballing codegen
+ */
+pub fn print_wat(module: &Module) -> anyhow::Result<String> {
+    wasmprinter::print_bytes(module.as_slice())
+}
+
+/**
+ * Quote and escape a string for writing into JSON
+ */
+pub(crate) fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
 }
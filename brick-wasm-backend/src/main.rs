@@ -1,15 +1,113 @@
-use std::env;
+use std::{env, io::Write, process::ExitCode};
 
-use brick::SourceFile;
-use brick_wasm_backend::compile;
+use brick::{CompileError, Diagnostic, SourceFile};
+use brick_wasm_backend::{compile_with_source_map, print_wat};
 
-fn main() {
+#[derive(Clone, Copy, PartialEq)]
+enum MessageFormat {
+    Human,
+    /// One JSON object per diagnostic, each on its own line
+    Json,
+}
+
+fn main() -> ExitCode {
     let mut args = env::args();
     args.next(); // skip binary name
-    let sources: Vec<_> = args
-        .map(|arg| SourceFile::from_filename(String::leak(arg) as &'static str).unwrap())
-        .collect();
+    let mut emit_wat = false;
+    let mut emit_source_map = false;
+    let mut message_format = MessageFormat::Human;
+    let mut sources = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--emit" {
+            match args.next().as_deref() {
+                Some("wat") => emit_wat = true,
+                Some("map") => emit_source_map = true,
+                Some("wasm") => {}
+                other => panic!("unknown --emit format: {other:?}"),
+            }
+        } else if arg == "--message-format" {
+            message_format = match args.next().as_deref() {
+                Some("human") => MessageFormat::Human,
+                Some("json") => MessageFormat::Json,
+                other => panic!("unknown --message-format: {other:?}"),
+            };
+        } else {
+            sources.push(SourceFile::from_filename(String::leak(arg) as &'static str).unwrap());
+        }
+    }
 
-    let module = compile(sources, true).unwrap();
+    let (module, source_map) = match compile_with_source_map(sources, true) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            report_error(message_format, &error);
+            return ExitCode::FAILURE;
+        }
+    };
     std::fs::write("out.wasm", module.as_slice()).unwrap();
+    if emit_wat {
+        std::fs::write("out.wat", print_wat(&module).unwrap()).unwrap();
+    }
+    if emit_source_map {
+        std::fs::write("out.wasm.map", source_map.to_json()).unwrap();
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn report_error(message_format: MessageFormat, error: &CompileError) {
+    match message_format {
+        MessageFormat::Human => eprintln!("{error}"),
+        MessageFormat::Json => {
+            let mut stdout = std::io::stdout().lock();
+            for diagnostic in error.diagnostics() {
+                writeln!(stdout, "{}", diagnostic_to_json(&diagnostic)).unwrap();
+            }
+        }
+    }
+}
+
+/**
+ * Lines and columns are 0-based, like most editor tooling expects, and the end column is just past
+ * the span's last character, the same as an LSP range
+ */
+fn diagnostic_to_json(diagnostic: &Diagnostic) -> String {
+    let code = diagnostic
+        .code
+        .map(json_string)
+        .unwrap_or_else(|| "null".to_string());
+    let message = json_string(&diagnostic.message);
+    let (file, span) = match &diagnostic.span {
+        Some(span) => (
+            json_string(span.source_name()),
+            format!(
+                "{{\"start_line\":{},\"start_column\":{},\"end_line\":{},\"end_column\":{}}}",
+                span.start_line.saturating_sub(1),
+                span.start_offset.saturating_sub(1),
+                span.end_line.saturating_sub(1),
+                span.end_offset,
+            ),
+        ),
+        None => ("null".to_string(), "null".to_string()),
+    };
+
+    format!("{{\"code\":{code},\"message\":{message},\"file\":{file},\"span\":{span}}}")
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
 }
@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
-use brick::RuntimeFunction;
+use brick::{RuntimeFunction, RNG_DEFAULT_STATE};
 use wasm_encoder::{
-    CodeSection, EntityType, Function, ImportSection, Instruction, TypeSection, ValType,
+    BlockType, CodeSection, EntityType, Function, FunctionSection, ImportSection, Instruction,
+    TypeSection, ValType,
 };
 
 pub fn add_runtime_imports(
@@ -71,6 +72,78 @@ pub fn add_init_import(imports: &mut ImportSection, ty_section: &mut TypeSection
     ty_section.function([ValType::I32, ValType::I32], [ValType::I32]);
 }
 
+/**
+ * The host is told why the program is about to trap, via an `AbortReason` code.
+ */
+pub fn add_abort_import(imports: &mut ImportSection, ty_section: &mut TypeSection) {
+    imports.import(
+        "brick-runtime",
+        "brick_runtime_abort",
+        EntityType::Function(ty_section.len()),
+    );
+    ty_section.function([ValType::I32], []);
+}
+
+/**
+ * The random number generator doesn't need anything from the host, so it's emitted into the module
+ * itself, keeping its xorshift state in a global. Must be called after all imports are added.
+ */
+pub fn add_rng_functions(
+    fn_section: &mut FunctionSection,
+    ty_section: &mut TypeSection,
+    codes_section: &mut CodeSection,
+    import_count: u32,
+    rng_state_global_idx: u32,
+) -> [(RuntimeFunction, u32); 2] {
+    let seed_idx = import_count + fn_section.len();
+    fn_section.function(ty_section.len());
+    ty_section.function([ValType::I64], []);
+    let mut seed = Function::new([]);
+    seed.instruction(&Instruction::LocalGet(0));
+    seed.instruction(&Instruction::I64Eqz);
+    seed.instruction(&Instruction::If(BlockType::Result(ValType::I64)));
+    seed.instruction(&Instruction::I64Const(RNG_DEFAULT_STATE as i64));
+    seed.instruction(&Instruction::Else);
+    seed.instruction(&Instruction::LocalGet(0));
+    seed.instruction(&Instruction::End);
+    seed.instruction(&Instruction::GlobalSet(rng_state_global_idx));
+    seed.instruction(&Instruction::End);
+    codes_section.function(&seed);
+
+    let next_idx = import_count + fn_section.len();
+    fn_section.function(ty_section.len());
+    ty_section.function([], [ValType::I32]);
+    let mut next = Function::new([(1, ValType::I64)]);
+    next.instruction(&Instruction::GlobalGet(rng_state_global_idx));
+    next.instruction(&Instruction::LocalSet(0));
+    for (shift, right) in [(13, false), (7, true), (17, false)] {
+        next.instruction(&Instruction::LocalGet(0));
+        next.instruction(&Instruction::LocalGet(0));
+        next.instruction(&Instruction::I64Const(shift));
+        next.instruction(&if right {
+            Instruction::I64ShrU
+        } else {
+            Instruction::I64Shl
+        });
+        next.instruction(&Instruction::I64Xor);
+        next.instruction(&Instruction::LocalSet(0));
+    }
+    next.instruction(&Instruction::LocalGet(0));
+    next.instruction(&Instruction::GlobalSet(rng_state_global_idx));
+    // The top 31 bits, so the result is never negative
+    next.instruction(&Instruction::LocalGet(0));
+    next.instruction(&Instruction::I64Const(33));
+    next.instruction(&Instruction::I64ShrU);
+    next.instruction(&Instruction::I32WrapI64);
+    next.instruction(&Instruction::End);
+    codes_section.function(&next);
+
+    [
+        (RuntimeFunction::RngSeed, seed_idx),
+        (RuntimeFunction::RngNext, next_idx),
+    ]
+}
+
 pub fn add_start(
     codes_section: &mut CodeSection,
     init_index: u32,
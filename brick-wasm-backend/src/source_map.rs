@@ -0,0 +1,154 @@
+use brick::SourceRange;
+use wasm_encoder::CodeSection;
+
+/**
+ * Where the instructions in a compiled module came from, for debugging the module in a browser.
+ * Each entry covers the instructions from its offset up until the next entry.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    /// Sorted by offset
+    pub entries: Vec<SourceMapEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceMapEntry {
+    /// Counted in bytes from the start of the module
+    pub offset: u32,
+    /// Instructions the compiler added on its own, like growing the stack, don't have a source
+    pub provenance: Option<SourceRange>,
+}
+
+impl SourceMap {
+    /**
+     * The source that the instruction at the offset was compiled from
+     */
+    pub fn lookup(&self, offset: u32) -> Option<&SourceRange> {
+        let following = self.entries.partition_point(|entry| entry.offset <= offset);
+        self.entries[..following]
+            .last()
+            .and_then(|entry| entry.provenance.as_ref())
+    }
+
+    /**
+     * Write the map in the standard source map format. WASM modules don't have lines, so the
+     * whole module is one line and the columns are byte offsets.
+     */
+    pub fn to_json(&self) -> String {
+        let mut sources: Vec<&'static str> = Vec::new();
+        let mut mappings = String::new();
+        // Every field besides the offset is relative to the last entry that had a source
+        let mut last_offset = 0;
+        let mut last_source = 0;
+        let mut last_line = 0;
+        let mut last_column = 0;
+        for entry in self.entries.iter() {
+            if !mappings.is_empty() {
+                mappings.push(',');
+            }
+            encode_vlq(&mut mappings, entry.offset as i64 - last_offset);
+            last_offset = entry.offset as i64;
+
+            let Some(provenance) = &entry.provenance else {
+                continue;
+            };
+            let source = match sources
+                .iter()
+                .position(|name| *name == provenance.source_name())
+            {
+                Some(source) => source,
+                None => {
+                    sources.push(provenance.source_name());
+                    sources.len() - 1
+                }
+            } as i64;
+            // Lines and columns are 0-based in source maps
+            let line = provenance.start_line.saturating_sub(1) as i64;
+            let column = provenance.start_offset.saturating_sub(1) as i64;
+            encode_vlq(&mut mappings, source - last_source);
+            encode_vlq(&mut mappings, line - last_line);
+            encode_vlq(&mut mappings, column - last_column);
+            last_source = source;
+            last_line = line;
+            last_column = column;
+        }
+        let sources: Vec<_> = sources.into_iter().map(crate::json_string).collect();
+
+        format!(
+            "{{\"version\":3,\"sources\":[{}],\"names\":[],\"mappings\":\"{mappings}\"}}",
+            sources.join(",")
+        )
+    }
+
+    /**
+     * Turn the entries for each function, which count from the start of its body, into entries
+     * that count from the start of the module
+     */
+    pub(crate) fn from_functions(
+        code_section_start: usize,
+        codes: &CodeSection,
+        functions: Vec<FunctionSourceMap>,
+    ) -> SourceMap {
+        // The section's id, then its size, then how many functions are in it
+        let section_size = leb128_len(codes.len() as usize) + codes.byte_len();
+        let bodies_start =
+            code_section_start + 1 + leb128_len(section_size) + leb128_len(codes.len() as usize);
+
+        let mut entries = Vec::new();
+        for function in functions {
+            // Each body starts with its size
+            let body_start = bodies_start + function.code_offset + leb128_len(function.body_len);
+            entries.extend(function.entries.into_iter().map(|entry| SourceMapEntry {
+                offset: body_start as u32 + entry.offset,
+                provenance: entry.provenance,
+            }));
+        }
+
+        SourceMap { entries }
+    }
+}
+
+/**
+ * The entries for a single function body, along with where it is in the code section
+ */
+pub(crate) struct FunctionSourceMap {
+    pub code_offset: usize,
+    pub body_len: usize,
+    pub entries: Vec<SourceMapEntry>,
+}
+
+fn leb128_len(mut value: usize) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+
+    len
+}
+
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/**
+ * Source maps store numbers as base 64 digits of 5 bits each, least significant first, with the
+ * sign in the lowest bit of the first digit
+ */
+fn encode_vlq(output: &mut String, value: i64) {
+    let mut remaining = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = remaining & 0b11111;
+        remaining >>= 5;
+        if remaining > 0 {
+            digit |= 0b100000;
+        }
+        output.push(BASE64_DIGITS[digit as usize] as char);
+        if remaining == 0 {
+            break;
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::process::Command;
+
+#[test]
+fn json_diagnostics() {
+    let dir = std::env::temp_dir().join(format!("brick-wasmc-json-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.brick");
+    std::fs::write(&path, "let x = 1;\nlet y: bool = x;\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_brick_wasmc"))
+        .current_dir(&dir)
+        .arg("--message-format")
+        .arg("json")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{stdout}");
+    let line = lines[0];
+    assert!(line.starts_with("{\"code\":\"E0102\",\"message\":\"mismatched types at "));
+    assert!(line.contains(&format!("\"file\":\"{}\"", path.display())));
+    assert!(line.ends_with(
+        "\"span\":{\"start_line\":1,\"start_column\":7,\"end_line\":1,\"end_column\":11}}"
+    ));
+}
@@ -0,0 +1,75 @@
+use brick::SourceFile;
+use brick_wasm_backend::compile;
+
+const HELPER: &str = r#"
+export fn double(x: i32): i32 { x * 2 }
+export fn triple(x: i32): i32 { x * 3 }
+"#;
+
+const SOURCE: &str = r#"
+import self.helper;
+
+struct Point {
+    x: i32,
+    y: i32,
+
+    fn sum(self: ref Point): i32 {
+        self.x + self.y
+    }
+}
+
+union Shape {
+    circle(i32),
+    square(Point),
+}
+
+fn area(shape: Shape): i32 {
+    case shape {
+        circle(radius) => radius * radius * 3,
+        square(corner) => corner.sum(),
+    }
+}
+
+fn a(): i32 { 1 }
+fn b(): i32 { a() + 2 }
+fn c(): i32 { b() + 3 }
+fn d(): i32 { c() + 4 }
+
+fn main(): i32 {
+    let shapes = list[Shape.circle(2), Shape.square(Point { x: 1, y: 2 })];
+    let total = area(Shape.circle(3)) + helper.double(d()) + helper.triple(1);
+    total
+}
+"#;
+
+fn compile_source() -> Vec<u8> {
+    compile(
+        vec![
+            SourceFile {
+                filename: "helper.brick",
+                module_name: "helper",
+                contents: HELPER.to_string(),
+            },
+            SourceFile {
+                filename: "main.brick",
+                module_name: "main",
+                contents: SOURCE.to_string(),
+            },
+        ],
+        false,
+    )
+    .unwrap()
+    .as_slice()
+    .to_vec()
+}
+
+#[test]
+fn compiling_twice_is_byte_identical() {
+    let first = compile_source();
+    for _ in 0..8 {
+        assert!(
+            first == compile_source(),
+            "wasm output changed between builds"
+        );
+    }
+}
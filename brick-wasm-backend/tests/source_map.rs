@@ -0,0 +1,83 @@
+use brick::SourceFile;
+use brick_wasm_backend::{compile_with_source_map, SourceMap};
+use wasmparser::{Operator, Parser, Payload};
+
+fn compile_source(filename: &'static str, contents: &str) -> (Vec<u8>, SourceMap) {
+    let (module, source_map) = compile_with_source_map(
+        vec![SourceFile {
+            filename,
+            module_name: "main",
+            contents: contents.to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    (module.finish(), source_map)
+}
+
+/**
+ * The offset of every instruction in the module that matches
+ */
+fn instruction_offsets(bytes: &[u8], matches: impl Fn(&Operator) -> bool) -> Vec<u32> {
+    let mut offsets = Vec::new();
+    for payload in Parser::new(0).parse_all(bytes) {
+        let Payload::CodeSectionEntry(body) = payload.unwrap() else {
+            continue;
+        };
+        let mut reader = body.get_operators_reader().unwrap();
+        while !reader.eof() {
+            let (operator, offset) = reader.read_with_offset().unwrap();
+            if matches(&operator) {
+                offsets.push(offset as u32);
+            }
+        }
+    }
+
+    offsets
+}
+
+#[test]
+fn instruction_maps_back_to_its_line() {
+    let (bytes, source_map) = compile_source(
+        "scale.brick",
+        "fn add(a: i32, b: i32): i32 {
+    a + b
+}
+
+fn scale(a: i32, b: i32): i32 {
+    a * b
+}",
+    );
+
+    let offsets = instruction_offsets(&bytes, |op| matches!(op, Operator::I32Mul));
+    assert_eq!(offsets.len(), 1);
+    let provenance = source_map.lookup(offsets[0]).unwrap();
+    assert_eq!(provenance.source_name(), "scale.brick");
+    assert_eq!(provenance.start_line, 6);
+}
+
+#[test]
+fn stack_setup_isnt_mapped() {
+    let (bytes, source_map) = compile_source("double.brick", "fn double(a: i32): i32 { a * 2 }");
+
+    // Every function body starts by growing the stack, which no source asked for
+    let multiply = instruction_offsets(&bytes, |op| matches!(op, Operator::I32Mul))[0];
+    let stack_growth = instruction_offsets(&bytes, |op| matches!(op, Operator::I32Sub))
+        .into_iter()
+        .rfind(|offset| *offset < multiply)
+        .unwrap();
+    assert_eq!(source_map.lookup(stack_growth), None);
+    assert!(source_map.lookup(multiply).is_some());
+}
+
+#[test]
+fn json_lists_sources() {
+    let (_, source_map) = compile_source("add.brick", "fn add(a: i32, b: i32): i32 { a + b }");
+
+    let json = source_map.to_json();
+    assert!(
+        json.starts_with("{\"version\":3,\"sources\":[\"add.brick\"],\"names\":[],\"mappings\":\""),
+        "{json}"
+    );
+}
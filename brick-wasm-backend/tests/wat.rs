@@ -0,0 +1,319 @@
+use brick::{AbortReason, CompileError, SourceFile, TargetConfig};
+use brick_wasm_backend::{compile, compile_for_target, print_wat};
+
+#[test]
+fn wat_contains_add() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "add.brick",
+            module_name: "main",
+            contents: "fn add(a: i32, b: i32): i32 { a + b }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+    let wat = print_wat(&module).unwrap();
+
+    assert!(wat.contains("(func"));
+    assert!(wat.contains("i32.add"));
+}
+
+#[test]
+fn wat_reports_abort_reason() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "unwrap.brick",
+            module_name: "main",
+            contents: "fn unwrap(x: i32?): i32 { x! }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+    let wat = print_wat(&module).unwrap();
+
+    assert!(wat.contains("\"brick_runtime_abort\""));
+    assert!(wat.contains(&format!("i32.const {}", AbortReason::NullUnwrap.code())));
+}
+
+#[test]
+fn wat_bounds_checks_array_slice() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "slice.brick",
+            module_name: "main",
+            contents: "fn middle(): list[i32] { let a = list[1, 2, 3]; a[1..2] }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+    let wat = print_wat(&module).unwrap();
+
+    assert!(wat.contains(&format!(
+        "i32.const {}",
+        AbortReason::ArrayIndexOutOfBounds.code()
+    )));
+}
+
+#[test]
+fn wat_compares_chars_unsigned() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "chars.brick",
+            module_name: "main",
+            contents: "fn before(a: char, b: char): bool { a < b }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+    let wat = print_wat(&module).unwrap();
+
+    assert!(wat.contains("i32.lt_u"));
+    assert!(!wat.contains("i32.lt_s"));
+}
+
+#[test]
+fn wat_emits_rng_helpers() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "rng.brick",
+            module_name: "main",
+            contents: "fn roll(): i32 { rng_seed(3); rng_next() }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+    let wat = print_wat(&module).unwrap();
+
+    // The generator is self-contained, so it shouldn't need anything from the host
+    assert!(!wat.contains("rng\""));
+    assert!(wat.contains("(global (;2;) (mut i64)"));
+    assert!(wat.contains("i64.shr_u"));
+}
+
+#[test]
+fn void_call_statements_are_not_dropped() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "void_call.brick",
+            module_name: "main",
+            contents: "fn log(x: i32) { let y = x; }
+fn five(): i32 { 5 }
+fn run(): i32 { log(1); five(); log(2); 3 }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    // A stray drop after a void call would underflow the stack and fail validation
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn negative_array_index_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "negative_index.brick",
+            module_name: "main",
+            contents: "fn last(): i32 { let a = list[1, 2, 3]; let i: i32 = 0 - 1; a[i] }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn case_over_union_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "case_over_union.brick",
+            module_name: "main",
+            contents: "union Shape { circle(i32), square(i32), point }
+fn weight(shape: Shape): i32 { case shape { circle(r) => r, square(s) => s * 10, point => 100 } }
+fn run(): i32 { weight(Shape.square(2)) }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn block_loop_condition_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "block_loop_condition.brick",
+            module_name: "main",
+            contents:
+                "fn count(): i32 { let i = 0; while { let next = i + 1; next <= 5 } { i += 1; } i }"
+                    .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn index_through_pointer_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "index_through_pointer.brick",
+            module_name: "main",
+            contents: "fn set_first(items: unique list[i32]) { items[0] = items[1]; }".to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn defer_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "defer.brick",
+            module_name: "main",
+            contents:
+                "fn run(early: bool): i32 { let x = 1; defer x = 2; if early { return x; } x + 1 }"
+                    .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn unary_operators_validate() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "unary_operators.brick",
+            module_name: "main",
+            contents: "fn f(x: i32, y: i64, z: f32): f32 { let a = ~x + -x + +x; let b = ~y; -z }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn dict_index_assignment_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "dict_index_assignment.brick",
+            module_name: "main",
+            contents: "fn add_entry(entries: unique dict[i32, i32]) { entries[5] = 50; }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn wasm64_is_unsupported() {
+    let result = compile_for_target(
+        vec![SourceFile {
+            filename: "add.brick",
+            module_name: "main",
+            contents: "fn add(a: i32, b: i32): i32 { a + b }".to_string(),
+        }],
+        false,
+        &TargetConfig::WASM64,
+    );
+
+    assert!(matches!(result, Err(CompileError::UnsupportedTarget(_))));
+}
+
+#[test]
+fn nullable_coalesce_chain_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "coalesce.brick",
+            module_name: "main",
+            contents: "fn first(a: i32?, b: i32?): i32 { let either: i32? = a ?? b; either ?? 0 }"
+                .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn downcast_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "downcast.brick",
+            module_name: "main",
+            contents: r#"
+interface Shape {
+    fn sides(self: ref Shape): i32,
+}
+
+struct Triangle {
+    base: i32,
+
+    fn sides(self: ref Triangle): i32 {
+        3
+    }
+}
+
+fn triangle_base(shape: Shape): i32 {
+    if let triangle = shape as Triangle {
+        triangle.base
+    } else {
+        0
+    }
+}
+"#
+            .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
+
+#[test]
+fn generator_resume_validates() {
+    let module = compile(
+        vec![SourceFile {
+            filename: "generator.brick",
+            module_name: "main",
+            contents: r#"
+gen fn up_to_two(): generator[i32, void] {
+    let current = 1;
+    while true {
+        if current > 2 {
+            return;
+        }
+        yield current;
+        current += 1;
+    }
+}
+"#
+            .to_string(),
+        }],
+        false,
+    )
+    .unwrap();
+
+    wasmparser::validate(module.as_slice()).unwrap();
+}
@@ -1,7 +1,7 @@
 use std::env;
 
 use brick::SourceFile;
-use brick_wasm_backend::compile;
+use brick_wasm_backend::{compile, print_wat};
 
 static RUNTIME_WASM: &[u8] =
     include_bytes!("../../target/wasm32-unknown-unknown/release/brick_browser_runtime.wasm");
@@ -9,11 +9,24 @@ static RUNTIME_WASM: &[u8] =
 fn main() {
     let mut args = env::args();
     args.next(); // skip binary name
-    let sources: Vec<_> = args
-        .map(|arg| SourceFile::from_filename(String::leak(arg) as &'static str).unwrap())
-        .collect();
+    let mut emit_wat = false;
+    let mut sources = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--emit" {
+            match args.next().as_deref() {
+                Some("wat") => emit_wat = true,
+                Some("wasm") => {}
+                other => panic!("unknown --emit format: {other:?}"),
+            }
+        } else {
+            sources.push(SourceFile::from_filename(String::leak(arg) as &'static str).unwrap());
+        }
+    }
 
     let module = compile(sources, true).unwrap();
     std::fs::write("out.wasm", module.as_slice()).unwrap();
+    if emit_wat {
+        std::fs::write("out.wat", print_wat(&module).unwrap()).unwrap();
+    }
     std::fs::write("runtime.wasm", RUNTIME_WASM).unwrap();
 }
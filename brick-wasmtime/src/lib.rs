@@ -1,4 +1,85 @@
-use wasmtime::{AsContextMut, Caller, Extern, Linker};
+use anyhow::{bail, Context};
+use brick::{
+    check_types, AbortReason, DeclarationRef, ExpressionType, PrimitiveType, SourceFile, Value,
+};
+use brick_wasm_backend::compile;
+use wasmtime::{AsContextMut, Caller, Engine, Extern, Linker, Module, Store, Val};
+
+/**
+ * Compile the source to WASM and call one of its functions through wasmtime, so the backend can be
+ * checked against the interpreter. Only `extern fn`s are exported from a compiled module, and only
+ * primitive parameters and results can cross into it. The top level of the source runs first.
+ */
+pub fn run_compiled(source: &str, fn_name: &str, args: &[Value]) -> anyhow::Result<Value> {
+    let source_file = || SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: source.to_string(),
+    };
+    let results = check_types(vec![source_file()])?;
+    if !results
+        .declarations
+        .extern_function_exports
+        .iter()
+        .any(|(name, _)| name == fn_name)
+    {
+        bail!("{fn_name} isn't exported, only extern functions can be called in compiled code");
+    }
+    let Some(DeclarationRef::Function(signature)) = results.declarations.lookup(fn_name) else {
+        unreachable!("exported functions are always declared");
+    };
+    let ExpressionType::Primitive(returns) = signature.returns else {
+        bail!("only primitive results can be read back from compiled code");
+    };
+
+    let binary = compile(vec![source_file()], true)?.finish();
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+    add_runtime_functions(&mut linker)?;
+    let module = Module::from_binary(&engine, binary.as_slice())?;
+    let instance = linker.instantiate(&mut store, &module)?;
+    let func = instance
+        .get_func(&mut store, fn_name)
+        .with_context(|| format!("failed to find {fn_name}"))?;
+
+    let params = args
+        .iter()
+        .map(to_wasm_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut result = [match returns {
+        PrimitiveType::Int64 => Val::I64(0),
+        PrimitiveType::Float32 => Val::F32(0),
+        PrimitiveType::Float64 => Val::F64(0),
+        PrimitiveType::Char
+        | PrimitiveType::Bool
+        | PrimitiveType::Int32
+        | PrimitiveType::PointerSize => Val::I32(0),
+    }];
+    func.call(&mut store, &params, &mut result)?;
+    let [result] = result;
+
+    Ok(match returns {
+        PrimitiveType::Char | PrimitiveType::Bool => Value::Byte(result.unwrap_i32() as u8),
+        PrimitiveType::Int32 => Value::Int32(result.unwrap_i32()),
+        PrimitiveType::PointerSize => Value::Size(result.unwrap_i32() as u32 as usize),
+        PrimitiveType::Int64 => Value::Int64(result.unwrap_i64()),
+        PrimitiveType::Float32 => Value::Float32(result.unwrap_f32()),
+        PrimitiveType::Float64 => Value::Float64(result.unwrap_f64()),
+    })
+}
+
+fn to_wasm_value(value: &Value) -> anyhow::Result<Val> {
+    Ok(match value {
+        Value::Byte(byte) => Val::I32(*byte as i32),
+        Value::Int32(int) => Val::I32(*int),
+        Value::Size(size) => Val::I32(*size as i32),
+        Value::Int64(int) => Val::I64(*int),
+        Value::Float32(float) => Val::F32(float.to_bits()),
+        Value::Float64(float) => Val::F64(float.to_bits()),
+        Value::FunctionID(_) => bail!("functions can't be passed to compiled code"),
+    })
+}
 
 pub fn add_runtime_functions(linker: &mut Linker<()>) -> anyhow::Result<()> {
     linker.func_wrap(
@@ -90,6 +171,18 @@ pub fn add_runtime_functions(linker: &mut Linker<()>) -> anyhow::Result<()> {
             }
         },
     )?;
+    linker.func_wrap(
+        "brick-runtime",
+        "brick_runtime_abort",
+        |reason: i32| -> anyhow::Result<()> {
+            match AbortReason::from_code(reason) {
+                Some(reason) => Err(anyhow::anyhow!("aborted during execution: {reason}")),
+                None => Err(anyhow::anyhow!(
+                    "aborted during execution: unknown code {reason}"
+                )),
+            }
+        },
+    )?;
 
     Ok(())
 }
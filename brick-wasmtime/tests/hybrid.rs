@@ -0,0 +1,25 @@
+use brick::{run_program, Value};
+use brick_wasmtime::run_compiled;
+
+const ARITHMETIC: &str = r#"
+extern fn mix(a: i32, b: i32): i32 {
+    let sum = a + b;
+    sum * sum - a / 2 + b * 3
+}
+"#;
+
+#[test]
+fn compiled_arithmetic_matches_interpreter() {
+    for (a, b) in [(1, 2), (10, -4), (-7, 3), (0, 0)] {
+        let interpreted = run_program(&format!("{ARITHMETIC}\nmix({a}, {b})")).unwrap();
+        let compiled =
+            run_compiled(ARITHMETIC, "mix", &[Value::Int32(a), Value::Int32(b)]).unwrap();
+        assert_eq!(interpreted, vec![compiled], "mix({a}, {b})");
+    }
+}
+
+#[test]
+fn only_extern_functions_can_be_called() {
+    let source = "fn hidden(): i32 { 1 }";
+    assert!(run_compiled(source, "hidden", &[]).is_err());
+}
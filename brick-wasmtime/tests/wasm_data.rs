@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::{bail, Context};
 use brick::SourceFile;
@@ -56,30 +59,7 @@ fn data() {
 
             look_for_value(store, memory, func, expected, counter)
         },
-        [
-            // Dictionaries don't compile correctly
-            "collections/basic_dict_keys.brick",
-            "collections/dict_contains.brick",
-            "collections/insert_existing_dict.brick",
-            "collections/insert_new_in_dict.brick",
-            "collections/write_to_dict.brick",
-            // Coroutines not yet implemented
-            "coroutine/count_up.brick",
-            "coroutine/echo.brick",
-            "coroutine/infinite.brick",
-            "coroutine/multiple_generators.brick",
-            "coroutine/mutable_ref.brick",
-            "coroutine/mutable_ref_repeated.brick",
-            "coroutine/nested_coroutines.brick",
-            "coroutine/other_functions.brick",
-            "coroutine/regression_test_branch_in_yielding_loop.brick",
-            "coroutine/yield_basic.brick",
-            "coroutine/yield_once.brick",
-            "coroutine/yield_twice.brick",
-            "coroutine/no_yield_value.brick",
-        ]
-        .into_iter()
-        .collect(),
+        HashSet::new(),
     );
 }
 
@@ -110,10 +90,10 @@ fn look_for_value(
             let mut results = [Val::I32(-1)];
             func.call(store, &[], &mut results)?;
 
-            if let Some(int_val) = results[0].i32() {
-                Ok(TestValue::Int(int_val as i64))
-            } else {
-                bail!("wrong result returned: {:?}", results[0])
+            match results[0] {
+                Val::I32(int_val) => Ok(TestValue::Int(int_val as i64)),
+                Val::I64(int_val) => Ok(TestValue::Int(int_val)),
+                _ => bail!("wrong result returned: {:?}", results[0]),
             }
         }
         TestValue::Float(_) => {
@@ -12,7 +12,7 @@ use crate::{
     declaration_context::TypeID,
     hir::{HirModule, HirNode},
     id::{AnyID, NodeID, VariableID},
-    multi_error::{merge_results, print_multi_errors, MultiError},
+    multi_error::{merge_results, merge_results_or_value, print_multi_errors, MultiError},
     typecheck::{ExpressionType, PointerKind},
     DeclarationContext, HirNodeValue, SourceRange, TypeDeclaration,
 };
@@ -79,24 +79,63 @@ fn maybe_range(range: &Option<SourceRange>) -> String {
 pub fn borrow_check(
     declarations: &DeclarationContext,
     module: &mut HirModule,
+) -> Result<(), LifetimeError> {
+    borrow_check_continuing(
+        declarations,
+        module,
+        &HashSet::new(),
+        &mut BlockLiveness::new(),
+    )
+}
+
+/**
+ * Check a module whose top level carries on from an earlier one, like the lines typed into a REPL.
+ * The persistent variables outlive the top level, so they start out however the earlier top level
+ * left them, and aren't dropped when this one ends. The liveness is updated with how this top level
+ * leaves them.
+ */
+pub(crate) fn borrow_check_continuing(
+    declarations: &DeclarationContext,
+    module: &mut HirModule,
+    persistent: &HashSet<VariableID>,
+    liveness: &mut BlockLiveness,
 ) -> Result<(), LifetimeError> {
     let mut result = Ok(());
 
     for func in module.functions.iter_mut() {
-        let func_result = borrow_check_body(declarations, &mut func.body);
-        merge_results(&mut result, func_result);
+        let func_result = borrow_check_body(
+            declarations,
+            &mut func.body,
+            BlockLiveness::new(),
+            &HashSet::new(),
+        );
+        merge_results(&mut result, func_result.map(|_| ()));
     }
 
-    let top_level_result = borrow_check_body(declarations, &mut module.top_level_statements);
-    merge_results(&mut result, top_level_result);
+    let top_level_result = borrow_check_body(
+        declarations,
+        &mut module.top_level_statements,
+        std::mem::take(liveness),
+        persistent,
+    );
+    if let Some(mut exit) = merge_results_or_value(&mut result, top_level_result) {
+        exit.var_state.retain(|id, _| persistent.contains(id));
+        exit.borrow_state.retain(|id, _| persistent.contains(id));
+        *liveness = exit;
+    }
 
     result
 }
 
+/**
+ * Returns the state of the variables when the body finishes
+ */
 fn borrow_check_body(
     declarations: &DeclarationContext,
     node: &mut HirNode,
-) -> Result<(), LifetimeError> {
+    entry: BlockLiveness,
+    persistent: &HashSet<VariableID>,
+) -> Result<BlockLiveness, LifetimeError> {
     let FunctionCFG {
         mut cfg,
         start,
@@ -108,6 +147,8 @@ fn borrow_check_body(
         declarations: &declarations.id_to_decl,
         block_queue,
         loops_visited: HashSet::new(),
+        start,
+        entry,
     };
 
     let mut result = Ok(());
@@ -119,14 +160,15 @@ fn borrow_check_body(
         );
     }
 
-    let drop_points = drop_points::calculate_drop_points(&cfg, end);
+    let exit = cfg.node_weight(end).unwrap().life_state().clone();
+    let drop_points = drop_points::calculate_drop_points(&cfg, end, persistent);
     drop_points::insert_drops(declarations, node, drop_points);
 
-    result
+    result.map(|_| exit)
 }
 
 #[derive(Clone, Debug, Default)]
-struct BlockLiveness {
+pub(crate) struct BlockLiveness {
     var_state: HashMap<VariableID, VariableState>,
     borrow_state: HashMap<VariableID, BorrowState>,
 }
@@ -173,12 +215,17 @@ enum BorrowLifeState {
 #[derive(Clone, PartialEq, Debug)]
 enum PathSegment {
     Access(String),
+    // Indices aren't known until runtime, so every element of a collection has the same path
+    Element,
 }
 
 struct Context<'a> {
     declarations: &'a HashMap<TypeID, TypeDeclaration>,
     block_queue: VecDeque<NodeIndex>,
     loops_visited: HashSet<EdgeIndex>,
+    start: NodeIndex,
+    /// The state of the variables that were declared before the body started
+    entry: BlockLiveness,
 }
 
 fn borrow_check_block<'a>(
@@ -187,8 +234,11 @@ fn borrow_check_block<'a>(
     block_idx: NodeIndex,
 ) -> Result<(), LifetimeError> {
     // Merge parent states into a new state
-    let mut var_state = HashMap::new();
-    let mut borrow_state = HashMap::new();
+    let (mut var_state, mut borrow_state) = if block_idx == ctx.start {
+        (ctx.entry.var_state.clone(), ctx.entry.borrow_state.clone())
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
     let first_node_in_child = cfg.node_weight(block_idx).unwrap().first_node();
     for incoming_edge in cfg.edges_directed(block_idx, Direction::Incoming) {
         let parent = cfg
@@ -363,10 +413,11 @@ fn borrow_check_node(
                     },
                 );
             } else {
+                // There's nothing to drop until the variable is first assigned
                 variable_state.insert(
                     *id,
                     VariableState {
-                        state: VariableLifeState::Used(node.id, node.provenance.clone()),
+                        state: VariableLifeState::Moved(node.id, node.provenance.clone()),
                         borrows: Vec::new(),
                         ty: node.ty.clone(),
                     },
@@ -450,6 +501,21 @@ fn borrow_check_node(
                 );
             }
         }
+        // Slicing copies the items out, so the array is only read
+        HirNodeValue::ArraySlice(arr, start, end) => {
+            merge_results(
+                &mut results,
+                borrow_check_node(ctx, variable_state, borrow_state, start),
+            );
+            merge_results(
+                &mut results,
+                borrow_check_node(ctx, variable_state, borrow_state, end),
+            );
+            merge_results(
+                &mut results,
+                mark_node_used(variable_state, borrow_state, arr),
+            );
+        }
         HirNodeValue::UnionVariant(lhs, _)
         | HirNodeValue::Access(lhs, _)
         | HirNodeValue::NullableTraverse(lhs, _) => {
@@ -789,11 +855,15 @@ fn find_variable_for_lvalue(lvalue: &HirNode) -> &AnyID {
         HirNodeValue::VariableReference(id) => id,
         HirNodeValue::NullableTraverse(child, _)
         | HirNodeValue::Access(child, _)
+        | HirNodeValue::UnionVariant(child, _)
         | HirNodeValue::ArrayIndex(child, _)
         | HirNodeValue::DictIndex(child, _)
         | HirNodeValue::Dereference(child)
         | HirNodeValue::TakeUnique(child)
         | HirNodeValue::TakeShared(child) => find_variable_for_lvalue(child),
+        HirNodeValue::Call(_, args) | HirNodeValue::VtableCall(_, _, args) => {
+            find_variable_for_lvalue(reference_argument(args))
+        }
         other => panic!("ICE: illegal lvalue: {other:?}"),
     }
 }
@@ -813,16 +883,32 @@ fn build_path_for_lvalue(lvalue: &HirNode, path: &mut Vec<PathSegment>) {
                 path.push(PathSegment::Access(field.clone()));
             }
         }
-        HirNodeValue::Access(lhs, field) => {
+        HirNodeValue::Access(lhs, field) | HirNodeValue::UnionVariant(lhs, field) => {
             build_path_for_lvalue(lhs, path);
             path.push(PathSegment::Access(field.clone()));
         }
-        HirNodeValue::ArrayIndex(_, _) | HirNodeValue::DictIndex(_, _) => todo!("{lvalue:?}"),
+        HirNodeValue::ArrayIndex(lhs, _) | HirNodeValue::DictIndex(lhs, _) => {
+            build_path_for_lvalue(lhs, path);
+            path.push(PathSegment::Element);
+        }
         HirNodeValue::Dereference(child)
         | HirNodeValue::TakeUnique(child)
         | HirNodeValue::TakeShared(child) => {
             build_path_for_lvalue(child, path);
         }
+        HirNodeValue::Call(_, args) | HirNodeValue::VtableCall(_, _, args) => {
+            build_path_for_lvalue(reference_argument(args), path);
+        }
         other => panic!("ICE: illegal lvalue: {other:?}"),
     }
 }
+
+/**
+ * Functions can only return a reference into their one reference argument, so a returned
+ * reference borrows whatever that argument does
+ */
+fn reference_argument(args: &[HirNode]) -> &HirNode {
+    args.iter()
+        .find(|arg| matches!(arg.ty, ExpressionType::Pointer(_, _)))
+        .expect("functions that return references have a reference parameter")
+}
@@ -4,7 +4,10 @@ use crate::{hir::HirNode, HirNodeValue};
 
 // Based on https://ics.uci.edu/~lopes/teaching/inf212W12/readings/rep-analysis-soft.pdf
 
-use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::{
+    stable_graph::{NodeIndex, StableGraph},
+    Direction,
+};
 
 use super::BlockLiveness;
 
@@ -19,12 +22,12 @@ pub struct FunctionCFG<'a> {
     pub end: NodeIndex,
 }
 
-pub fn build_control_flow_graph(body: &HirNode) -> FunctionCFG {
+pub fn build_control_flow_graph(body: &HirNode) -> FunctionCFG<'_> {
     let mut intermediate_cfg = StableGraph::new();
     let intermediate_entrance = intermediate_cfg.add_node(IntermediateNode::Entrance);
     let exit = intermediate_cfg.add_node(IntermediateNode::Exit);
 
-    let (start_body, end_body) = create_graph_for_node(body, &mut intermediate_cfg, exit);
+    let (start_body, end_body) = create_graph_for_node(body, &mut intermediate_cfg, exit, &[]);
     intermediate_cfg.add_edge(intermediate_entrance, start_body, CfgEdge::Flow);
     if let Some(end_body) = end_body {
         intermediate_cfg.add_edge(end_body, exit, CfgEdge::Flow);
@@ -196,10 +199,29 @@ impl CfgEdge {
     }
 }
 
+/**
+ * Where a break or continue inside of a loop body jumps to
+ */
+#[derive(Copy, Clone)]
+struct LoopTargets {
+    start: NodeIndex,
+    exit: NodeIndex,
+}
+
+/**
+ * The loops that a nested loop's body can jump out of, innermost last
+ */
+fn with_loop(enclosing_loops: &[LoopTargets], innermost: LoopTargets) -> Vec<LoopTargets> {
+    let mut loops = enclosing_loops.to_vec();
+    loops.push(innermost);
+    loops
+}
+
 fn create_graph_for_node<'a>(
     current: &'a HirNode,
     graph: &mut IntermediateCFG<'a>,
     function_exit: NodeIndex,
+    enclosing_loops: &[LoopTargets],
 ) -> (NodeIndex, Option<NodeIndex>) {
     use HirNodeValue::*;
 
@@ -209,7 +231,8 @@ fn create_graph_for_node<'a>(
             let mut current_node = start;
 
             for child in children.iter() {
-                let (start_child, end_child) = create_graph_for_node(child, graph, function_exit);
+                let (start_child, end_child) =
+                    create_graph_for_node(child, graph, function_exit, enclosing_loops);
                 graph.add_edge(current_node, start_child, CfgEdge::Flow);
                 if let Some(end_child) = end_child {
                     current_node = end_child;
@@ -228,14 +251,35 @@ fn create_graph_for_node<'a>(
             // TODO: what should the exit be?
             (node, None)
         }
+        Break(_, depth) => {
+            let node = graph.add_node(IntermediateNode::Node(current));
+            let enclosing_loop = enclosing_loops
+                .iter()
+                .nth_back(*depth)
+                .expect("break to be inside a loop");
+            graph.add_edge(node, enclosing_loop.exit, CfgEdge::Goto);
+
+            (node, None)
+        }
+        Continue(depth) => {
+            let node = graph.add_node(IntermediateNode::Node(current));
+            let enclosing_loop = enclosing_loops
+                .iter()
+                .nth_back(*depth)
+                .expect("continue to be inside a loop");
+            graph.add_edge(node, enclosing_loop.start, CfgEdge::Loop);
+
+            (node, None)
+        }
         // TODO: short-circuiting boolean logic
         If(condition, if_branch, else_branch) => {
             let (start_condition, end_condition) =
-                create_graph_for_node(condition, graph, function_exit);
+                create_graph_for_node(condition, graph, function_exit, enclosing_loops);
             let Some(end_condition) = end_condition else {
                 return (start_condition, None);
             };
-            let (start_body, end_body) = create_graph_for_node(if_branch, graph, function_exit);
+            let (start_body, end_body) =
+                create_graph_for_node(if_branch, graph, function_exit, enclosing_loops);
             graph.add_edge(end_condition, start_body, CfgEdge::If);
 
             let rejoin_node = graph.add_node(IntermediateNode::Empty);
@@ -245,7 +289,7 @@ fn create_graph_for_node<'a>(
 
             let else_destination = if let Some(else_branch) = else_branch {
                 let (start_else, end_else) =
-                    create_graph_for_node(else_branch, graph, function_exit);
+                    create_graph_for_node(else_branch, graph, function_exit, enclosing_loops);
 
                 if let Some(end_else) = end_else {
                     graph.add_edge(end_else, rejoin_node, CfgEdge::Flow);
@@ -260,14 +304,16 @@ fn create_graph_for_node<'a>(
             (start_condition, Some(rejoin_node))
         }
         Switch { value, cases } => {
-            let (start_value, end_value) = create_graph_for_node(value, graph, function_exit);
+            let (start_value, end_value) =
+                create_graph_for_node(value, graph, function_exit, enclosing_loops);
             let Some(end_value) = end_value else {
                 return (start_value, None);
             };
 
             let rejoin_node = graph.add_node(IntermediateNode::Empty);
             for case in cases.iter() {
-                let (start_case, end_case) = create_graph_for_node(case, graph, function_exit);
+                let (start_case, end_case) =
+                    create_graph_for_node(case, graph, function_exit, enclosing_loops);
                 graph.add_edge(end_value, start_case, CfgEdge::If);
                 if let Some(end_case) = end_case {
                     graph.add_edge(end_case, rejoin_node, CfgEdge::Flow);
@@ -278,14 +324,24 @@ fn create_graph_for_node<'a>(
         }
         While(condition, body) => {
             let (start_condition, end_condition) =
-                create_graph_for_node(condition, graph, function_exit);
+                create_graph_for_node(condition, graph, function_exit, enclosing_loops);
             let Some(end_condition) = end_condition else {
                 return (start_condition, None);
             };
-            let (start_body, end_body) = create_graph_for_node(body, graph, function_exit);
-            graph.add_edge(end_condition, start_body, CfgEdge::If);
-
             let after_loop = graph.add_node(IntermediateNode::Empty);
+            let (start_body, end_body) = create_graph_for_node(
+                body,
+                graph,
+                function_exit,
+                &with_loop(
+                    enclosing_loops,
+                    LoopTargets {
+                        start: start_condition,
+                        exit: after_loop,
+                    },
+                ),
+            );
+            graph.add_edge(end_condition, start_body, CfgEdge::If);
             graph.add_edge(end_condition, after_loop, CfgEdge::Else);
             if let Some(end_body) = end_body {
                 graph.add_edge(end_body, start_condition, CfgEdge::Loop);
@@ -294,13 +350,31 @@ fn create_graph_for_node<'a>(
             (start_condition, Some(after_loop))
         }
         Loop(body) => {
-            let (start_child, end_child) = create_graph_for_node(body, graph, function_exit);
+            let before_loop = graph.add_node(IntermediateNode::Empty);
+            let after_loop = graph.add_node(IntermediateNode::Empty);
+            let (start_child, end_child) = create_graph_for_node(
+                body,
+                graph,
+                function_exit,
+                &with_loop(
+                    enclosing_loops,
+                    LoopTargets {
+                        start: before_loop,
+                        exit: after_loop,
+                    },
+                ),
+            );
+            graph.add_edge(before_loop, start_child, CfgEdge::Flow);
             if let Some(end_child) = end_child {
-                graph.add_edge(end_child, start_child, CfgEdge::Loop);
-                (start_child, Some(end_child))
-            } else {
-                (start_child, None)
+                graph.add_edge(end_child, before_loop, CfgEdge::Loop);
+                graph.add_edge(end_child, after_loop, CfgEdge::Flow);
             }
+            let is_exited = graph
+                .neighbors_directed(after_loop, Direction::Incoming)
+                .next()
+                .is_some();
+
+            (before_loop, is_exited.then_some(after_loop))
         }
         _ => {
             let node = graph.add_node(IntermediateNode::Node(current));
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use petgraph::{stable_graph::NodeIndex, Direction};
 
@@ -21,11 +21,16 @@ pub struct DropPoint {
     ty: ExpressionType,
 }
 
+/**
+ * Persistent variables are still around once the body ends, so they're only dropped on the paths
+ * that don't move them when another path does
+ */
 pub fn calculate_drop_points(
     cfg: &ControlFlowGraph<'_>,
     exit: NodeIndex,
+    persistent: &HashSet<VariableID>,
 ) -> HashMap<NodeID, Vec<DropPoint>> {
-    let mut drop_points = calculate_non_move_drops(cfg, exit);
+    let mut drop_points = calculate_non_move_drops(cfg, exit, persistent);
     calculate_move_drops(cfg, &mut drop_points);
 
     drop_points
@@ -34,12 +39,16 @@ pub fn calculate_drop_points(
 fn calculate_non_move_drops(
     cfg: &ControlFlowGraph<'_>,
     exit: NodeIndex,
+    persistent: &HashSet<VariableID>,
 ) -> HashMap<NodeID, Vec<DropPoint>> {
     let Some(CfgNode::Exit { life_state, .. }) = cfg.node_weight(exit) else {
         unreachable!()
     };
     let mut drop_points: HashMap<NodeID, Vec<DropPoint>> = HashMap::new();
     for (var_id, variable) in life_state.var_state.iter() {
+        if persistent.contains(var_id) {
+            continue;
+        }
         match variable.state {
             VariableLifeState::Used(last_use, _) => {
                 let drop_point = DropPoint {
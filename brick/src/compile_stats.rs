@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/**
+ * How long each phase of compilation took, for finding where the compiler spends its time on
+ * larger programs. Only recorded by check_types_with_stats, so normal compiles don't pay for the
+ * timers.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompileStats {
+    /// The phases that run separately for each module, in the order the sources were given
+    pub modules: Vec<ModuleStats>,
+    /// Collecting every module's declarations, resolving the names between them, and validating
+    /// the resulting types
+    pub resolution: Duration,
+    pub borrow_checking: Duration,
+    pub const_evaluation: Duration,
+    /// The whole compile from start to finish. Modules are typechecked and lowered in parallel,
+    /// so with more than one module this can be less than the sum of the phases.
+    pub total: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleStats {
+    pub module_name: &'static str,
+    pub lexing: Duration,
+    pub parsing: Duration,
+    /// Includes finding the module's warnings, which walks the typechecked tree
+    pub typechecking: Duration,
+    /// Lowering the typechecked tree to HIR, including the desugaring passes
+    pub lowering: Duration,
+}
+
+impl CompileStats {
+    /**
+     * The time spent in every phase added together, for comparing against the total
+     */
+    pub fn phase_total(&self) -> Duration {
+        self.modules
+            .iter()
+            .map(|module| module.lexing + module.parsing + module.typechecking + module.lowering)
+            .sum::<Duration>()
+            + self.resolution
+            + self.borrow_checking
+            + self.const_evaluation
+    }
+}
+
+/**
+ * Run the phase, measuring how long it took if stats are being recorded
+ */
+pub(crate) fn timed<T>(record: bool, phase: impl FnOnce() -> T) -> (T, Duration) {
+    if !record {
+        return (phase(), Duration::ZERO);
+    }
+    let start = Instant::now();
+    let result = phase();
+
+    (result, start.elapsed())
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::{
+    hir::{HirModule, HirNode, HirNodeValue},
+    id::ConstantID,
+    interpreter::{Function, Value, VM},
+    linear_ir::{layout_types, LinearContext},
+    typecheck::{ExpressionType, PrimitiveType},
+    CompileError, CompileOptions, DeclarationContext,
+};
+
+/**
+ * Run each constant that calls a const fn through the interpreter, and replace every use of it
+ * with the literal it produced. This is laid out for the interpreter rather than whatever target
+ * we're compiling for, which is fine because const fns only deal in primitives.
+ */
+pub fn fold_const_evaluations(
+    modules: &mut HashMap<&'static str, HirModule>,
+    declarations: &DeclarationContext,
+    options: &CompileOptions,
+) -> Result<(), CompileError> {
+    let mut evaluations = HashMap::new();
+    for module in modules.values() {
+        module.visit(|_, node| {
+            if let HirNodeValue::ConstEvaluation(id, call) = &node.value {
+                evaluations
+                    .entry(*id)
+                    .or_insert_with(|| call.as_ref().clone());
+            }
+        });
+    }
+    if evaluations.is_empty() {
+        return Ok(());
+    }
+
+    let mut layouts = HashMap::new();
+    layout_types(
+        &declarations.id_to_decl,
+        &mut layouts,
+        1,
+        std::mem::size_of::<usize>(),
+    );
+    let mut constant_data = Vec::new();
+    let mut indirect_function_types = HashMap::new();
+    let mut linear_context = LinearContext {
+        layouts: &layouts,
+        constant_data_region: &mut constant_data,
+        string_literal_offsets: HashMap::new(),
+        indirect_function_types: &mut indirect_function_types,
+        byte_size: 1,
+        pointer_size: std::mem::size_of::<usize>(),
+        checked_arithmetic: options.checked_arithmetic,
+        module: declarations.new_file(),
+        invalid_lvalues: Vec::new(),
+    };
+
+    let mut functions = HashMap::new();
+    for module in modules.values() {
+        for function in module.functions.iter() {
+            let function = linear_context.linearize_function(declarations, function.clone());
+            functions.insert(function.id, Function::Ir(function));
+        }
+    }
+
+    let mut values: HashMap<ConstantID, HirNodeValue> = HashMap::new();
+    for (id, call) in evaluations {
+        let provenance = call
+            .provenance
+            .clone()
+            .expect("constants are written in the source");
+        let ty = call.ty.clone();
+        let statements = linear_context.linearize_nodes(vec![call]);
+        if let Some(provenance) = linear_context.invalid_lvalues.first() {
+            return Err(CompileError::InvalidLvalue(provenance.clone()));
+        }
+        let vm = VM::new(
+            layouts.clone(),
+            &functions,
+            linear_context.constant_data_region.clone(),
+        );
+        let Ok((results, _)) = vm.evaluate_top_level_statements(&statements[..]) else {
+            return Err(CompileError::ConstEvaluationFailed(provenance));
+        };
+        values.insert(id, value_to_literal(&ty, &results[..]));
+    }
+
+    for module in modules.values_mut() {
+        module.par_visit_mut(|node| {
+            if let HirNodeValue::ConstEvaluation(id, _) = &node.value {
+                node.value = values[id].clone();
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/**
+ * Concatenating two string literals is done at compile time rather than with a runtime call. This
+ * runs after constants are inlined, so constants holding strings are folded too.
+ */
+pub fn fold_string_concats(modules: &mut HashMap<&'static str, HirModule>) {
+    for module in modules.values_mut() {
+        module.par_visit_mut(fold_string_concat);
+    }
+}
+
+fn fold_string_concat(node: &mut HirNode) {
+    let HirNodeValue::StringConcat(left, right) = &node.value else {
+        return;
+    };
+    // Children are visited first, so chains of concatenations fold from the inside out
+    let (HirNodeValue::StringLiteral(left), HirNodeValue::StringLiteral(right)) =
+        (&left.value, &right.value)
+    else {
+        return;
+    };
+    node.value = HirNodeValue::StringLiteral(format!("{left}{right}"));
+}
+
+fn value_to_literal(ty: &ExpressionType, results: &[Value]) -> HirNodeValue {
+    let ExpressionType::Primitive(primitive) = ty else {
+        unreachable!("const fns must return primitives, not {ty:?}");
+    };
+    match (primitive, results) {
+        (PrimitiveType::Bool, [Value::Byte(x)]) => HirNodeValue::Bool(*x != 0),
+        (PrimitiveType::Char, [Value::Byte(x)]) => HirNodeValue::CharLiteral(*x as char),
+        (PrimitiveType::Int32, [Value::Int32(x)]) => HirNodeValue::Int(*x as i64),
+        (PrimitiveType::Int64, [Value::Int64(x)]) => HirNodeValue::Int(*x),
+        (PrimitiveType::Float32, [Value::Float32(x)]) => HirNodeValue::Float(*x as f64),
+        (PrimitiveType::Float64, [Value::Float64(x)]) => HirNodeValue::Float(*x),
+        (PrimitiveType::PointerSize, [Value::Size(x)]) => HirNodeValue::PointerSize(*x),
+        (primitive, results) => {
+            unreachable!("const fn returning {primitive:?} produced {results:?}")
+        }
+    }
+}
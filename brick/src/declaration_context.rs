@@ -3,7 +3,7 @@ use bytemuck::{Pod, Zeroable};
 use crate::{
     multi_error::{merge_result_list, merge_results, merge_results_or_value},
     parser::{
-        AstNode, AstNodeValue, FunctionDeclarationValue, FunctionHeaderValue,
+        AstNode, AstNodeValue, FunctionDeclarationValue, FunctionHeaderValue, ImplBlockValue,
         InterfaceDeclarationValue, NameAndType, StructDeclarationValue, UnionDeclarationValue,
         UnionDeclarationVariant,
     },
@@ -14,30 +14,86 @@ use crate::{
     ExpressionType, SourceRange, TypeDeclaration,
 };
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap, HashSet},
     sync::atomic::{AtomicU32, Ordering},
 };
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct DeclarationContext {
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub intrinsic_module: FileDeclarations,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub files: HashMap<&'static str, FileDeclarations>,
     pub id_to_decl: HashMap<TypeID, TypeDeclaration>,
     pub id_to_func: HashMap<FunctionID, FuncType>,
     pub intrinsic_to_id: HashMap<IntrinsicFunction, FunctionID>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub array_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub dict_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub rc_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub cell_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub char_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub int_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub numeric_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub float_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
+    /// Intrinsics called like free functions, which user declarations may shadow
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub global_intrinsics: HashMap<&'static str, CollectionIntrinsic>,
     pub extern_function_bindings: Vec<(String, FunctionID)>,
     pub extern_function_exports: Vec<(String, FunctionID)>,
+    /// Types declared inside a function's body, which are only visible within that function
+    pub local_type_names: HashMap<FunctionID, HashMap<String, TypeID>>,
+    /// Functions declared inside another function's body, which are only visible within it
+    pub local_function_names: HashMap<FunctionID, HashMap<String, FunctionID>>,
+    /// The types that each alias stands for. Aliases get type IDs so they can be exported and
+    /// imported like other types, but they never have a declaration of their own.
+    pub type_aliases: HashMap<TypeID, ExpressionType>,
+    /// File IDs are handed out per compilation so the same sources always get the same IDs
+    #[cfg_attr(feature = "serde", serde(skip))]
+    file_id_counter: AtomicU32,
+}
+
+// The ID counters are atomics, so this can't be derived
+impl Clone for DeclarationContext {
+    fn clone(&self) -> Self {
+        DeclarationContext {
+            intrinsic_module: self.intrinsic_module.clone(),
+            files: self.files.clone(),
+            id_to_decl: self.id_to_decl.clone(),
+            id_to_func: self.id_to_func.clone(),
+            intrinsic_to_id: self.intrinsic_to_id.clone(),
+            array_intrinsics: self.array_intrinsics.clone(),
+            dict_intrinsics: self.dict_intrinsics.clone(),
+            rc_intrinsics: self.rc_intrinsics.clone(),
+            cell_intrinsics: self.cell_intrinsics.clone(),
+            char_intrinsics: self.char_intrinsics.clone(),
+            int_intrinsics: self.int_intrinsics.clone(),
+            numeric_intrinsics: self.numeric_intrinsics.clone(),
+            float_intrinsics: self.float_intrinsics.clone(),
+            global_intrinsics: self.global_intrinsics.clone(),
+            extern_function_bindings: self.extern_function_bindings.clone(),
+            extern_function_exports: self.extern_function_exports.clone(),
+            local_type_names: self.local_type_names.clone(),
+            local_function_names: self.local_function_names.clone(),
+            type_aliases: self.type_aliases.clone(),
+            file_id_counter: AtomicU32::new(self.file_id_counter.load(Ordering::Relaxed)),
+        }
+    }
 }
 
 impl DeclarationContext {
-    pub fn new(
-        files: &[(&'static str, Vec<AstNode<'_>>)],
+    pub fn new<'a>(
+        files: &'a [(&'static str, Vec<AstNode<'a>>)],
     ) -> Result<DeclarationContext, TypecheckError> {
         let mut ctx = DeclarationContext {
-            intrinsic_module: FileDeclarations::new(),
+            intrinsic_module: FileDeclarations::new(FileID(1)),
             files: HashMap::new(),
             id_to_decl: HashMap::new(),
             id_to_func: HashMap::new(),
@@ -46,36 +102,411 @@ impl DeclarationContext {
             dict_intrinsics: HashMap::new(),
             rc_intrinsics: HashMap::new(),
             cell_intrinsics: HashMap::new(),
+            char_intrinsics: HashMap::new(),
+            int_intrinsics: HashMap::new(),
+            numeric_intrinsics: HashMap::new(),
+            float_intrinsics: HashMap::new(),
+            global_intrinsics: HashMap::new(),
             extern_function_bindings: Vec::new(),
             extern_function_exports: Vec::new(),
+            local_type_names: HashMap::new(),
+            local_function_names: HashMap::new(),
+            type_aliases: HashMap::new(),
+            file_id_counter: AtomicU32::new(2),
         };
 
-        for (name, source) in files {
-            ctx.assign_ids_to_names(name, source);
+        let results = ctx.declare_files(files, &mut HashMap::new());
+
+        add_intrinsics(&mut ctx);
+
+        results?;
+        // Walking the fields of a type with an inline cycle would never terminate, so this has
+        // to wait until we know there are none
+        ctx.propagate_viral_types();
+        Ok(ctx)
+    }
+
+    /**
+     * Replace the declarations of a single module, without resolving the others again. Every file
+     * given has to belong to the one module, and together they replace all of its declarations.
+     *
+     * The module keeps its IDs as long as it declares the same things in the same order, so the
+     * other modules' references into it stay valid. If anything they can see changed (its exports,
+     * any of its top-level types or aliases, or the signatures of its exported and associated
+     * functions) they may be out of date, and the whole context has to be built again with `new`.
+     * The same goes for an update that fails. An update without any files changes nothing.
+     */
+    pub fn update_module<'a>(
+        &mut self,
+        files: &'a [(&'static str, Vec<AstNode<'a>>)],
+    ) -> Result<ModuleUpdate, TypecheckError> {
+        let Some((module_name, _)) = files.first() else {
+            return Ok(ModuleUpdate::Contained);
+        };
+        let module_name = *module_name;
+        assert!(
+            files.iter().all(|(name, _)| *name == module_name),
+            "every file in a module update belongs to the same module"
+        );
+        let file = self
+            .files
+            .get_mut(module_name)
+            .expect("updated module was already declared");
+        let file_id = file.id;
+        let module_id = file.module_id;
+        file.type_id_counter = AtomicU32::new(2);
+        file.func_id_counter = AtomicU32::new(1);
+        file.imports.clear();
+
+        let old_types: HashMap<_, _> = self
+            .id_to_decl
+            .extract_if(|id, _| id.0 == file_id)
+            .collect();
+        let old_funcs: HashMap<_, _> = self
+            .id_to_func
+            .extract_if(|id, _| id.0 == file_id)
+            .collect();
+        let old_aliases: HashMap<_, _> = self
+            .type_aliases
+            .extract_if(|id, _| id.0 == file_id)
+            .collect();
+        self.local_type_names.retain(|id, _| id.0 != file_id);
+        self.local_function_names.retain(|id, _| id.0 != file_id);
+        self.extern_function_bindings
+            .retain(|(_, id)| id.0 != file_id);
+        self.extern_function_exports
+            .retain(|(_, id)| id.0 != file_id);
+        self.id_to_decl.insert(
+            module_id,
+            TypeDeclaration::Module(ModuleType {
+                id: module_id,
+                exports: HashMap::new(),
+                exported: HashSet::new(),
+            }),
+        );
+
+        self.declare_files(files, &mut HashMap::new())?;
+        self.propagate_viral_types();
+
+        // Types declared inside function bodies can't be seen from other modules
+        let local_types: HashSet<_> = self
+            .local_type_names
+            .values()
+            .flat_map(|names| names.values())
+            .collect();
+        let new_types: Vec<_> = self
+            .id_to_decl
+            .iter()
+            .filter(|(id, _)| id.0 == file_id && !local_types.contains(id))
+            .collect();
+        let types_match = new_types.len()
+            == old_types
+                .keys()
+                .filter(|id| !local_types.contains(id))
+                .count()
+            && new_types
+                .iter()
+                .all(|(id, decl)| old_types.get(id) == Some(decl));
+        let aliases_match = self
+            .type_aliases
+            .iter()
+            .filter(|(id, _)| id.0 == file_id)
+            .count()
+            == old_aliases.len()
+            && old_aliases
+                .iter()
+                .all(|(id, ty)| self.type_aliases.get(id) == Some(ty));
+        let mut visible_funcs = Vec::new();
+        for (_, decl) in new_types.iter() {
+            match decl {
+                TypeDeclaration::Module(ModuleType {
+                    exports, exported, ..
+                }) => {
+                    visible_funcs.extend(exported.iter().filter_map(|name| match &exports[name] {
+                        ExpressionType::ReferenceToFunction(id) => Some(id),
+                        _ => None,
+                    }))
+                }
+                TypeDeclaration::Struct(StructType {
+                    associated_functions,
+                    ..
+                })
+                | TypeDeclaration::Interface(InterfaceType {
+                    associated_functions,
+                    ..
+                }) => visible_funcs.extend(associated_functions.values()),
+                TypeDeclaration::Union(_) => {}
+            }
         }
+        let signatures_match =
+            visible_funcs
+                .iter()
+                .all(|id| match (old_funcs.get(id), self.id_to_func.get(id)) {
+                    (Some(old), Some(new)) => same_signature(old, new),
+                    (None, None) => true,
+                    _ => false,
+                });
+
+        Ok(if types_match && aliases_match && signatures_match {
+            ModuleUpdate::Contained
+        } else {
+            ModuleUpdate::SignaturesChanged
+        })
+    }
 
+    /**
+     * Add more declarations to a module that was already declared, or start a new one, like each
+     * line typed into a REPL. The names declared so far are kept between calls, so declaring one
+     * again is still an error. An extension that fails may leave the context partway updated.
+     */
+    pub(crate) fn extend_module<'a>(
+        &mut self,
+        files: &'a [(&'static str, Vec<AstNode<'a>>)],
+        declared_names: &mut HashMap<(&'static str, String), SourceRange>,
+    ) -> Result<(), TypecheckError> {
+        self.declare_files(files, declared_names)?;
+        self.propagate_viral_types();
+
+        Ok(())
+    }
+
+    /**
+     * Declare everything in the files. Viral types are left for the caller to propagate, since
+     * that can't be done until there are no errors.
+     */
+    fn declare_files<'a>(
+        &mut self,
+        files: &'a [(&'static str, Vec<AstNode<'a>>)],
+        declared_names: &mut HashMap<(&'static str, String), SourceRange>,
+    ) -> Result<(), TypecheckError> {
         let mut results = Ok(());
         for (name, source) in files {
-            merge_results(&mut results, ctx.fill_in_file_type_info(name, source));
+            merge_results(
+                &mut results,
+                self.assign_ids_to_names(name, source, declared_names),
+            );
         }
+        // Names that collide can't be resolved to a single declaration
+        results?;
+        let mut results = Ok(());
 
-        add_intrinsics(&mut ctx);
-        ctx.propagate_viral_types();
+        for (name, source) in files {
+            merge_results(&mut results, self.resolve_file_imports(name, source));
+        }
+        // Aliases have to be expanded before the types that use them are filled in
+        merge_results(&mut results, self.resolve_type_aliases(files));
 
-        results?;
-        Ok(ctx)
+        let mut declared_types = Vec::new();
+        for (name, source) in files {
+            merge_results(
+                &mut results,
+                self.fill_in_file_type_info(name, source, &mut declared_types),
+            );
+        }
+        merge_results(
+            &mut results,
+            find_infinitely_sized_types(&self.id_to_decl, &declared_types),
+        );
+
+        results
+    }
+
+    /**
+     * Look up a method-style intrinsic called on a value of a primitive type
+     */
+    pub fn primitive_intrinsic(
+        &self,
+        prim: PrimitiveType,
+        name: &str,
+    ) -> Option<&CollectionIntrinsic> {
+        let type_specific = match prim {
+            PrimitiveType::Char => &self.char_intrinsics,
+            PrimitiveType::Int32 => &self.int_intrinsics,
+            _ => return self.numeric_intrinsic(prim, name),
+        };
+        type_specific
+            .get(name)
+            .or_else(|| self.numeric_intrinsic(prim, name))
+    }
+
+    /**
+     * Declarations for a new file, or for functions generated during lowering
+     */
+    pub(crate) fn new_file(&self) -> FileDeclarations {
+        FileDeclarations::new(FileID(self.file_id_counter.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    /**
+     * Find which free-function intrinsic a function ID refers to, if any
+     */
+    pub fn global_intrinsic(&self, id: &FunctionID) -> Option<IntrinsicFunction> {
+        self.global_intrinsics
+            .values()
+            .find(|intrinsic| intrinsic.fn_id == *id)
+            .map(|intrinsic| intrinsic.intrinsic_fn)
     }
 
-    fn assign_ids_to_names(&mut self, module_name: &'static str, source: &[AstNode<'_>]) {
+    /**
+     * The name a type was declared with, found through its module's exports
+     */
+    pub fn type_name(&self, id: &TypeID) -> Option<&str> {
+        self.id_to_decl
+            .values()
+            .find_map(|decl| match decl {
+                TypeDeclaration::Module(module) => {
+                    module
+                        .exports
+                        .iter()
+                        .find_map(|(name, export)| match export {
+                            ExpressionType::ReferenceToType(export_id) if export_id == id => {
+                                Some(name.as_str())
+                            }
+                            _ => None,
+                        })
+                }
+                _ => None,
+            })
+            .or_else(|| {
+                self.local_type_names.values().find_map(|names| {
+                    names
+                        .iter()
+                        .find(|(_, local_id)| *local_id == id)
+                        .map(|(name, _)| name.as_str())
+                })
+            })
+    }
+
+    /**
+     * Every function exported from a module, ordered by module and then by name
+     */
+    pub fn exported_functions(&self) -> Vec<ExportedFunction<'_>> {
+        let mut functions = Vec::new();
+        for (module, exports) in self.module_exports() {
+            for (name, export) in exports.iter() {
+                let ExpressionType::ReferenceToFunction(id) = export else {
+                    continue;
+                };
+                if let Some(signature) = self.id_to_func.get(id) {
+                    functions.push(ExportedFunction {
+                        module,
+                        name: name.as_str(),
+                        signature,
+                    });
+                }
+            }
+        }
+        functions.sort_by(|a, b| (a.module, a.name).cmp(&(b.module, b.name)));
+
+        functions
+    }
+
+    /**
+     * The fields of a struct sorted by name, or the variants of a union in declaration order.
+     * Union variants without a value have a void type. See `lookup` for how names are resolved.
+     */
+    pub fn type_fields(&self, path: &str) -> Option<Vec<TypeField<'_>>> {
+        let DeclarationRef::Type(decl) = self.lookup(path)? else {
+            return None;
+        };
+        match decl {
+            TypeDeclaration::Struct(StructType { fields, .. }) => {
+                let mut fields: Vec<_> = fields
+                    .iter()
+                    .map(|(name, ty)| TypeField {
+                        name: name.as_str(),
+                        ty,
+                    })
+                    .collect();
+                fields.sort_by_key(|field| field.name);
+                Some(fields)
+            }
+            TypeDeclaration::Union(UnionType {
+                variant_order,
+                variants,
+                ..
+            }) => Some(
+                variant_order
+                    .iter()
+                    .map(|name| TypeField {
+                        name: name.as_str(),
+                        ty: variants[name].as_ref().unwrap_or(&ExpressionType::Void),
+                    })
+                    .collect(),
+            ),
+            TypeDeclaration::Interface(_) | TypeDeclaration::Module(_) => None,
+        }
+    }
+
+    /**
+     * Resolve a name exported from a module to its declaration. The name may be qualified with
+     * its module (like `math.add`); otherwise modules are searched in order of their names.
+     */
+    pub fn lookup(&self, path: &str) -> Option<DeclarationRef<'_>> {
+        let (module_name, name) = match path.split_once('.') {
+            Some((module_name, name)) => (Some(module_name), name),
+            None => (None, path),
+        };
+        self.module_exports()
+            .into_iter()
+            .filter(|(module, _)| module_name.is_none_or(|module_name| module_name == *module))
+            .find_map(|(_, exports)| match exports.get(name)? {
+                ExpressionType::ReferenceToType(id) => {
+                    self.id_to_decl.get(id).map(DeclarationRef::Type)
+                }
+                ExpressionType::ReferenceToFunction(id) => {
+                    self.id_to_func.get(id).map(DeclarationRef::Function)
+                }
+                _ => None,
+            })
+    }
+
+    fn module_exports(&self) -> Vec<(&'static str, &HashMap<String, ExpressionType>)> {
+        let mut modules: Vec<_> = self
+            .files
+            .iter()
+            .filter_map(|(name, file)| match self.id_to_decl.get(&file.module_id)? {
+                TypeDeclaration::Module(module) => Some((*name, &module.exports)),
+                _ => None,
+            })
+            .collect();
+        modules.sort_by_key(|(name, _)| *name);
+
+        modules
+    }
+
+    fn numeric_intrinsic(&self, prim: PrimitiveType, name: &str) -> Option<&CollectionIntrinsic> {
+        match prim {
+            PrimitiveType::Char | PrimitiveType::Bool => None,
+            PrimitiveType::Float32 | PrimitiveType::Float64 => self
+                .float_intrinsics
+                .get(name)
+                .or_else(|| self.numeric_intrinsics.get(name)),
+            PrimitiveType::Int32 | PrimitiveType::Int64 | PrimitiveType::PointerSize => {
+                self.numeric_intrinsics.get(name)
+            }
+        }
+    }
+
+    /**
+     * Each name can only be declared once at the top level of a module, even when the module is
+     * split across files
+     */
+    fn assign_ids_to_names<'a>(
+        &mut self,
+        module_name: &'static str,
+        source: &'a [AstNode<'a>],
+        declared_names: &mut HashMap<(&'static str, String), SourceRange>,
+    ) -> Result<(), TypecheckError> {
         let module = match self.files.get_mut(module_name) {
             Some(module) => module,
             None => {
-                let file = FileDeclarations::new();
+                let file = self.new_file();
                 self.id_to_decl.insert(
                     file.module_id,
                     TypeDeclaration::Module(ModuleType {
                         id: file.module_id,
                         exports: HashMap::new(),
+                        exported: HashSet::new(),
                     }),
                 );
                 self.files.insert(module_name, file);
@@ -88,18 +519,71 @@ impl DeclarationContext {
             unreachable!()
         };
 
+        let mut result = Ok(());
         for statement in source.iter() {
+            let declared_name = match &statement.value {
+                AstNodeValue::StructDeclaration(StructDeclarationValue { name, .. })
+                | AstNodeValue::UnionDeclaration(UnionDeclarationValue { name, .. })
+                | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue { name, .. })
+                | AstNodeValue::FunctionDeclaration(FunctionDeclarationValue { name, .. })
+                | AstNodeValue::ExternFunctionBinding {
+                    header: FunctionHeaderValue { name, .. },
+                    ..
+                }
+                | AstNodeValue::TypeAliasDeclaration { name, .. }
+                | AstNodeValue::ConstDeclaration { name, .. } => Some(name),
+                _ => None,
+            };
+            if let Some(name) = declared_name {
+                if is_exported(statement) {
+                    module_decl.exported.insert(name.clone());
+                }
+                match declared_names.entry((module_name, name.clone())) {
+                    Entry::Occupied(first) => {
+                        merge_results(
+                            &mut result,
+                            Err(TypecheckError::DuplicateDeclaration(
+                                name.clone(),
+                                first.get().clone(),
+                                statement.provenance.clone(),
+                            )),
+                        );
+                        continue;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(statement.provenance.clone());
+                    }
+                }
+            }
             match &statement.value {
                 AstNodeValue::StructDeclaration(StructDeclarationValue { name, .. })
                 | AstNodeValue::UnionDeclaration(UnionDeclarationValue { name, .. })
-                | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue { name, .. }) => {
+                | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue { name, .. })
+                | AstNodeValue::TypeAliasDeclaration { name, .. } => {
                     module_decl.exports.insert(
                         name.clone(),
                         ExpressionType::ReferenceToType(module.new_type_id()),
                     );
                 }
-                AstNodeValue::FunctionDeclaration(FunctionDeclarationValue { name, .. })
-                | AstNodeValue::ExternFunctionBinding(FunctionHeaderValue { name, .. }) => {
+                AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
+                    name, body, ..
+                }) => {
+                    let func_id = module.new_func_id();
+                    module_decl
+                        .exports
+                        .insert(name.clone(), ExpressionType::ReferenceToFunction(func_id));
+                    assign_ids_to_locals(
+                        module,
+                        &mut self.local_type_names,
+                        &mut self.local_function_names,
+                        func_id,
+                        body,
+                    );
+                }
+                AstNodeValue::ExternFunctionBinding {
+                    header: FunctionHeaderValue { name, .. },
+                    ..
+                } => {
                     module_decl.exports.insert(
                         name.clone(),
                         ExpressionType::ReferenceToFunction(module.new_func_id()),
@@ -108,25 +592,42 @@ impl DeclarationContext {
                 _ => {}
             }
         }
+
+        result
     }
 
-    fn fill_in_file_type_info(
+    fn resolve_file_imports<'a>(
         &mut self,
         module_name: &'static str,
-        source: &[AstNode<'_>],
+        source: &'a [AstNode<'a>],
     ) -> Result<(), TypecheckError> {
         let mut result = Ok(());
 
         let mut imports = Vec::new();
         for statement in source.iter() {
-            let AstNodeValue::Import(path) = &statement.value else {
+            let AstNodeValue::Import(path, names, alias) = &statement.value else {
                 continue;
             };
-            if let Some((name, expr)) = merge_results_or_value(
-                &mut result,
-                resolve_import(self, path, &statement.provenance),
-            ) {
-                imports.push((name, expr));
+            let paths = match names {
+                Some(names) => names
+                    .iter()
+                    .map(|name| {
+                        let mut path = path.clone();
+                        path.push(name.clone());
+                        path
+                    })
+                    .collect(),
+                None => vec![path.clone()],
+            };
+            for path in paths.iter() {
+                if let Some((name, expr)) = merge_results_or_value(
+                    &mut result,
+                    resolve_import(self, path, &statement.provenance),
+                ) {
+                    // The alias only changes the local binding, the module is still found by name
+                    let name = alias.clone().unwrap_or(name);
+                    imports.push((name, expr));
+                }
             }
         }
 
@@ -135,18 +636,87 @@ impl DeclarationContext {
             file.imports.insert(name, expr);
         }
 
-        let mut names_to_type_id = HashMap::new();
-        let TypeDeclaration::Module(module) = &self.id_to_decl[&file.module_id] else {
-            unreachable!()
-        };
-        for (name, expr) in module.exports.iter() {
-            if let ExpressionType::ReferenceToType(ty_id) = expr {
-                names_to_type_id.insert(name.as_str(), *ty_id);
+        result
+    }
+
+    /**
+     * Expand every alias to the type it stands for. Aliases can refer to other aliases, even
+     * across modules, so they're resolved after the aliases they depend on.
+     */
+    fn resolve_type_aliases<'a>(
+        &mut self,
+        files: &'a [(&'static str, Vec<AstNode<'a>>)],
+    ) -> Result<(), TypecheckError> {
+        let mut aliases = Vec::new();
+        for (module_name, source) in files {
+            let names_to_type_id = type_names(&self.id_to_decl, &self.files[module_name]);
+            for statement in source.iter() {
+                if let AstNodeValue::TypeAliasDeclaration { name, ty, .. } = &statement.value {
+                    aliases.push(TypeAlias {
+                        id: names_to_type_id[name.as_str()],
+                        name,
+                        ty,
+                        provenance: &statement.provenance,
+                        names_to_type_id: names_to_type_id.clone(),
+                    });
+                }
             }
         }
-        for (name, expr) in file.imports.iter() {
-            if let ExpressionType::ReferenceToType(ty_id) = expr {
-                names_to_type_id.insert(name.as_str(), *ty_id);
+
+        let mut result = Ok(());
+        let mut finished = HashSet::new();
+        let mut cycles = Vec::new();
+        for idx in 0..aliases.len() {
+            merge_results(
+                &mut result,
+                resolve_type_alias(
+                    &aliases,
+                    idx,
+                    &mut Vec::new(),
+                    &mut finished,
+                    &mut self.type_aliases,
+                    &mut cycles,
+                ),
+            );
+        }
+        // Each cycle is reported once, at the first alias in it to be declared
+        for cycle in cycles {
+            let names = cycle
+                .iter()
+                .chain(cycle.first())
+                .map(|idx| aliases[*idx].name.clone())
+                .collect();
+            merge_results(
+                &mut result,
+                Err(TypecheckError::TypeAliasCycle(
+                    aliases[cycle[0]].provenance.clone(),
+                    names,
+                )),
+            );
+        }
+
+        result
+    }
+
+    fn fill_in_file_type_info<'a>(
+        &mut self,
+        module_name: &'static str,
+        source: &'a [AstNode<'a>],
+        declared_types: &mut Vec<(TypeID, String, SourceRange)>,
+    ) -> Result<(), TypecheckError> {
+        let mut result = Ok(());
+
+        let file = &self.files[module_name];
+        let names_to_type_id = type_names(&self.id_to_decl, file);
+        let aliases = &self.type_aliases;
+
+        let mut impls: HashMap<&str, Vec<&ImplBlockValue>> = HashMap::new();
+        for statement in source.iter() {
+            if let AstNodeValue::ImplBlock(impl_block) = &statement.value {
+                impls
+                    .entry(impl_block.name.as_str())
+                    .or_default()
+                    .push(impl_block);
             }
         }
 
@@ -157,33 +727,51 @@ impl DeclarationContext {
                     &mut result,
                     fill_in_struct_info(
                         &names_to_type_id,
+                        aliases,
                         file,
                         &mut self.id_to_func,
                         decl,
+                        impls.remove(decl.name.as_str()).unwrap_or_default(),
                         &statement.provenance,
                     ),
                 ),
                 AstNodeValue::UnionDeclaration(decl) => merge_results_or_value(
                     &mut result,
-                    fill_in_union_decl(&names_to_type_id, decl, &statement.provenance),
+                    fill_in_union_decl(&names_to_type_id, aliases, decl, &statement.provenance),
                 ),
                 AstNodeValue::InterfaceDeclaration(decl) => merge_results_or_value(
                     &mut result,
-                    fill_in_interface_decl(&names_to_type_id, file, &mut self.id_to_func, decl),
+                    fill_in_interface_decl(
+                        &names_to_type_id,
+                        aliases,
+                        file,
+                        &mut self.id_to_func,
+                        decl,
+                    ),
                 ),
                 _ => None,
             };
             if let Some(decl) = decl {
+                let name = match &statement.value {
+                    AstNodeValue::StructDeclaration(StructDeclarationValue { name, .. })
+                    | AstNodeValue::UnionDeclaration(UnionDeclarationValue { name, .. })
+                    | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue {
+                        name, ..
+                    }) => name.clone(),
+                    _ => unreachable!(),
+                };
+                declared_types.push((decl.id(), name, statement.provenance.clone()));
                 declarations.push(decl);
             }
             let func = match &statement.value {
-                AstNodeValue::ExternFunctionBinding(func) => {
+                AstNodeValue::ExternFunctionBinding { header: func, .. } => {
                     let id = get_id_for_func_name(file, &self.id_to_decl, func.name.as_str());
                     self.extern_function_bindings.push((func.name.clone(), id));
                     merge_results_or_value(
                         &mut result,
                         fill_in_fn_header(
                             &names_to_type_id,
+                            aliases,
                             id,
                             func,
                             false,
@@ -196,9 +784,31 @@ impl DeclarationContext {
                     if func.is_extern {
                         self.extern_function_exports.push((func.name.clone(), id));
                     }
+                    merge_results(
+                        &mut result,
+                        fill_in_locals(
+                            &names_to_type_id,
+                            aliases,
+                            file,
+                            &self.local_type_names,
+                            &self.local_function_names,
+                            &mut self.id_to_func,
+                            id,
+                            func.body,
+                            &mut declarations,
+                            declared_types,
+                        ),
+                    );
                     merge_results_or_value(
                         &mut result,
-                        fill_in_fn_decl(&names_to_type_id, id, func, false, &statement.provenance),
+                        fill_in_fn_decl(
+                            &names_to_type_id,
+                            aliases,
+                            id,
+                            func,
+                            false,
+                            &statement.provenance,
+                        ),
                     )
                 }
                 _ => None,
@@ -212,6 +822,17 @@ impl DeclarationContext {
             self.id_to_decl.insert(decl.id(), decl);
         }
 
+        // Every impl for a struct in this module was claimed by it
+        for impl_block in impls.into_values().flatten() {
+            merge_results(
+                &mut result,
+                Err(TypecheckError::ImplForUnknownStruct(
+                    impl_block.name.clone(),
+                    impl_block.name_provenance.clone(),
+                )),
+            );
+        }
+
         result
     }
 
@@ -230,6 +851,36 @@ impl DeclarationContext {
     }
 }
 
+/**
+ * Whether the rest of a context is still valid after one module's declarations were updated
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModuleUpdate {
+    /// Nothing other modules can see changed, so their declarations are still correct
+    Contained,
+    /// Other modules may refer to declarations that changed, so the context has to be rebuilt
+    SignaturesChanged,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportedFunction<'a> {
+    pub module: &'static str,
+    pub name: &'a str,
+    pub signature: &'a FuncType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeField<'a> {
+    pub name: &'a str,
+    pub ty: &'a ExpressionType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeclarationRef<'a> {
+    Function(&'a FuncType),
+    Type(&'a TypeDeclaration),
+}
+
 pub struct FileDeclarations {
     id: FileID,
     pub module_id: TypeID,
@@ -238,10 +889,20 @@ pub struct FileDeclarations {
     imports: HashMap<String, ExpressionType>,
 }
 
-impl FileDeclarations {
-    pub(crate) fn new() -> FileDeclarations {
-        let id = FileID::new();
+impl Clone for FileDeclarations {
+    fn clone(&self) -> Self {
+        FileDeclarations {
+            id: self.id,
+            module_id: self.module_id,
+            type_id_counter: AtomicU32::new(self.type_id_counter.load(Ordering::Relaxed)),
+            func_id_counter: AtomicU32::new(self.func_id_counter.load(Ordering::Relaxed)),
+            imports: self.imports.clone(),
+        }
+    }
+}
 
+impl FileDeclarations {
+    fn new(id: FileID) -> FileDeclarations {
         FileDeclarations {
             id,
             module_id: TypeID(id, 1),
@@ -266,27 +927,60 @@ impl FileDeclarations {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct FileID(u32);
 
-impl FileID {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        static PACKAGE_COUNTER: AtomicU32 = AtomicU32::new(1);
-        Self(PACKAGE_COUNTER.fetch_add(1, Ordering::Relaxed))
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeID(FileID, u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FunctionID(FileID, u32);
+
+impl TypeID {
+    /**
+     * Identifies the type at runtime, like in the tag that interface values carry. Packed into 32
+     * bits so it fits in a pointer on every target.
+     */
+    pub fn runtime_tag(&self) -> usize {
+        ((self.0 .0 as usize) << 20) | self.1 as usize
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub struct TypeID(FileID, u32);
+/**
+ * IDs are serialized as a single integer, with the file in the upper half, so they can be used as
+ * keys in JSON objects
+ */
+#[cfg(feature = "serde")]
+impl serde::Serialize for TypeID {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(((self.0 .0 as u64) << 32) | self.1 as u64)
+    }
+}
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub struct FunctionID(FileID, u32);
+#[cfg(feature = "serde")]
+impl serde::Serialize for FunctionID {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(((self.0 .0 as u64) << 32) | self.1 as u64)
+    }
+}
 
 unsafe impl Zeroable for FunctionID {}
 
 unsafe impl Pod for FunctionID {}
 
+/**
+ * Whether two declarations of a function can be called the same way, wherever they're declared
+ */
+fn same_signature(a: &FuncType, b: &FuncType) -> bool {
+    a.id == b.id
+        && a.type_param_count == b.type_param_count
+        && a.params == b.params
+        && a.returns == b.returns
+        && a.is_associated == b.is_associated
+        && a.is_coroutine == b.is_coroutine
+        && a.is_const == b.is_const
+}
+
 fn is_decl_affine(
     id_to_decl: &HashMap<TypeID, TypeDeclaration>,
     decl: &TypeDeclaration,
@@ -318,6 +1012,119 @@ fn is_decl_affine(
     is_children_affine
 }
 
+/**
+ * Structs and unions store their fields inline, so a cycle of them that never passes through a
+ * pointer-sized type (rc, array, dict, etc.) would need infinite space. Each cycle found is
+ * reported once, at the first type in it to be declared.
+ */
+fn find_infinitely_sized_types(
+    id_to_decl: &HashMap<TypeID, TypeDeclaration>,
+    declared_types: &[(TypeID, String, SourceRange)],
+) -> Result<(), TypecheckError> {
+    let mut cycles = Vec::new();
+    let mut finished = HashSet::new();
+    for (id, _, _) in declared_types.iter() {
+        find_inline_cycles(id_to_decl, *id, &mut Vec::new(), &mut finished, &mut cycles);
+    }
+
+    let mut result = Ok(());
+    for cycle in cycles {
+        let names = cycle
+            .iter()
+            .chain(cycle.first())
+            .map(|id| {
+                declared_types
+                    .iter()
+                    .find(|(declared, _, _)| declared == id)
+                    .map(|(_, name, _)| name.clone())
+                    .unwrap()
+            })
+            .collect();
+        let (_, _, provenance) = declared_types
+            .iter()
+            .find(|(declared, _, _)| *declared == cycle[0])
+            .unwrap();
+        merge_results(
+            &mut result,
+            Err(TypecheckError::InfinitelySizedType(
+                provenance.clone(),
+                names,
+            )),
+        );
+    }
+
+    result
+}
+
+fn find_inline_cycles(
+    id_to_decl: &HashMap<TypeID, TypeDeclaration>,
+    id: TypeID,
+    path: &mut Vec<TypeID>,
+    finished: &mut HashSet<TypeID>,
+    cycles: &mut Vec<Vec<TypeID>>,
+) {
+    if finished.contains(&id) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|visited| *visited == id) {
+        cycles.push(path[start..].to_vec());
+        return;
+    }
+
+    let mut children = Vec::new();
+    match id_to_decl.get(&id) {
+        Some(TypeDeclaration::Struct(decl)) => {
+            for ty in decl.fields.values() {
+                inline_type_ids(ty, &mut children);
+            }
+        }
+        Some(TypeDeclaration::Union(decl)) => {
+            for ty in decl.variants.values().flatten() {
+                inline_type_ids(ty, &mut children);
+            }
+        }
+        Some(TypeDeclaration::Interface(_) | TypeDeclaration::Module(_)) | None => {}
+    }
+
+    path.push(id);
+    for child in children {
+        find_inline_cycles(id_to_decl, child, path, finished, cycles);
+    }
+    path.pop();
+    finished.insert(id);
+}
+
+/**
+ * The declared types whose layout is embedded directly in a value of this type
+ */
+fn inline_type_ids(ty: &ExpressionType, ids: &mut Vec<TypeID>) {
+    match ty {
+        ExpressionType::InstanceOf(id) => {
+            if !ids.contains(id) {
+                ids.push(*id);
+            }
+        }
+        ExpressionType::Nullable(inner)
+        | ExpressionType::Collection(CollectionType::Cell(inner)) => inline_type_ids(inner, ids),
+        ExpressionType::Void
+        | ExpressionType::Unreachable
+        | ExpressionType::Primitive(_)
+        | ExpressionType::ReferenceToType(_)
+        | ExpressionType::ReferenceToFunction(_)
+        | ExpressionType::Pointer(_, _)
+        | ExpressionType::Collection(
+            CollectionType::Array(_)
+            | CollectionType::Dict(_, _)
+            | CollectionType::ReferenceCounter(_)
+            | CollectionType::String,
+        )
+        | ExpressionType::Null
+        | ExpressionType::TypeParameterReference(_)
+        | ExpressionType::Generator { .. }
+        | ExpressionType::FunctionReference { .. } => {}
+    }
+}
+
 fn get_id_for_func_name(
     module: &FileDeclarations,
     id_to_decl: &HashMap<TypeID, TypeDeclaration>,
@@ -332,6 +1139,18 @@ fn get_id_for_func_name(
     *func_id
 }
 
+fn is_exported(statement: &AstNode<'_>) -> bool {
+    match &statement.value {
+        AstNodeValue::StructDeclaration(StructDeclarationValue { is_exported, .. })
+        | AstNodeValue::UnionDeclaration(UnionDeclarationValue { is_exported, .. })
+        | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue { is_exported, .. })
+        | AstNodeValue::FunctionDeclaration(FunctionDeclarationValue { is_exported, .. })
+        | AstNodeValue::ExternFunctionBinding { is_exported, .. }
+        | AstNodeValue::TypeAliasDeclaration { is_exported, .. } => *is_exported,
+        _ => false,
+    }
+}
+
 fn resolve_import(
     declarations: &DeclarationContext,
     path: &[String],
@@ -357,7 +1176,14 @@ fn resolve_import(
         let current_module = imported_value
             .as_module()
             .ok_or_else(|| TypecheckError::ImportPathMustBeModule(provenance.clone()))?;
-        match &current_module.exports[current_path.as_str()] {
+        if !current_module.exports.contains_key(current_path.as_str()) {
+            return Err(TypecheckError::ImportNotFound(
+                current_path.clone(),
+                provenance.clone(),
+            ));
+        }
+        let export = current_module.exported(current_path, provenance)?;
+        match &export {
             ExpressionType::ReferenceToType(ty_id) => {
                 match declarations.id_to_decl.get(ty_id) {
                     Some(decl) => {
@@ -404,11 +1230,267 @@ fn resolve_import(
     ))
 }
 
-fn fill_in_struct_info(
+/**
+ * The types that can be named in a module: its own declarations and the ones it imports
+ */
+fn type_names<'a>(
+    id_to_decl: &'a HashMap<TypeID, TypeDeclaration>,
+    file: &'a FileDeclarations,
+) -> HashMap<&'a str, TypeID> {
+    let mut names_to_type_id = HashMap::new();
+    let TypeDeclaration::Module(module) = &id_to_decl[&file.module_id] else {
+        unreachable!()
+    };
+    for (name, expr) in module.exports.iter() {
+        if let ExpressionType::ReferenceToType(ty_id) = expr {
+            names_to_type_id.insert(name.as_str(), *ty_id);
+        }
+    }
+    for (name, expr) in file.imports.iter() {
+        if let ExpressionType::ReferenceToType(ty_id) = expr {
+            names_to_type_id.insert(name.as_str(), *ty_id);
+        }
+    }
+
+    names_to_type_id
+}
+
+struct TypeAlias<'a, 'names> {
+    id: TypeID,
+    name: &'a String,
+    ty: &'a AstNode<'a>,
+    provenance: &'a SourceRange,
+    /// The names visible in the module the alias is declared in
+    names_to_type_id: HashMap<&'names str, TypeID>,
+}
+
+fn resolve_type_alias(
+    aliases: &[TypeAlias<'_, '_>],
+    idx: usize,
+    path: &mut Vec<usize>,
+    finished: &mut HashSet<usize>,
+    resolved: &mut HashMap<TypeID, ExpressionType>,
+    cycles: &mut Vec<Vec<usize>>,
+) -> Result<(), TypecheckError> {
+    if finished.contains(&idx) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|visited| *visited == idx) {
+        cycles.push(path[start..].to_vec());
+        return Ok(());
+    }
+
+    let alias = &aliases[idx];
+    let mut dependencies = Vec::new();
+    alias.ty.walk(|node| {
+        if let AstNodeValue::Name { value, .. } = &node.value {
+            let id = alias.names_to_type_id.get(value.as_str());
+            if let Some(dependency) = aliases.iter().position(|other| Some(&other.id) == id) {
+                dependencies.push(dependency);
+            }
+        }
+    });
+
+    let mut result = Ok(());
+    path.push(idx);
+    for dependency in dependencies.iter() {
+        merge_results(
+            &mut result,
+            resolve_type_alias(aliases, *dependency, path, finished, resolved, cycles),
+        );
+    }
+    path.pop();
+    finished.insert(idx);
+
+    // An alias that depends on a cycle can't be expanded, but the cycle is already reported
+    if dependencies
+        .iter()
+        .all(|dependency| resolved.contains_key(&aliases[*dependency].id))
+    {
+        if let Some(ty) = merge_results_or_value(
+            &mut result,
+            resolve_type_expr(&alias.names_to_type_id, resolved, alias.ty),
+        ) {
+            resolved.insert(alias.id, ty);
+        }
+    }
+
+    result
+}
+
+/**
+ * Structs declared anywhere in a function's body are visible throughout it, like top-level types are
+ * throughout their module
+ */
+fn find_local_structs<'a>(
+    node: &'a AstNode<'a>,
+    found: &mut Vec<(&'a StructDeclarationValue<'a>, &'a SourceRange)>,
+) {
+    match &node.value {
+        AstNodeValue::StructDeclaration(decl) => found.push((decl, &node.provenance)),
+        AstNodeValue::FunctionDeclaration(_) => {}
+        _ => node.children(|child| find_local_structs(child, found)),
+    }
+}
+
+/**
+ * Functions declared inside a function's body are hoisted like its local structs. Functions nested
+ * inside of those belong to them instead.
+ */
+pub(crate) fn find_local_functions<'a>(
+    node: &'a AstNode<'a>,
+    found: &mut Vec<(&'a FunctionDeclarationValue<'a>, &'a SourceRange)>,
+) {
+    node.children(|child| match &child.value {
+        AstNodeValue::FunctionDeclaration(func) => found.push((func, &child.provenance)),
+        _ => find_local_functions(child, found),
+    });
+}
+
+fn assign_ids_to_locals<'a>(
+    module: &FileDeclarations,
+    local_type_names: &mut HashMap<FunctionID, HashMap<String, TypeID>>,
+    local_function_names: &mut HashMap<FunctionID, HashMap<String, FunctionID>>,
+    func_id: FunctionID,
+    body: &'a AstNode<'a>,
+) {
+    let mut local_structs = Vec::new();
+    find_local_structs(body, &mut local_structs);
+    if !local_structs.is_empty() {
+        let local_names = local_structs
+            .iter()
+            .map(|(decl, _)| (decl.name.clone(), module.new_type_id()))
+            .collect();
+        local_type_names.insert(func_id, local_names);
+    }
+
+    let mut local_functions = Vec::new();
+    find_local_functions(body, &mut local_functions);
+    if !local_functions.is_empty() {
+        let mut local_names = HashMap::new();
+        for (func, _) in local_functions {
+            let local_id = module.new_func_id();
+            local_names.insert(func.name.clone(), local_id);
+            assign_ids_to_locals(
+                module,
+                local_type_names,
+                local_function_names,
+                local_id,
+                func.body,
+            );
+        }
+        local_function_names.insert(func_id, local_names);
+    }
+}
+
+/**
+ * Fill in the structs and functions declared inside a function's body. They can refer to the types
+ * declared in any of the functions they're nested in.
+ */
+#[allow(clippy::too_many_arguments)]
+fn fill_in_locals<'a, 'names>(
+    names_to_type_id: &HashMap<&'names str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
+    file: &FileDeclarations,
+    local_type_names: &'names HashMap<FunctionID, HashMap<String, TypeID>>,
+    local_function_names: &HashMap<FunctionID, HashMap<String, FunctionID>>,
+    id_to_func: &mut HashMap<FunctionID, FuncType>,
+    func_id: FunctionID,
+    body: &'a AstNode<'a>,
+    declarations: &mut Vec<TypeDeclaration>,
+    declared_types: &mut Vec<(TypeID, String, SourceRange)>,
+) -> Result<(), TypecheckError> {
+    let mut result = Ok(());
+
+    let mut local_names_to_type_id = names_to_type_id.clone();
+    if let Some(local_names) = local_type_names.get(&func_id) {
+        local_names_to_type_id.extend(local_names.iter().map(|(name, id)| (name.as_str(), *id)));
+        let mut local_structs = Vec::new();
+        find_local_structs(body, &mut local_structs);
+        for (decl, provenance) in local_structs {
+            if let Some(decl_ty) = merge_results_or_value(
+                &mut result,
+                fill_in_struct_info(
+                    &local_names_to_type_id,
+                    aliases,
+                    file,
+                    id_to_func,
+                    decl,
+                    Vec::new(),
+                    provenance,
+                ),
+            ) {
+                declared_types.push((decl_ty.id(), decl.name.clone(), provenance.clone()));
+                declarations.push(decl_ty);
+            }
+        }
+    }
+
+    if let Some(local_names) = local_function_names.get(&func_id) {
+        let mut local_functions = Vec::new();
+        find_local_functions(body, &mut local_functions);
+        // Local functions are named by the function they're in, not the block, so two with the
+        // same name would be the same function
+        let mut declared_functions: HashMap<&str, &SourceRange> = HashMap::new();
+        for (func, provenance) in local_functions {
+            match declared_functions.entry(func.name.as_str()) {
+                Entry::Occupied(first) => {
+                    merge_results(
+                        &mut result,
+                        Err(TypecheckError::DuplicateDeclaration(
+                            func.name.clone(),
+                            (*first.get()).clone(),
+                            provenance.clone(),
+                        )),
+                    );
+                    continue;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(provenance);
+                }
+            }
+            let local_id = local_names[func.name.as_str()];
+            merge_results(
+                &mut result,
+                fill_in_locals(
+                    &local_names_to_type_id,
+                    aliases,
+                    file,
+                    local_type_names,
+                    local_function_names,
+                    id_to_func,
+                    local_id,
+                    func.body,
+                    declarations,
+                    declared_types,
+                ),
+            );
+            if let Some(func_ty) = merge_results_or_value(
+                &mut result,
+                fill_in_fn_decl(
+                    &local_names_to_type_id,
+                    aliases,
+                    local_id,
+                    func,
+                    false,
+                    provenance,
+                ),
+            ) {
+                id_to_func.insert(local_id, func_ty);
+            }
+        }
+    }
+
+    result
+}
+
+fn fill_in_struct_info<'a>(
     names_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     module: &FileDeclarations,
     id_to_func: &mut HashMap<FunctionID, FuncType>,
-    decl: &StructDeclarationValue,
+    decl: &'a StructDeclarationValue<'a>,
+    impls: Vec<&'a ImplBlockValue<'a>>,
     provenance: &SourceRange,
 ) -> Result<TypeDeclaration, TypecheckError> {
     let fields: HashMap<_, _> = merge_result_list(decl.fields.iter().map(
@@ -417,7 +1499,7 @@ fn fill_in_struct_info(
              ty,
              provenance,
          }| {
-            let ty = resolve_type_expr(names_to_type_id, ty)?;
+            let ty = resolve_type_expr(names_to_type_id, aliases, ty)?;
             if matches!(ty, ExpressionType::Pointer(_, _)) {
                 return Err(TypecheckError::IllegalReferenceInsideDataType(
                     provenance.clone(),
@@ -430,14 +1512,36 @@ fn fill_in_struct_info(
     let mut result = Ok(());
 
     let mut associated_functions = HashMap::new();
-    for node in decl.associated_functions.iter() {
+    let mut declared_at = HashMap::new();
+    let impl_functions = impls
+        .into_iter()
+        .flat_map(|impl_block| impl_block.associated_functions.iter());
+    for node in decl.associated_functions.iter().chain(impl_functions) {
         match &node.value {
             AstNodeValue::FunctionDeclaration(func) => {
+                if let Some(first) = declared_at.insert(func.name.as_str(), &node.provenance) {
+                    merge_results(
+                        &mut result,
+                        Err(TypecheckError::DuplicateDeclaration(
+                            func.name.clone(),
+                            first.clone(),
+                            node.provenance.clone(),
+                        )),
+                    );
+                    continue;
+                }
                 let func_id = module.new_func_id();
                 associated_functions.insert(func.name.clone(), func_id);
                 if let Some(func_type) = merge_results_or_value(
                     &mut result,
-                    fill_in_fn_decl(names_to_type_id, func_id, func, true, &node.provenance),
+                    fill_in_fn_decl(
+                        names_to_type_id,
+                        aliases,
+                        func_id,
+                        func,
+                        true,
+                        &node.provenance,
+                    ),
                 ) {
                     id_to_func.insert(func_id, func_type);
                 }
@@ -467,6 +1571,11 @@ fn fill_in_struct_info(
     Ok(TypeDeclaration::Struct(StructType {
         id: names_to_type_id[decl.name.as_str()],
         fields,
+        defaulted_fields: decl
+            .field_defaults
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect(),
         associated_functions,
         is_affine,
     }))
@@ -474,6 +1583,7 @@ fn fill_in_struct_info(
 
 fn fill_in_interface_decl(
     names_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     module: &FileDeclarations,
     id_to_func: &mut HashMap<FunctionID, FuncType>,
     interface: &InterfaceDeclarationValue,
@@ -487,11 +1597,25 @@ fn fill_in_interface_decl(
         let func_type = match &node.value {
             AstNodeValue::RequiredFunction(func) => {
                 associated_functions.insert(func.name.clone(), func_id);
-                fill_in_fn_header(names_to_type_id, func_id, func, true, &node.provenance)
+                fill_in_fn_header(
+                    names_to_type_id,
+                    aliases,
+                    func_id,
+                    func,
+                    true,
+                    &node.provenance,
+                )
             }
             AstNodeValue::FunctionDeclaration(func) => {
                 associated_functions.insert(func.name.clone(), func_id);
-                fill_in_fn_decl(names_to_type_id, func_id, func, true, &node.provenance)
+                fill_in_fn_decl(
+                    names_to_type_id,
+                    aliases,
+                    func_id,
+                    func,
+                    true,
+                    &node.provenance,
+                )
             }
             _ => panic!("Associated function should not be anything but function declaration"),
         };
@@ -509,10 +1633,12 @@ fn fill_in_interface_decl(
 
 fn fill_in_union_decl(
     names_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     UnionDeclarationValue {
         variants: variant_ast,
         name,
         properties,
+        ..
     }: &UnionDeclarationValue,
     provenance: &SourceRange,
 ) -> Result<TypeDeclaration, TypecheckError> {
@@ -523,7 +1649,7 @@ fn fill_in_union_decl(
                 ty,
                 provenance,
             }) => {
-                let ty = resolve_type_expr(names_to_type_id, ty)?;
+                let ty = resolve_type_expr(names_to_type_id, aliases, ty)?;
                 if matches!(ty, ExpressionType::Pointer(_, _)) {
                     return Err(TypecheckError::IllegalReferenceInsideDataType(
                         provenance.clone(),
@@ -564,11 +1690,13 @@ fn fill_in_union_decl(
 
 fn fill_in_fn_decl(
     names_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     id: FunctionID,
     FunctionDeclarationValue {
         params,
         returns,
         is_coroutine,
+        is_const,
         ..
     }: &FunctionDeclarationValue,
     is_associated: bool,
@@ -579,20 +1707,24 @@ fn fill_in_fn_decl(
         type_param_count: 0,
         params: params
             .iter()
-            .map(|(_, NameAndType { ty: type_, .. })| resolve_type_expr(names_to_type_id, type_))
+            .map(|(_, NameAndType { ty: type_, .. })| {
+                resolve_type_expr(names_to_type_id, aliases, type_)
+            })
             .collect::<Result<Vec<_>, _>>()?,
         returns: returns
             .as_ref()
-            .map(|returns| resolve_type_expr(names_to_type_id, returns))
+            .map(|returns| resolve_type_expr(names_to_type_id, aliases, returns))
             .unwrap_or(Ok(ExpressionType::Void))?,
         is_associated,
         is_coroutine: *is_coroutine,
+        is_const: *is_const,
         provenance: Some(provenance.clone()),
     })
 }
 
 fn fill_in_fn_header(
     names_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     id: FunctionID,
     FunctionHeaderValue {
         params, returns, ..
@@ -605,20 +1737,27 @@ fn fill_in_fn_header(
         type_param_count: 0,
         params: params
             .iter()
-            .map(|NameAndType { ty: type_, .. }| resolve_type_expr(names_to_type_id, type_))
+            .map(|NameAndType { ty: type_, .. }| {
+                resolve_type_expr(names_to_type_id, aliases, type_)
+            })
             .collect::<Result<Vec<_>, _>>()?,
         returns: returns
             .as_ref()
-            .map(|returns| resolve_type_expr(names_to_type_id, returns))
+            .map(|returns| resolve_type_expr(names_to_type_id, aliases, returns))
             .unwrap_or(Ok(ExpressionType::Void))?,
         is_associated,
         is_coroutine: false,
+        is_const: false,
         provenance: Some(provenance.clone()),
     })
 }
 
+/**
+ * The type a type expression names, with any aliases in it expanded
+ */
 pub fn resolve_type_expr(
     name_to_type_id: &HashMap<&str, TypeID>,
+    aliases: &HashMap<TypeID, ExpressionType>,
     node: &AstNode<'_>,
 ) -> Result<ExpressionType, TypecheckError> {
     Ok(match &node.value {
@@ -631,52 +1770,75 @@ pub fn resolve_type_expr(
             "char" => ExpressionType::Primitive(PrimitiveType::Char),
             "string" => ExpressionType::Collection(CollectionType::String),
             "size" => ExpressionType::Primitive(PrimitiveType::PointerSize),
-            other => ExpressionType::InstanceOf(
-                *name_to_type_id
+            other => {
+                let id = name_to_type_id
                     .get(other)
-                    .ok_or(TypecheckError::NameNotFound(node.provenance.clone()))?,
-            ),
+                    .ok_or(TypecheckError::NameNotFound(node.provenance.clone()))?;
+                match aliases.get(id) {
+                    Some(ty) => ty.clone(),
+                    None => ExpressionType::InstanceOf(*id),
+                }
+            }
         },
         AstNodeValue::VoidType => ExpressionType::Void,
         AstNodeValue::UniqueType(inner) => ExpressionType::Pointer(
             PointerKind::Unique,
-            Box::new(resolve_type_expr(name_to_type_id, inner)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, inner)?),
         ),
         AstNodeValue::SharedType(inner) => ExpressionType::Pointer(
             PointerKind::Shared,
-            Box::new(resolve_type_expr(name_to_type_id, inner)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, inner)?),
         ),
         AstNodeValue::ArrayType(inner) => ExpressionType::Collection(CollectionType::Array(
-            Box::new(resolve_type_expr(name_to_type_id, inner)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, inner)?),
         )),
-        AstNodeValue::RcType(inner) => ExpressionType::Collection(
-            CollectionType::ReferenceCounter(Box::new(resolve_type_expr(name_to_type_id, inner)?)),
-        ),
+        AstNodeValue::RcType(inner) => {
+            ExpressionType::Collection(CollectionType::ReferenceCounter(Box::new(
+                resolve_type_expr(name_to_type_id, aliases, inner)?,
+            )))
+        }
         AstNodeValue::DictType(key, value) => ExpressionType::Collection(CollectionType::Dict(
-            Box::new(resolve_type_expr(name_to_type_id, key)?),
-            Box::new(resolve_type_expr(name_to_type_id, value)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, key)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, value)?),
         )),
-        AstNodeValue::NullableType(inner) => {
-            ExpressionType::Nullable(Box::new(resolve_type_expr(name_to_type_id, inner)?))
-        }
+        AstNodeValue::NullableType(inner) => ExpressionType::Nullable(Box::new(resolve_type_expr(
+            name_to_type_id,
+            aliases,
+            inner,
+        )?)),
         AstNodeValue::GeneratorType { yield_ty, param_ty } => ExpressionType::Generator {
-            yield_ty: Box::new(resolve_type_expr(name_to_type_id, yield_ty)?),
-            param_ty: Box::new(resolve_type_expr(name_to_type_id, param_ty)?),
+            yield_ty: Box::new(resolve_type_expr(name_to_type_id, aliases, yield_ty)?),
+            param_ty: Box::new(resolve_type_expr(name_to_type_id, aliases, param_ty)?),
         },
         AstNodeValue::CellType(inner_ty) => ExpressionType::Collection(CollectionType::Cell(
-            Box::new(resolve_type_expr(name_to_type_id, inner_ty)?),
+            Box::new(resolve_type_expr(name_to_type_id, aliases, inner_ty)?),
         )),
+        AstNodeValue::FunctionType { params, returns } => ExpressionType::FunctionReference {
+            parameters: params
+                .iter()
+                .map(|param| resolve_type_expr(name_to_type_id, aliases, param))
+                .collect::<Result<_, _>>()?,
+            returns: Box::new(match returns {
+                Some(returns) => resolve_type_expr(name_to_type_id, aliases, returns)?,
+                None => ExpressionType::Void,
+            }),
+        },
         AstNodeValue::FunctionDeclaration(_)
         | AstNodeValue::RequiredFunction(_)
-        | AstNodeValue::ExternFunctionBinding(_)
+        | AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::StructDeclaration(_)
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
         | AstNodeValue::Declaration(_, _, _, _)
         | AstNodeValue::ConstDeclaration { .. }
-        | AstNodeValue::Import(_)
+        | AstNodeValue::Import(..)
         | AstNodeValue::Return(_)
+        | AstNodeValue::Break(_, _)
+        | AstNodeValue::Continue(_)
         | AstNodeValue::Yield(_)
+        | AstNodeValue::Defer(_)
         | AstNodeValue::Statement(_)
         | AstNodeValue::Deref(_)
         | AstNodeValue::Int(_)
@@ -685,20 +1847,25 @@ pub fn resolve_type_expr(
         | AstNodeValue::Bool(_)
         | AstNodeValue::BinExpr(_, _, _)
         | AstNodeValue::If(_)
-        | AstNodeValue::While(_, _)
+        | AstNodeValue::IfLet(_)
+        | AstNodeValue::While(_, _, _)
         | AstNodeValue::Loop(_)
+        | AstNodeValue::LabeledLoop(_, _)
         | AstNodeValue::Call(_, _)
         | AstNodeValue::TakeUnique(_)
         | AstNodeValue::TakeRef(_)
         | AstNodeValue::RecordLiteral { .. }
         | AstNodeValue::ArrayLiteral(_)
         | AstNodeValue::ArrayLiteralLength(_, _)
+        | AstNodeValue::ArraySlice(_, _, _)
         | AstNodeValue::Block(_)
         | AstNodeValue::StringLiteral(_)
         | AstNodeValue::CharLiteral(_)
         | AstNodeValue::UnaryExpr(_, _)
         | AstNodeValue::DictLiteral(_)
         | AstNodeValue::Match(_)
+        | AstNodeValue::IsVariant(_, _)
+        | AstNodeValue::Downcast(_, _)
         | AstNodeValue::BorrowDeclaration(..)
         | AstNodeValue::ReferenceCountLiteral(_)
         | AstNodeValue::CellLiteral(_) => {
@@ -708,13 +1875,16 @@ pub fn resolve_type_expr(
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum IntrinsicFunction {
     ArrayLength,
     ArrayPush,
+    ArrayContains,
     ArrayFree,
 
     DictionaryInsert,
     DictionaryContains,
+    DictionaryKeys,
 
     RcClone,
     RcDecrement,
@@ -722,8 +1892,24 @@ pub enum IntrinsicFunction {
 
     CellGet,
     CellSet,
+
+    CharIsDigit,
+    CharIsAlpha,
+    CharToInt,
+    IntToChar,
+
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Clamp,
+    Pow,
+
+    RngSeed,
+    RngNext,
 }
 
+#[derive(Clone)]
 pub struct CollectionIntrinsic {
     pub intrinsic_fn: IntrinsicFunction,
     pub fn_id: FunctionID,
@@ -763,6 +1949,24 @@ fn add_intrinsics(ctx: &mut DeclarationContext) {
         ExpressionType::Void,
         PointerKind::Unique,
     );
+    add_intrinsic(
+        ctx,
+        &mut array_intrinsics,
+        "contains",
+        IntrinsicFunction::ArrayContains,
+        1,
+        vec![
+            ExpressionType::Pointer(
+                PointerKind::Shared,
+                Box::new(ExpressionType::Collection(CollectionType::Array(Box::new(
+                    ExpressionType::TypeParameterReference(0),
+                )))),
+            ),
+            ExpressionType::TypeParameterReference(0),
+        ],
+        ExpressionType::Primitive(PrimitiveType::Bool),
+        PointerKind::Shared,
+    );
     ctx.array_intrinsics = array_intrinsics;
 
     let mut dict_intrinsics = HashMap::new();
@@ -808,6 +2012,24 @@ fn add_intrinsics(ctx: &mut DeclarationContext) {
         ExpressionType::Void,
         PointerKind::Unique,
     );
+    add_intrinsic(
+        ctx,
+        &mut dict_intrinsics,
+        "keys",
+        IntrinsicFunction::DictionaryKeys,
+        2,
+        vec![ExpressionType::Pointer(
+            PointerKind::Shared,
+            Box::new(ExpressionType::Collection(CollectionType::Dict(
+                Box::new(ExpressionType::TypeParameterReference(0)),
+                Box::new(ExpressionType::TypeParameterReference(1)),
+            ))),
+        )],
+        ExpressionType::Collection(CollectionType::Array(Box::new(
+            ExpressionType::TypeParameterReference(0),
+        ))),
+        PointerKind::Shared,
+    );
     ctx.dict_intrinsics = dict_intrinsics;
 
     let mut rc_intrinsics = HashMap::new();
@@ -873,6 +2095,177 @@ fn add_intrinsics(ctx: &mut DeclarationContext) {
         PointerKind::Shared,
     );
     ctx.cell_intrinsics = cell_intrinsics;
+
+    let mut char_intrinsics = HashMap::new();
+    add_intrinsic(
+        ctx,
+        &mut char_intrinsics,
+        "is_digit",
+        IntrinsicFunction::CharIsDigit,
+        0,
+        vec![ExpressionType::Primitive(PrimitiveType::Char)],
+        ExpressionType::Primitive(PrimitiveType::Bool),
+        PointerKind::Shared,
+    );
+    add_intrinsic(
+        ctx,
+        &mut char_intrinsics,
+        "is_alpha",
+        IntrinsicFunction::CharIsAlpha,
+        0,
+        vec![ExpressionType::Primitive(PrimitiveType::Char)],
+        ExpressionType::Primitive(PrimitiveType::Bool),
+        PointerKind::Shared,
+    );
+    add_intrinsic(
+        ctx,
+        &mut char_intrinsics,
+        "to_int",
+        IntrinsicFunction::CharToInt,
+        0,
+        vec![ExpressionType::Primitive(PrimitiveType::Char)],
+        ExpressionType::Primitive(PrimitiveType::Int32),
+        PointerKind::Shared,
+    );
+    ctx.char_intrinsics = char_intrinsics;
+
+    let mut int_intrinsics = HashMap::new();
+    // Aborts if the integer doesn't fit in a char
+    add_intrinsic(
+        ctx,
+        &mut int_intrinsics,
+        "to_char",
+        IntrinsicFunction::IntToChar,
+        0,
+        vec![ExpressionType::Primitive(PrimitiveType::Int32)],
+        ExpressionType::Primitive(PrimitiveType::Char),
+        PointerKind::Shared,
+    );
+    ctx.int_intrinsics = int_intrinsics;
+
+    let mut numeric_intrinsics = HashMap::new();
+    add_intrinsic(
+        ctx,
+        &mut numeric_intrinsics,
+        "abs",
+        IntrinsicFunction::Abs,
+        1,
+        vec![ExpressionType::TypeParameterReference(0)],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    add_intrinsic(
+        ctx,
+        &mut numeric_intrinsics,
+        "min",
+        IntrinsicFunction::Min,
+        1,
+        vec![
+            ExpressionType::TypeParameterReference(0),
+            ExpressionType::TypeParameterReference(0),
+        ],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    add_intrinsic(
+        ctx,
+        &mut numeric_intrinsics,
+        "max",
+        IntrinsicFunction::Max,
+        1,
+        vec![
+            ExpressionType::TypeParameterReference(0),
+            ExpressionType::TypeParameterReference(0),
+        ],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    // When the bounds are out of order, the upper bound wins
+    add_intrinsic(
+        ctx,
+        &mut numeric_intrinsics,
+        "clamp",
+        IntrinsicFunction::Clamp,
+        1,
+        vec![
+            ExpressionType::TypeParameterReference(0),
+            ExpressionType::TypeParameterReference(0),
+            ExpressionType::TypeParameterReference(0),
+        ],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    // Aborts on negative exponents
+    add_intrinsic(
+        ctx,
+        &mut numeric_intrinsics,
+        "pow",
+        IntrinsicFunction::Pow,
+        1,
+        vec![
+            ExpressionType::TypeParameterReference(0),
+            ExpressionType::Primitive(PrimitiveType::Int32),
+        ],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    ctx.numeric_intrinsics = numeric_intrinsics;
+
+    let mut float_intrinsics = HashMap::new();
+    add_intrinsic(
+        ctx,
+        &mut float_intrinsics,
+        "sqrt",
+        IntrinsicFunction::Sqrt,
+        1,
+        vec![ExpressionType::TypeParameterReference(0)],
+        ExpressionType::TypeParameterReference(0),
+        PointerKind::Shared,
+    );
+    ctx.float_intrinsics = float_intrinsics;
+
+    let mut global_intrinsics = HashMap::new();
+    add_global_intrinsic(
+        ctx,
+        &mut global_intrinsics,
+        "rng_seed",
+        IntrinsicFunction::RngSeed,
+        vec![ExpressionType::Primitive(PrimitiveType::Int64)],
+        ExpressionType::Void,
+    );
+    // Always non-negative
+    add_global_intrinsic(
+        ctx,
+        &mut global_intrinsics,
+        "rng_next",
+        IntrinsicFunction::RngNext,
+        Vec::new(),
+        ExpressionType::Primitive(PrimitiveType::Int32),
+    );
+    ctx.global_intrinsics = global_intrinsics;
+}
+
+fn add_global_intrinsic(
+    ctx: &mut DeclarationContext,
+    global_fns: &mut HashMap<&'static str, CollectionIntrinsic>,
+    name: &'static str,
+    intrinsic_fn: IntrinsicFunction,
+    params: Vec<ExpressionType>,
+    returns: ExpressionType,
+) {
+    add_intrinsic(
+        ctx,
+        global_fns,
+        name,
+        intrinsic_fn,
+        0,
+        params,
+        returns,
+        PointerKind::Shared,
+    );
+    // There's no receiver, so every parameter is an argument at the call site
+    let fn_id = global_fns[name].fn_id;
+    ctx.id_to_func.get_mut(&fn_id).unwrap().is_associated = false;
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -904,6 +2297,7 @@ fn add_intrinsic(
             params,
             returns,
             is_coroutine: false,
+            is_const: false,
             provenance: None,
         },
     );
@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+
+use crate::{
+    id::{AnyID, VariableID},
+    multi_error::merge_results,
+    parser::{
+        AstNode, AstNodeValue, BinOp, IfDeclaration, IfLetDeclaration, LoopLabel, MatchDeclaration,
+    },
+    typecheck::{TypecheckError, TypecheckedFunction},
+};
+
+/**
+ * Variables declared without a value (`let x: i32;`) must be assigned on every path that reaches a
+ * read of them. Each branch tracks which of those variables it has assigned, and branches are
+ * joined by keeping only the variables assigned in all of them.
+ */
+pub fn check_definite_assignment<'a>(
+    functions: &[TypecheckedFunction<'a>],
+    top_level_statements: &[&'a AstNode<'a>],
+) -> Result<(), TypecheckError> {
+    let mut results = Ok(());
+    for function in functions.iter() {
+        let mut checker = Checker::default();
+        let mut state = Some(HashSet::new());
+        checker.visit(function.func.body, &mut state);
+        merge_results(&mut results, checker.result);
+    }
+    let mut checker = Checker::default();
+    let mut state = Some(HashSet::new());
+    for statement in top_level_statements.iter() {
+        checker.visit(statement, &mut state);
+    }
+    merge_results(&mut results, checker.result);
+
+    results
+}
+
+/**
+ * The uninitialized variables that have been assigned so far, or None if the current point can't
+ * be reached (after a return, break or continue)
+ */
+type AssignedSet = Option<HashSet<VariableID>>;
+
+fn join(a: AssignedSet, b: AssignedSet) -> AssignedSet {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+        (Some(set), None) | (None, Some(set)) => Some(set),
+        (None, None) => None,
+    }
+}
+
+struct Checker {
+    uninitialized: HashSet<VariableID>,
+    /// The state at each break out of the enclosing loops, innermost last
+    loop_breaks: Vec<AssignedSet>,
+    result: Result<(), TypecheckError>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker {
+            uninitialized: HashSet::new(),
+            loop_breaks: Vec::new(),
+            result: Ok(()),
+        }
+    }
+}
+
+impl Checker {
+    fn visit<'a>(&mut self, node: &'a AstNode<'a>, state: &mut AssignedSet) {
+        match &node.value {
+            AstNodeValue::Declaration(_, _, None, id) => {
+                self.uninitialized.insert(*id);
+                if let Some(assigned) = state {
+                    assigned.remove(id);
+                }
+            }
+            AstNodeValue::Name {
+                value,
+                referenced_id,
+            } => {
+                let Some(AnyID::Variable(id)) = referenced_id.get() else {
+                    return;
+                };
+                let Some(assigned) = state else {
+                    return;
+                };
+                if self.uninitialized.contains(id) && !assigned.contains(id) {
+                    merge_results(
+                        &mut self.result,
+                        Err(TypecheckError::UseBeforeAssignment(
+                            value.clone(),
+                            node.provenance.clone(),
+                        )),
+                    );
+                    // Only report the first read on each path
+                    assigned.insert(*id);
+                }
+            }
+            AstNodeValue::BinExpr(BinOp::Assignment, lhs, rhs) => {
+                self.visit(rhs, state);
+                if let AstNodeValue::Name {
+                    referenced_id: id, ..
+                } = &lhs.value
+                {
+                    if let (Some(AnyID::Variable(id)), Some(assigned)) = (id.get(), state) {
+                        assigned.insert(*id);
+                    }
+                } else {
+                    self.visit(lhs, state);
+                }
+            }
+            AstNodeValue::BinExpr(
+                BinOp::BooleanAnd | BinOp::BooleanOr | BinOp::NullCoalesce,
+                lhs,
+                rhs,
+            ) => {
+                self.visit(lhs, state);
+                // The right side doesn't always run
+                let mut rhs_state = state.clone();
+                self.visit(rhs, &mut rhs_state);
+            }
+            AstNodeValue::If(IfDeclaration {
+                condition,
+                if_branch,
+                else_branch,
+            }) => {
+                self.visit(condition, state);
+                self.visit_branches(state, if_branch, else_branch.as_deref());
+            }
+            AstNodeValue::IfLet(IfLetDeclaration {
+                value,
+                if_branch,
+                else_branch,
+                ..
+            }) => {
+                self.visit(value, state);
+                self.visit_branches(state, if_branch, else_branch.as_deref());
+            }
+            AstNodeValue::Match(MatchDeclaration { value, cases }) => {
+                self.visit(value, state);
+                let mut joined = None;
+                for case in cases.iter() {
+                    let mut case_state = state.clone();
+                    self.visit(&case.body, &mut case_state);
+                    joined = join(joined, case_state);
+                }
+                *state = joined;
+            }
+            AstNodeValue::While(condition, step, body) => {
+                self.visit(condition, state);
+                // The body may never run, so nothing it assigns counts afterwards
+                let mut body_state = state.clone();
+                self.loop_breaks.push(None);
+                self.visit(body, &mut body_state);
+                if let Some(step) = step {
+                    self.visit(step, &mut body_state);
+                }
+                self.loop_breaks.pop();
+            }
+            AstNodeValue::Loop(body) => {
+                self.loop_breaks.push(None);
+                self.visit(body, state);
+                // A loop is only exited by breaking out of it
+                *state = self.loop_breaks.pop().unwrap();
+            }
+            AstNodeValue::Break(value, label) => {
+                if let Some(value) = value {
+                    self.visit(value, state);
+                }
+                let depth = LoopLabel::jump_depth(label);
+                let target = self.loop_breaks.len().checked_sub(depth + 1);
+                if let Some(breaks) = target.and_then(|target| self.loop_breaks.get_mut(target)) {
+                    *breaks = join(breaks.take(), state.take());
+                }
+                *state = None;
+            }
+            AstNodeValue::Return(value) => {
+                if let Some(value) = value {
+                    self.visit(value, state);
+                }
+                *state = None;
+            }
+            AstNodeValue::Continue(_) => {
+                *state = None;
+            }
+            // Deferred expressions run when the block exits, so what they assign doesn't count
+            AstNodeValue::Defer(deferred) => {
+                let mut deferred_state = state.clone();
+                self.visit(deferred, &mut deferred_state);
+            }
+            // Functions are checked on their own
+            AstNodeValue::FunctionDeclaration(_) => {}
+            _ => node.children(|child| self.visit(child, state)),
+        }
+    }
+
+    fn visit_branches<'a>(
+        &mut self,
+        state: &mut AssignedSet,
+        if_branch: &'a AstNode<'a>,
+        else_branch: Option<&'a AstNode<'a>>,
+    ) {
+        let mut if_state = state.clone();
+        self.visit(if_branch, &mut if_state);
+        if let Some(else_branch) = else_branch {
+            self.visit(else_branch, state);
+        }
+        *state = join(if_state, state.take());
+    }
+}
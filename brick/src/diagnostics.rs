@@ -0,0 +1,175 @@
+use std::fmt::Write;
+
+use crate::{
+    provenance::next_display_column, typecheck::TypecheckError, CompileError, SourceRange,
+};
+
+/**
+ * A single problem found while compiling, in a form tools can report without matching on the
+ * error types themselves.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Only parse and typecheck errors have stable codes so far
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<SourceRange>,
+}
+
+/**
+ * How diagnostics are laid out when they're rendered as text
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// How many columns a tab takes up, so underlines line up with how a terminal shows tabs
+    pub tab_width: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions { tab_width: 4 }
+    }
+}
+
+impl Diagnostic {
+    /**
+     * Show the diagnostic the way a command line tool would: the message, where it happened, and
+     * the lines of its span with the span underlined. Tabs in the lines are expanded to spaces so
+     * the underline lines up no matter how the terminal shows them.
+     */
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let mut rendered = match self.code {
+            Some(code) => format!("error[{code}]: {}", self.message),
+            None => format!("error: {}", self.message),
+        };
+        let Some(span) = &self.span else {
+            return rendered;
+        };
+        let start = span.start();
+        let end = span.end();
+
+        let width = span.end_line.to_string().len();
+        let gutter = " ".repeat(width);
+        let _ = write!(rendered, "\n{gutter}--> {span}\n{gutter} |");
+
+        if span.start_line == span.end_line {
+            let line = start.line_text();
+            let underline_start = start.display_column(options.tab_width);
+            // Offsets count from 1, and the end of a span is the last character in it
+            let underline_end = line
+                .chars()
+                .take(end.offset() as usize)
+                .fold(1, |column, ch| {
+                    next_display_column(column, ch, options.tab_width)
+                })
+                .max(underline_start + 1);
+
+            let _ = write!(
+                rendered,
+                "\n{} | {}\n{gutter} | {}{}",
+                span.start_line,
+                expand_tabs(line, options.tab_width),
+                " ".repeat(underline_start as usize - 1),
+                "^".repeat((underline_end - underline_start) as usize),
+            );
+        } else {
+            // Spans across several lines are drawn as a bracket in the margin, from the start
+            // column on the first line down to the end column on the last one
+            for (line_number, text) in (span.start_line..).zip(span.line_texts()) {
+                let margin = if line_number == span.start_line {
+                    ' '
+                } else {
+                    '|'
+                };
+                let _ = write!(
+                    rendered,
+                    "\n{line_number:>width$} | {margin} {}",
+                    expand_tabs(text, options.tab_width)
+                );
+                if line_number == span.start_line {
+                    let _ = write!(
+                        rendered,
+                        "\n{gutter} |  {}^",
+                        "_".repeat(start.display_column(options.tab_width) as usize)
+                    );
+                }
+            }
+            let _ = write!(
+                rendered,
+                "\n{gutter} | |{}^",
+                "_".repeat(end.display_column(options.tab_width) as usize)
+            );
+        }
+
+        rendered
+    }
+}
+
+fn expand_tabs(text: &str, tab_width: u32) -> String {
+    let mut expanded = String::new();
+    let mut column = 1;
+    for ch in text.chars() {
+        let next_column = next_display_column(column, ch, tab_width);
+        if ch == '\t' {
+            expanded.push_str(&" ".repeat((next_column - column) as usize));
+        } else {
+            expanded.push(ch);
+        }
+        column = next_column;
+    }
+
+    expanded
+}
+
+impl CompileError {
+    /**
+     * Split the error into one diagnostic per problem. Groups of typecheck errors are flattened,
+     * so each reports its own code and location.
+     */
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        match self {
+            CompileError::ParseError(error) => diagnostics.push(Diagnostic {
+                code: Some(error.code()),
+                message: error.to_string(),
+                span: error.primary_span(),
+            }),
+            CompileError::TypecheckError(error) => typecheck_diagnostics(error, &mut diagnostics),
+            CompileError::ConstEvaluationFailed(provenance) => diagnostics.push(Diagnostic {
+                code: None,
+                message: self.to_string(),
+                span: Some(provenance.clone()),
+            }),
+            CompileError::InvalidLvalue(provenance) => diagnostics.push(Diagnostic {
+                code: None,
+                message: self.to_string(),
+                span: provenance.clone(),
+            }),
+            CompileError::FilesystemError(_, _)
+            | CompileError::TypeValidationError(_)
+            | CompileError::LifetimeError(_)
+            | CompileError::UnsupportedTarget(_) => diagnostics.push(Diagnostic {
+                code: None,
+                message: self.to_string(),
+                span: None,
+            }),
+        }
+
+        diagnostics
+    }
+}
+
+fn typecheck_diagnostics(error: &TypecheckError, diagnostics: &mut Vec<Diagnostic>) {
+    match error {
+        TypecheckError::MultiError(errors) => {
+            for error in errors.iter() {
+                typecheck_diagnostics(error, diagnostics);
+            }
+        }
+        error => diagnostics.push(Diagnostic {
+            code: Some(error.code()),
+            message: error.to_string(),
+            span: error.primary_span(),
+        }),
+    }
+}
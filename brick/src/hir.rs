@@ -2,12 +2,12 @@ use std::collections::HashMap;
 
 use crate::{
     declaration_context::{IntrinsicFunction, TypeID},
-    id::{AnyID, FunctionID, NodeID, VariableID},
+    id::{AnyID, ConstantID, FunctionID, NodeID, VariableID},
     parser::AstNode,
     provenance::SourceRange,
     typecheck::{
-        is_assignable_to, CollectionType, ExpressionType, PrimitiveType, TypeDeclaration,
-        TypecheckedFile,
+        is_assignable_to, CollectionType, ExpressionType, FieldDefaults, PrimitiveType,
+        TypeDeclaration, TypecheckedFile,
     },
     DeclarationContext,
 };
@@ -17,6 +17,7 @@ mod auto_numeric_cast;
 mod constant_inlining;
 mod coroutines;
 mod create_temp_vars_for_lvalues;
+mod defers;
 mod discard_unused_values;
 mod interface_conversion_pass;
 mod lower;
@@ -28,8 +29,22 @@ mod widen_null;
 pub fn lower_module<'dest>(
     module: TypecheckedFile<'_, 'dest>,
     declarations: &'dest DeclarationContext,
+    field_defaults: &FieldDefaults,
 ) -> HirModule {
-    let constant_values = constant_inlining::extract_constant_values(&module, declarations);
+    lower_module_continuing(module, declarations, field_defaults, &mut HashMap::new())
+}
+
+/**
+ * Lower a module that can use the constants of the files before it, adding its own constants to
+ * theirs
+ */
+pub fn lower_module_continuing<'dest>(
+    module: TypecheckedFile<'_, 'dest>,
+    declarations: &'dest DeclarationContext,
+    field_defaults: &FieldDefaults,
+    constant_values: &mut HashMap<ConstantID, HirNode>,
+) -> HirModule {
+    constant_inlining::extract_constant_values(&module, declarations, constant_values);
 
     let mut module = lower::lower_module(module, declarations);
 
@@ -37,6 +52,8 @@ pub fn lower_module<'dest>(
     coroutines::rewrite_generator_calls(&mut module);
 
     constant_inlining::inline_constants(&mut module, constant_values);
+    constant_inlining::fill_in_field_defaults(&mut module, field_defaults);
+    defers::rewrite_defers(&mut module);
 
     // This should come before anyone looks too hard at dot operators and function calls
     unions::convert_calls_to_union_literals(&mut module, declarations);
@@ -61,6 +78,7 @@ pub fn lower_module<'dest>(
     module
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HirModule {
     pub top_level_statements: HirNode,
     // TODO: include imports, structs, and extern function declaration
@@ -100,7 +118,8 @@ impl HirModule {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HirFunction {
     pub id: FunctionID,
     pub name: Option<String>,
@@ -109,6 +128,7 @@ pub struct HirFunction {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct GeneratorProperties {
     pub generator_var_id: VariableID,
     pub param_var_id: Option<VariableID>,
@@ -116,6 +136,7 @@ pub struct GeneratorProperties {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct HirNode {
     pub id: NodeID,
     pub value: HirNodeValue,
@@ -263,6 +284,17 @@ impl HirNode {
                     idx,
                 );
             }
+            HirNodeValue::ArraySlice(arr, start, end) => {
+                callback(None, arr);
+                callback(
+                    Some(&ExpressionType::Primitive(PrimitiveType::PointerSize)),
+                    start,
+                );
+                callback(
+                    Some(&ExpressionType::Primitive(PrimitiveType::PointerSize)),
+                    end,
+                );
+            }
             HirNodeValue::UnionLiteral(ty, variant, child) => {
                 let variant_ty = declarations.and_then(|declarations| {
                     let TypeDeclaration::Union(ty) = &declarations.id_to_decl[ty] else {
@@ -290,9 +322,20 @@ impl HirNode {
             }
             HirNodeValue::NullCoalesce(lhs, rhs) => {
                 callback(Some(&self.ty), rhs);
+                // A nullable right side makes the whole expression nullable
+                if matches!(&self.ty, ExpressionType::Nullable(_)) {
+                    callback(Some(&self.ty), lhs);
+                } else {
+                    callback(
+                        Some(&ExpressionType::Nullable(Box::new(self.ty.clone()))),
+                        lhs,
+                    );
+                }
+            }
+            HirNodeValue::NullableUnwrap(child) => {
                 callback(
                     Some(&ExpressionType::Nullable(Box::new(self.ty.clone()))),
-                    lhs,
+                    child,
                 );
             }
             HirNodeValue::BinaryLogical(_, lhs, rhs) => {
@@ -336,7 +379,10 @@ impl HirNode {
                 callback(Some(&lhs.ty), rhs);
                 callback(None, lhs);
             }
-            HirNodeValue::Yield(child) | HirNodeValue::Return(child) => {
+            HirNodeValue::Yield(child)
+            | HirNodeValue::Return(child)
+            | HirNodeValue::Break(child, _)
+            | HirNodeValue::AssertionFailed(child) => {
                 if let Some(child) = child {
                     // TODO: check return types
                     callback(None, child);
@@ -407,17 +453,21 @@ impl HirNode {
             | HirNodeValue::CharLiteral(_)
             | HirNodeValue::StringLiteral(_)
             | HirNodeValue::Null
+            | HirNodeValue::Continue(_)
+            | HirNodeValue::GeneratorFinished
             | HirNodeValue::GotoLabel(_) => {}
             HirNodeValue::Access(child, _)
             | HirNodeValue::NullableTraverse(child, _)
             | HirNodeValue::InterfaceAddress(child)
+            | HirNodeValue::InterfaceDowncast(child, _)
             | HirNodeValue::TakeUnique(child)
             | HirNodeValue::TakeShared(child)
             | HirNodeValue::Dereference(child)
             | HirNodeValue::NumericCast { value: child, .. }
             | HirNodeValue::MakeNullable(child)
             | HirNodeValue::StructToInterface { value: child, .. }
-            | HirNodeValue::Loop(child) => {
+            | HirNodeValue::Loop(child)
+            | HirNodeValue::Defer(child) => {
                 callback(None, child);
             }
             HirNodeValue::GeneratorSuspend(yielded, _) => {
@@ -448,7 +498,9 @@ impl HirNode {
                     callback(None, case);
                 }
             }
-            HirNodeValue::UnionTag(inner) | HirNodeValue::UnionVariant(inner, _) => {
+            HirNodeValue::UnionTag(inner)
+            | HirNodeValue::NullTag(inner)
+            | HirNodeValue::UnionVariant(inner, _) => {
                 callback(None, inner);
             }
             HirNodeValue::ReferenceCountLiteral(inner) => {
@@ -458,6 +510,7 @@ impl HirNode {
                 callback(None, inner);
             }
             HirNodeValue::Discard(inner) => callback(None, inner),
+            HirNodeValue::ConstEvaluation(_, inner) => callback(None, inner),
         }
     }
 
@@ -480,6 +533,17 @@ impl HirNode {
                     idx,
                 );
             }
+            HirNodeValue::ArraySlice(arr, start, end) => {
+                callback(None, arr);
+                callback(
+                    Some(&ExpressionType::Primitive(PrimitiveType::PointerSize)),
+                    start,
+                );
+                callback(
+                    Some(&ExpressionType::Primitive(PrimitiveType::PointerSize)),
+                    end,
+                );
+            }
             HirNodeValue::UnionLiteral(ty, variant, child) => {
                 let variant_ty = declarations.and_then(|declarations| {
                     let TypeDeclaration::Union(ty) = &declarations.id_to_decl[ty as &TypeID] else {
@@ -507,9 +571,20 @@ impl HirNode {
             }
             HirNodeValue::NullCoalesce(lhs, rhs) => {
                 callback(Some(&self.ty), rhs);
+                // A nullable right side makes the whole expression nullable
+                if matches!(&self.ty, ExpressionType::Nullable(_)) {
+                    callback(Some(&self.ty), lhs);
+                } else {
+                    callback(
+                        Some(&ExpressionType::Nullable(Box::new(self.ty.clone()))),
+                        lhs,
+                    );
+                }
+            }
+            HirNodeValue::NullableUnwrap(child) => {
                 callback(
                     Some(&ExpressionType::Nullable(Box::new(self.ty.clone()))),
-                    lhs,
+                    child,
                 );
             }
             HirNodeValue::BinaryLogical(_, lhs, rhs) => {
@@ -555,7 +630,10 @@ impl HirNode {
                 callback(Some(&lhs.ty), rhs);
                 callback(None, lhs);
             }
-            HirNodeValue::Yield(child) | HirNodeValue::Return(child) => {
+            HirNodeValue::Yield(child)
+            | HirNodeValue::Return(child)
+            | HirNodeValue::Break(child, _)
+            | HirNodeValue::AssertionFailed(child) => {
                 if let Some(child) = child {
                     // TODO: check return types
                     callback(None, child);
@@ -627,17 +705,21 @@ impl HirNode {
             | HirNodeValue::CharLiteral(_)
             | HirNodeValue::StringLiteral(_)
             | HirNodeValue::Null
+            | HirNodeValue::Continue(_)
+            | HirNodeValue::GeneratorFinished
             | HirNodeValue::GotoLabel(_) => {}
             HirNodeValue::Access(child, _)
             | HirNodeValue::NullableTraverse(child, _)
             | HirNodeValue::InterfaceAddress(child)
+            | HirNodeValue::InterfaceDowncast(child, _)
             | HirNodeValue::TakeUnique(child)
             | HirNodeValue::TakeShared(child)
             | HirNodeValue::Dereference(child)
             | HirNodeValue::NumericCast { value: child, .. }
             | HirNodeValue::MakeNullable(child)
             | HirNodeValue::StructToInterface { value: child, .. }
-            | HirNodeValue::Loop(child) => {
+            | HirNodeValue::Loop(child)
+            | HirNodeValue::Defer(child) => {
                 callback(None, child);
             }
             HirNodeValue::GeneratorSuspend(yielded, _) => {
@@ -668,7 +750,9 @@ impl HirNode {
                     callback(None, case);
                 }
             }
-            HirNodeValue::UnionTag(inner) | HirNodeValue::UnionVariant(inner, _) => {
+            HirNodeValue::UnionTag(inner)
+            | HirNodeValue::NullTag(inner)
+            | HirNodeValue::UnionVariant(inner, _) => {
                 callback(None, inner);
             }
             HirNodeValue::ReferenceCountLiteral(inner) => {
@@ -678,6 +762,7 @@ impl HirNode {
                 callback(None, inner);
             }
             HirNodeValue::Discard(inner) => callback(None, inner),
+            HirNodeValue::ConstEvaluation(_, inner) => callback(None, inner),
         }
     }
 
@@ -697,6 +782,7 @@ impl HirNode {
 // TODO: should struct fields also be referred to via opaque IDs?
 
 #[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum HirNodeValue {
     /// Give the Nth parameter the given ID
     Parameter(usize, VariableID),
@@ -710,16 +796,31 @@ pub enum HirNodeValue {
     Assignment(Box<HirNode>, Box<HirNode>),
     ArrayIndex(Box<HirNode>, Box<HirNode>),
     DictIndex(Box<HirNode>, Box<HirNode>),
+    /// Copy the items between the start (inclusive) and end (exclusive) into a new array
+    ArraySlice(Box<HirNode>, Box<HirNode>, Box<HirNode>),
     StringConcat(Box<HirNode>, Box<HirNode>),
     Arithmetic(ArithmeticOp, Box<HirNode>, Box<HirNode>),
     Comparison(ComparisonOp, Box<HirNode>, Box<HirNode>),
     BinaryLogical(BinaryLogicalOp, Box<HirNode>, Box<HirNode>),
     NullCoalesce(Box<HirNode>, Box<HirNode>),
+    /// Read the value out of a nullable, aborting if it's null
+    NullableUnwrap(Box<HirNode>),
     UnaryLogical(UnaryLogicalOp, Box<HirNode>),
 
     Return(Option<Box<HirNode>>),
+    /// A break's value is moved into the loop's result variable during lowering, so only breaks
+    /// without a value make it past the HIR. The number is how many loops out it breaks from,
+    /// where 0 is the innermost.
+    Break(Option<Box<HirNode>>, usize),
+    /// A `while` loop's step is copied in front of each continue during lowering, so continuing
+    /// only has to jump back to the condition. Counts loops the same way as breaks.
+    Continue(usize),
+    /// Abort because an assertion didn't hold, with an optional string message
+    AssertionFailed(Option<Box<HirNode>>),
     /// Desugared out of existence, but hard to do before lowering to HIR
     Yield(Option<Box<HirNode>>),
+    /// Copied to every exit of the enclosing sequence by the defer pass
+    Defer(Box<HirNode>),
 
     Int(i64),
     Float(f64),
@@ -764,14 +865,21 @@ pub enum HirNodeValue {
         value: Box<HirNode>,
         vtable: HashMap<FunctionID, FunctionID>,
     },
+    /// The struct an interface value was made from, or null if it was made from a different one
+    InterfaceDowncast(Box<HirNode>, TypeID),
     MakeNullable(Box<HirNode>),
 
     Discard(Box<HirNode>),
+    /// A constant that calls a const fn, replaced with the value it evaluates to before
+    /// linearization
+    ConstEvaluation(ConstantID, Box<HirNode>),
 
     // Generator instructions, all created during HIR
     GeneratorSuspend(Box<HirNode>, usize),
     GotoLabel(usize),
     GeneratorResume(Box<HirNode>),
+    /// Abort because a generator that yields values was resumed after it finished
+    GeneratorFinished,
     GeneratorCreate {
         generator_function: FunctionID,
         args: Vec<HirNode>,
@@ -782,6 +890,8 @@ pub enum HirNodeValue {
         cases: Vec<HirNode>,
     },
     UnionTag(Box<HirNode>),
+    /// Whether a nullable holds a value
+    NullTag(Box<HirNode>),
     UnionVariant(Box<HirNode>, String),
 }
 
@@ -794,21 +904,26 @@ impl HirNodeValue {
             | HirNodeValue::TakeUnique(lvalue)
             | HirNodeValue::TakeShared(lvalue)
             | HirNodeValue::UnionVariant(lvalue, _)
-            | HirNodeValue::StructToInterface { value: lvalue, .. } => Some(lvalue),
+            | HirNodeValue::StructToInterface { value: lvalue, .. }
+            | HirNodeValue::InterfaceDowncast(lvalue, _) => Some(lvalue),
             _ => None,
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ArithmeticOp {
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// Only defined for integers
+    BitwiseXor,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ComparisonOp {
     LessThan,
     GreaterThan,
@@ -819,12 +934,14 @@ pub enum ComparisonOp {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BinaryLogicalOp {
     BooleanAnd,
     BooleanOr,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum UnaryLogicalOp {
     BooleanNot,
 }
@@ -4,7 +4,10 @@ use super::{HirModule, HirNode, HirNodeValue};
 
 pub fn auto_deref_dot(module: &mut HirModule) {
     module.par_visit_mut(|node| {
-        let HirNodeValue::Access(lhs, _) = &mut node.value else {
+        let (HirNodeValue::Access(lhs, _)
+        | HirNodeValue::ArrayIndex(lhs, _)
+        | HirNodeValue::DictIndex(lhs, _)) = &mut node.value
+        else {
             return;
         };
         while let ExpressionType::Pointer(_, inner) = &lhs.ty {
@@ -16,8 +19,5 @@ pub fn auto_deref_dot(module: &mut HirModule) {
 fn deref(node: &mut HirNode, ty: ExpressionType) {
     let mut temp = HirNode::dummy();
     std::mem::swap(node, &mut temp);
-    std::mem::swap(
-        &mut HirNode::autogenerated(HirNodeValue::Dereference(Box::new(temp)), ty),
-        node,
-    );
+    *node = HirNode::autogenerated(HirNodeValue::Dereference(Box::new(temp)), ty);
 }
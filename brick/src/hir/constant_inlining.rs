@@ -1,27 +1,37 @@
 use std::collections::HashMap;
 
 use crate::{
-    id::{AnyID, ConstantID},
+    id::{AnyID, ConstantID, NodeID},
     parser::{AstNode, AstNodeValue},
-    typecheck::TypecheckedFile,
+    typecheck::{ConstValue, FieldDefaults, TypecheckedFile},
     DeclarationContext, HirNodeValue,
 };
 
 use super::{lower::lower_node, HirModule, HirNode};
 
+/**
+ * Add the values of the file's constants to the map, which may already hold constants they refer to
+ */
 pub fn extract_constant_values(
     ast: &TypecheckedFile,
     declarations: &DeclarationContext,
-) -> HashMap<ConstantID, HirNode> {
-    let mut map = HashMap::new();
+    map: &mut HashMap<ConstantID, HirNode>,
+) {
     for statement in ast.top_level_statements.iter() {
-        extract_constant_value(statement, declarations, &mut map);
+        extract_constant_value(statement, declarations, map);
     }
     for func in ast.functions.iter() {
-        extract_constant_value(func.func.body, declarations, &mut map);
+        extract_constant_value(func.func.body, declarations, map);
     }
 
-    map
+    // Constants may be defined in terms of other constants
+    let ids: Vec<_> = map.keys().copied().collect();
+    for id in ids {
+        while let HirNodeValue::VariableReference(AnyID::Constant(other)) = &map[&id].value {
+            let value = map[other].clone();
+            map.insert(id, value);
+        }
+    }
 }
 
 fn extract_constant_value<'a>(
@@ -34,6 +44,16 @@ fn extract_constant_value<'a>(
     } = &node.value
     {
         let value = lower_node(decls, value);
+        let value = if let HirNodeValue::Call(_, _) = &value.value {
+            HirNode {
+                id: NodeID::new(),
+                ty: value.ty.clone(),
+                provenance: value.provenance.clone(),
+                value: HirNodeValue::ConstEvaluation(*variable_id, Box::new(value)),
+            }
+        } else {
+            value
+        };
         map.insert(*variable_id, value);
     }
     node.children(|child| {
@@ -41,14 +61,41 @@ fn extract_constant_value<'a>(
     });
 }
 
-pub fn inline_constants(module: &mut HirModule, constant_values: HashMap<ConstantID, HirNode>) {
+pub fn inline_constants(module: &mut HirModule, constant_values: &HashMap<ConstantID, HirNode>) {
+    module.par_visit_mut(|node| inline_constant(node, constant_values))
+}
+
+fn inline_constant(node: &mut HirNode, constant_values: &HashMap<ConstantID, HirNode>) {
+    let HirNodeValue::VariableReference(AnyID::Constant(const_id)) = &node.value else {
+        return;
+    };
+    *node = constant_values[const_id].clone();
+    // Constants can be used inside other constants, like as the arguments to a const fn
+    node.visit_mut(|node| inline_constant(node, constant_values));
+}
+
+/**
+ * Give each struct literal the defaults of the fields it leaves out
+ */
+pub fn fill_in_field_defaults(module: &mut HirModule, field_defaults: &FieldDefaults) {
     module.par_visit_mut(|node| {
-        let HirNodeValue::VariableReference(id) = &node.value else {
+        let HirNodeValue::StructLiteral(ty_id, fields) = &mut node.value else {
             return;
         };
-        let AnyID::Constant(const_id) = id else {
+        let Some(defaults) = field_defaults.get(ty_id) else {
             return;
         };
-        *node = constant_values[const_id].clone();
-    })
+        for (name, (value, ty)) in defaults.iter() {
+            fields.entry(name.clone()).or_insert_with(|| {
+                let value = match value {
+                    ConstValue::Int(x) => HirNodeValue::Int(*x),
+                    ConstValue::Float(x) => HirNodeValue::Float(*x),
+                    ConstValue::Bool(x) => HirNodeValue::Bool(*x),
+                    ConstValue::Char(x) => HirNodeValue::CharLiteral(*x),
+                    ConstValue::String(x) => HirNodeValue::StringLiteral(x.clone()),
+                };
+                HirNode::autogenerated(value, ty.clone())
+            });
+        }
+    });
 }
@@ -68,6 +68,9 @@ pub fn rewrite_yields(module: &mut HirModule) {
             continue;
         };
         let generator_var_id: AnyID = (*generator_var_id).into();
+        let finished_label = jump_index;
+        jump_index += 1;
+        finish_at_returns(gen_ty, generator_var_id, finished_label, &mut func.body);
         find_yield_to_rewrite(
             gen_ty,
             generator_var_id,
@@ -79,6 +82,62 @@ pub fn rewrite_yields(module: &mut HirModule) {
     }
 }
 
+/**
+ * Returning from a generator, or reaching the end of its body, finishes it. A finished generator
+ * can't produce any more values, so resuming one that yields values aborts, and resuming one that
+ * doesn't does nothing.
+ */
+fn finish_at_returns(
+    gen_ty: &ExpressionType,
+    generator_var_id: AnyID,
+    finished_label: usize,
+    body: &mut HirNode,
+) {
+    let ExpressionType::Pointer(_, inner_gen_ty) = &gen_ty else {
+        unreachable!();
+    };
+    let ExpressionType::Generator { yield_ty, .. } = inner_gen_ty.as_ref() else {
+        unreachable!();
+    };
+    let finish = || {
+        if yield_ty.as_ref() == &ExpressionType::Void {
+            HirNode::autogenerated(HirNodeValue::Return(None), ExpressionType::Unreachable)
+        } else {
+            HirNode::autogenerated(HirNodeValue::GeneratorFinished, ExpressionType::Unreachable)
+        }
+    };
+    let mark_finished = || {
+        HirNode::autogenerated(
+            HirNodeValue::GeneratorSuspend(
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::VariableReference(generator_var_id),
+                    gen_ty.clone(),
+                )),
+                finished_label,
+            ),
+            ExpressionType::Void,
+        )
+    };
+
+    body.visit_mut(|node| {
+        if let HirNodeValue::Return(None) = &node.value {
+            node.value = HirNodeValue::Sequence(vec![mark_finished(), finish()]);
+        }
+    });
+
+    let HirNodeValue::Sequence(children) = &mut body.value else {
+        unreachable!()
+    };
+    children.extend([
+        mark_finished(),
+        HirNode::autogenerated(
+            HirNodeValue::GotoLabel(finished_label),
+            ExpressionType::Void,
+        ),
+        finish(),
+    ]);
+}
+
 /**
  * Hoist all yield expressions out of other statements, and correctly leave variable references in
  * their place
@@ -4,6 +4,28 @@ use super::{HirModule, HirNode};
 
 pub fn create_temp_vars_for_lvalues(module: &mut HirModule) {
     module.par_visit_mut(|node| {
+        if let HirNodeValue::VtableCall(table, _, args) = &mut node.value {
+            if is_valid_lvalue(table) {
+                return;
+            }
+            // The interface is both where the vtable is read from and the first argument to the
+            // function, so both need to point at the same temporary
+            let temp_var_id = VariableID::new();
+            let value = replace_with_temp(table, temp_var_id);
+            if let Some(HirNode {
+                value: HirNodeValue::InterfaceAddress(interface),
+                ..
+            }) = args.first_mut()
+            {
+                **interface = HirNode::autogenerated(
+                    HirNodeValue::VariableReference(temp_var_id.into()),
+                    value.ty.clone(),
+                );
+            }
+            assign_temp_before(node, temp_var_id, value);
+            return;
+        }
+
         let Some(lvalue) = node.value.lvalue_mut() else {
             return;
         };
@@ -12,32 +34,45 @@ pub fn create_temp_vars_for_lvalues(module: &mut HirModule) {
             return;
         }
         let temp_var_id = VariableID::new();
-        let mut temp_var_value = HirNode::autogenerated(
-            HirNodeValue::VariableReference(temp_var_id.into()),
-            lvalue.ty.clone(),
-        );
-        std::mem::swap(lvalue, &mut temp_var_value);
-        let node_value = std::mem::take(&mut node.value);
-        node.value = HirNodeValue::Sequence(vec![
-            HirNode::autogenerated(
-                HirNodeValue::Declaration(temp_var_id),
-                temp_var_value.ty.clone(),
-            ),
-            HirNode::autogenerated(
-                HirNodeValue::Assignment(
-                    Box::new(HirNode::autogenerated(
-                        HirNodeValue::VariableReference(temp_var_id.into()),
-                        temp_var_value.ty.clone(),
-                    )),
-                    Box::new(temp_var_value),
-                ),
-                ExpressionType::Void,
-            ),
-            HirNode::autogenerated(node_value, node.ty.clone()),
-        ]);
+        let value = replace_with_temp(lvalue, temp_var_id);
+        assign_temp_before(node, temp_var_id, value);
     });
 }
 
+/**
+ * Swap the lvalue out for a reference to the temporary, returning the original value
+ */
+fn replace_with_temp(lvalue: &mut HirNode, temp_var_id: VariableID) -> HirNode {
+    let mut temp_var_value = HirNode::autogenerated(
+        HirNodeValue::VariableReference(temp_var_id.into()),
+        lvalue.ty.clone(),
+    );
+    std::mem::swap(lvalue, &mut temp_var_value);
+
+    temp_var_value
+}
+
+fn assign_temp_before(node: &mut HirNode, temp_var_id: VariableID, temp_var_value: HirNode) {
+    let node_value = std::mem::take(&mut node.value);
+    node.value = HirNodeValue::Sequence(vec![
+        HirNode::autogenerated(
+            HirNodeValue::Declaration(temp_var_id),
+            temp_var_value.ty.clone(),
+        ),
+        HirNode::autogenerated(
+            HirNodeValue::Assignment(
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::VariableReference(temp_var_id.into()),
+                    temp_var_value.ty.clone(),
+                )),
+                Box::new(temp_var_value),
+            ),
+            ExpressionType::Void,
+        ),
+        HirNode::autogenerated(node_value, node.ty.clone()),
+    ]);
+}
+
 fn is_valid_lvalue(lvalue: &HirNode) -> bool {
     match &lvalue.value {
         HirNodeValue::VariableReference(_) => true,
@@ -0,0 +1,159 @@
+use crate::{id::VariableID, typecheck::ExpressionType};
+
+use super::{HirModule, HirNode, HirNodeValue};
+
+/**
+ * Copy each deferred expression to every way out of the sequence it was declared in: the end of
+ * the sequence, and any return, break, or continue that leaves it. Deferred expressions run in the
+ * reverse of the order they were declared, and only once control has reached their declaration.
+ */
+pub fn rewrite_defers(module: &mut HirModule) {
+    rewrite(
+        &mut module.top_level_statements,
+        &mut DeferScopes::default(),
+    );
+    for func in module.functions.iter_mut() {
+        rewrite(&mut func.body, &mut DeferScopes::default());
+    }
+}
+
+#[derive(Default)]
+struct DeferScopes {
+    /// The expressions deferred so far in each enclosing sequence, innermost last
+    sequences: Vec<Vec<HirNode>>,
+    /// How many sequences were already open when each enclosing loop started, innermost last
+    loops: Vec<usize>,
+}
+
+impl DeferScopes {
+    /**
+     * Everything deferred in the sequences past the given depth, in the order it should run
+     */
+    fn deferred_since(&self, depth: usize) -> Vec<HirNode> {
+        self.sequences[depth..]
+            .iter()
+            .rev()
+            .flat_map(|deferred| deferred.iter().rev().cloned())
+            .collect()
+    }
+}
+
+fn rewrite(node: &mut HirNode, scopes: &mut DeferScopes) {
+    match &mut node.value {
+        HirNodeValue::Sequence(children) => {
+            scopes.sequences.push(Vec::new());
+            let mut rewritten = Vec::with_capacity(children.len());
+            for mut child in std::mem::take(children) {
+                match child.value {
+                    HirNodeValue::Defer(mut deferred) => {
+                        rewrite(&mut deferred, scopes);
+                        scopes.sequences.last_mut().unwrap().push(*deferred);
+                    }
+                    value => {
+                        child.value = value;
+                        rewrite(&mut child, scopes);
+                        rewritten.push(child);
+                    }
+                }
+            }
+            let deferred = scopes.deferred_since(scopes.sequences.len() - 1);
+            scopes.sequences.pop();
+            if !deferred.is_empty() {
+                run_at_end(&mut rewritten, deferred);
+            }
+            *children = rewritten;
+        }
+        HirNodeValue::Return(value) => {
+            if let Some(value) = value {
+                rewrite(value, scopes);
+            }
+            let deferred = scopes.deferred_since(0);
+            if deferred.is_empty() {
+                return;
+            }
+            let mut statements = Vec::new();
+            if let Some(value) = value {
+                // The returned value is computed before anything deferred runs
+                statements.extend(move_into_temporary(value));
+            }
+            statements.extend(deferred);
+            run_before(node, statements);
+        }
+        HirNodeValue::Break(_, target) | HirNodeValue::Continue(target) => {
+            let target = *target;
+            node.children_mut(|child| rewrite(child, scopes));
+            let depth = scopes
+                .loops
+                .len()
+                .checked_sub(target + 1)
+                .map(|loop_index| scopes.loops[loop_index])
+                .unwrap_or(0);
+            let deferred = scopes.deferred_since(depth);
+            if !deferred.is_empty() {
+                run_before(node, deferred);
+            }
+        }
+        HirNodeValue::While(_, _) | HirNodeValue::Loop(_) => {
+            scopes.loops.push(scopes.sequences.len());
+            node.children_mut(|child| rewrite(child, scopes));
+            scopes.loops.pop();
+        }
+        HirNodeValue::Defer(_) => unreachable!("defers are only declared as statements"),
+        _ => node.children_mut(|child| rewrite(child, scopes)),
+    }
+}
+
+/**
+ * Run the deferred expressions after the rest of the sequence, keeping its value (if it has one)
+ * as the last thing in it
+ */
+fn run_at_end(children: &mut Vec<HirNode>, deferred: Vec<HirNode>) {
+    let Some(last) = children.last_mut() else {
+        children.extend(deferred);
+        return;
+    };
+    match &last.ty {
+        // Nothing after the last statement would run anyway
+        ExpressionType::Unreachable => {}
+        ExpressionType::Void => children.extend(deferred),
+        ty => {
+            let ty = ty.clone();
+            let mut value = children.pop().unwrap();
+            let statements = move_into_temporary(&mut value);
+            children.extend(statements);
+            children.extend(deferred);
+            children.push(value);
+            debug_assert_eq!(children.last().unwrap().ty, ty);
+        }
+    }
+}
+
+/**
+ * Replace the value with a temporary variable, returning the statements that declare and assign it
+ */
+fn move_into_temporary(value: &mut HirNode) -> [HirNode; 2] {
+    let temp_id = VariableID::new();
+    let ty = value.ty.clone();
+    let reference =
+        HirNode::autogenerated(HirNodeValue::VariableReference(temp_id.into()), ty.clone());
+    let value = std::mem::replace(value, reference.clone());
+
+    [
+        HirNode::autogenerated(HirNodeValue::Declaration(temp_id), ty),
+        HirNode::autogenerated(
+            HirNodeValue::Assignment(Box::new(reference), Box::new(value)),
+            ExpressionType::Void,
+        ),
+    ]
+}
+
+/**
+ * Wrap an exit from the sequence so that the given statements run right before it
+ */
+fn run_before(node: &mut HirNode, mut statements: Vec<HirNode>) {
+    let ty = node.ty.clone();
+    let mut exit = HirNode::dummy();
+    std::mem::swap(&mut exit, node);
+    statements.push(exit);
+    *node = HirNode::autogenerated(HirNodeValue::Sequence(statements), ty);
+}
@@ -4,12 +4,12 @@ use super::{
 };
 
 use crate::{
-    declaration_context::FileDeclarations,
-    id::{NodeID, VariableID},
-    parser::{AstNode, AstNodeValue, BinOp, IfDeclaration, UnaryOp},
+    declaration_context::{FileDeclarations, IntrinsicFunction},
+    id::{AnyID, NodeID, VariableID},
+    parser::{AstNode, AstNodeValue, BinOp, IfDeclaration, IfLetDeclaration, LoopLabel, UnaryOp},
     typecheck::{
-        fully_dereference, shallow_dereference, traverse_dots, CollectionType, ExpressionType,
-        FuncType, PointerKind, PrimitiveType, TypeDeclaration, TypecheckedFile,
+        fully_dereference, shallow_dereference, traverse_dots, type_name, CollectionType,
+        ExpressionType, FuncType, PointerKind, PrimitiveType, TypeDeclaration, TypecheckedFile,
         TypecheckedFunction,
     },
     DeclarationContext,
@@ -23,6 +23,7 @@ pub fn lower_module(
         functions,
         top_level_statements,
         module,
+        ..
     } = module;
 
     let mut module_functions = Vec::with_capacity(functions.len());
@@ -167,6 +168,15 @@ fn lower_function(
     }
 }
 
+/**
+ * A conditional that's only used as a statement shouldn't leave its branches' values behind
+ */
+fn discard_branch_value(branch: &mut HirNode, conditional_ty: &ExpressionType) {
+    if *conditional_ty == ExpressionType::Void && branch.ty != ExpressionType::Unreachable {
+        branch.ty = ExpressionType::Void;
+    }
+}
+
 pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
     let value = match &node.value {
         AstNodeValue::Int(x) => HirNodeValue::Int(*x),
@@ -176,13 +186,46 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
         AstNodeValue::CharLiteral(x) => HirNodeValue::CharLiteral(*x),
         AstNodeValue::StringLiteral(x) => HirNodeValue::StringLiteral(x.clone()),
 
-        AstNodeValue::While(cond, body) => {
+        AstNodeValue::While(cond, step, body) => {
             // TODO: can you assign out of a while?
             let cond = lower_node_alloc(decls, cond);
-            let body = lower_node_alloc(decls, body);
+            let mut body = lower_node_alloc(decls, body);
+            if let Some(step) = step {
+                // The step runs at the end of the body and before every continue
+                let step = lower_node(decls, step);
+                run_step_before_continue(&mut body, &step, 0);
+                body = Box::new(HirNode::from_ast_void(
+                    node,
+                    HirNodeValue::Sequence(vec![*body, step]),
+                ));
+            }
             HirNodeValue::While(cond, body)
         }
-        AstNodeValue::Loop(body) => HirNodeValue::Loop(lower_node_alloc(decls, body)),
+        AstNodeValue::Loop(body) => {
+            let mut body = lower_node_alloc(decls, body);
+            let loop_ty = node.ty.get().unwrap();
+            if matches!(loop_ty, ExpressionType::Void | ExpressionType::Unreachable) {
+                move_break_values(&mut body, None, 0);
+                HirNodeValue::Loop(body)
+            } else {
+                // The loop's value is whatever it was broken out with, so each break writes
+                // to a result variable that's read after the loop is done
+                let result_id = VariableID::new();
+                let result = HirNode::from_ast(
+                    node,
+                    HirNodeValue::VariableReference(result_id.into()),
+                    loop_ty.clone(),
+                );
+                move_break_values(&mut body, Some(&result), 0);
+                HirNodeValue::Sequence(vec![
+                    HirNode::from_ast(node, HirNodeValue::Declaration(result_id), loop_ty.clone()),
+                    HirNode::from_ast_void(node, HirNodeValue::Loop(body)),
+                    result,
+                ])
+            }
+        }
+        // Breaks and continues already know how many loops out they're going
+        AstNodeValue::LabeledLoop(_, inner) => return lower_node(decls, inner),
         AstNodeValue::Block(contents) => {
             let contents = contents
                 .iter()
@@ -198,11 +241,17 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
             else_branch,
         }) => {
             let condition = lower_node_alloc(decls, condition);
-            let if_branch = lower_node_alloc(decls, if_branch);
-            let else_branch = else_branch
+            let mut if_branch = lower_node_alloc(decls, if_branch);
+            let mut else_branch = else_branch
                 .as_ref()
                 .map(|else_branch| lower_node_alloc(decls, else_branch));
 
+            let if_ty = node.ty.get().unwrap();
+            discard_branch_value(&mut if_branch, if_ty);
+            if let Some(else_branch) = else_branch.as_mut() {
+                discard_branch_value(else_branch, if_ty);
+            }
+
             HirNodeValue::If(condition, if_branch, else_branch)
         }
 
@@ -214,7 +263,18 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
         // has what really counts
         AstNodeValue::Statement(inner) => return lower_node(decls, inner),
 
-        AstNodeValue::Declaration(_lvalue, type_hint, rvalue, variable_id) => {
+        AstNodeValue::Declaration(_lvalue, type_hint, None, variable_id) => {
+            // Definite assignment checking guarantees a write before any reads
+            let ty = type_hint
+                .as_ref()
+                .expect("uninitialized declarations to have a type hint")
+                .ty
+                .get()
+                .unwrap()
+                .clone();
+            return HirNode::from_ast(node, HirNodeValue::Declaration(*variable_id), ty);
+        }
+        AstNodeValue::Declaration(_lvalue, type_hint, Some(rvalue), variable_id) => {
             let ty = type_hint
                 .as_ref()
                 .map(|node| node.ty.get().unwrap().clone())
@@ -244,6 +304,12 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
         AstNodeValue::Return(inner) => {
             HirNodeValue::Return(inner.as_ref().map(|inner| lower_node_alloc(decls, inner)))
         }
+        AstNodeValue::Break(inner, label) => HirNodeValue::Break(
+            inner.as_ref().map(|inner| lower_node_alloc(decls, inner)),
+            LoopLabel::jump_depth(label),
+        ),
+        AstNodeValue::Continue(label) => HirNodeValue::Continue(LoopLabel::jump_depth(label)),
+        AstNodeValue::Defer(inner) => HirNodeValue::Defer(lower_node_alloc(decls, inner)),
         AstNodeValue::Yield(inner) => {
             HirNodeValue::Yield(inner.as_ref().map(|inner| lower_node_alloc(decls, inner)))
         }
@@ -277,7 +343,7 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
             HirNodeValue::NullableTraverse(left, name_list)
         }
         AstNodeValue::BinExpr(BinOp::Index, left, right) => {
-            let ty = left.ty.get().unwrap();
+            let ty = fully_dereference(left.ty.get().unwrap());
             let left = lower_node_alloc(decls, left);
             let right = lower_node_alloc(decls, right);
             match ty {
@@ -293,47 +359,49 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
         }
         AstNodeValue::UnaryExpr(op, child) => {
             let child = lower_node_alloc(decls, child);
-            HirNodeValue::UnaryLogical(
-                match op {
-                    UnaryOp::BooleanNot => UnaryLogicalOp::BooleanNot,
-                },
-                child,
-            )
+            match op {
+                UnaryOp::BooleanNot => {
+                    HirNodeValue::UnaryLogical(UnaryLogicalOp::BooleanNot, child)
+                }
+                UnaryOp::NullableUnwrap => HirNodeValue::NullableUnwrap(child),
+                UnaryOp::Negate => {
+                    let zero = HirNode::autogenerated(
+                        HirNodeValue::Int(0),
+                        ExpressionType::Primitive(PrimitiveType::Int32),
+                    );
+                    HirNodeValue::Arithmetic(ArithmeticOp::Subtract, Box::new(zero), child)
+                }
+                UnaryOp::BitNot => {
+                    // -1 is all ones at every width it's cast to
+                    let all_ones = HirNode::autogenerated(
+                        HirNodeValue::Int(-1),
+                        ExpressionType::Primitive(PrimitiveType::Int32),
+                    );
+                    HirNodeValue::Arithmetic(ArithmeticOp::BitwiseXor, child, Box::new(all_ones))
+                }
+                UnaryOp::Plus => child.value,
+            }
         }
         AstNodeValue::BinExpr(op, left, right) => {
             let left = lower_node_alloc(decls, left);
             let right = lower_node_alloc(decls, right);
 
             match op {
-                BinOp::AddAssign => HirNodeValue::Assignment(
-                    left.clone(),
-                    Box::new(HirNode::from_ast_void(
-                        node,
-                        HirNodeValue::Arithmetic(ArithmeticOp::Add, left, right),
-                    )),
-                ),
-                BinOp::SubtractAssign => HirNodeValue::Assignment(
-                    left.clone(),
-                    Box::new(HirNode::from_ast_void(
-                        node,
-                        HirNodeValue::Arithmetic(ArithmeticOp::Subtract, left, right),
-                    )),
-                ),
-                BinOp::MultiplyAssign => HirNodeValue::Assignment(
-                    left.clone(),
-                    Box::new(HirNode::from_ast_void(
-                        node,
-                        HirNodeValue::Arithmetic(ArithmeticOp::Multiply, left, right),
-                    )),
-                ),
-                BinOp::DivideAssign => HirNodeValue::Assignment(
-                    left.clone(),
-                    Box::new(HirNode::from_ast_void(
-                        node,
-                        HirNodeValue::Arithmetic(ArithmeticOp::Divide, left, right),
-                    )),
-                ),
-                BinOp::Assignment => HirNodeValue::Assignment(left, right),
+                BinOp::AddAssign => compound_assignment(node, ArithmeticOp::Add, left, right),
+                BinOp::SubtractAssign => {
+                    compound_assignment(node, ArithmeticOp::Subtract, left, right)
+                }
+                BinOp::MultiplyAssign => {
+                    compound_assignment(node, ArithmeticOp::Multiply, left, right)
+                }
+                BinOp::DivideAssign => compound_assignment(node, ArithmeticOp::Divide, left, right),
+                BinOp::Assignment => match *left {
+                    HirNode {
+                        value: HirNodeValue::DictIndex(dict, key),
+                        ..
+                    } => dict_insert(*dict, *key, *right),
+                    left => HirNodeValue::Assignment(Box::new(left), right),
+                },
 
                 BinOp::Add => HirNodeValue::Arithmetic(ArithmeticOp::Add, left, right),
                 BinOp::Subtract => HirNodeValue::Arithmetic(ArithmeticOp::Subtract, left, right),
@@ -362,13 +430,56 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
                 BinOp::Concat => HirNodeValue::StringConcat(left, right),
             }
         }
+        AstNodeValue::Call(func, params)
+            if matches!(&func.value, AstNodeValue::Name { value, referenced_id }
+                if value == "typename" && referenced_id.get().is_none()) =>
+        {
+            // The typename builtin is resolved at compile time, so its argument is never evaluated
+            let arg_ty = params[0].ty.get().expect("type info to be filled in");
+            HirNodeValue::StringLiteral(type_name(decls, arg_ty))
+        }
+        AstNodeValue::Call(func, params)
+            if matches!(&func.value, AstNodeValue::Name { value, referenced_id }
+                if value == "assert" && referenced_id.get().is_none()) =>
+        {
+            // The condition is evaluated exactly once, and the message only if the assertion fails
+            let condition = lower_node_alloc(decls, &params[0]);
+            let message = params
+                .get(1)
+                .map(|message| lower_node_alloc(decls, message));
+            let failure = HirNode {
+                id: NodeID::new(),
+                value: HirNodeValue::AssertionFailed(message),
+                ty: ExpressionType::Unreachable,
+                provenance: Some(node.provenance.clone()),
+            };
+            HirNodeValue::If(
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::UnaryLogical(UnaryLogicalOp::BooleanNot, condition),
+                    ExpressionType::Primitive(PrimitiveType::Bool),
+                )),
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::Sequence(vec![failure]),
+                    ExpressionType::Unreachable,
+                )),
+                None,
+            )
+        }
         AstNodeValue::Call(func, params) => {
-            let func = lower_node_alloc(decls, func);
-            let params = params
-                .iter()
-                .map(|param| lower_node(decls, param))
-                .collect();
-            HirNodeValue::Call(func, params)
+            if let Some(intrinsic) = global_intrinsic_callee(decls, func) {
+                let params = params
+                    .iter()
+                    .map(|param| lower_node(decls, param))
+                    .collect();
+                HirNodeValue::IntrinsicCall(intrinsic, params)
+            } else {
+                let func = lower_node_alloc(decls, func);
+                let params = params
+                    .iter()
+                    .map(|param| lower_node(decls, param))
+                    .collect();
+                HirNodeValue::Call(func, params)
+            }
         }
         AstNodeValue::RecordLiteral { name, fields } => {
             let AstNodeValue::Name { referenced_id, .. } = &name.value else {
@@ -410,35 +521,14 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
             let count = lower_node_alloc(decls, count);
             HirNodeValue::ArrayLiteralLength(elem, count)
         }
+        AstNodeValue::ArraySlice(arr, start, end) => HirNodeValue::ArraySlice(
+            lower_node_alloc(decls, arr),
+            lower_node_alloc(decls, start),
+            lower_node_alloc(decls, end),
+        ),
 
         AstNodeValue::Match(match_decl) => {
-            let union_node = lower_node_alloc(decls, match_decl.value);
-            let mut temp_variable_declaration = None;
-            let union_node = if union_node.is_valid_lvalue() {
-                union_node
-            } else {
-                let union_temp_id = VariableID::new();
-                let ty = union_node.ty.clone();
-                let var_reference = HirNode::from_ast(
-                    match_decl.value,
-                    HirNodeValue::VariableReference(union_temp_id.into()),
-                    ty.clone(),
-                );
-                temp_variable_declaration = Some(HirNodeValue::Sequence(vec![
-                    HirNode::from_ast(
-                        match_decl.value,
-                        HirNodeValue::Declaration(union_temp_id),
-                        ty.clone(),
-                    ),
-                    HirNode::from_ast(
-                        match_decl.value,
-                        HirNodeValue::Assignment(Box::new(var_reference.clone()), union_node),
-                        ExpressionType::Void,
-                    ),
-                ]));
-
-                Box::new(var_reference)
-            };
+            let (union_node, temp_variable_declaration) = lower_to_lvalue(decls, match_decl.value);
             let match_decl_ty = match_decl.value.ty.get().unwrap();
             let value = Box::new(HirNode::autogenerated(
                 HirNodeValue::UnionTag(union_node.clone()),
@@ -490,43 +580,12 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
                                     HirNodeValue::VariableReference(case_decl.var_id.into()),
                                     variant_ty.clone(),
                                 )),
-                                // If the union passed to the case statement is a pointer, then
-                                // the binding inside the case statement should also be a pointer
-                                if let ExpressionType::Pointer(ptr_ty, _) = match_decl_ty {
-                                    let variant_ty = ExpressionType::Pointer(
-                                        *ptr_ty,
-                                        Box::new(variant_ty.clone()),
-                                    );
-                                    let union_variant_node = Box::new(HirNode::autogenerated(
-                                        HirNodeValue::UnionVariant(
-                                            Box::new(HirNode::autogenerated(
-                                                HirNodeValue::Dereference(union_node.clone()),
-                                                shallow_dereference(match_decl_ty).clone(),
-                                            )),
-                                            union_variant.clone(),
-                                        ),
-                                        variant_ty.clone(),
-                                    ));
-                                    Box::new(HirNode::autogenerated(
-                                        match ptr_ty {
-                                            PointerKind::Shared => {
-                                                HirNodeValue::TakeShared(union_variant_node)
-                                            }
-                                            PointerKind::Unique => {
-                                                HirNodeValue::TakeUnique(union_variant_node)
-                                            }
-                                        },
-                                        variant_ty.clone(),
-                                    ))
-                                } else {
-                                    Box::new(HirNode::autogenerated(
-                                        HirNodeValue::UnionVariant(
-                                            union_node.clone(),
-                                            union_variant.clone(),
-                                        ),
-                                        variant_ty.clone(),
-                                    ))
-                                },
+                                union_variant_binding(
+                                    &union_node,
+                                    match_decl_ty,
+                                    union_variant,
+                                    variant_ty,
+                                ),
                             ),
                             ExpressionType::Void,
                         ),
@@ -550,6 +609,151 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
                 HirNodeValue::Switch { value, cases }
             }
         }
+        AstNodeValue::IfLet(IfLetDeclaration {
+            pattern,
+            var_id,
+            value,
+            if_branch,
+            else_branch,
+        }) => {
+            let (value_node, temp_variable_declaration) = lower_to_lvalue(decls, value);
+            let value_ty = value_node.ty.clone();
+            let binding_ty = pattern.ty.get().unwrap().as_ref().unwrap();
+            let (condition, binding) = if let ExpressionType::Nullable(_) = &value_ty {
+                (
+                    HirNodeValue::NullTag(value_node.clone()),
+                    Box::new(HirNode::autogenerated(
+                        HirNodeValue::NullableUnwrap(value_node),
+                        binding_ty.clone(),
+                    )),
+                )
+            } else {
+                let Some(TypeDeclaration::Union(union_decl)) = shallow_dereference(&value_ty)
+                    .type_id()
+                    .map(|id| &decls.id_to_decl[id])
+                else {
+                    unreachable!()
+                };
+                let variant_idx = union_decl
+                    .variant_order
+                    .iter()
+                    .position(|name| name == &pattern.name)
+                    .expect("variant to exist after typecheck");
+                (
+                    HirNodeValue::Comparison(
+                        ComparisonOp::EqualTo,
+                        Box::new(HirNode::autogenerated(
+                            HirNodeValue::UnionTag(value_node.clone()),
+                            ExpressionType::Primitive(PrimitiveType::PointerSize),
+                        )),
+                        Box::new(HirNode::autogenerated(
+                            HirNodeValue::PointerSize(variant_idx),
+                            ExpressionType::Primitive(PrimitiveType::PointerSize),
+                        )),
+                    ),
+                    union_variant_binding(&value_node, &value_ty, &pattern.name, binding_ty),
+                )
+            };
+
+            let if_branch = lower_node(decls, if_branch);
+            let if_ty = if_branch.ty.clone();
+            let if_branch = HirNode::autogenerated(
+                HirNodeValue::Sequence(vec![
+                    HirNode::autogenerated(HirNodeValue::Declaration(*var_id), binding.ty.clone()),
+                    HirNode::autogenerated(
+                        HirNodeValue::Assignment(
+                            Box::new(HirNode::autogenerated(
+                                HirNodeValue::VariableReference((*var_id).into()),
+                                binding.ty.clone(),
+                            )),
+                            binding,
+                        ),
+                        ExpressionType::Void,
+                    ),
+                    if_branch,
+                ]),
+                if_ty,
+            );
+            let mut if_branch = Box::new(if_branch);
+            let mut else_branch = else_branch
+                .as_ref()
+                .map(|else_branch| lower_node_alloc(decls, else_branch));
+            let if_let_ty = node.ty.get().unwrap();
+            discard_branch_value(&mut if_branch, if_let_ty);
+            if let Some(else_branch) = else_branch.as_mut() {
+                discard_branch_value(else_branch, if_let_ty);
+            }
+            let if_node = HirNodeValue::If(
+                Box::new(HirNode::autogenerated(
+                    condition,
+                    ExpressionType::Primitive(PrimitiveType::Bool),
+                )),
+                if_branch,
+                else_branch,
+            );
+
+            if let Some(mut seq) = temp_variable_declaration {
+                let HirNodeValue::Sequence(body) = &mut seq else {
+                    unreachable!()
+                };
+                body.push(HirNode::from_ast(
+                    node,
+                    if_node,
+                    node.ty.get().unwrap().clone(),
+                ));
+                seq
+            } else {
+                if_node
+            }
+        }
+        AstNodeValue::IsVariant(value, variant) => {
+            let (union_node, temp_variable_declaration) = lower_to_lvalue(decls, value);
+            let Some(TypeDeclaration::Union(union_decl)) = shallow_dereference(&union_node.ty)
+                .type_id()
+                .map(|id| &decls.id_to_decl[id])
+            else {
+                unreachable!()
+            };
+            let variant_idx = union_decl
+                .variant_order
+                .iter()
+                .position(|name| name == variant)
+                .expect("variant to exist after typecheck");
+            let comparison = HirNodeValue::Comparison(
+                ComparisonOp::EqualTo,
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::UnionTag(union_node),
+                    ExpressionType::Primitive(PrimitiveType::PointerSize),
+                )),
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::PointerSize(variant_idx),
+                    ExpressionType::Primitive(PrimitiveType::PointerSize),
+                )),
+            );
+
+            if let Some(mut seq) = temp_variable_declaration {
+                let HirNodeValue::Sequence(body) = &mut seq else {
+                    unreachable!()
+                };
+                body.push(HirNode::from_ast(
+                    node,
+                    comparison,
+                    ExpressionType::Primitive(PrimitiveType::Bool),
+                ));
+                seq
+            } else {
+                comparison
+            }
+        }
+        AstNodeValue::Downcast(value, _) => {
+            let ExpressionType::Nullable(struct_ty) = node.ty.get().unwrap() else {
+                unreachable!()
+            };
+            let ExpressionType::InstanceOf(struct_id) = struct_ty.as_ref() else {
+                unreachable!()
+            };
+            HirNodeValue::InterfaceDowncast(lower_node_alloc(decls, value), *struct_id)
+        }
         AstNodeValue::BorrowDeclaration(_name, value, variable_id) => {
             let rvalue = lower_node_alloc(decls, value);
             let lvalue = Box::new(HirNode {
@@ -576,13 +780,17 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
 
         // Essentially strip constant declarations out when lowering
         AstNodeValue::ConstDeclaration { .. } => HirNodeValue::Sequence(vec![]),
+        // Local types are already in the declaration context
+        AstNodeValue::StructDeclaration(_) => HirNodeValue::Sequence(vec![]),
+        // Local functions are lowered on their own, like top-level ones
+        AstNodeValue::FunctionDeclaration(_) => HirNodeValue::Sequence(vec![]),
 
-        AstNodeValue::FunctionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_)
-        | AstNodeValue::StructDeclaration(_)
+        AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
-        | AstNodeValue::Import(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
+        | AstNodeValue::Import(..)
         | AstNodeValue::UniqueType(_)
         | AstNodeValue::VoidType
         | AstNodeValue::SharedType(_)
@@ -592,12 +800,268 @@ pub fn lower_node(decls: &DeclarationContext, node: &AstNode<'_>) -> HirNode {
         | AstNodeValue::ArrayType(_)
         | AstNodeValue::CellType(_)
         | AstNodeValue::RcType(_)
-        | AstNodeValue::GeneratorType { .. } => unreachable!("Can't have these in a function body"),
+        | AstNodeValue::GeneratorType { .. }
+        | AstNodeValue::FunctionType { .. } => unreachable!("Can't have these in a function body"),
     };
 
     HirNode::from_ast(node, value, node.ty.get().expect("type filled").clone())
 }
 
+/**
+ * Assigning to a dict entry inserts it, so keys that aren't in the dict yet are added
+ */
+fn dict_insert(mut dict: HirNode, key: HirNode, value: HirNode) -> HirNodeValue {
+    // The insert intrinsic takes exactly one pointer to the dict
+    while let ExpressionType::Pointer(_, inner) = &dict.ty {
+        if !matches!(inner.as_ref(), ExpressionType::Pointer(_, _)) {
+            break;
+        }
+        let inner = inner.as_ref().clone();
+        dict = HirNode::autogenerated(HirNodeValue::Dereference(Box::new(dict)), inner);
+    }
+    if !matches!(&dict.ty, ExpressionType::Pointer(_, _)) {
+        let inner_ty = dict.ty.clone();
+        let provenance = dict.provenance.clone();
+        dict = HirNode {
+            id: NodeID::new(),
+            value: HirNodeValue::TakeUnique(Box::new(dict)),
+            ty: ExpressionType::Pointer(PointerKind::Unique, Box::new(inner_ty)),
+            provenance,
+        };
+    }
+
+    HirNodeValue::IntrinsicCall(IntrinsicFunction::DictionaryInsert, vec![dict, key, value])
+}
+
+/**
+ * Pull the values out of the breaks that exit this loop, assigning them to the loop's result if it
+ * has one. Breaks in nested loops are left for those loops to handle, unless they're labeled with
+ * this one; `depth` counts how many nested loops we're inside.
+ */
+fn move_break_values(node: &mut HirNode, result: Option<&HirNode>, depth: usize) {
+    match &mut node.value {
+        HirNodeValue::While(_, _) | HirNodeValue::Loop(_) => {
+            node.children_mut(|child| move_break_values(child, result, depth + 1));
+            return;
+        }
+        HirNodeValue::Break(value @ Some(_), target) if *target == depth => {
+            let target = *target;
+            let value = value.take().unwrap();
+            let value = match result {
+                Some(result) => HirNode {
+                    id: NodeID::new(),
+                    value: HirNodeValue::Assignment(Box::new(result.clone()), value),
+                    ty: ExpressionType::Void,
+                    provenance: node.provenance.clone(),
+                },
+                None => *value,
+            };
+            let break_node = HirNode {
+                id: NodeID::new(),
+                value: HirNodeValue::Break(None, target),
+                ty: ExpressionType::Unreachable,
+                provenance: node.provenance.clone(),
+            };
+            node.value = HirNodeValue::Sequence(vec![value, break_node]);
+            return;
+        }
+        _ => {}
+    }
+    node.children_mut(|child| move_break_values(child, result, depth));
+}
+
+/**
+ * Put a copy of a `while` loop's step in front of each continue that belongs to that loop.
+ * Continues in nested loops are left alone, unless they're labeled with this one.
+ */
+fn run_step_before_continue(node: &mut HirNode, step: &HirNode, depth: usize) {
+    match &node.value {
+        HirNodeValue::While(_, _) | HirNodeValue::Loop(_) => {
+            node.children_mut(|child| run_step_before_continue(child, step, depth + 1));
+            return;
+        }
+        HirNodeValue::Continue(target) if *target == depth => {
+            let continue_node = HirNode {
+                id: NodeID::new(),
+                value: HirNodeValue::Continue(depth),
+                ty: ExpressionType::Unreachable,
+                provenance: node.provenance.clone(),
+            };
+            // Each copy of the step is its own node, so it can't share IDs with the original
+            let mut step = step.clone();
+            step.visit_mut(|node| node.id = NodeID::new());
+            node.value = HirNodeValue::Sequence(vec![step, continue_node]);
+            return;
+        }
+        _ => {}
+    }
+    node.children_mut(|child| run_step_before_continue(child, step, depth));
+}
+
+/**
+ * The lvalue is both read and written, so anything in it that could have side effects is
+ * evaluated into a temporary first
+ */
+fn compound_assignment(
+    node: &AstNode<'_>,
+    op: ArithmeticOp,
+    mut left: Box<HirNode>,
+    right: Box<HirNode>,
+) -> HirNodeValue {
+    let mut statements = Vec::new();
+    hoist_lvalue_operands(&mut left, &mut statements);
+    let assignment = HirNodeValue::Assignment(
+        left.clone(),
+        Box::new(HirNode::from_ast_void(
+            node,
+            HirNodeValue::Arithmetic(op, left, right),
+        )),
+    );
+    if statements.is_empty() {
+        assignment
+    } else {
+        statements.push(HirNode::from_ast_void(node, assignment));
+        HirNodeValue::Sequence(statements)
+    }
+}
+
+fn global_intrinsic_callee(
+    decls: &DeclarationContext,
+    func: &AstNode,
+) -> Option<IntrinsicFunction> {
+    let AstNodeValue::Name { referenced_id, .. } = &func.value else {
+        return None;
+    };
+    let AnyID::Function(id) = referenced_id.get()? else {
+        return None;
+    };
+    decls.global_intrinsic(id)
+}
+
+fn hoist_lvalue_operands(lvalue: &mut HirNode, statements: &mut Vec<HirNode>) {
+    match &mut lvalue.value {
+        HirNodeValue::Access(inner, _) | HirNodeValue::UnionVariant(inner, _) => {
+            hoist_lvalue_operands(inner, statements);
+        }
+        HirNodeValue::Dereference(inner) => {
+            if inner.is_valid_lvalue() {
+                hoist_lvalue_operands(inner, statements);
+            } else {
+                hoist_into_temporary(inner, statements);
+            }
+        }
+        HirNodeValue::ArrayIndex(collection, index)
+        | HirNodeValue::DictIndex(collection, index) => {
+            hoist_lvalue_operands(collection, statements);
+            if !matches!(
+                index.value,
+                HirNodeValue::VariableReference(_)
+                    | HirNodeValue::Int(_)
+                    | HirNodeValue::PointerSize(_)
+                    | HirNodeValue::Float(_)
+                    | HirNodeValue::Bool(_)
+                    | HirNodeValue::CharLiteral(_)
+                    | HirNodeValue::StringLiteral(_)
+            ) {
+                hoist_into_temporary(index, statements);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn hoist_into_temporary(value: &mut HirNode, statements: &mut Vec<HirNode>) {
+    let temp_id = VariableID::new();
+    let ty = value.ty.clone();
+    let mut var_reference =
+        HirNode::autogenerated(HirNodeValue::VariableReference(temp_id.into()), ty.clone());
+    std::mem::swap(value, &mut var_reference);
+    statements.push(HirNode::autogenerated(
+        HirNodeValue::Declaration(temp_id),
+        ty.clone(),
+    ));
+    statements.push(HirNode::autogenerated(
+        HirNodeValue::Assignment(
+            Box::new(HirNode::autogenerated(
+                HirNodeValue::VariableReference(temp_id.into()),
+                ty,
+            )),
+            Box::new(var_reference),
+        ),
+        ExpressionType::Void,
+    ));
+}
+
+/**
+ * Reading part of a value (like a union's tag) requires an lvalue, so store any
+ * other expression in a temporary variable. The returned sequence declares that
+ * variable, and should be completed with the expression that uses the value.
+ */
+fn lower_to_lvalue(
+    decls: &DeclarationContext,
+    value: &AstNode<'_>,
+) -> (Box<HirNode>, Option<HirNodeValue>) {
+    let node = lower_node_alloc(decls, value);
+    if node.is_valid_lvalue() {
+        return (node, None);
+    }
+
+    let temp_id = VariableID::new();
+    let ty = node.ty.clone();
+    let var_reference = HirNode::from_ast(
+        value,
+        HirNodeValue::VariableReference(temp_id.into()),
+        ty.clone(),
+    );
+    let temp_variable_declaration = HirNodeValue::Sequence(vec![
+        HirNode::from_ast(value, HirNodeValue::Declaration(temp_id), ty.clone()),
+        HirNode::from_ast(
+            value,
+            HirNodeValue::Assignment(Box::new(var_reference.clone()), node),
+            ExpressionType::Void,
+        ),
+    ]);
+
+    (Box::new(var_reference), Some(temp_variable_declaration))
+}
+
+/**
+ * Read a variant's value out of a union. If the union is a pointer, then the
+ * binding should also be a pointer
+ */
+fn union_variant_binding(
+    union_node: &HirNode,
+    union_ty: &ExpressionType,
+    variant: &str,
+    variant_ty: &ExpressionType,
+) -> Box<HirNode> {
+    if let ExpressionType::Pointer(ptr_ty, _) = union_ty {
+        let variant_ty = ExpressionType::Pointer(*ptr_ty, Box::new(variant_ty.clone()));
+        let union_variant_node = Box::new(HirNode::autogenerated(
+            HirNodeValue::UnionVariant(
+                Box::new(HirNode::autogenerated(
+                    HirNodeValue::Dereference(Box::new(union_node.clone())),
+                    shallow_dereference(union_ty).clone(),
+                )),
+                variant.to_string(),
+            ),
+            variant_ty.clone(),
+        ));
+        Box::new(HirNode::autogenerated(
+            match ptr_ty {
+                PointerKind::Shared => HirNodeValue::TakeShared(union_variant_node),
+                PointerKind::Unique => HirNodeValue::TakeUnique(union_variant_node),
+            },
+            variant_ty,
+        ))
+    } else {
+        Box::new(HirNode::autogenerated(
+            HirNodeValue::UnionVariant(Box::new(union_node.clone()), variant.to_string()),
+            variant_ty.clone(),
+        ))
+    }
+}
+
 fn lower_node_alloc(decls: &DeclarationContext, node: &AstNode<'_>) -> Box<HirNode> {
     Box::new(lower_node(decls, node))
 }
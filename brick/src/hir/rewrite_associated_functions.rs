@@ -141,14 +141,81 @@ pub fn rewrite(declarations: &DeclarationContext, root: &mut HirNode) {
             std::mem::swap(args, &mut runtime_args);
             runtime_args.insert(0, temp_lhs);
 
-            std::mem::swap(
-                root,
-                &mut HirNode::generated_with_id(
+            *root = HirNode::generated_with_id(
+                root.id,
+                HirNodeValue::IntrinsicCall(runtime_fn.intrinsic_fn, runtime_args),
+                root_ty.clone(),
+            );
+        }
+        ExpressionType::Primitive(prim) => {
+            let prim = *prim;
+            let runtime_fn = declarations
+                .primitive_intrinsic(prim, func_name)
+                .expect("primitive intrinsic to exist after typecheck");
+
+            let mut temp_lhs = HirNode::dummy();
+            std::mem::swap(lhs as &mut HirNode, &mut temp_lhs);
+            // Primitive intrinsics take their receiver by value
+            if let ExpressionType::Pointer(_, inner_ty) = &temp_lhs.ty {
+                let inner_ty = inner_ty.as_ref().clone();
+                temp_lhs =
+                    HirNode::autogenerated(HirNodeValue::Dereference(Box::new(temp_lhs)), inner_ty);
+            }
+
+            let mut runtime_args = Vec::new();
+            std::mem::swap(args, &mut runtime_args);
+            runtime_args.insert(0, temp_lhs);
+
+            // Generic parameters aren't visible to the numeric cast pass, so cast here. Numeric
+            // intrinsics return their type parameter, which may be wider than the receiver. An i32
+            // receiver can be negative, so it's compared against sizes as an i64 and only cast to
+            // a size afterwards.
+            let widens_to_size = prim == PrimitiveType::Int32
+                && *root_ty == ExpressionType::Primitive(PrimitiveType::PointerSize);
+            let call_ty = if widens_to_size {
+                ExpressionType::Primitive(PrimitiveType::Int64)
+            } else {
+                root_ty.clone()
+            };
+            let func_ty = &declarations.id_to_func[&runtime_fn.fn_id];
+            let generic_args = [call_ty.clone()];
+            for (arg, param) in runtime_args.iter_mut().zip(func_ty.params.iter()) {
+                let mut param = param.clone();
+                param.resolve_generics(&generic_args[..]);
+                let (ExpressionType::Primitive(from), ExpressionType::Primitive(to)) =
+                    (&arg.ty, &param)
+                else {
+                    continue;
+                };
+                if from != to {
+                    let (from, to) = (*from, *to);
+                    let mut temp = HirNode::dummy();
+                    std::mem::swap(&mut temp, arg);
+                    *arg = HirNode::autogenerated(
+                        HirNodeValue::NumericCast {
+                            value: Box::new(temp),
+                            from,
+                            to,
+                        },
+                        param,
+                    );
+                }
+            }
+
+            let call = HirNodeValue::IntrinsicCall(runtime_fn.intrinsic_fn, runtime_args);
+            *root = if widens_to_size {
+                HirNode::generated_with_id(
                     root.id,
-                    HirNodeValue::IntrinsicCall(runtime_fn.intrinsic_fn, runtime_args),
+                    HirNodeValue::NumericCast {
+                        value: Box::new(HirNode::autogenerated(call, call_ty)),
+                        from: PrimitiveType::Int64,
+                        to: PrimitiveType::PointerSize,
+                    },
                     root_ty.clone(),
-                ),
-            );
+                )
+            } else {
+                HirNode::generated_with_id(root.id, call, root_ty.clone())
+            };
         }
         ExpressionType::Generator { .. } => {}
         ty => unreachable!(
@@ -1,7 +1,7 @@
 use crate::{
     id::{NodeID, VariableID},
     provenance::SourceRange,
-    typecheck::ExpressionType,
+    typecheck::{ExpressionType, PrimitiveType},
     DeclarationContext,
 };
 
@@ -69,70 +69,34 @@ fn replace_last_with_assignment(
 ) {
     let mut new_value = HirNode::dummy();
     std::mem::swap(values.last_mut().unwrap(), &mut new_value);
-    std::mem::swap(
-        values.last_mut().unwrap(),
-        &mut HirNode {
-            id: NodeID::new(),
-            value: HirNodeValue::Assignment(Box::new(lhs), Box::new(new_value)),
-            ty: ExpressionType::Void,
-            provenance,
-        },
-    );
+    *values.last_mut().unwrap() = HirNode {
+        id: NodeID::new(),
+        value: HirNodeValue::Assignment(Box::new(lhs), Box::new(new_value)),
+        ty: ExpressionType::Void,
+        provenance,
+    };
 }
 
 pub fn simplify_sequence_uses(module: &mut HirModule, declarations: &DeclarationContext) {
     module.par_visit_mut(|node| {
-        let mut temporaries = Vec::new();
-        node.walk_expected_types_for_children_mut(declarations, |ty, child| {
-            if !matches!(
-                &child.value,
-                HirNodeValue::Sequence(_) | HirNodeValue::If(_, _, _) | HirNodeValue::Switch { .. },
-            ) {
-                return;
+        // A loop condition runs on every iteration, so its temporaries are kept inside the
+        // condition instead of being hoisted in front of the loop
+        if let HirNodeValue::While(cond, _) = &mut node.value {
+            let ty = ExpressionType::Primitive(PrimitiveType::Bool);
+            let mut temporaries = Vec::new();
+            assign_to_temporary(&ty, cond, &mut temporaries);
+            if !temporaries.is_empty() {
+                let mut temp = HirNode::dummy();
+                std::mem::swap(&mut temp, cond);
+                temporaries.push(temp);
+                **cond = HirNode::autogenerated(HirNodeValue::Sequence(temporaries), ty);
             }
+            return;
+        }
 
-            let temp_id = VariableID::new();
-            let lhs =
-                HirNode::autogenerated(HirNodeValue::VariableReference(temp_id.into()), ty.clone());
-
-            match &mut child.value {
-                HirNodeValue::Sequence(values) => {
-                    replace_last_with_assignment(child.provenance.clone(), values, lhs.clone());
-                }
-                HirNodeValue::If(_, if_branch, Some(else_branch)) => {
-                    let HirNodeValue::Sequence(if_branch) = &mut if_branch.value else {
-                        unreachable!()
-                    };
-                    let HirNodeValue::Sequence(else_branch) = &mut else_branch.value else {
-                        unreachable!()
-                    };
-                    replace_last_with_assignment(child.provenance.clone(), if_branch, lhs.clone());
-                    replace_last_with_assignment(
-                        child.provenance.clone(),
-                        else_branch,
-                        lhs.clone(),
-                    );
-                }
-                HirNodeValue::Switch { value: _, cases } => {
-                    for case in cases.iter_mut() {
-                        let HirNodeValue::Sequence(case) = &mut case.value else {
-                            unreachable!()
-                        };
-                        replace_last_with_assignment(child.provenance.clone(), case, lhs.clone());
-                    }
-                }
-                _ => unreachable!(),
-            }
-            child.ty = ExpressionType::Void;
-
-            let mut temp = lhs;
-            std::mem::swap(child, &mut temp);
-
-            temporaries.push(HirNode::autogenerated(
-                HirNodeValue::Declaration(temp_id),
-                ty.clone(),
-            ));
-            temporaries.push(temp);
+        let mut temporaries = Vec::new();
+        node.walk_expected_types_for_children_mut(declarations, |ty, child| {
+            assign_to_temporary(ty, child, &mut temporaries);
         });
 
         if !temporaries.is_empty() {
@@ -147,6 +111,57 @@ pub fn simplify_sequence_uses(module: &mut HirModule, declarations: &Declaration
     });
 }
 
+/**
+ * Replace a sequence, if, or switch that's used as a value with a temporary variable, pushing the
+ * statements that declare and assign the temporary
+ */
+fn assign_to_temporary(ty: &ExpressionType, child: &mut HirNode, temporaries: &mut Vec<HirNode>) {
+    if !matches!(
+        &child.value,
+        HirNodeValue::Sequence(_) | HirNodeValue::If(_, _, _) | HirNodeValue::Switch { .. },
+    ) {
+        return;
+    }
+
+    let temp_id = VariableID::new();
+    let lhs = HirNode::autogenerated(HirNodeValue::VariableReference(temp_id.into()), ty.clone());
+
+    match &mut child.value {
+        HirNodeValue::Sequence(values) => {
+            replace_last_with_assignment(child.provenance.clone(), values, lhs.clone());
+        }
+        HirNodeValue::If(_, if_branch, Some(else_branch)) => {
+            let HirNodeValue::Sequence(if_branch) = &mut if_branch.value else {
+                unreachable!()
+            };
+            let HirNodeValue::Sequence(else_branch) = &mut else_branch.value else {
+                unreachable!()
+            };
+            replace_last_with_assignment(child.provenance.clone(), if_branch, lhs.clone());
+            replace_last_with_assignment(child.provenance.clone(), else_branch, lhs.clone());
+        }
+        HirNodeValue::Switch { value: _, cases } => {
+            for case in cases.iter_mut() {
+                let HirNodeValue::Sequence(case) = &mut case.value else {
+                    unreachable!()
+                };
+                replace_last_with_assignment(child.provenance.clone(), case, lhs.clone());
+            }
+        }
+        _ => unreachable!(),
+    }
+    child.ty = ExpressionType::Void;
+
+    let mut temp = lhs;
+    std::mem::swap(child, &mut temp);
+
+    temporaries.push(HirNode::autogenerated(
+        HirNodeValue::Declaration(temp_id),
+        ty.clone(),
+    ));
+    temporaries.push(temp);
+}
+
 pub fn simplify_trailing_if(module: &mut HirModule) {
     module.par_visit_mut(|node| {
         let HirNodeValue::Sequence(children) = &mut node.value else {
@@ -4,19 +4,43 @@ use super::{HirModule, HirNode, HirNodeValue};
 
 pub fn widen_null(module: &mut HirModule, declarations: &DeclarationContext) {
     module.par_visit_mut(|node| {
-        node.walk_expected_types_for_children_mut(declarations, |ty, child| {
-            if !matches!(&ty, ExpressionType::Nullable(_)) {
-                return;
-            }
-            if matches!(&child.value, HirNodeValue::Null) {
-                child.ty = ty.clone();
-            } else if !matches!(&child.ty, ExpressionType::Nullable(_)) {
-                let mut temp = HirNode::dummy();
-                std::mem::swap(&mut temp, child);
-                let mut widened =
-                    HirNode::autogenerated(HirNodeValue::MakeNullable(Box::new(temp)), ty.clone());
-                std::mem::swap(&mut widened, child);
+        node.walk_expected_types_for_children_mut(declarations, widen_to);
+    });
+    for func in module.functions.iter_mut() {
+        let Some(func_ty) = declarations.id_to_func.get(&func.id) else {
+            continue;
+        };
+        if !matches!(&func_ty.returns, ExpressionType::Nullable(_)) {
+            continue;
+        }
+        // Returned values aren't expected types of their parent nodes, so they're widened here
+        func.body.visit_mut(|node| {
+            if let HirNodeValue::Return(Some(value)) = &mut node.value {
+                widen_to(&func_ty.returns, value);
             }
         });
-    });
+        if let HirNodeValue::Sequence(children) = &mut func.body.value {
+            if let Some(last) = children.last_mut() {
+                if !matches!(&last.ty, ExpressionType::Void | ExpressionType::Unreachable) {
+                    widen_to(&func_ty.returns, last);
+                    func.body.ty = func_ty.returns.clone();
+                }
+            }
+        }
+    }
+}
+
+fn widen_to(ty: &ExpressionType, child: &mut HirNode) {
+    if !matches!(&ty, ExpressionType::Nullable(_)) {
+        return;
+    }
+    if matches!(&child.value, HirNodeValue::Null) {
+        child.ty = ty.clone();
+    } else if !matches!(&child.ty, ExpressionType::Nullable(_)) {
+        let mut temp = HirNode::dummy();
+        std::mem::swap(&mut temp, child);
+        let mut widened =
+            HirNode::autogenerated(HirNodeValue::MakeNullable(Box::new(temp)), ty.clone());
+        std::mem::swap(&mut widened, child);
+    }
 }
@@ -6,6 +6,7 @@ pub use crate::declaration_context::FunctionID;
 use crate::declaration_context::TypeID;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum AnyID {
     Type(TypeID),
     Variable(VariableID),
@@ -61,6 +62,7 @@ impl From<ConstantID> for AnyID {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodeID(u32);
 
 impl NodeID {
@@ -80,6 +82,7 @@ impl NodeID {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VariableID(u32);
 
 impl VariableID {
@@ -95,6 +98,7 @@ impl VariableID {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ConstantID(u32);
 
 impl ConstantID {
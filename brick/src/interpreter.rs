@@ -10,9 +10,11 @@ use crate::{
     hir::{ArithmeticOp, BinaryLogicalOp, ComparisonOp, UnaryLogicalOp},
     id::{FunctionID, RegisterID, VariableID},
     linear_ir::{
-        DeclaredTypeLayout, LinearFunction, LinearNode, LinearNodeValue, PhysicalCollection,
-        PhysicalPrimitive, PhysicalType, RuntimeFunction, TypeLayoutValue, NULL_TAG_SIZE,
+        AbortReason, DeclaredTypeLayout, LinearFunction, LinearNode, LinearNodeValue, MathFunction,
+        PhysicalCollection, PhysicalPrimitive, PhysicalType, RuntimeFunction, TypeLayoutValue,
+        NULL_TAG_SIZE, RNG_DEFAULT_STATE,
     },
+    provenance::SourceRange,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -37,8 +39,167 @@ impl Value {
             _ => None,
         }
     }
+
+    /**
+     * Either width of integer, widened to an i64
+     *
+     * ```
+     * use brick::run_program;
+     *
+     * let results = run_program("1 + 2").unwrap();
+     * assert_eq!(results[0].as_int(), Some(3));
+     * ```
+     */
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int32(x) => Some(*x as i64),
+            Value::Int64(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /**
+     * Either width of float, widened to an f64
+     *
+     * ```
+     * use brick::run_program;
+     *
+     * let results = run_program("1.5 * 2.0").unwrap();
+     * assert_eq!(results[0].as_float(), Some(3.0));
+     * ```
+     */
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float32(x) => Some(*x as f64),
+            Value::Float64(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /**
+     * Bools are stored as a byte that's either 0 or 1
+     *
+     * ```
+     * use brick::run_program;
+     *
+     * let results = run_program("1 < 2").unwrap();
+     * assert_eq!(results[0].as_bool(), Some(true));
+     * ```
+     */
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Byte(0) => Some(false),
+            Value::Byte(1) => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn as_size(&self) -> Option<usize> {
+        match self {
+            Value::Size(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /**
+     * Strings take up two values, a pointer into memory and a length, so they're read from the
+     * values a program returned along with its memory.
+     *
+     * ```
+     * use brick::{interpret_code, SourceFile, Value};
+     *
+     * let (results, memory) = interpret_code(
+     *     vec![SourceFile {
+     *         filename: "main.brick",
+     *         module_name: "main",
+     *         contents: r#""hello" ++ " world""#.to_string(),
+     *     }],
+     *     Vec::new(),
+     * )
+     * .unwrap();
+     * assert_eq!(Value::as_string(&results, &memory), Some("hello world"));
+     * ```
+     */
+    pub fn as_string<'a>(values: &[Value], memory: &'a [u8]) -> Option<&'a str> {
+        let [Value::Size(pointer), Value::Size(length)] = values else {
+            return None;
+        };
+        let bytes = memory.get(*pointer..(*pointer + *length))?;
+
+        std::str::from_utf8(bytes).ok()
+    }
+
+    /**
+     * Arrays take up three values, their capacity, length, and a pointer to their items, so
+     * they're read from the values a program returned along with its memory. The items are read
+     * as `T`, which must match the size of the array's item type.
+     *
+     * ```
+     * use brick::{interpret_code, SourceFile, Value};
+     *
+     * let (results, memory) = interpret_code(
+     *     vec![SourceFile {
+     *         filename: "main.brick",
+     *         module_name: "main",
+     *         contents: "list[1, 2, 3]".to_string(),
+     *     }],
+     *     Vec::new(),
+     * )
+     * .unwrap();
+     * assert_eq!(Value::as_array::<i32>(&results, &memory), Some(vec![1, 2, 3]));
+     * ```
+     */
+    pub fn as_array<T: bytemuck::Pod>(values: &[Value], memory: &[u8]) -> Option<Vec<T>> {
+        let [Value::Size(_capacity), Value::Size(length), Value::Size(pointer)] = values else {
+            return None;
+        };
+        let item_size = std::mem::size_of::<T>();
+        let bytes = memory.get(*pointer..(*pointer + *length * item_size))?;
+
+        Some(
+            bytes
+                .chunks_exact(item_size)
+                .map(bytemuck::pod_read_unaligned)
+                .collect(),
+        )
+    }
+}
+
+/**
+ * Returned when converting a value into a Rust type it doesn't hold
+ */
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("expected {expected}, found {found:?}")]
+pub struct ValueConversionError {
+    pub expected: &'static str,
+    pub found: Value,
+}
+
+macro_rules! impl_try_from_value {
+    ($ty:ty, $expected:literal, $pattern:pat => $result:expr) => {
+        impl TryFrom<Value> for $ty {
+            type Error = ValueConversionError;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    $pattern => Ok($result),
+                    found => Err(ValueConversionError {
+                        expected: $expected,
+                        found,
+                    }),
+                }
+            }
+        }
+    };
 }
 
+impl_try_from_value!(i32, "i32", Value::Int32(x) => x);
+impl_try_from_value!(i64, "i64", Value::Int64(x) => x);
+impl_try_from_value!(f32, "f32", Value::Float32(x) => x);
+impl_try_from_value!(f64, "f64", Value::Float64(x) => x);
+impl_try_from_value!(usize, "size", Value::Size(x) => x);
+impl_try_from_value!(bool, "bool", Value::Byte(x @ (0 | 1)) => x == 1);
+
 #[derive(Debug)]
 pub enum Numeric {
     Int32(i32),
@@ -67,8 +228,12 @@ impl Debug for Function {
 #[derive(Debug)]
 pub enum Unwind {
     Return(Option<Value>),
-    Break,
-    Aborted,
+    /// How many loops out the break is going, where 0 is the innermost one it's still inside of
+    Break(u32),
+    /// How many loops out the continue is going, counted the same way as breaks
+    Continue(u32),
+    Aborted(AbortReason),
+    AssertionFailed(Option<String>, SourceRange),
 }
 
 const CONSTANT_DATA_START: usize = 1024 * 1024 * 2;
@@ -85,16 +250,94 @@ pub struct VM<'a> {
     in_progress_goto: Option<usize>,
     variable_locations: Vec<HashMap<VariableID, (usize, PhysicalType)>>,
     fns: &'a HashMap<FunctionID, Function>,
+    rng_state: u64,
 }
 
 const USIZE: usize = std::mem::size_of::<usize>();
 
+/**
+ * What a VM leaves behind for the next statements to carry on from, like the next line typed into a
+ * REPL: its memory, and where the top-level variables are in it
+ */
+pub(crate) struct VmState {
+    memory: Vec<u8>,
+    stack_ptr: usize,
+    variables: HashMap<VariableID, (usize, PhysicalType)>,
+    rng_state: u64,
+}
+
+impl VmState {
+    /**
+     * The heap keeps pointers into the memory, so it can never be moved. Room is made up front
+     * for constant data to be added to it later.
+     */
+    pub(crate) fn new(constant_data_capacity: usize) -> VmState {
+        let mut memory = Vec::with_capacity(CONSTANT_DATA_START + constant_data_capacity);
+        memory.resize(CONSTANT_DATA_START, 0);
+        unsafe {
+            brick_runtime::brick_runtime_init(memory.as_mut_ptr(), HEAP_SEGMENT_LENGTH);
+        }
+        VmState {
+            memory,
+            stack_ptr: CONSTANT_DATA_START,
+            variables: HashMap::new(),
+            rng_state: RNG_DEFAULT_STATE,
+        }
+    }
+
+    /**
+     * How much constant data has been added so far
+     */
+    pub(crate) fn constant_data_len(&self) -> usize {
+        self.memory.len() - CONSTANT_DATA_START
+    }
+
+    /**
+     * Add constant data after what's already there
+     */
+    pub(crate) fn extend_constant_data(&mut self, data: &[u8]) {
+        assert!(
+            self.memory.len() + data.len() <= self.memory.capacity(),
+            "out of room for constant data"
+        );
+        self.memory.extend_from_slice(data);
+    }
+
+    /**
+     * A copy of the state to go back to later. The memory is copied back into place rather than
+     * replaced, so the heap's pointers into it stay valid.
+     */
+    pub(crate) fn checkpoint(&self) -> VmCheckpoint {
+        VmCheckpoint {
+            memory: self.memory.clone(),
+            stack_ptr: self.stack_ptr,
+            variables: self.variables.clone(),
+            rng_state: self.rng_state,
+        }
+    }
+
+    pub(crate) fn restore(&mut self, checkpoint: VmCheckpoint) {
+        self.memory.truncate(checkpoint.memory.len());
+        self.memory.copy_from_slice(&checkpoint.memory);
+        self.stack_ptr = checkpoint.stack_ptr;
+        self.variables = checkpoint.variables;
+        self.rng_state = checkpoint.rng_state;
+    }
+}
+
+pub(crate) struct VmCheckpoint {
+    memory: Vec<u8>,
+    stack_ptr: usize,
+    variables: HashMap<VariableID, (usize, PhysicalType)>,
+    rng_state: u64,
+}
+
 impl<'a> VM<'a> {
     pub fn new(
         layouts: HashMap<TypeID, DeclaredTypeLayout>,
         functions: &'a HashMap<FunctionID, Function>,
         constant_data_region: Vec<u8>,
-    ) -> VM {
+    ) -> VM<'a> {
         let mut memory = vec![0; CONSTANT_DATA_START];
         memory.extend(constant_data_region);
         unsafe {
@@ -111,12 +354,17 @@ impl<'a> VM<'a> {
             in_progress_goto: None,
             variable_locations: vec![HashMap::new()],
             fns: functions,
+            rng_state: RNG_DEFAULT_STATE,
         }
     }
 
+    /**
+     * Each parameter is the values that its argument put on the stack: one for primitives, one per
+     * field for structs, and none at all for structs without fields.
+     */
     pub fn evaluate_function(
         &mut self,
-        params: &mut [Value],
+        params: &mut [Vec<Value>],
         fn_id: FunctionID,
     ) -> Result<(), Unwind> {
         let function = &self.fns[&fn_id];
@@ -153,7 +401,15 @@ impl<'a> VM<'a> {
                 Ok(())
             }
             Function::Extern(ext) => {
-                if let Some(returned) = ext(self, params.to_vec()) {
+                // Externs are passed the last word of each parameter, and find the rest of wide
+                // parameters on the op stack
+                for values in params.iter_mut().rev() {
+                    self.op_stack.append(values);
+                }
+                let params = (0..params.len())
+                    .map(|_| self.op_stack.pop().unwrap())
+                    .collect();
+                if let Some(returned) = ext(self, params) {
                     self.op_stack.push(returned);
                 }
                 Ok(())
@@ -161,14 +417,30 @@ impl<'a> VM<'a> {
         }
     }
 
+    fn evaluate_parameters(
+        &mut self,
+        params: &mut [Vec<Value>],
+        parameters: &[LinearNode],
+    ) -> Result<Vec<Vec<Value>>, Unwind> {
+        let mut values = Vec::with_capacity(parameters.len());
+        for param in parameters.iter().rev() {
+            let stack_len = self.op_stack.len();
+            self.evaluate_node(params, param)?;
+            values.push(self.op_stack.split_off(stack_len));
+        }
+        values.reverse();
+
+        Ok(values)
+    }
+
     pub fn resume_generator(&mut self, generator_ptr: Value) -> Result<(), Unwind> {
         let Value::Size(location) = generator_ptr else {
             panic!("must provide a valid generator pointer to resume_generator");
         };
-        let fn_id: FunctionID = *bytemuck::from_bytes(
+        let fn_id: FunctionID = bytemuck::pod_read_unaligned(
             &self.memory[location..(location + std::mem::size_of::<FunctionID>())],
         );
-        self.evaluate_function(&mut [generator_ptr], fn_id)?;
+        self.evaluate_function(&mut [vec![generator_ptr]], fn_id)?;
 
         Ok(())
     }
@@ -185,8 +457,52 @@ impl<'a> VM<'a> {
         Ok((self.op_stack, self.memory))
     }
 
+    /**
+     * Run top-level statements that carry on from where the last ones left the state, which is
+     * updated even if they fail partway through
+     */
+    pub(crate) fn evaluate_continuing(
+        layouts: HashMap<TypeID, DeclaredTypeLayout>,
+        functions: &'a HashMap<FunctionID, Function>,
+        state: &mut VmState,
+        statements: &[LinearNode],
+    ) -> Result<Vec<Value>, Unwind> {
+        let mut vm = VM {
+            memory: std::mem::take(&mut state.memory),
+            temporaries: HashMap::new(),
+            layouts,
+            op_stack: Vec::new(),
+            base_ptr: CONSTANT_DATA_START,
+            stack_ptr: state.stack_ptr,
+            heap_ptr: 0,
+            in_progress_goto: None,
+            variable_locations: vec![std::mem::take(&mut state.variables)],
+            fns: functions,
+            rng_state: state.rng_state,
+        };
+        let result = statements
+            .iter()
+            .try_for_each(|statement| vm.evaluate_node(&mut [], statement));
+        // Statements that failed inside a function leave its variables behind
+        vm.variable_locations.truncate(1);
+        *state = VmState {
+            memory: vm.memory,
+            stack_ptr: vm.stack_ptr,
+            variables: vm.variable_locations.pop().unwrap(),
+            rng_state: vm.rng_state,
+        };
+        result?;
+        debug_assert_eq!(vm.temporaries.len(), 0);
+
+        Ok(vm.op_stack)
+    }
+
     // Kinda a hack: when we return, unwind the stack via Result
-    fn evaluate_node(&mut self, params: &mut [Value], node: &LinearNode) -> Result<(), Unwind> {
+    fn evaluate_node(
+        &mut self,
+        params: &mut [Vec<Value>],
+        node: &LinearNode,
+    ) -> Result<(), Unwind> {
         if let Some(target_label) = self.in_progress_goto {
             match &node.value {
                 LinearNodeValue::GotoLabel(current_label) if *current_label == target_label => {
@@ -203,7 +519,12 @@ impl<'a> VM<'a> {
                 LinearNodeValue::Loop(children) => 'outer: loop {
                     for node in children.iter() {
                         match self.evaluate_node(params, node) {
-                            Err(Unwind::Break) => break 'outer,
+                            Err(Unwind::Break(0)) => break 'outer,
+                            Err(Unwind::Continue(0)) => continue 'outer,
+                            Err(Unwind::Break(depth)) => return Err(Unwind::Break(depth - 1)),
+                            Err(Unwind::Continue(depth)) => {
+                                return Err(Unwind::Continue(depth - 1))
+                            }
                             other @ Err(_) => return other,
                             Ok(_) => {}
                         }
@@ -263,9 +584,8 @@ impl<'a> VM<'a> {
                 ));
             }
             LinearNodeValue::Parameter(_, idx) => {
-                let mut temp = Value::Byte(0);
-                std::mem::swap(&mut temp, &mut params[*idx]);
-                self.op_stack.push(temp);
+                let values = std::mem::take(&mut params[*idx]);
+                self.op_stack.extend(values);
             }
             LinearNodeValue::ReadMemory {
                 location,
@@ -310,22 +630,12 @@ impl<'a> VM<'a> {
                 let Some(Value::FunctionID(fn_id)) = self.op_stack.pop() else {
                     unreachable!()
                 };
-                for param in parameters.iter().rev() {
-                    self.evaluate_node(params, param)?;
-                }
-                let mut parameters: Vec<_> = (0..parameters.len())
-                    .map(|_| self.op_stack.pop().unwrap())
-                    .collect();
+                let mut parameters = self.evaluate_parameters(params, parameters)?;
 
                 self.evaluate_function(&mut parameters[..], fn_id)?;
             }
             LinearNodeValue::Call(fn_id, parameters) => {
-                for param in parameters.iter().rev() {
-                    self.evaluate_node(params, param)?;
-                }
-                let mut parameters: Vec<_> = (0..parameters.len())
-                    .map(|_| self.op_stack.pop().unwrap())
-                    .collect();
+                let mut parameters = self.evaluate_parameters(params, parameters)?;
 
                 self.evaluate_function(&mut parameters[..], *fn_id)?;
             }
@@ -354,12 +664,16 @@ impl<'a> VM<'a> {
                     }
                 }
             }
-            LinearNodeValue::Break => return Err(Unwind::Break),
+            LinearNodeValue::Break(depth) => return Err(Unwind::Break(*depth)),
+            LinearNodeValue::Continue(depth) => return Err(Unwind::Continue(*depth)),
             // easy-to-miss loop after 'outer
             LinearNodeValue::Loop(inner) => 'outer: loop {
                 for node in inner.iter() {
                     match self.evaluate_node(params, node) {
-                        Err(Unwind::Break) => break 'outer,
+                        Err(Unwind::Break(0)) => break 'outer,
+                        Err(Unwind::Continue(0)) => continue 'outer,
+                        Err(Unwind::Break(depth)) => return Err(Unwind::Break(depth - 1)),
+                        Err(Unwind::Continue(depth)) => return Err(Unwind::Continue(depth - 1)),
                         other @ Err(_) => return other,
                         Ok(_) => {}
                     }
@@ -372,41 +686,111 @@ impl<'a> VM<'a> {
                 };
                 self.op_stack.push(bool_value(val == 0));
             }
+            LinearNodeValue::Math(func, _ty, args) => {
+                for arg in args.iter() {
+                    self.evaluate_node(params, arg)?;
+                }
+                let mut args: Vec<_> = self
+                    .op_stack
+                    .drain((self.op_stack.len() - args.len())..)
+                    .collect();
+                let val = args.remove(0);
+                let val = match func {
+                    MathFunction::Sqrt => match val {
+                        Value::Float32(x) => Value::Float32(x.sqrt()),
+                        Value::Float64(x) => Value::Float64(x.sqrt()),
+                        other => unreachable!("sqrt of non-float {:?}", other),
+                    },
+                    MathFunction::Abs => match val {
+                        Value::Int32(x) => Value::Int32(x.wrapping_abs()),
+                        Value::Int64(x) => Value::Int64(x.wrapping_abs()),
+                        Value::Float32(x) => Value::Float32(x.abs()),
+                        Value::Float64(x) => Value::Float64(x.abs()),
+                        Value::Size(x) => Value::Size(x),
+                        other => unreachable!("abs of non-numeric {:?}", other),
+                    },
+                    MathFunction::Min | MathFunction::Max => {
+                        let is_min = *func == MathFunction::Min;
+                        match (val, args.remove(0)) {
+                            (Value::Int32(a), Value::Int32(b)) => {
+                                Value::Int32(if is_min { a.min(b) } else { a.max(b) })
+                            }
+                            (Value::Int64(a), Value::Int64(b)) => {
+                                Value::Int64(if is_min { a.min(b) } else { a.max(b) })
+                            }
+                            (Value::Float32(a), Value::Float32(b)) => {
+                                Value::Float32(if is_min { a.min(b) } else { a.max(b) })
+                            }
+                            (Value::Float64(a), Value::Float64(b)) => {
+                                Value::Float64(if is_min { a.min(b) } else { a.max(b) })
+                            }
+                            (Value::Size(a), Value::Size(b)) => {
+                                Value::Size(if is_min { a.min(b) } else { a.max(b) })
+                            }
+                            other => unreachable!("min/max of mismatched values {:?}", other),
+                        }
+                    }
+                    MathFunction::Pow => {
+                        let Value::Int32(exponent) = args.remove(0) else {
+                            unreachable!()
+                        };
+                        let Ok(exponent) = u32::try_from(exponent) else {
+                            return Err(Unwind::Aborted(AbortReason::NegativeExponent));
+                        };
+                        match val {
+                            Value::Int32(x) => Value::Int32(x.wrapping_pow(exponent)),
+                            Value::Int64(x) => Value::Int64(x.wrapping_pow(exponent)),
+                            Value::Float32(x) => Value::Float32(x.powi(exponent as i32)),
+                            Value::Float64(x) => Value::Float64(x.powi(exponent as i32)),
+                            Value::Size(x) => Value::Size(x.wrapping_pow(exponent)),
+                            other => unreachable!("pow of non-numeric {:?}", other),
+                        }
+                    }
+                };
+                self.op_stack.push(val);
+            }
             LinearNodeValue::Arithmetic(op, _ty, lhs, rhs) => {
                 self.evaluate_node(params, rhs)?;
                 self.evaluate_node(params, lhs)?;
                 let left = self.op_stack.pop().unwrap().to_numeric().unwrap();
                 let right = self.op_stack.pop().unwrap().to_numeric().unwrap();
+                // Integers wrap like they do in WASM. Checked arithmetic is lowered to explicit
+                // comparisons, so it doesn't need anything from the interpreter.
                 let val = match (left, right) {
                     (Numeric::Int32(left), Numeric::Int32(right)) => match op {
-                        ArithmeticOp::Add => Value::Int32(left + right),
-                        ArithmeticOp::Subtract => Value::Int32(left - right),
-                        ArithmeticOp::Multiply => Value::Int32(left * right),
+                        ArithmeticOp::Add => Value::Int32(left.wrapping_add(right)),
+                        ArithmeticOp::Subtract => Value::Int32(left.wrapping_sub(right)),
+                        ArithmeticOp::Multiply => Value::Int32(left.wrapping_mul(right)),
                         ArithmeticOp::Divide => Value::Int32(left / right),
+                        ArithmeticOp::BitwiseXor => Value::Int32(left ^ right),
                     },
                     (Numeric::Float32(left), Numeric::Float32(right)) => match op {
                         ArithmeticOp::Add => Value::Float32(left + right),
                         ArithmeticOp::Subtract => Value::Float32(left - right),
                         ArithmeticOp::Multiply => Value::Float32(left * right),
                         ArithmeticOp::Divide => Value::Float32(left / right),
+                        ArithmeticOp::BitwiseXor => unreachable!("xor of floats"),
                     },
                     (Numeric::Int64(left), Numeric::Int64(right)) => match op {
-                        ArithmeticOp::Add => Value::Int64(left + right),
-                        ArithmeticOp::Subtract => Value::Int64(left - right),
-                        ArithmeticOp::Multiply => Value::Int64(left * right),
+                        ArithmeticOp::Add => Value::Int64(left.wrapping_add(right)),
+                        ArithmeticOp::Subtract => Value::Int64(left.wrapping_sub(right)),
+                        ArithmeticOp::Multiply => Value::Int64(left.wrapping_mul(right)),
                         ArithmeticOp::Divide => Value::Int64(left / right),
+                        ArithmeticOp::BitwiseXor => Value::Int64(left ^ right),
                     },
                     (Numeric::Float64(left), Numeric::Float64(right)) => match op {
                         ArithmeticOp::Add => Value::Float64(left + right),
                         ArithmeticOp::Subtract => Value::Float64(left - right),
                         ArithmeticOp::Multiply => Value::Float64(left * right),
                         ArithmeticOp::Divide => Value::Float64(left / right),
+                        ArithmeticOp::BitwiseXor => unreachable!("xor of floats"),
                     },
                     (Numeric::Size(left), Numeric::Size(right)) => match op {
                         ArithmeticOp::Add => Value::Size(left + right),
                         ArithmeticOp::Subtract => Value::Size(left - right),
                         ArithmeticOp::Multiply => Value::Size(left * right),
                         ArithmeticOp::Divide => Value::Size(left / right),
+                        ArithmeticOp::BitwiseXor => Value::Size(left ^ right),
                     },
                     (_, _) => unreachable!(),
                 };
@@ -426,6 +810,23 @@ impl<'a> VM<'a> {
                     (*op == ComparisonOp::EqualTo) == (left == right),
                 ));
             }
+            LinearNodeValue::Comparison(op, PhysicalPrimitive::Byte, lhs, rhs) => {
+                self.evaluate_node(params, rhs)?;
+                self.evaluate_node(params, lhs)?;
+                let Some(Value::Byte(left)) = self.op_stack.pop() else {
+                    unreachable!()
+                };
+                let Some(Value::Byte(right)) = self.op_stack.pop() else {
+                    unreachable!()
+                };
+                self.op_stack.push(match op {
+                    ComparisonOp::LessThan => bool_value(left < right),
+                    ComparisonOp::GreaterThan => bool_value(left > right),
+                    ComparisonOp::LessEqualThan => bool_value(left <= right),
+                    ComparisonOp::GreaterEqualThan => bool_value(left >= right),
+                    ComparisonOp::EqualTo | ComparisonOp::NotEquals => unreachable!(),
+                });
+            }
             LinearNodeValue::Comparison(op, _ty, lhs, rhs) => {
                 self.evaluate_node(params, rhs)?;
                 self.evaluate_node(params, lhs)?;
@@ -537,71 +938,32 @@ impl<'a> VM<'a> {
             LinearNodeValue::KillRegister(tmp) => {
                 self.temporaries.remove(tmp);
             }
-            LinearNodeValue::Abort => {
-                return Err(Unwind::Aborted);
+            LinearNodeValue::Abort(reason) => {
+                return Err(Unwind::Aborted(*reason));
+            }
+            LinearNodeValue::AssertionFailed(message) => {
+                let message = match message {
+                    Some(message) => {
+                        self.evaluate_node(params, message)?;
+                        let (Some(Value::Size(len)), Some(Value::Size(ptr))) =
+                            (self.op_stack.pop(), self.op_stack.pop())
+                        else {
+                            unreachable!("assertion message should be a string");
+                        };
+                        Some(String::from_utf8_lossy(&self.memory[ptr..(ptr + len)]).into_owned())
+                    }
+                    None => None,
+                };
+                let provenance = node
+                    .provenance
+                    .clone()
+                    .expect("assertions are lowered with their source location");
+                return Err(Unwind::AssertionFailed(message, provenance));
             }
             LinearNodeValue::Cast { value, from: _, to } => {
                 self.evaluate_node(params, value)?;
                 let val = self.op_stack.pop().unwrap();
-                self.op_stack.push(match val {
-                    Value::FunctionID(_) => {
-                        unreachable!()
-                    }
-                    Value::Size(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val as u8),
-                        PhysicalPrimitive::Int32 => Value::Int32(val as i32),
-                        PhysicalPrimitive::Int64 => Value::Int64(val as i64),
-                        PhysicalPrimitive::Float32 => Value::Float32(val as f32),
-                        PhysicalPrimitive::Float64 => Value::Float64(val as f64),
-                        PhysicalPrimitive::PointerSize => Value::Size(val),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                    Value::Byte(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val),
-                        PhysicalPrimitive::Int32 => Value::Int32(val as i32),
-                        PhysicalPrimitive::Int64 => Value::Int64(val as i64),
-                        PhysicalPrimitive::Float32 => Value::Float32(val as f32),
-                        PhysicalPrimitive::Float64 => Value::Float64(val as f64),
-                        PhysicalPrimitive::PointerSize => Value::Size(val as usize),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                    Value::Int32(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val as u8),
-                        PhysicalPrimitive::Int32 => Value::Int32(val),
-                        PhysicalPrimitive::Int64 => Value::Int64(val as i64),
-                        PhysicalPrimitive::Float32 => Value::Float32(val as f32),
-                        PhysicalPrimitive::Float64 => Value::Float64(val as f64),
-                        PhysicalPrimitive::PointerSize => Value::Size(val as usize),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                    Value::Int64(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val as u8),
-                        PhysicalPrimitive::Int32 => Value::Int32(val as i32),
-                        PhysicalPrimitive::Int64 => Value::Int64(val),
-                        PhysicalPrimitive::Float32 => Value::Float32(val as f32),
-                        PhysicalPrimitive::Float64 => Value::Float64(val as f64),
-                        PhysicalPrimitive::PointerSize => Value::Size(val as usize),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                    Value::Float32(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val as u8),
-                        PhysicalPrimitive::Int32 => Value::Int32(val as i32),
-                        PhysicalPrimitive::Int64 => Value::Int64(val as i64),
-                        PhysicalPrimitive::Float32 => Value::Float32(val),
-                        PhysicalPrimitive::Float64 => Value::Float64(val as f64),
-                        PhysicalPrimitive::PointerSize => Value::Size(val as usize),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                    Value::Float64(val) => match to {
-                        PhysicalPrimitive::Byte => Value::Byte(val as u8),
-                        PhysicalPrimitive::Int32 => Value::Int32(val as i32),
-                        PhysicalPrimitive::Int64 => Value::Int64(val as i64),
-                        PhysicalPrimitive::Float32 => Value::Float32(val as f32),
-                        PhysicalPrimitive::Float64 => Value::Float64(val),
-                        PhysicalPrimitive::PointerSize => Value::Size(val as usize),
-                        PhysicalPrimitive::FunctionPointer => unreachable!(),
-                    },
-                });
+                self.op_stack.push(cast(val, *to));
             }
             LinearNodeValue::Debug(inner) => {
                 self.evaluate_node(params, inner)?;
@@ -668,6 +1030,26 @@ impl<'a> VM<'a> {
                     );
                 }
             }
+            LinearNodeValue::RuntimeCall(RuntimeFunction::RngSeed, args) => {
+                self.evaluate_node(params, &args[0])?;
+                let Some(Value::Int64(seed)) = self.op_stack.pop() else {
+                    unreachable!()
+                };
+                self.rng_state = if seed == 0 {
+                    RNG_DEFAULT_STATE
+                } else {
+                    seed as u64
+                };
+            }
+            LinearNodeValue::RuntimeCall(RuntimeFunction::RngNext, _) => {
+                let mut x = self.rng_state;
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                self.rng_state = x;
+                // The top 31 bits, so the result is never negative
+                self.op_stack.push(Value::Int32((x >> 33) as i32));
+            }
             LinearNodeValue::RuntimeCall(RuntimeFunction::Alloc { alignment }, args) => {
                 self.evaluate_node(params, &args[0])?;
                 let Some(Value::Size(amount)) = self.op_stack.pop() else {
@@ -774,6 +1156,8 @@ fn write(
                     for _field in fields.iter() {
                         offset += write_primitive(op_stack, memory, location + offset);
                     }
+                    // The tag of the struct the interface was made from
+                    write_primitive(op_stack, memory, location + offset);
                 }
                 TypeLayoutValue::Union(variants) => {
                     let Value::Size(variant) = op_stack.pop().unwrap() else {
@@ -796,7 +1180,7 @@ fn write(
                         let padding = op_stack.pop().unwrap();
                         padding_to_drop -= match padding {
                             Value::FunctionID(_) | Value::Int32(_) | Value::Float32(_) => 4,
-                            Value::Int64(_) | Value::Float64(_) => 4,
+                            Value::Int64(_) | Value::Float64(_) => 8,
                             Value::Size(_) => USIZE,
                             Value::Byte(_) => 1,
                         };
@@ -868,7 +1252,14 @@ fn read(
                     }
                 }
                 TypeLayoutValue::Interface(fields) => {
-                    let mut location = location + layout.size - std::mem::size_of::<FunctionID>();
+                    let tag_location = location + layout.size - std::mem::size_of::<usize>();
+                    read_primitive(
+                        op_stack,
+                        memory,
+                        tag_location,
+                        PhysicalPrimitive::PointerSize,
+                    );
+                    let mut location = tag_location - std::mem::size_of::<FunctionID>();
                     for _ in fields.iter().rev() {
                         read(
                             op_stack,
@@ -882,15 +1273,24 @@ fn read(
                     read_primitive(op_stack, memory, location, PhysicalPrimitive::PointerSize);
                 }
                 TypeLayoutValue::Union(union) => {
-                    let largest_variant = union
-                        .values()
-                        .filter_map(|(_, ty)| ty.as_ref())
-                        .max_by(|a, b| {
-                            a.size_from_decls(layouts, 1, USIZE)
-                                .cmp(&b.size_from_decls(layouts, 1, USIZE))
-                        })
-                        .unwrap();
-                    read(op_stack, layouts, memory, location + USIZE, largest_variant);
+                    // Push the same values a union literal does, so `write` can take them back
+                    // off: padding, then whichever variant the tag says is there, then the tag
+                    let tag = usize::from_le_bytes(
+                        memory[location..(location + USIZE)].try_into().unwrap(),
+                    );
+                    let variant =
+                        union
+                            .values()
+                            .find_map(|(id, ty)| if *id == tag { ty.as_ref() } else { None });
+                    let variant_size = variant
+                        .map(|ty| ty.size_from_decls(layouts, 1, USIZE))
+                        .unwrap_or(0);
+                    let padding = layout.size - USIZE - variant_size;
+                    op_stack.extend((0..(padding / 4)).map(|_| Value::Int32(0)));
+                    op_stack.extend((0..(padding % 4)).map(|_| Value::Byte(0)));
+                    if let Some(variant) = variant {
+                        read(op_stack, layouts, memory, location + USIZE, variant);
+                    }
                     read_primitive(op_stack, memory, location, PhysicalPrimitive::PointerSize);
                 }
             }
@@ -941,28 +1341,170 @@ fn read_primitive(
 ) {
     op_stack.push(match primitive {
         PhysicalPrimitive::Byte => Value::Byte(memory[location]),
-        PhysicalPrimitive::Int32 => {
-            Value::Int32(*bytemuck::from_bytes(&memory[location..(location + 4)]))
-        }
-        PhysicalPrimitive::Float32 => {
-            Value::Float32(*bytemuck::from_bytes(&memory[location..(location + 4)]))
-        }
-        PhysicalPrimitive::Int64 => {
-            Value::Int64(*bytemuck::from_bytes(&memory[location..(location + 8)]))
-        }
-        PhysicalPrimitive::Float64 => {
-            Value::Float64(*bytemuck::from_bytes(&memory[location..(location + 8)]))
-        }
+        PhysicalPrimitive::Int32 => Value::Int32(bytemuck::pod_read_unaligned(
+            &memory[location..(location + 4)],
+        )),
+        PhysicalPrimitive::Float32 => Value::Float32(bytemuck::pod_read_unaligned(
+            &memory[location..(location + 4)],
+        )),
+        PhysicalPrimitive::Int64 => Value::Int64(bytemuck::pod_read_unaligned(
+            &memory[location..(location + 8)],
+        )),
+        PhysicalPrimitive::Float64 => Value::Float64(bytemuck::pod_read_unaligned(
+            &memory[location..(location + 8)],
+        )),
         PhysicalPrimitive::PointerSize => {
             let base_ptr = &memory[location..(location + 8)];
             let base_ptr = usize::from_le_bytes(base_ptr.try_into().unwrap());
             Value::Size(base_ptr)
         }
         PhysicalPrimitive::FunctionPointer => {
-            let fn_id: FunctionID = *bytemuck::from_bytes(
+            let fn_id: FunctionID = bytemuck::pod_read_unaligned(
                 &memory[location..(location + std::mem::size_of::<FunctionID>())],
             );
             Value::FunctionID(fn_id)
         }
     });
 }
+
+/**
+ * Convert between primitives the same way the WASM backend does. Floats truncate toward zero when
+ * converted to integers, and values out of the integer's range saturate to its min or max (NaN
+ * becomes 0, as do negative floats converted to sizes) rather than trapping. Integers round to the nearest float, and changing integer
+ * widths sign-extends or drops the high bits.
+ */
+fn cast(val: Value, to: PhysicalPrimitive) -> Value {
+    match val {
+        Value::FunctionID(_) => {
+            unreachable!()
+        }
+        Value::Size(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val as u8),
+            PhysicalPrimitive::Int32 => Value::Int32(val as i32),
+            PhysicalPrimitive::Int64 => Value::Int64(val as i64),
+            PhysicalPrimitive::Float32 => Value::Float32(val as f32),
+            PhysicalPrimitive::Float64 => Value::Float64(val as f64),
+            PhysicalPrimitive::PointerSize => Value::Size(val),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+        Value::Byte(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val),
+            PhysicalPrimitive::Int32 => Value::Int32(val as i32),
+            PhysicalPrimitive::Int64 => Value::Int64(val as i64),
+            PhysicalPrimitive::Float32 => Value::Float32(val as f32),
+            PhysicalPrimitive::Float64 => Value::Float64(val as f64),
+            PhysicalPrimitive::PointerSize => Value::Size(val as usize),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+        Value::Int32(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val as u8),
+            PhysicalPrimitive::Int32 => Value::Int32(val),
+            PhysicalPrimitive::Int64 => Value::Int64(val as i64),
+            PhysicalPrimitive::Float32 => Value::Float32(val as f32),
+            PhysicalPrimitive::Float64 => Value::Float64(val as f64),
+            PhysicalPrimitive::PointerSize => Value::Size(val as usize),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+        Value::Int64(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val as u8),
+            PhysicalPrimitive::Int32 => Value::Int32(val as i32),
+            PhysicalPrimitive::Int64 => Value::Int64(val),
+            PhysicalPrimitive::Float32 => Value::Float32(val as f32),
+            PhysicalPrimitive::Float64 => Value::Float64(val as f64),
+            PhysicalPrimitive::PointerSize => Value::Size(val as usize),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+        Value::Float32(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val as i32 as u8),
+            PhysicalPrimitive::Int32 => Value::Int32(val as i32),
+            PhysicalPrimitive::Int64 => Value::Int64(val as i64),
+            PhysicalPrimitive::Float32 => Value::Float32(val),
+            PhysicalPrimitive::Float64 => Value::Float64(val as f64),
+            PhysicalPrimitive::PointerSize => Value::Size(val as usize),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+        Value::Float64(val) => match to {
+            PhysicalPrimitive::Byte => Value::Byte(val as i32 as u8),
+            PhysicalPrimitive::Int32 => Value::Int32(val as i32),
+            PhysicalPrimitive::Int64 => Value::Int64(val as i64),
+            PhysicalPrimitive::Float32 => Value::Float32(val as f32),
+            PhysicalPrimitive::Float64 => Value::Float64(val),
+            PhysicalPrimitive::PointerSize => Value::Size(val as usize),
+            PhysicalPrimitive::FunctionPointer => unreachable!(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use PhysicalPrimitive::*;
+
+    #[test]
+    fn floats_truncate_toward_zero() {
+        assert_eq!(cast(Value::Float64(3.9), Int32), Value::Int32(3));
+        assert_eq!(cast(Value::Float64(-3.9), Int32), Value::Int32(-3));
+        assert_eq!(cast(Value::Float32(0.5), Int64), Value::Int64(0));
+        assert_eq!(cast(Value::Float32(-7.99), Int64), Value::Int64(-7));
+    }
+
+    #[test]
+    fn out_of_range_floats_saturate() {
+        assert_eq!(cast(Value::Float64(1e10), Int32), Value::Int32(i32::MAX));
+        assert_eq!(cast(Value::Float64(-1e10), Int32), Value::Int32(i32::MIN));
+        assert_eq!(
+            cast(Value::Float32(f32::INFINITY), Int64),
+            Value::Int64(i64::MAX)
+        );
+        assert_eq!(cast(Value::Float64(f64::NAN), Int32), Value::Int32(0));
+    }
+
+    #[test]
+    fn negative_floats_saturate_to_zero_sizes() {
+        assert_eq!(cast(Value::Float32(-2.5), PointerSize), Value::Size(0));
+        assert_eq!(cast(Value::Float64(-1e10), PointerSize), Value::Size(0));
+        assert_eq!(cast(Value::Float64(2.9), PointerSize), Value::Size(2));
+    }
+
+    #[test]
+    fn integers_widen_with_sign() {
+        assert_eq!(cast(Value::Int32(-5), Int64), Value::Int64(-5));
+        assert_eq!(
+            cast(Value::Int32(i32::MAX), Int64),
+            Value::Int64(i32::MAX as i64)
+        );
+        assert_eq!(cast(Value::Byte(200), Int32), Value::Int32(200));
+    }
+
+    #[test]
+    fn integers_narrow_by_dropping_high_bits() {
+        assert_eq!(cast(Value::Int64(1 << 32), Int32), Value::Int32(0));
+        assert_eq!(cast(Value::Int64((1 << 32) + 7), Int32), Value::Int32(7));
+        assert_eq!(cast(Value::Int64(-1), Int32), Value::Int32(-1));
+        assert_eq!(cast(Value::Int32(0x1ff), Byte), Value::Byte(0xff));
+    }
+
+    #[test]
+    fn integers_round_to_nearest_float() {
+        assert_eq!(cast(Value::Int32(3), Float64), Value::Float64(3.0));
+        // 2^24 + 1 isn't representable as an f32, and ties round to even
+        assert_eq!(
+            cast(Value::Int32(16_777_217), Float32),
+            Value::Float32(16_777_216.0)
+        );
+        assert_eq!(
+            cast(Value::Int32(16_777_219), Float32),
+            Value::Float32(16_777_220.0)
+        );
+        assert_eq!(
+            cast(Value::Int64(-(1 << 53) - 1), Float64),
+            Value::Float64(-(2f64.powi(53)))
+        );
+    }
+
+    #[test]
+    fn floats_change_precision() {
+        assert_eq!(cast(Value::Float32(1.5), Float64), Value::Float64(1.5));
+        assert_eq!(cast(Value::Float64(0.1), Float32), Value::Float32(0.1));
+    }
+}
@@ -1,34 +1,50 @@
 #![allow(clippy::result_large_err)]
 
-use declaration_context::FileDeclarations;
-pub use declaration_context::{DeclarationContext, TypeID};
-use std::{collections::HashMap, io};
+use compile_stats::timed;
+pub use compile_stats::{CompileStats, ModuleStats};
+pub use declaration_context::{
+    DeclarationContext, DeclarationRef, ExportedFunction, ModuleUpdate, TypeField, TypeID,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 use type_validator::TypeValidationError;
+use warnings::FunctionUsage;
 
 use borrowck::LifetimeError;
-use hir::HirModule;
-use interpreter::{Function, VM};
+use interpreter::{Function, Unwind, VM};
 pub use linear_ir::{
-    expr_ty_to_physical, DeclaredTypeLayout, LinearFunction, LinearNode, LinearNodeValue,
-    PhysicalCollection, PhysicalPrimitive, PhysicalType, RuntimeFunction, TypeLayoutValue,
+    expr_ty_to_physical, AbortReason, DeclaredTypeLayout, LinearFunction, LinearNode,
+    LinearNodeValue, MathFunction, PhysicalCollection, PhysicalPrimitive, PhysicalType,
+    RuntimeFunction, TypeLayoutValue, RNG_DEFAULT_STATE,
 };
 use linear_ir::{layout_types, LinearContext};
 use parser::AstNode;
 use thiserror::Error;
-use typecheck::typecheck;
-pub use typecheck::{ExpressionType, FuncType, TypeDeclaration};
+use typecheck::{typecheck, FieldDefaults};
+pub use typecheck::{CollectionType, ExpressionType, FuncType, PrimitiveType, TypeDeclaration};
 
 mod borrowck;
+mod compile_stats;
+mod const_evaluation;
 mod declaration_context;
+mod definite_assignment;
+mod diagnostics;
 mod hir;
 mod interpreter;
 mod linear_ir;
 mod multi_error;
 pub mod parser;
 mod provenance;
+mod repl;
 mod tokenizer;
 mod type_validator;
 mod typecheck;
+mod warnings;
 
 use parser::ParseError;
 use typed_arena::Arena;
@@ -36,14 +52,27 @@ use typed_arena::Arena;
 use crate::{hir::lower_module, type_validator::validate_types, typecheck::TypecheckError};
 
 pub mod id;
-pub use hir::{ArithmeticOp, BinaryLogicalOp, ComparisonOp, HirNodeValue, UnaryLogicalOp};
-pub use interpreter::{ExternBinding, Value};
+pub use diagnostics::{Diagnostic, RenderOptions};
+pub use hir::{
+    ArithmeticOp, BinaryLogicalOp, ComparisonOp, GeneratorProperties, HirFunction, HirModule,
+    HirNode, HirNodeValue, UnaryLogicalOp,
+};
+pub use interpreter::{ExternBinding, Value, ValueConversionError};
 pub use provenance::{SourceMarker, SourceRange};
+pub use repl::ReplSession;
+pub use warnings::CompileWarning;
 
 #[derive(Debug, Error)]
 pub enum IntepreterError {
-    #[error("aborted during execution")]
-    Abort,
+    #[error("aborted during execution: {0}")]
+    Abort(AbortReason),
+    #[error("assertion failed at {provenance}{}", .message.as_ref().map(|message| format!(": {message}")).unwrap_or_default())]
+    AssertionFailed {
+        message: Option<String>,
+        provenance: SourceRange,
+    },
+    #[error("integer overflow")]
+    Overflow,
     #[error("compile error: {0}")]
     CompileError(#[from] CompileError),
 }
@@ -60,6 +89,12 @@ pub enum CompileError {
     TypecheckError(#[from] TypecheckError),
     #[error("lifetime errors: {0}")]
     LifetimeError(#[from] LifetimeError),
+    #[error("constant failed to evaluate at compile time: {0}")]
+    ConstEvaluationFailed(SourceRange),
+    #[error("code can't be generated for target {0:?}")]
+    UnsupportedTarget(TargetConfig),
+    #[error("expression can't be assigned to or borrowed")]
+    InvalidLvalue(Option<SourceRange>),
 }
 
 pub struct SourceFile {
@@ -88,6 +123,14 @@ impl SourceFile {
 pub fn interpret_code(
     sources: Vec<SourceFile>,
     bindings: Vec<(&str, ExternBinding)>,
+) -> Result<(Vec<Value>, Vec<u8>), IntepreterError> {
+    interpret_code_with_options(sources, bindings, &CompileOptions::default())
+}
+
+pub fn interpret_code_with_options(
+    sources: Vec<SourceFile>,
+    bindings: Vec<(&str, ExternBinding)>,
+    options: &CompileOptions,
 ) -> Result<(Vec<Value>, Vec<u8>), IntepreterError> {
     let LowerResults {
         statements,
@@ -96,7 +139,7 @@ pub fn interpret_code(
         declarations,
         type_layouts: ty_declarations,
         constant_data,
-    } = lower_code(sources, 1, std::mem::size_of::<usize>())?;
+    } = lower_code_with_options(sources, &TargetConfig::native(), options)?;
     let mut functions: HashMap<_, _> = functions
         .into_iter()
         .map(|func| (func.id, Function::Ir(func)))
@@ -114,12 +157,139 @@ pub fn interpret_code(
     }
 
     let vm = VM::new(ty_declarations, &functions, constant_data);
-    match vm.evaluate_top_level_statements(&statements[..]) {
-        Ok(results) => Ok(results),
-        Err(_) => Err(IntepreterError::Abort),
+    vm.evaluate_top_level_statements(&statements[..])
+        .map_err(top_level_unwind_error)
+}
+
+/**
+ * The error for whatever unwound all the way out of the top-level statements
+ */
+fn top_level_unwind_error(unwind: Unwind) -> IntepreterError {
+    match unwind {
+        Unwind::AssertionFailed(message, provenance) => IntepreterError::AssertionFailed {
+            message,
+            provenance,
+        },
+        Unwind::Aborted(AbortReason::IntegerOverflow) => IntepreterError::Overflow,
+        Unwind::Aborted(reason) => IntepreterError::Abort(reason),
+        Unwind::Return(_) | Unwind::Break(_) | Unwind::Continue(_) => {
+            unreachable!("returns, breaks and continues can't escape the top level")
+        }
+    }
+}
+
+/**
+ * Compile and interpret a single-file program with no extern bindings, returning the values left
+ * over after its top-level statements run.
+ *
+ * ```
+ * use brick::{run_program, Value};
+ *
+ * let results = run_program("let x = 5; x * 2").unwrap();
+ * assert_eq!(results, vec![Value::Int32(10)]);
+ * ```
+ */
+pub fn run_program(source: &str) -> Result<Vec<Value>, IntepreterError> {
+    let (results, _memory) = interpret_code(vec![single_source_file(source)], Vec::new())?;
+    Ok(results)
+}
+
+/**
+ * Like `run_program`, but also binds `extern fn print(str: string)` and returns everything it
+ * printed, one line per call.
+ *
+ * ```
+ * use brick::{run_program_capturing_output, Value};
+ *
+ * let (results, output) = run_program_capturing_output(
+ *     r#"
+ * extern fn print(str: string);
+ * print("hello");
+ * print("world");
+ * 3
+ * "#,
+ * )
+ * .unwrap();
+ * assert_eq!(results, vec![Value::Int32(3)]);
+ * assert_eq!(output, "hello\nworld\n");
+ * ```
+ */
+pub fn run_program_capturing_output(source: &str) -> Result<(Vec<Value>, String), IntepreterError> {
+    let output = Rc::new(RefCell::new(String::new()));
+    let print_output = output.clone();
+    let print: ExternBinding = Box::new(move |vm, values| {
+        // Only the last word of a wide parameter is passed, the rest stays on the op stack
+        let [Value::Size(len)] = &values[..] else {
+            unreachable!("print takes a string, not {values:?}");
+        };
+        let Some(Value::Size(ptr)) = vm.op_stack.pop() else {
+            unreachable!("string pointer should be on the op stack");
+        };
+        let mut output = print_output.borrow_mut();
+        output.push_str(&String::from_utf8_lossy(&vm.memory[ptr..(ptr + *len)]));
+        output.push('\n');
+        None
+    });
+    let (results, _memory) =
+        interpret_code(vec![single_source_file(source)], vec![("print", print)])?;
+    let output = output.borrow().clone();
+
+    Ok((results, output))
+}
+
+fn single_source_file(source: &str) -> SourceFile {
+    SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: source.to_string(),
     }
 }
 
+/**
+ * The machine the code is being lowered for, which decides how big values are in memory
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetConfig {
+    /// How much space a bool takes up
+    pub byte_size: usize,
+    pub pointer_size: usize,
+    /// Whether memory is addressed with 64-bit pointers, for WASM targets
+    pub memory64: bool,
+}
+
+impl TargetConfig {
+    pub const WASM32: TargetConfig = TargetConfig {
+        byte_size: 4,
+        pointer_size: 4,
+        memory64: false,
+    };
+    pub const WASM64: TargetConfig = TargetConfig {
+        byte_size: 4,
+        pointer_size: 8,
+        memory64: true,
+    };
+
+    /**
+     * The machine the compiler itself is running on, which is what the interpreter uses
+     */
+    pub fn native() -> TargetConfig {
+        TargetConfig {
+            byte_size: 1,
+            pointer_size: std::mem::size_of::<usize>(),
+            memory64: std::mem::size_of::<usize>() == 8,
+        }
+    }
+}
+
+/**
+ * Choices about how the code is compiled that don't depend on the target
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// Abort when adding, subtracting, or multiplying integers overflows, instead of wrapping
+    pub checked_arithmetic: bool,
+}
+
 pub struct LowerResults {
     pub statements: Vec<LinearNode>,
     pub statements_ty: Option<PhysicalType>,
@@ -131,22 +301,30 @@ pub struct LowerResults {
 
 pub fn lower_code(
     sources: Vec<SourceFile>,
-    byte_size: usize,
-    pointer_size: usize,
+    target: &TargetConfig,
+) -> Result<LowerResults, CompileError> {
+    lower_code_with_options(sources, target, &CompileOptions::default())
+}
+
+pub fn lower_code_with_options(
+    sources: Vec<SourceFile>,
+    target: &TargetConfig,
+    options: &CompileOptions,
 ) -> Result<LowerResults, CompileError> {
     let single_source = sources.len() == 1;
 
     let CompilationResults {
         modules,
         mut declarations,
-    } = check_types(sources)?;
+        warnings: _,
+    } = check_types_with_options(sources, options)?;
 
     let mut type_layouts = HashMap::new();
     layout_types(
         &declarations.id_to_decl,
         &mut type_layouts,
-        byte_size,
-        pointer_size,
+        target.byte_size,
+        target.pointer_size,
     );
 
     let mut statements = Vec::new();
@@ -157,12 +335,18 @@ pub fn lower_code(
     let mut linear_context = LinearContext {
         layouts: &type_layouts,
         constant_data_region: &mut constant_data,
+        string_literal_offsets: HashMap::new(),
         indirect_function_types: &mut indirect_function_types,
-        byte_size,
-        pointer_size,
-        module: FileDeclarations::new(),
+        byte_size: target.byte_size,
+        pointer_size: target.pointer_size,
+        checked_arithmetic: options.checked_arithmetic,
+        module: declarations.new_file(),
+        invalid_lvalues: Vec::new(),
     };
 
+    // Modules are visited in a fixed order so function indices don't change between builds
+    let mut modules: Vec<_> = modules.into_iter().collect();
+    modules.sort_by_key(|(name, _)| *name);
     for (name, module) in modules {
         if name == "main" || single_source {
             statements.push(module.top_level_statements);
@@ -177,6 +361,9 @@ pub fn lower_code(
         return_ty => Some(expr_ty_to_physical(return_ty)),
     });
     let statements = linear_context.linearize_nodes(statements);
+    if let Some(provenance) = linear_context.invalid_lvalues.first() {
+        return Err(CompileError::InvalidLvalue(provenance.clone()));
+    }
 
     for (expr, fn_id) in indirect_function_types {
         let ExpressionType::FunctionReference {
@@ -195,6 +382,7 @@ pub fn lower_code(
                 returns: *returns,
                 is_associated: false,
                 is_coroutine: false,
+                is_const: false,
                 provenance: None,
             },
         );
@@ -210,74 +398,292 @@ pub fn lower_code(
     })
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct CompilationResults {
     pub modules: HashMap<&'static str, HirModule>,
     pub declarations: DeclarationContext,
+    pub warnings: Vec<CompileWarning>,
 }
 
 pub fn check_types(sources: Vec<SourceFile>) -> Result<CompilationResults, CompileError> {
+    check_types_with_options(sources, &CompileOptions::default())
+}
+
+/**
+ * Typecheck the sources, evaluating constants the way the options say the rest of the code will
+ * run
+ */
+pub fn check_types_with_options(
+    sources: Vec<SourceFile>,
+    options: &CompileOptions,
+) -> Result<CompilationResults, CompileError> {
+    check_types_recording(sources, options, None, None)
+}
+
+/**
+ * Typecheck the sources again after one module was edited, reusing the declarations from the last
+ * time they were checked. Only the edited module is declared again, unless something the other
+ * modules can see changed, in which case every module is.
+ */
+pub fn recheck_types(
+    sources: Vec<SourceFile>,
+    edited: &'static str,
+    declarations: DeclarationContext,
+) -> Result<CompilationResults, CompileError> {
+    check_types_recording(
+        sources,
+        &CompileOptions::default(),
+        Some((edited, declarations)),
+        None,
+    )
+}
+
+/**
+ * Typecheck the sources the same way as check_types, also recording how long each phase of the
+ * compile took
+ *
+ * ```
+ * use brick::{check_types_with_stats, SourceFile};
+ *
+ * let (_, stats) = check_types_with_stats(vec![SourceFile {
+ *     filename: "main.brick",
+ *     module_name: "main",
+ *     contents: "fn double(x: i32): i32 { x * 2 }\ndouble(4)".to_string(),
+ * }])
+ * .unwrap();
+ * assert_eq!(stats.modules[0].module_name, "main");
+ * assert!(stats.phase_total() <= stats.total);
+ * ```
+ */
+pub fn check_types_with_stats(
+    sources: Vec<SourceFile>,
+) -> Result<(CompilationResults, CompileStats), CompileError> {
+    let start = Instant::now();
+    let mut stats = CompileStats::default();
+    let results =
+        check_types_recording(sources, &CompileOptions::default(), None, Some(&mut stats))?;
+    stats.total = start.elapsed();
+
+    Ok((results, stats))
+}
+
+fn check_types_recording(
+    mut sources: Vec<SourceFile>,
+    options: &CompileOptions,
+    previous: Option<(&'static str, DeclarationContext)>,
+    mut stats: Option<&mut CompileStats>,
+) -> Result<CompilationResults, CompileError> {
+    // The edited module's files go first, so they can be declared again on their own
+    let previous = previous.map(|(edited, declarations)| {
+        sources.sort_by_key(|source| source.module_name != edited);
+        let edited_files = sources
+            .iter()
+            .filter(|source| source.module_name == edited)
+            .count();
+        (edited_files, declarations)
+    });
+
     let parse_arena = Arena::new();
     // TODO: return more than one parse error
-    let modules: Vec<_> = sources
-        .into_iter()
-        .map(
-            |SourceFile {
-                 filename,
-                 module_name,
-                 contents,
-             }| {
-                parse_file(&parse_arena, filename, contents).map(|ast| (module_name, ast))
-            },
-        )
-        .collect::<Result<_, _>>()?;
+    let mut modules = Vec::with_capacity(sources.len());
+    for SourceFile {
+        filename,
+        module_name,
+        contents,
+    } in sources
+    {
+        let (ast, lexing, parsing) =
+            parse_file_recording(&parse_arena, filename, contents, stats.is_some())?;
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.modules.push(ModuleStats {
+                module_name,
+                lexing,
+                parsing,
+                ..ModuleStats::default()
+            });
+        }
+        modules.push((module_name, ast));
+    }
 
-    typecheck_module(&modules[..])
+    typecheck_module_recording(&modules[..], options, previous, stats)
 }
 
 pub fn typecheck_module<'a>(
     contents: &'a [(&'static str, Vec<AstNode<'a>>)],
+) -> Result<CompilationResults, CompileError> {
+    typecheck_module_recording(contents, &CompileOptions::default(), None, None)
+}
+
+/**
+ * What checking a single module produces, before the modules are combined
+ */
+struct CheckedModule {
+    name: &'static str,
+    ir: HirModule,
+    warnings: Vec<CompileWarning>,
+    usage: FunctionUsage,
+    typechecking: Duration,
+    lowering: Duration,
+}
+
+/**
+ * When recording, the stats already have an entry for each module, in the same order as the
+ * contents. Previous declarations come with how many of the contents, at the start, are the files
+ * of the edited module.
+ */
+fn typecheck_module_recording<'a>(
+    contents: &'a [(&'static str, Vec<AstNode<'a>>)],
+    options: &CompileOptions,
+    previous: Option<(usize, DeclarationContext)>,
+    mut stats: Option<&mut CompileStats>,
 ) -> Result<CompilationResults, CompileError> {
     use rayon::prelude::*;
 
-    let declarations = DeclarationContext::new(contents)?;
-    validate_types(&declarations)?;
+    let record = stats.is_some();
+    let (declarations, resolution) = timed(record, || -> Result<_, CompileError> {
+        let updated = previous.and_then(|(edited_files, mut declarations)| {
+            let edited = &contents[..edited_files];
+            let contained = edited
+                .first()
+                .is_some_and(|(name, _)| declarations.files.contains_key(name))
+                && matches!(
+                    declarations.update_module(edited),
+                    Ok(ModuleUpdate::Contained)
+                );
+            contained.then_some(declarations)
+        });
+        // If the update failed or reached other modules, everything is declared again
+        let declarations = match updated {
+            Some(declarations) => declarations,
+            None => DeclarationContext::new(contents)?,
+        };
+        validate_types(&declarations)?;
+        Ok(declarations)
+    });
+    let declarations = declarations?;
 
-    let module_results = contents
+    let typecheck_results = contents
         .par_iter()
-        .map(
-            |(name, contents)| -> Result<(&'static str, HirModule), TypecheckError> {
+        .map(|(name, contents)| {
+            let (checked, typechecking) = timed(record, || {
                 let types = typecheck(&contents[..], name, &declarations)?;
-                let ir = lower_module(types, &declarations);
-                Ok((name, ir))
-            },
-        )
+                let mut warnings = warnings::find_unused_parameters(&types);
+                warnings.extend(warnings::find_discarded_loop_values(&types));
+                warnings.extend(warnings::find_float_equality(&types));
+                let usage = warnings::function_usage(&contents[..], &types);
+                Ok::<_, TypecheckError>((types, warnings, usage))
+            });
+            checked.map(|(types, warnings, usage)| (*name, types, warnings, usage, typechecking))
+        })
         .collect::<Vec<_>>();
-    let mut modules = HashMap::new();
+    let mut typechecked = Vec::with_capacity(typecheck_results.len());
     let mut typecheck_errors = Ok(());
-    for module_result in module_results {
-        if let Ok((name, module)) = module_result {
-            modules.insert(name, module);
-        } else {
-            multi_error::merge_results(&mut typecheck_errors, module_result.map(|_| {}));
+    for result in typecheck_results {
+        match result {
+            Ok(module) => typechecked.push(module),
+            Err(err) => multi_error::merge_results(&mut typecheck_errors, Err(err)),
         }
     }
     typecheck_errors?;
 
-    let mut lifetime_errors = Ok(());
-    for module in modules.values_mut() {
-        multi_error::merge_results(
-            &mut lifetime_errors,
-            borrowck::borrow_check(&declarations, module),
-        );
+    // Struct literals can leave out defaulted fields of structs from any module
+    let field_defaults: FieldDefaults = typechecked
+        .iter_mut()
+        .flat_map(|(_, types, _, _, _)| std::mem::take(&mut types.field_defaults))
+        .collect();
+    let module_results = typechecked
+        .into_par_iter()
+        .map(|(name, types, warnings, usage, typechecking)| {
+            let (ir, lowering) = timed(record, || {
+                lower_module(types, &declarations, &field_defaults)
+            });
+            CheckedModule {
+                name,
+                ir,
+                warnings,
+                usage,
+                typechecking,
+                lowering,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut modules = HashMap::new();
+    let mut warnings = Vec::new();
+    let mut function_usages = Vec::new();
+    for (index, module) in module_results.into_iter().enumerate() {
+        modules.insert(module.name, module.ir);
+        warnings.extend(module.warnings);
+        function_usages.push(module.usage);
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.modules[index].typechecking = module.typechecking;
+            stats.modules[index].lowering = module.lowering;
+        }
     }
+    warnings.extend(warnings::find_unused_functions(&function_usages[..]));
+
+    let (lifetime_errors, borrow_checking) = timed(record, || {
+        let mut lifetime_errors = Ok(());
+        for module in modules.values_mut() {
+            multi_error::merge_results(
+                &mut lifetime_errors,
+                borrowck::borrow_check(&declarations, module),
+            );
+        }
+        lifetime_errors
+    });
     lifetime_errors?;
 
+    let (const_result, const_evaluation) = timed(record, || -> Result<(), CompileError> {
+        const_evaluation::fold_const_evaluations(&mut modules, &declarations, options)?;
+        const_evaluation::fold_string_concats(&mut modules);
+        Ok(())
+    });
+    const_result?;
+
+    if let Some(stats) = stats {
+        stats.resolution = resolution;
+        stats.borrow_checking = borrow_checking;
+        stats.const_evaluation = const_evaluation;
+    }
+
     Ok(CompilationResults {
         modules,
         declarations,
+        warnings,
     })
 }
 
+/**
+ * Typecheck the sources and lower each module to HIR, without going on to the linear IR. The HIR
+ * has already been through every desugaring pass, so it's what the later stages see.
+ *
+ * ```
+ * use brick::{lower_to_hir, HirNodeValue, SourceFile};
+ *
+ * let modules = lower_to_hir(vec![SourceFile {
+ *     filename: "main.brick",
+ *     module_name: "main",
+ *     contents: "fn double(x: i32): i32 { x * 2 }\ndouble(4)".to_string(),
+ * }])
+ * .unwrap();
+ * let module = &modules["main"];
+ * let double = &module.functions[0];
+ * assert_eq!(double.name.as_deref(), Some("double"));
+ *
+ * let HirNodeValue::Sequence(body) = &double.body.value else {
+ *     panic!("function bodies are sequences");
+ * };
+ * // Parameters are bound at the start of the body, and the returned value is at the end
+ * assert!(matches!(&body[0].value, HirNodeValue::Parameter(0, _)));
+ * assert!(matches!(&body.last().unwrap().value, HirNodeValue::Arithmetic(..)));
+ * ```
+ */
+pub fn lower_to_hir(
+    sources: Vec<SourceFile>,
+) -> Result<HashMap<&'static str, HirModule>, CompileError> {
+    Ok(check_types(sources)?.modules)
+}
+
 pub fn parse_file<'a>(
     arena: &'a Arena<AstNode<'a>>,
     filename: &'static str,
@@ -288,3 +694,27 @@ pub fn parse_file<'a>(
 
     Ok(parsed_module)
 }
+
+/**
+ * Parse the file, timing lexing and parsing separately if stats are being recorded
+ */
+fn parse_file_recording<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    filename: &'static str,
+    contents: String,
+    record: bool,
+) -> Result<(Vec<AstNode<'a>>, Duration, Duration), CompileError> {
+    if !record {
+        let ast = parse_file(arena, filename, contents)?;
+        return Ok((ast, Duration::ZERO, Duration::ZERO));
+    }
+
+    // Tokens are normally lexed as the parser asks for them, so they're collected up front to
+    // time the two apart
+    let (tokens, lexing) = timed(record, || {
+        tokenizer::lex(filename, contents).collect::<Vec<_>>()
+    });
+    let (parsed_module, parsing) = timed(record, || parser::parse(arena, tokens.into_iter()));
+
+    Ok((parsed_module?, lexing, parsing))
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
 
 use bytemuck::Zeroable;
+use thiserror::Error;
 
 use crate::{
     declaration_context::{FileDeclarations, IntrinsicFunction, TypeID},
@@ -176,9 +177,9 @@ impl LinearNode {
         }
     }
 
-    fn abort() -> LinearNode {
+    fn abort(reason: AbortReason) -> LinearNode {
         LinearNode {
-            value: LinearNodeValue::Abort,
+            value: LinearNodeValue::Abort(reason),
             provenance: None,
         }
     }
@@ -212,9 +213,11 @@ impl LinearNode {
             LinearNodeValue::WriteMemory { .. }
             | LinearNodeValue::VariableInit(_, _)
             | LinearNodeValue::VariableDestroy(_)
-            | LinearNodeValue::Break
+            | LinearNodeValue::Break(_)
+            | LinearNodeValue::Continue(_)
             | LinearNodeValue::Loop(_)
-            | LinearNodeValue::Abort
+            | LinearNodeValue::Abort(_)
+            | LinearNodeValue::AssertionFailed(_)
             | LinearNodeValue::Goto(_)
             | LinearNodeValue::GotoLabel(_)
             | LinearNodeValue::Switch { .. }
@@ -235,7 +238,10 @@ impl LinearNode {
                 RuntimeFunction::StringConcat => {
                     Some(PhysicalType::Collection(PhysicalCollection::String))
                 }
-                RuntimeFunction::Memcpy | RuntimeFunction::Dealloc => None,
+                RuntimeFunction::Memcpy | RuntimeFunction::Dealloc | RuntimeFunction::RngSeed => {
+                    None
+                }
+                RuntimeFunction::RngNext => Some(PhysicalType::Primitive(PhysicalPrimitive::Int32)),
                 RuntimeFunction::Realloc | RuntimeFunction::Alloc { .. } => {
                     Some(PhysicalType::Primitive(PhysicalPrimitive::PointerSize))
                 }
@@ -243,9 +249,9 @@ impl LinearNode {
             LinearNodeValue::Return(child) => {
                 child.as_ref().and_then(|child| child.ty(function_returns))
             }
-            LinearNodeValue::Cast { to: prim, .. } | LinearNodeValue::Arithmetic(_, prim, _, _) => {
-                Some(PhysicalType::Primitive(*prim))
-            }
+            LinearNodeValue::Cast { to: prim, .. }
+            | LinearNodeValue::Arithmetic(_, prim, _, _)
+            | LinearNodeValue::Math(_, prim, _) => Some(PhysicalType::Primitive(*prim)),
             LinearNodeValue::Comparison(_, _, _, _)
             | LinearNodeValue::BinaryLogical(_, _, _)
             | LinearNodeValue::Byte(_)
@@ -297,11 +303,15 @@ pub enum LinearNodeValue {
         Option<Vec<LinearNode>>,
         Option<PhysicalType>,
     ),
-    // TODO: labelled breaks?
-    Break,
+    /// Jump out of a loop, counting outwards from the innermost one at 0
+    Break(u32),
+    /// Jump back to the start of a loop, counting the same way as breaks
+    Continue(u32),
     Loop(Vec<LinearNode>),
     // TODO: stack unwind?
-    Abort,
+    Abort(AbortReason),
+    /// Like Abort, but reports a failed assertion with an optional string message
+    AssertionFailed(Option<Box<LinearNode>>),
     Goto(Box<LinearNode>),
     GotoLabel(usize),
     Switch {
@@ -330,6 +340,7 @@ pub enum LinearNodeValue {
     ),
     BinaryLogical(BinaryLogicalOp, Box<LinearNode>, Box<LinearNode>),
     UnaryLogical(UnaryLogicalOp, Box<LinearNode>),
+    Math(MathFunction, PhysicalPrimitive, Vec<LinearNode>),
     Cast {
         value: Box<LinearNode>,
         from: PhysicalPrimitive,
@@ -347,6 +358,73 @@ pub enum LinearNodeValue {
     Debug(Box<LinearNode>),
 }
 
+/**
+ * Why a program stopped running. Each reason has a stable numeric code, which is how the WASM
+ * backend reports it to the host.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Error)]
+pub enum AbortReason {
+    #[error("null value unwrapped")]
+    NullUnwrap,
+    #[error("array index out of bounds")]
+    ArrayIndexOutOfBounds,
+    #[error("key not found in dictionary")]
+    DictKeyNotFound,
+    #[error("integer out of range for a char")]
+    CharOutOfRange,
+    #[error("negative exponent")]
+    NegativeExponent,
+    #[error("assertion failed")]
+    AssertionFailed,
+    #[error("generator resumed after it finished")]
+    GeneratorFinished,
+    #[error("integer overflow")]
+    IntegerOverflow,
+}
+
+impl AbortReason {
+    pub fn code(self) -> i32 {
+        match self {
+            AbortReason::NullUnwrap => 1,
+            AbortReason::ArrayIndexOutOfBounds => 2,
+            AbortReason::DictKeyNotFound => 3,
+            AbortReason::CharOutOfRange => 4,
+            AbortReason::NegativeExponent => 5,
+            AbortReason::AssertionFailed => 6,
+            AbortReason::GeneratorFinished => 7,
+            AbortReason::IntegerOverflow => 8,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<AbortReason> {
+        Some(match code {
+            1 => AbortReason::NullUnwrap,
+            2 => AbortReason::ArrayIndexOutOfBounds,
+            3 => AbortReason::DictKeyNotFound,
+            4 => AbortReason::CharOutOfRange,
+            5 => AbortReason::NegativeExponent,
+            6 => AbortReason::AssertionFailed,
+            7 => AbortReason::GeneratorFinished,
+            8 => AbortReason::IntegerOverflow,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MathFunction {
+    // (x) -> x
+    Sqrt,
+    // (x) -> x
+    Abs,
+    // (x, x) -> x
+    Min,
+    // (x, x) -> x
+    Max,
+    // (x, i32) -> x, aborts on a negative exponent
+    Pow,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum RuntimeFunction {
     // (alloc_size) -> ptr
@@ -359,8 +437,18 @@ pub enum RuntimeFunction {
     StringConcat,
     // (dest, src, size) -> void
     Memcpy,
+    // (i64) -> void
+    RngSeed,
+    // () -> i32
+    RngNext,
 }
 
+/**
+ * The xorshift state the random number generator starts in before it's seeded. A zero state
+ * would only ever produce zeroes, so seeding with zero starts here too.
+ */
+pub const RNG_DEFAULT_STATE: u64 = 0x9E37_79B9_7F4A_7C15;
+
 impl LinearNode {
     pub fn visit(&self, mut callback: impl FnMut(&LinearNode)) {
         self.visit_recursive(&mut callback);
@@ -383,7 +471,8 @@ impl LinearNode {
             | LinearNodeValue::Goto(child)
             | LinearNodeValue::UnaryLogical(_, child)
             | LinearNodeValue::Cast { value: child, .. }
-            | LinearNodeValue::Return(Some(child)) => callback(child),
+            | LinearNodeValue::Return(Some(child))
+            | LinearNodeValue::AssertionFailed(Some(child)) => callback(child),
             LinearNodeValue::WriteMemory {
                 location: a,
                 value: b,
@@ -410,6 +499,7 @@ impl LinearNode {
             }
             LinearNodeValue::Call(_, children)
             | LinearNodeValue::RuntimeCall(_, children)
+            | LinearNodeValue::Math(_, _, children)
             | LinearNodeValue::Loop(children)
             | LinearNodeValue::Sequence(children) => {
                 children.iter().for_each(callback);
@@ -430,12 +520,14 @@ impl LinearNode {
             | LinearNodeValue::VariableInit(_, _)
             | LinearNodeValue::VariableDestroy(_)
             | LinearNodeValue::VariableLocation(_)
-            | LinearNodeValue::Break
+            | LinearNodeValue::Break(_)
+            | LinearNodeValue::Continue(_)
             | LinearNodeValue::GotoLabel(_)
-            | LinearNodeValue::Abort
+            | LinearNodeValue::Abort(_)
             | LinearNodeValue::ReadRegister(_)
             | LinearNodeValue::KillRegister(_)
             | LinearNodeValue::Return(None)
+            | LinearNodeValue::AssertionFailed(None)
             | LinearNodeValue::ConstantDataAddress(_) => {}
         }
     }
@@ -461,7 +553,8 @@ impl LinearNode {
             | LinearNodeValue::Goto(child)
             | LinearNodeValue::UnaryLogical(_, child)
             | LinearNodeValue::Cast { value: child, .. }
-            | LinearNodeValue::Return(Some(child)) => callback(child),
+            | LinearNodeValue::Return(Some(child))
+            | LinearNodeValue::AssertionFailed(Some(child)) => callback(child),
             LinearNodeValue::WriteMemory {
                 location: a,
                 value: b,
@@ -488,6 +581,7 @@ impl LinearNode {
             }
             LinearNodeValue::Call(_, children)
             | LinearNodeValue::RuntimeCall(_, children)
+            | LinearNodeValue::Math(_, _, children)
             | LinearNodeValue::Loop(children)
             | LinearNodeValue::Sequence(children) => {
                 children.iter_mut().for_each(callback);
@@ -508,12 +602,14 @@ impl LinearNode {
             | LinearNodeValue::VariableInit(_, _)
             | LinearNodeValue::VariableDestroy(_)
             | LinearNodeValue::VariableLocation(_)
-            | LinearNodeValue::Break
+            | LinearNodeValue::Break(_)
+            | LinearNodeValue::Continue(_)
             | LinearNodeValue::GotoLabel(_)
-            | LinearNodeValue::Abort
+            | LinearNodeValue::Abort(_)
             | LinearNodeValue::ReadRegister(_)
             | LinearNodeValue::KillRegister(_)
             | LinearNodeValue::Return(None)
+            | LinearNodeValue::AssertionFailed(None)
             | LinearNodeValue::ConstantDataAddress(_) => {}
         }
     }
@@ -522,10 +618,16 @@ impl LinearNode {
 pub struct LinearContext<'a> {
     pub layouts: &'a HashMap<TypeID, DeclaredTypeLayout>,
     pub constant_data_region: &'a mut Vec<u8>,
+    /// Where each string literal was put in the constant data region, so repeats share one copy
+    pub string_literal_offsets: HashMap<String, usize>,
     pub indirect_function_types: &'a mut HashMap<ExpressionType, FunctionID>,
     pub byte_size: usize,
     pub pointer_size: usize,
+    /// Whether integer arithmetic aborts on overflow instead of wrapping
+    pub checked_arithmetic: bool,
     pub module: FileDeclarations,
+    /// Where expressions that can't be assigned to or borrowed ended up in an lvalue position
+    pub invalid_lvalues: Vec<Option<SourceRange>>,
 }
 
 impl<'a> LinearContext<'a> {
@@ -538,10 +640,10 @@ impl<'a> LinearContext<'a> {
             unreachable!()
         };
         let mut body = self.linearize_nodes(block);
-        if let Some(GeneratorProperties {
+        let (params, returns) = if let Some(GeneratorProperties {
             generator_var_id,
             param_var_id,
-            ..
+            ty,
         }) = function.generator
         {
             generator_local_storage::generator_local_storage(
@@ -550,15 +652,31 @@ impl<'a> LinearContext<'a> {
                 param_var_id,
                 &mut body[..],
             );
-        }
-
-        let fn_ty = &declarations.id_to_func[&function.id];
+            // Generator bodies aren't declared, so their signature comes from the generator
+            let ExpressionType::Pointer(_, inner_ty) = &ty else {
+                unreachable!()
+            };
+            let ExpressionType::Generator { yield_ty, param_ty } = inner_ty.as_ref() else {
+                unreachable!()
+            };
+            let mut params = vec![expr_ty_to_physical(&ty)];
+            if param_ty.as_ref() != &ExpressionType::Void {
+                params.push(expr_ty_to_physical(param_ty));
+            }
+            (params, yield_ty.as_ref().clone())
+        } else {
+            let fn_ty = &declarations.id_to_func[&function.id];
+            (
+                fn_ty.params.iter().map(expr_ty_to_physical).collect(),
+                fn_ty.returns.clone(),
+            )
+        };
 
         LinearFunction {
             id: function.id,
             body,
-            params: fn_ty.params.iter().map(expr_ty_to_physical).collect(),
-            returns: match &fn_ty.returns {
+            params,
+            returns: match &returns {
                 ExpressionType::Void | ExpressionType::Unreachable => None,
                 return_ty => Some(expr_ty_to_physical(return_ty)),
             },
@@ -594,26 +712,39 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
         }
         HirNodeValue::CharLiteral(x) => LinearNodeValue::CharLiteral(x),
         HirNodeValue::StringLiteral(string) => {
-            let bytes = string.as_bytes();
-            let offset = ctx.constant_data_region.len();
-            ctx.constant_data_region.extend(bytes);
+            let length = string.len();
+            let offset = match ctx.string_literal_offsets.entry(string) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let offset = ctx.constant_data_region.len();
+                    ctx.constant_data_region.extend(entry.key().as_bytes());
+                    *entry.insert(offset)
+                }
+            };
             LinearNodeValue::Sequence(vec![
                 LinearNode::new(LinearNodeValue::ConstantDataAddress(offset)),
-                LinearNode::size(bytes.len()),
+                LinearNode::size(length),
             ])
         }
 
         HirNodeValue::Arithmetic(op, lhs, rhs) => {
-            let ExpressionType::Primitive(ty) = rhs.ty else {
+            let ExpressionType::Primitive(prim) = rhs.ty else {
                 unreachable!("binoperands must be primitive not {:?}", ty)
             };
-            let ty = primitive_to_physical(ty);
-            LinearNodeValue::Arithmetic(
-                op,
-                ty,
-                Box::new(lower_expression(ctx, *lhs)),
-                Box::new(lower_expression(ctx, *rhs)),
-            )
+            let ty = primitive_to_physical(prim);
+            let lhs = lower_expression(ctx, *lhs);
+            let rhs = lower_expression(ctx, *rhs);
+            if ctx.checked_arithmetic
+                && matches!(prim, PrimitiveType::Int32 | PrimitiveType::Int64)
+                && matches!(
+                    op,
+                    ArithmeticOp::Add | ArithmeticOp::Subtract | ArithmeticOp::Multiply
+                )
+            {
+                checked_arithmetic(op, ty, lhs, rhs)
+            } else {
+                LinearNodeValue::Arithmetic(op, ty, Box::new(lhs), Box::new(rhs))
+            }
         }
         HirNodeValue::Comparison(op, lhs, rhs) => {
             let ExpressionType::Primitive(ty) = rhs.ty else {
@@ -635,6 +766,10 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
         HirNodeValue::UnaryLogical(op, child) => {
             LinearNodeValue::UnaryLogical(op, Box::new(lower_expression(ctx, *child)))
         }
+        // Functions used as values, rather than called directly, become function pointers
+        HirNodeValue::VariableReference(AnyID::Function(fn_id)) => {
+            LinearNodeValue::FunctionID(fn_id)
+        }
         HirNodeValue::VariableReference(id) => {
             let ty = expr_ty_to_physical(&ty);
             LinearNodeValue::ReadMemory {
@@ -781,6 +916,102 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 ty: expr_ty_to_physical(&ty),
             }
         }
+        HirNodeValue::ArraySlice(arr, start, end) => {
+            let ExpressionType::Collection(CollectionType::Array(inner_ty)) = &ty else {
+                unreachable!()
+            };
+            let inner_ty = expr_ty_to_physical(inner_ty.as_ref());
+            let size = inner_ty.size(ctx);
+            let alignment = inner_ty.alignment_ctx(ctx);
+            let arr = lower_expression(ctx, *arr);
+            let start = lower_expression(ctx, *start);
+            let end = lower_expression(ctx, *end);
+
+            let arr_ptr_register = RegisterID::new();
+            let length_register = RegisterID::new();
+            let start_register = RegisterID::new();
+            let end_register = RegisterID::new();
+            let buffer_register = RegisterID::new();
+
+            LinearNodeValue::Sequence(vec![
+                LinearNode::write_multi_register(
+                    arr,
+                    vec![Some(arr_ptr_register), Some(length_register), None],
+                ),
+                LinearNode::write_register(start_register, start),
+                LinearNode::write_register(end_register, end),
+                LinearNode::if_node(
+                    LinearNode::ptr_comparison(
+                        ComparisonOp::GreaterThan,
+                        LinearNode::read_register(start_register),
+                        LinearNode::read_register(end_register),
+                    ),
+                    vec![LinearNode::abort(AbortReason::ArrayIndexOutOfBounds)],
+                    None,
+                    None,
+                ),
+                LinearNode::if_node(
+                    LinearNode::ptr_comparison(
+                        ComparisonOp::GreaterThan,
+                        LinearNode::read_register(end_register),
+                        LinearNode::read_register(length_register),
+                    ),
+                    vec![LinearNode::abort(AbortReason::ArrayIndexOutOfBounds)],
+                    None,
+                    None,
+                ),
+                LinearNode::kill_register(length_register),
+                // Reuse the end register to hold the new length
+                LinearNode::write_register(
+                    end_register,
+                    LinearNode::ptr_arithmetic(
+                        ArithmeticOp::Subtract,
+                        LinearNode::read_register(end_register),
+                        LinearNode::read_register(start_register),
+                    ),
+                ),
+                LinearNode::write_register(
+                    buffer_register,
+                    LinearNode::heap_alloc_var(
+                        LinearNode::ptr_arithmetic(
+                            ArithmeticOp::Multiply,
+                            LinearNode::size(size),
+                            LinearNode::read_register(end_register),
+                        ),
+                        alignment,
+                    ),
+                ),
+                LinearNode::call_runtime(
+                    RuntimeFunction::Memcpy,
+                    vec![
+                        LinearNode::read_register(buffer_register),
+                        LinearNode::ptr_arithmetic(
+                            ArithmeticOp::Add,
+                            LinearNode::read_register(arr_ptr_register),
+                            LinearNode::ptr_arithmetic(
+                                ArithmeticOp::Multiply,
+                                LinearNode::size(size),
+                                LinearNode::read_register(start_register),
+                            ),
+                        ),
+                        LinearNode::ptr_arithmetic(
+                            ArithmeticOp::Multiply,
+                            LinearNode::size(size),
+                            LinearNode::read_register(end_register),
+                        ),
+                    ],
+                ),
+                LinearNode::kill_register(arr_ptr_register),
+                LinearNode::kill_register(start_register),
+                // capacity
+                LinearNode::read_register(end_register),
+                // length
+                LinearNode::read_register(end_register),
+                LinearNode::read_register(buffer_register),
+                LinearNode::kill_register(end_register),
+                LinearNode::kill_register(buffer_register),
+            ])
+        }
         HirNodeValue::If(cond, if_block, else_block) => {
             let cond = lower_expression(ctx, *cond);
             let HirNodeValue::Sequence(if_block) = if_block.value else {
@@ -799,13 +1030,17 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
             let expr = expr.map(|expr| Box::new(lower_expression(ctx, *expr)));
             LinearNodeValue::Return(expr)
         }
+        HirNodeValue::AssertionFailed(message) => {
+            let message = message.map(|message| Box::new(lower_expression(ctx, *message)));
+            LinearNodeValue::AssertionFailed(message)
+        }
         HirNodeValue::While(cond, block) => {
             let cond = lower_expression(ctx, *cond);
             let block = lower_expression(ctx, *block);
             LinearNodeValue::Loop(vec![LinearNode::if_node(
                 cond,
                 vec![block],
-                Some(vec![LinearNode::new(LinearNodeValue::Break)]),
+                Some(vec![LinearNode::new(LinearNodeValue::Break(0))]),
                 None,
             )])
         }
@@ -813,6 +1048,14 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
             let body = lower_expression(ctx, *body);
             LinearNodeValue::Loop(vec![body])
         }
+        HirNodeValue::Break(value, depth) => {
+            assert!(
+                value.is_none(),
+                "break values should be moved out during lowering"
+            );
+            LinearNodeValue::Break(depth as u32)
+        }
+        HirNodeValue::Continue(depth) => LinearNodeValue::Continue(depth as u32),
         HirNodeValue::Parameter(idx, id) => {
             let ty = expr_ty_to_physical(&ty);
             LinearNodeValue::Sequence(vec![
@@ -945,7 +1188,7 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                         LinearNode::read_register(index_register),
                         LinearNode::read_register(length_register),
                     ),
-                    vec![LinearNode::new(LinearNodeValue::Break)],
+                    vec![LinearNode::new(LinearNodeValue::Break(0))],
                     Some(vec![
                         // *(ptr + idx * size) = value
                         LinearNode::write_memory(
@@ -1031,7 +1274,11 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
             )
         }
         HirNodeValue::StructToInterface { value, vtable } => {
-            let mut values = Vec::new();
+            let ExpressionType::InstanceOf(struct_id) = &value.ty else {
+                unreachable!()
+            };
+            // Values are in reverse order, so the tag at the end comes first
+            let mut values = vec![LinearNode::size(struct_id.runtime_tag())];
 
             let ExpressionType::InstanceOf(ty_id) = &ty else {
                 unreachable!()
@@ -1054,6 +1301,82 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
 
             LinearNodeValue::Sequence(values)
         }
+        HirNodeValue::InterfaceDowncast(interface, struct_id) => {
+            let ExpressionType::InstanceOf(interface_id) = &interface.ty else {
+                unreachable!()
+            };
+            let Some(DeclaredTypeLayout {
+                value: TypeLayoutValue::Interface(fields),
+                ..
+            }) = ctx.layouts.get(interface_id)
+            else {
+                unreachable!()
+            };
+            // The tag comes after the pointer and the vtable
+            let tag_offset = (fields.len() + 1) * ctx.pointer_size;
+            let struct_ty = PhysicalType::Referenced(struct_id);
+            let result_ty = PhysicalType::Nullable(Box::new(struct_ty.clone()));
+            let mut null = Vec::new();
+            result_ty.zeroed(ctx, &mut null);
+
+            let (location, offset) = lower_lvalue(ctx, *interface);
+            let interface_register = RegisterID::new();
+            let temp_var_id = VariableID::new();
+            let result_location =
+                || LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id));
+
+            LinearNodeValue::Sequence(vec![
+                LinearNode::new(LinearNodeValue::VariableInit(
+                    temp_var_id,
+                    result_ty.clone(),
+                )),
+                LinearNode::write_register(
+                    interface_register,
+                    LinearNode::ptr_arithmetic(
+                        ArithmeticOp::Add,
+                        location,
+                        LinearNode::size(offset),
+                    ),
+                ),
+                LinearNode::if_node(
+                    LinearNode::ptr_comparison(
+                        ComparisonOp::EqualTo,
+                        LinearNode::read_memory(
+                            LinearNode::read_register(interface_register),
+                            tag_offset,
+                            PhysicalType::Primitive(PhysicalPrimitive::PointerSize),
+                        ),
+                        LinearNode::size(struct_id.runtime_tag()),
+                    ),
+                    vec![LinearNode::write_memory(
+                        result_location(),
+                        0,
+                        result_ty.clone(),
+                        LinearNode::new(LinearNodeValue::Sequence(vec![
+                            LinearNode::read_memory(
+                                LinearNode::read_memory(
+                                    LinearNode::read_register(interface_register),
+                                    0,
+                                    PhysicalType::Primitive(PhysicalPrimitive::PointerSize),
+                                ),
+                                0,
+                                struct_ty,
+                            ),
+                            LinearNode::bool_value(true),
+                        ])),
+                    )],
+                    Some(vec![LinearNode::write_memory(
+                        result_location(),
+                        0,
+                        result_ty.clone(),
+                        LinearNode::new(LinearNodeValue::Sequence(null)),
+                    )]),
+                    provenance.clone(),
+                ),
+                LinearNode::kill_register(interface_register),
+                LinearNode::read_memory(result_location(), 0, result_ty),
+            ])
+        }
         HirNodeValue::NumericCast { value, from, to } => LinearNodeValue::Cast {
             value: Box::new(lower_expression(ctx, *value)),
             from: primitive_to_physical(from),
@@ -1156,10 +1479,15 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
             let padding = union_ty.size()
                 - UNION_TAG_SIZE.size(ctx.pointer_size)
                 - variant_ty.as_ref().map(|ty| ty.size(ctx)).unwrap_or(0);
+            // Fill the space the largest variant would take with zeroes, in words where they fit
+            // and bytes for the rest, so the padding is exactly as large as what it fills
             let mut values = Vec::new();
             for _ in 0..(padding / 4) {
                 values.push(LinearNode::new(LinearNodeValue::Int(0)));
             }
+            for _ in 0..(padding % 4) {
+                values.push(LinearNode::new(LinearNodeValue::Byte(0)));
+            }
             if let Some(value) = value {
                 values.push(lower_expression(ctx, *value));
             }
@@ -1181,6 +1509,46 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                     lhs_ty,
                     lhs,
                 ),
+                LinearNode::if_node_value(
+                    LinearNode::read_memory(
+                        LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id)),
+                        0,
+                        PhysicalType::Primitive(PhysicalPrimitive::Byte),
+                    ),
+                    vec![if matches!(&ty, ExpressionType::Nullable(_)) {
+                        // The result is nullable too, so the left side is used as-is
+                        LinearNode::read_memory(
+                            LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id)),
+                            0,
+                            result_ty.clone(),
+                        )
+                    } else {
+                        LinearNode::read_memory(
+                            LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id)),
+                            NULL_TAG_SIZE.size(ctx.pointer_size),
+                            result_ty.clone(),
+                        )
+                    }],
+                    Some(vec![rhs]),
+                    provenance.clone(),
+                    result_ty,
+                ),
+            ])
+        }
+        HirNodeValue::NullableUnwrap(value) => {
+            let result_ty = expr_ty_to_physical(&ty);
+            let value_ty = expr_ty_to_physical(&value.ty);
+            let value = lower_expression(ctx, *value);
+            let temp_var_id = VariableID::new();
+
+            LinearNodeValue::Sequence(vec![
+                LinearNode::new(LinearNodeValue::VariableInit(temp_var_id, value_ty.clone())),
+                LinearNode::write_memory(
+                    LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id)),
+                    0,
+                    value_ty,
+                    value,
+                ),
                 LinearNode::if_node_value(
                     LinearNode::read_memory(
                         LinearNode::new(LinearNodeValue::VariableLocation(temp_var_id)),
@@ -1192,10 +1560,11 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                         NULL_TAG_SIZE.size(ctx.pointer_size),
                         result_ty.clone(),
                     )],
-                    Some(vec![rhs]),
+                    Some(vec![LinearNode::abort(AbortReason::NullUnwrap)]),
                     provenance.clone(),
                     result_ty,
                 ),
+                LinearNode::new(LinearNodeValue::VariableDestroy(temp_var_id)),
             ])
         }
         HirNodeValue::MakeNullable(value) => LinearNodeValue::Sequence(vec![
@@ -1230,6 +1599,27 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 value: Box::new(inserted),
             }
         }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::ArrayContains, mut args) => {
+            let value = args.pop().unwrap();
+            let arr = args.pop().unwrap();
+            let ExpressionType::Pointer(_, arr_ty) = &arr.ty else {
+                unreachable!()
+            };
+            let ExpressionType::Collection(CollectionType::Array(item_ty)) = arr_ty.as_ref() else {
+                unreachable!()
+            };
+
+            let item_ty = expr_ty_to_physical(item_ty);
+            let item_size = item_ty.size(ctx);
+            let PhysicalType::Primitive(item_ty) = item_ty else {
+                unreachable!()
+            };
+
+            let arr = lower_expression(ctx, arr);
+            let value = lower_expression(ctx, value);
+
+            array_contains(arr, value, item_ty, item_size)
+        }
         HirNodeValue::IntrinsicCall(IntrinsicFunction::ArrayFree, mut args) => {
             let arr = args.pop().unwrap();
             let array = lower_expression(ctx, arr);
@@ -1382,6 +1772,110 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 LinearNode::kill_register(entry_pointer_output),
             ])
         }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::DictionaryKeys, mut args) => {
+            let dict = args.pop().unwrap();
+            let ExpressionType::Pointer(_, dict_ty) = &dict.ty else {
+                unreachable!()
+            };
+            let ExpressionType::Collection(CollectionType::Dict(key_ty, value_ty)) =
+                dict_ty.as_ref()
+            else {
+                unreachable!()
+            };
+
+            let key_ty = expr_ty_to_physical(key_ty);
+            let value_ty = expr_ty_to_physical(value_ty);
+            let key_size = key_ty.size(ctx);
+            let alignment = key_ty.alignment_ctx(ctx);
+            let entry_size = key_size + value_ty.size(ctx);
+
+            let dict = lower_expression(ctx, dict);
+
+            let entries = RegisterID::new();
+            let length = RegisterID::new();
+            let keys = RegisterID::new();
+            let index = RegisterID::new();
+
+            // Entries are stored in insertion order, so copying them front-to-back yields the keys
+            // in the order they were first inserted
+            LinearNodeValue::Sequence(vec![
+                LinearNode::write_multi_register(
+                    LinearNode::read_memory(
+                        dict,
+                        0,
+                        PhysicalType::Collection(PhysicalCollection::Dict),
+                    ),
+                    vec![Some(entries), Some(length), None],
+                ),
+                LinearNode::write_register(
+                    keys,
+                    LinearNode::heap_alloc_var(
+                        LinearNode::ptr_arithmetic(
+                            ArithmeticOp::Multiply,
+                            LinearNode::size(key_size),
+                            LinearNode::read_register(length),
+                        ),
+                        alignment,
+                    ),
+                ),
+                LinearNode::write_register(index, LinearNode::size(0)),
+                LinearNode::new(LinearNodeValue::Loop(vec![LinearNode::if_node(
+                    LinearNode::ptr_comparison(
+                        ComparisonOp::EqualTo,
+                        LinearNode::read_register(index),
+                        LinearNode::read_register(length),
+                    ),
+                    vec![LinearNode::new(LinearNodeValue::Break(0))],
+                    Some(vec![
+                        // keys[index] = entries[index].key
+                        LinearNode::write_memory(
+                            LinearNode::ptr_arithmetic(
+                                ArithmeticOp::Add,
+                                LinearNode::read_register(keys),
+                                LinearNode::ptr_arithmetic(
+                                    ArithmeticOp::Multiply,
+                                    LinearNode::size(key_size),
+                                    LinearNode::read_register(index),
+                                ),
+                            ),
+                            0,
+                            key_ty.clone(),
+                            LinearNode::read_memory(
+                                LinearNode::ptr_arithmetic(
+                                    ArithmeticOp::Add,
+                                    LinearNode::read_register(entries),
+                                    LinearNode::ptr_arithmetic(
+                                        ArithmeticOp::Multiply,
+                                        LinearNode::size(entry_size),
+                                        LinearNode::read_register(index),
+                                    ),
+                                ),
+                                0,
+                                key_ty,
+                            ),
+                        ),
+                        LinearNode::write_register(
+                            index,
+                            LinearNode::ptr_arithmetic(
+                                ArithmeticOp::Add,
+                                LinearNode::read_register(index),
+                                LinearNode::size(1),
+                            ),
+                        ),
+                    ]),
+                    provenance.clone(),
+                )])),
+                // capacity
+                LinearNode::read_register(length),
+                // length
+                LinearNode::read_register(length),
+                LinearNode::read_register(keys),
+                LinearNode::kill_register(entries),
+                LinearNode::kill_register(length),
+                LinearNode::kill_register(keys),
+                LinearNode::kill_register(index),
+            ])
+        }
         HirNodeValue::IntrinsicCall(IntrinsicFunction::RcClone, mut args) => {
             let rc = args.remove(0);
             let ptr_register = RegisterID::new();
@@ -1527,6 +2021,164 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 value: Box::new(argument),
             }
         }
+        HirNodeValue::IntrinsicCall(
+            intrinsic @ (IntrinsicFunction::CharIsDigit | IntrinsicFunction::CharIsAlpha),
+            mut args,
+        ) => {
+            let value = lower_expression(ctx, args.remove(0));
+            let char_register = RegisterID::new();
+            let in_range = |start: char, end: char| {
+                LinearNode::new(LinearNodeValue::BinaryLogical(
+                    BinaryLogicalOp::BooleanAnd,
+                    Box::new(LinearNode::ptr_comparison(
+                        ComparisonOp::GreaterEqualThan,
+                        LinearNode::read_register(char_register),
+                        LinearNode::size(start as usize),
+                    )),
+                    Box::new(LinearNode::ptr_comparison(
+                        ComparisonOp::LessEqualThan,
+                        LinearNode::read_register(char_register),
+                        LinearNode::size(end as usize),
+                    )),
+                ))
+            };
+            let test = if intrinsic == IntrinsicFunction::CharIsDigit {
+                in_range('0', '9')
+            } else {
+                LinearNode::new(LinearNodeValue::BinaryLogical(
+                    BinaryLogicalOp::BooleanOr,
+                    Box::new(in_range('a', 'z')),
+                    Box::new(in_range('A', 'Z')),
+                ))
+            };
+
+            LinearNodeValue::Sequence(vec![
+                LinearNode::write_register(
+                    char_register,
+                    LinearNode::new(LinearNodeValue::Cast {
+                        value: Box::new(value),
+                        from: PhysicalPrimitive::Byte,
+                        to: PhysicalPrimitive::PointerSize,
+                    }),
+                ),
+                test,
+                LinearNode::kill_register(char_register),
+            ])
+        }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::RngSeed, mut args) => {
+            LinearNodeValue::RuntimeCall(
+                RuntimeFunction::RngSeed,
+                vec![lower_expression(ctx, args.remove(0))],
+            )
+        }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::RngNext, _) => {
+            LinearNodeValue::RuntimeCall(RuntimeFunction::RngNext, Vec::new())
+        }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::CharToInt, mut args) => {
+            LinearNodeValue::Cast {
+                value: Box::new(lower_expression(ctx, args.remove(0))),
+                from: PhysicalPrimitive::Byte,
+                to: PhysicalPrimitive::Int32,
+            }
+        }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::IntToChar, mut args) => {
+            // Chars are a single byte, so anything outside of 0-255 aborts
+            let value = lower_expression(ctx, args.remove(0));
+            let temp_id = VariableID::new();
+            let int_ty = PhysicalType::Primitive(PhysicalPrimitive::Int32);
+            let read_value = || {
+                LinearNode::read_memory(
+                    LinearNode::new(LinearNodeValue::VariableLocation(temp_id)),
+                    0,
+                    int_ty.clone(),
+                )
+            };
+
+            LinearNodeValue::Sequence(vec![
+                LinearNode::new(LinearNodeValue::VariableInit(temp_id, int_ty.clone())),
+                LinearNode::write_memory(
+                    LinearNode::new(LinearNodeValue::VariableLocation(temp_id)),
+                    0,
+                    int_ty.clone(),
+                    value,
+                ),
+                LinearNode::if_node(
+                    LinearNode::new(LinearNodeValue::BinaryLogical(
+                        BinaryLogicalOp::BooleanOr,
+                        Box::new(LinearNode::new(LinearNodeValue::Comparison(
+                            ComparisonOp::LessThan,
+                            PhysicalPrimitive::Int32,
+                            Box::new(read_value()),
+                            Box::new(LinearNode::new(LinearNodeValue::Int(0))),
+                        ))),
+                        Box::new(LinearNode::new(LinearNodeValue::Comparison(
+                            ComparisonOp::GreaterThan,
+                            PhysicalPrimitive::Int32,
+                            Box::new(read_value()),
+                            Box::new(LinearNode::new(LinearNodeValue::Int(u8::MAX as i64))),
+                        ))),
+                    )),
+                    vec![LinearNode::abort(AbortReason::CharOutOfRange)],
+                    None,
+                    provenance.clone(),
+                ),
+                LinearNode::new(LinearNodeValue::Cast {
+                    value: Box::new(read_value()),
+                    from: PhysicalPrimitive::Int32,
+                    to: PhysicalPrimitive::Byte,
+                }),
+                LinearNode::new(LinearNodeValue::VariableDestroy(temp_id)),
+            ])
+        }
+        HirNodeValue::IntrinsicCall(
+            intrinsic @ (IntrinsicFunction::Sqrt
+            | IntrinsicFunction::Abs
+            | IntrinsicFunction::Min
+            | IntrinsicFunction::Max
+            | IntrinsicFunction::Pow),
+            args,
+        ) => {
+            let ExpressionType::Primitive(prim) = &ty else {
+                unreachable!("math intrinsics must return primitives, not {:?}", ty)
+            };
+            let func = match intrinsic {
+                IntrinsicFunction::Sqrt => MathFunction::Sqrt,
+                IntrinsicFunction::Abs => MathFunction::Abs,
+                IntrinsicFunction::Min => MathFunction::Min,
+                IntrinsicFunction::Max => MathFunction::Max,
+                IntrinsicFunction::Pow => MathFunction::Pow,
+                _ => unreachable!(),
+            };
+            LinearNodeValue::Math(
+                func,
+                primitive_to_physical(*prim),
+                args.into_iter()
+                    .map(|arg| lower_expression(ctx, arg))
+                    .collect(),
+            )
+        }
+        HirNodeValue::IntrinsicCall(IntrinsicFunction::Clamp, args) => {
+            let ExpressionType::Primitive(prim) = &ty else {
+                unreachable!("math intrinsics must return primitives, not {:?}", ty)
+            };
+            let prim = primitive_to_physical(*prim);
+            let [value, low, high] = <[HirNode; 3]>::try_from(args).unwrap();
+            let value = lower_expression(ctx, value);
+            let low = lower_expression(ctx, low);
+            let high = lower_expression(ctx, high);
+            LinearNodeValue::Math(
+                MathFunction::Min,
+                prim,
+                vec![
+                    LinearNode::new(LinearNodeValue::Math(
+                        MathFunction::Max,
+                        prim,
+                        vec![value, low],
+                    )),
+                    high,
+                ],
+            )
+        }
         HirNodeValue::GeneratorSuspend(generator, label) => {
             let location = lower_expression(ctx, *generator);
             LinearNodeValue::WriteMemory {
@@ -1551,7 +2203,9 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 LinearNode::new(LinearNodeValue::FunctionID(generator_function)),
             ])
         }
+        HirNodeValue::GeneratorFinished => LinearNodeValue::Abort(AbortReason::GeneratorFinished),
         HirNodeValue::Yield(_) => unreachable!("yields should be rewritten in HIR"),
+        HirNodeValue::Defer(_) => unreachable!("defers should be rewritten in HIR"),
         HirNodeValue::StringConcat(left, right) => {
             let left = lower_expression(ctx, *left);
             let right = lower_expression(ctx, *right);
@@ -1576,6 +2230,14 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
                 ty: PhysicalType::Primitive(PhysicalPrimitive::PointerSize),
             }
         }
+        HirNodeValue::NullTag(nullable) => {
+            let (location, offset) = lower_lvalue(ctx, *nullable);
+            LinearNodeValue::ReadMemory {
+                location: Box::new(location),
+                offset,
+                ty: PhysicalType::Primitive(PhysicalPrimitive::Byte),
+            }
+        }
         HirNodeValue::UnionVariant(union, variant) => {
             let ty = shallow_dereference(&union.ty)
                 .type_id()
@@ -1603,11 +2265,157 @@ fn lower_expression(ctx: &mut LinearContext<'_>, expression: HirNode) -> LinearN
         HirNodeValue::CellLiteral(inner) => {
             return lower_expression(ctx, *inner);
         }
+        // Only reached while evaluating constants, otherwise these are already folded
+        HirNodeValue::ConstEvaluation(_, inner) => {
+            return lower_expression(ctx, *inner);
+        }
     };
 
     LinearNode { value, provenance }
 }
 
+/**
+ * Do the arithmetic, aborting if the result wrapped around. Each operand is only evaluated once,
+ * so they're stored in temporaries to compare against the result.
+ */
+fn checked_arithmetic(
+    op: ArithmeticOp,
+    ty: PhysicalPrimitive,
+    lhs: LinearNode,
+    rhs: LinearNode,
+) -> LinearNodeValue {
+    let lhs_id = VariableID::new();
+    let rhs_id = VariableID::new();
+    let result_id = VariableID::new();
+    let physical_ty = PhysicalType::Primitive(ty);
+    let byte_ty = PhysicalType::Primitive(PhysicalPrimitive::Byte);
+    let read = |id| {
+        LinearNode::read_memory(
+            LinearNode::new(LinearNodeValue::VariableLocation(id)),
+            0,
+            physical_ty.clone(),
+        )
+    };
+    let compare = |op, lhs, rhs| {
+        LinearNode::new(LinearNodeValue::Comparison(
+            op,
+            ty,
+            Box::new(lhs),
+            Box::new(rhs),
+        ))
+    };
+    let logical = |op, lhs, rhs| {
+        LinearNode::new(LinearNodeValue::BinaryLogical(
+            op,
+            Box::new(lhs),
+            Box::new(rhs),
+        ))
+    };
+    let int = |value| {
+        let value = LinearNode::new(LinearNodeValue::Int(value));
+        if ty == PhysicalPrimitive::Int64 {
+            LinearNode::new(LinearNodeValue::Cast {
+                value: Box::new(value),
+                from: PhysicalPrimitive::Int32,
+                to: PhysicalPrimitive::Int64,
+            })
+        } else {
+            value
+        }
+    };
+
+    let overflowed = match op {
+        // Adding a positive number has to make the result bigger, and a negative one smaller
+        ArithmeticOp::Add | ArithmeticOp::Subtract => {
+            let (grew, shrank) = if op == ArithmeticOp::Add {
+                (ComparisonOp::LessThan, ComparisonOp::GreaterThan)
+            } else {
+                (ComparisonOp::GreaterThan, ComparisonOp::LessThan)
+            };
+            logical(
+                BinaryLogicalOp::BooleanOr,
+                logical(
+                    BinaryLogicalOp::BooleanAnd,
+                    compare(ComparisonOp::GreaterThan, read(rhs_id), int(0)),
+                    compare(grew, read(result_id), read(lhs_id)),
+                ),
+                logical(
+                    BinaryLogicalOp::BooleanAnd,
+                    compare(ComparisonOp::LessThan, read(rhs_id), int(0)),
+                    compare(shrank, read(result_id), read(lhs_id)),
+                ),
+            )
+        }
+        // Dividing the result back out recovers the other operand unless it wrapped. Negating the
+        // minimum value is the one overflow that division can't catch, and would trap besides.
+        ArithmeticOp::Multiply => LinearNode::if_node_value(
+            compare(ComparisonOp::EqualTo, read(lhs_id), int(0)),
+            vec![LinearNode::bool_value(false)],
+            Some(vec![LinearNode::if_node_value(
+                compare(ComparisonOp::EqualTo, read(lhs_id), int(-1)),
+                vec![logical(
+                    BinaryLogicalOp::BooleanAnd,
+                    compare(ComparisonOp::LessThan, read(rhs_id), int(0)),
+                    compare(ComparisonOp::LessThan, read(result_id), int(0)),
+                )],
+                Some(vec![compare(
+                    ComparisonOp::NotEquals,
+                    LinearNode::new(LinearNodeValue::Arithmetic(
+                        ArithmeticOp::Divide,
+                        ty,
+                        Box::new(read(result_id)),
+                        Box::new(read(lhs_id)),
+                    )),
+                    read(rhs_id),
+                )]),
+                None,
+                byte_ty.clone(),
+            )]),
+            None,
+            byte_ty,
+        ),
+        ArithmeticOp::Divide | ArithmeticOp::BitwiseXor => unreachable!("{op:?} can't overflow"),
+    };
+
+    let mut nodes = Vec::new();
+    for (id, value) in [
+        (lhs_id, lhs),
+        (rhs_id, rhs),
+        (
+            result_id,
+            LinearNode::new(LinearNodeValue::Arithmetic(
+                op,
+                ty,
+                Box::new(read(lhs_id)),
+                Box::new(read(rhs_id)),
+            )),
+        ),
+    ] {
+        nodes.push(LinearNode::new(LinearNodeValue::VariableInit(
+            id,
+            physical_ty.clone(),
+        )));
+        nodes.push(LinearNode::write_memory(
+            LinearNode::new(LinearNodeValue::VariableLocation(id)),
+            0,
+            physical_ty.clone(),
+            value,
+        ));
+    }
+    nodes.push(LinearNode::if_node(
+        overflowed,
+        vec![LinearNode::abort(AbortReason::IntegerOverflow)],
+        None,
+        None,
+    ));
+    nodes.push(read(result_id));
+    for id in [lhs_id, rhs_id, result_id] {
+        nodes.push(LinearNode::new(LinearNodeValue::VariableDestroy(id)));
+    }
+
+    LinearNodeValue::Sequence(nodes)
+}
+
 fn lower_lvalue(ctx: &mut LinearContext<'_>, lvalue: HirNode) -> (LinearNode, usize) {
     match lvalue.value {
         HirNodeValue::VariableReference(id) => (
@@ -1620,52 +2428,13 @@ fn lower_lvalue(ctx: &mut LinearContext<'_>, lvalue: HirNode) -> (LinearNode, us
         HirNodeValue::DictIndex(dict, idx) => dict_index_location_or_abort(ctx, *dict, *idx),
         HirNodeValue::UnionVariant(union, variant) => access_location(ctx, *union, variant),
 
-        HirNodeValue::Parameter(_, _) => todo!(),
-        HirNodeValue::Declaration(_) => todo!(),
-        HirNodeValue::Call(_, _) => todo!(),
-        HirNodeValue::Assignment(_, _) => todo!(),
-        HirNodeValue::UnaryLogical(_, _) => todo!(),
-        HirNodeValue::Arithmetic(_, _, _) => todo!(),
-        HirNodeValue::Comparison(_, _, _) => todo!(),
-        HirNodeValue::BinaryLogical(_, _, _) => todo!(),
-        HirNodeValue::Return(_) => todo!(),
-        HirNodeValue::Int(_) => todo!(),
-        HirNodeValue::PointerSize(_) => todo!(),
-        HirNodeValue::Float(_) => todo!(),
-        HirNodeValue::Bool(_) => todo!(),
-        HirNodeValue::Null => todo!(),
-        HirNodeValue::CharLiteral(_) => todo!(),
-        HirNodeValue::StringLiteral(_) => todo!(),
-        HirNodeValue::TakeUnique(_) => todo!(),
-        HirNodeValue::TakeShared(_) => todo!(),
-        HirNodeValue::Sequence(_) => todo!(),
-        HirNodeValue::If(_, _, _) => todo!(),
-        HirNodeValue::While(_, _) => todo!(),
-        HirNodeValue::Loop(_) => todo!(),
-        HirNodeValue::StructLiteral(_, _) => todo!(),
-        HirNodeValue::VtableCall(_, _, _) => todo!(),
-        HirNodeValue::StructToInterface { .. } => todo!(),
-        HirNodeValue::InterfaceAddress(_) => todo!(),
-
-        HirNodeValue::ArrayLiteral(_) | HirNodeValue::ArrayLiteralLength(_, _) => unreachable!(),
-        HirNodeValue::NumericCast { .. } => todo!(),
-        HirNodeValue::DictLiteral(_) => todo!(),
-        HirNodeValue::UnionLiteral(_, _, _) => todo!(),
-        HirNodeValue::NullCoalesce(_, _) => todo!(),
-        HirNodeValue::MakeNullable(_) => todo!(),
-        HirNodeValue::NullableTraverse(_, _) => todo!(),
-        HirNodeValue::Yield(_) => todo!(),
-        HirNodeValue::IntrinsicCall(_, _) => todo!(),
-        HirNodeValue::GeneratorSuspend(_, _) => todo!(),
-        HirNodeValue::GotoLabel(_) => todo!(),
-        HirNodeValue::GeneratorResume(_) => todo!(),
-        HirNodeValue::GeneratorCreate { .. } => todo!(),
-        HirNodeValue::StringConcat(_, _) => todo!(),
-        HirNodeValue::Switch { value: _, cases: _ } => todo!(),
-        HirNodeValue::UnionTag(_value) => todo!(),
-        HirNodeValue::ReferenceCountLiteral(_) => todo!(),
-        HirNodeValue::Discard(_) => todo!(),
-        HirNodeValue::CellLiteral(_) => todo!(),
+        // Typecheck rejects assignments to anything else, and the temporary variable pass stores
+        // other expressions that are borrowed or accessed before they get here. Anything that
+        // slips through is reported once lowering finishes rather than crashing mid-way.
+        _ => {
+            ctx.invalid_lvalues.push(lvalue.provenance);
+            (LinearNode::size(0), 0)
+        }
     }
 }
 
@@ -1694,6 +2463,10 @@ fn access_location(ctx: &mut LinearContext<'_>, lhs: HirNode, rhs: String) -> (L
     (lhs, offset)
 }
 
+/**
+ * A negative index counts back from the end of the array, so `arr[-1]` is the last item. Indices
+ * that are still negative after adding the length abort like any other out of bounds index.
+ */
 fn array_index_location(
     ctx: &mut LinearContext<'_>,
     arr: HirNode,
@@ -1701,6 +2474,15 @@ fn array_index_location(
     ty: &ExpressionType,
 ) -> (LinearNode, usize) {
     let size = expr_ty_to_physical(ty).size(ctx);
+    // Signed indices were cast to pointer size in the HIR, but the sign is needed to wrap them
+    let (idx, is_signed) = match idx.value {
+        HirNodeValue::NumericCast {
+            value,
+            from: PrimitiveType::Int32,
+            to: PrimitiveType::PointerSize,
+        } => (*value, true),
+        value => (HirNode { value, ..idx }, false),
+    };
     let idx = lower_expression(ctx, idx);
     let arr = lower_expression(ctx, arr);
 
@@ -1708,20 +2490,33 @@ fn array_index_location(
     let arr_ptr_register = RegisterID::new();
     let length_register = RegisterID::new();
 
+    let mut instrs = Vec::new();
+    if is_signed {
+        instrs.extend(wrap_negative_index(
+            idx,
+            arr,
+            idx_register,
+            arr_ptr_register,
+            length_register,
+        ));
+    } else {
+        instrs.push(LinearNode::write_register(idx_register, idx));
+        instrs.push(LinearNode::write_multi_register(
+            arr,
+            vec![Some(arr_ptr_register), Some(length_register), None],
+        ));
+    }
+
     (
         LinearNode::new(LinearNodeValue::Sequence(vec![
-            LinearNode::write_register(idx_register, idx),
-            LinearNode::write_multi_register(
-                arr,
-                vec![Some(arr_ptr_register), Some(length_register), None],
-            ),
+            LinearNode::new(LinearNodeValue::Sequence(instrs)),
             LinearNode::if_node(
                 LinearNode::ptr_comparison(
                     ComparisonOp::GreaterEqualThan,
                     LinearNode::read_register(idx_register),
                     LinearNode::read_register(length_register),
                 ),
-                vec![LinearNode::abort()],
+                vec![LinearNode::abort(AbortReason::ArrayIndexOutOfBounds)],
                 None,
                 None,
             ),
@@ -1742,6 +2537,77 @@ fn array_index_location(
     )
 }
 
+/**
+ * Write an i32 index into a register as a pointer size, adding the array length first if it's
+ * negative
+ */
+fn wrap_negative_index(
+    idx: LinearNode,
+    arr: LinearNode,
+    idx_register: RegisterID,
+    arr_ptr_register: RegisterID,
+    length_register: RegisterID,
+) -> Vec<LinearNode> {
+    let temp_id = VariableID::new();
+    let int_ty = PhysicalType::Primitive(PhysicalPrimitive::Int32);
+    let read_idx = || {
+        LinearNode::read_memory(
+            LinearNode::new(LinearNodeValue::VariableLocation(temp_id)),
+            0,
+            int_ty.clone(),
+        )
+    };
+    let length = LinearNode::new(LinearNodeValue::Cast {
+        value: Box::new(LinearNode::read_register(length_register)),
+        from: PhysicalPrimitive::PointerSize,
+        to: PhysicalPrimitive::Int32,
+    });
+
+    vec![
+        LinearNode::new(LinearNodeValue::VariableInit(temp_id, int_ty.clone())),
+        LinearNode::write_memory(
+            LinearNode::new(LinearNodeValue::VariableLocation(temp_id)),
+            0,
+            int_ty.clone(),
+            idx,
+        ),
+        LinearNode::write_multi_register(
+            arr,
+            vec![Some(arr_ptr_register), Some(length_register), None],
+        ),
+        LinearNode::if_node(
+            LinearNode::new(LinearNodeValue::Comparison(
+                ComparisonOp::LessThan,
+                PhysicalPrimitive::Int32,
+                Box::new(read_idx()),
+                Box::new(LinearNode::new(LinearNodeValue::Int(0))),
+            )),
+            vec![LinearNode::write_memory(
+                LinearNode::new(LinearNodeValue::VariableLocation(temp_id)),
+                0,
+                int_ty.clone(),
+                LinearNode::new(LinearNodeValue::Arithmetic(
+                    ArithmeticOp::Add,
+                    PhysicalPrimitive::Int32,
+                    Box::new(read_idx()),
+                    Box::new(length),
+                )),
+            )],
+            None,
+            None,
+        ),
+        LinearNode::write_register(
+            idx_register,
+            LinearNode::new(LinearNodeValue::Cast {
+                value: Box::new(read_idx()),
+                from: PhysicalPrimitive::Int32,
+                to: PhysicalPrimitive::PointerSize,
+            }),
+        ),
+        LinearNode::new(LinearNodeValue::VariableDestroy(temp_id)),
+    ]
+}
+
 fn dict_index_location_or_abort(
     ctx: &mut LinearContext<'_>,
     dict: HirNode,
@@ -1789,7 +2655,7 @@ fn dict_index_location_or_abort(
                     LinearNode::read_register(entry_pointer_output),
                     LinearNode::kill_register(entry_pointer_output),
                 ],
-                Some(vec![LinearNode::new(LinearNodeValue::Abort)]),
+                Some(vec![LinearNode::abort(AbortReason::DictKeyNotFound)]),
                 None,
                 PhysicalType::Primitive(PhysicalPrimitive::PointerSize),
             ),
@@ -1928,6 +2794,11 @@ fn array_alloc_space_to_push(
 // TODO: make this work as a function, rather than inlined?
 /**
  * Returns a nullable pointer to the given dictionary entry
+ *
+ * Dictionaries are a flat buffer of key-value entries, scanned front to back. New keys are
+ * appended to the end of the buffer and existing keys are overwritten in place, so entries (and
+ * `keys()`) are always in the order each key was first inserted. Both the interpreter and the
+ * wasm backend run this same representation, so the order is the same in either.
  */
 fn dict_get_entry_for_key(
     dict_pointer: LinearNode,
@@ -1940,6 +2811,8 @@ fn dict_get_entry_for_key(
     let key_ptr = RegisterID::new();
     let dict_length = RegisterID::new();
     let index = RegisterID::new();
+    // Loops can't produce a value, so whether the key was found is carried out in a register
+    let found = RegisterID::new();
 
     LinearNode::new(LinearNodeValue::Sequence(vec![
         LinearNode::write_register(key_ptr, key_location),
@@ -1966,8 +2839,8 @@ fn dict_get_entry_for_key(
                     )),
                 )),
                 vec![
-                    LinearNode::new(LinearNodeValue::Byte(1)),
-                    LinearNode::new(LinearNodeValue::Break),
+                    LinearNode::write_register(found, LinearNode::size(1)),
+                    LinearNode::new(LinearNodeValue::Break(0)),
                 ],
                 None,
                 None,
@@ -1996,19 +2869,125 @@ fn dict_get_entry_for_key(
                     LinearNode::read_register(index),
                 ),
                 vec![
-                    LinearNode::new(LinearNodeValue::Byte(0)),
-                    LinearNode::new(LinearNodeValue::Break),
+                    LinearNode::write_register(found, LinearNode::size(0)),
+                    LinearNode::new(LinearNodeValue::Break(0)),
                 ],
                 None,
                 None,
             ),
         ])),
+        LinearNode::ptr_comparison(
+            ComparisonOp::EqualTo,
+            LinearNode::read_register(found),
+            LinearNode::size(1),
+        ),
         LinearNode::kill_register(key_ptr),
         LinearNode::kill_register(dict_length),
         LinearNode::kill_register(index),
+        LinearNode::kill_register(found),
     ]))
 }
 
+/**
+ * Scan the array front to back, stopping at the first element equal to the value
+ */
+fn array_contains(
+    array_pointer: LinearNode,
+    value: LinearNode,
+    item_ty: PhysicalPrimitive,
+    item_size: usize,
+) -> LinearNodeValue {
+    let temp_value_id = VariableID::new();
+    let item_ptr = RegisterID::new();
+    let length = RegisterID::new();
+    let index = RegisterID::new();
+    let found = RegisterID::new();
+
+    LinearNodeValue::Sequence(vec![
+        LinearNode::write_multi_register(
+            LinearNode::read_memory(
+                array_pointer,
+                0,
+                PhysicalType::Collection(PhysicalCollection::Array),
+            ),
+            vec![Some(item_ptr), Some(length), None],
+        ),
+        LinearNode::new(LinearNodeValue::VariableInit(
+            temp_value_id,
+            PhysicalType::Primitive(item_ty),
+        )),
+        LinearNode::write_memory(
+            LinearNode::new(LinearNodeValue::VariableLocation(temp_value_id)),
+            0,
+            PhysicalType::Primitive(item_ty),
+            value,
+        ),
+        LinearNode::write_register(index, LinearNode::size(0)),
+        LinearNode::write_register(found, LinearNode::size(0)),
+        LinearNode::new(LinearNodeValue::Loop(vec![
+            // Check the bounds first, so empty arrays never read an element
+            LinearNode::if_node(
+                LinearNode::ptr_comparison(
+                    ComparisonOp::EqualTo,
+                    LinearNode::read_register(index),
+                    LinearNode::read_register(length),
+                ),
+                vec![LinearNode::new(LinearNodeValue::Break(0))],
+                None,
+                None,
+            ),
+            LinearNode::if_node(
+                LinearNode::new(LinearNodeValue::Comparison(
+                    ComparisonOp::EqualTo,
+                    item_ty,
+                    Box::new(LinearNode::read_memory(
+                        LinearNode::read_register(item_ptr),
+                        0,
+                        PhysicalType::Primitive(item_ty),
+                    )),
+                    Box::new(LinearNode::read_memory(
+                        LinearNode::new(LinearNodeValue::VariableLocation(temp_value_id)),
+                        0,
+                        PhysicalType::Primitive(item_ty),
+                    )),
+                )),
+                vec![
+                    LinearNode::write_register(found, LinearNode::size(1)),
+                    LinearNode::new(LinearNodeValue::Break(0)),
+                ],
+                None,
+                None,
+            ),
+            LinearNode::write_register(
+                index,
+                LinearNode::ptr_arithmetic(
+                    ArithmeticOp::Add,
+                    LinearNode::read_register(index),
+                    LinearNode::size(1),
+                ),
+            ),
+            LinearNode::write_register(
+                item_ptr,
+                LinearNode::ptr_arithmetic(
+                    ArithmeticOp::Add,
+                    LinearNode::read_register(item_ptr),
+                    LinearNode::size(item_size),
+                ),
+            ),
+        ])),
+        LinearNode::new(LinearNodeValue::VariableDestroy(temp_value_id)),
+        LinearNode::ptr_comparison(
+            ComparisonOp::EqualTo,
+            LinearNode::read_register(found),
+            LinearNode::size(1),
+        ),
+        LinearNode::kill_register(item_ptr),
+        LinearNode::kill_register(length),
+        LinearNode::kill_register(index),
+        LinearNode::kill_register(found),
+    ])
+}
+
 const UNION_TAG_SIZE: SizeInPointers = SizeInPointers(1);
 // TODO: wait shouldn't this always be a single byte
 pub const NULL_TAG_SIZE: SizeInPointers = SizeInPointers(1);
@@ -2038,7 +3017,7 @@ fn primitive_type_size(prim: PhysicalPrimitive, byte_size: usize, pointer_size:
 }
 
 // TODO: move to its own module?
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct DeclaredTypeLayout {
     pub value: TypeLayoutValue,
     // TODO: remove field?
@@ -2052,7 +3031,7 @@ impl DeclaredTypeLayout {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum TypeLayoutValue {
     Structure(Vec<(String, usize, PhysicalType)>),
     Interface(Vec<FunctionID>),
@@ -2162,9 +3141,10 @@ impl PhysicalType {
                     TypeLayoutValue::Union(_) => todo!(),
                 }
             }
+            // Same order as MakeNullable: the value, then the tag
             PhysicalType::Nullable(inner) => {
-                nodes.push(LinearNode::bool_value(false));
                 inner.zeroed(ctx, nodes);
+                nodes.push(LinearNode::bool_value(false));
             }
             PhysicalType::Collection(PhysicalCollection::String) => {
                 for _ in 0..3 {
@@ -2224,7 +3204,8 @@ fn layout_static_decl(
                 }
             });
             let mut size: usize = 0;
-            let mut alignment = 0;
+            // Structs without any fields take up no space, but can still be placed anywhere
+            let mut alignment = 1;
             for (_, offset, ty) in fields.iter_mut() {
                 let field_size = ty.size_from_decls(layouts, byte_size, pointer_size);
                 let field_alignment = ty.alignment(layouts, byte_size, pointer_size);
@@ -2240,7 +3221,8 @@ fn layout_static_decl(
             }
         }
         TypeDeclaration::Interface(interface_ty) => {
-            let mut size = pointer_size;
+            // The pointer to the value, then the vtable, then the tag of the value's type
+            let mut size = pointer_size * 2;
             // Sort functions by name to ensure that the order is deterministic
             let mut associated_functions_alphabetical: Vec<_> =
                 interface_ty.associated_functions.keys().collect();
@@ -1,20 +1,154 @@
-use brick::{interpret_code, SourceFile};
-use std::fs::read_to_string;
+use brick::{
+    interpret_code, ExternBinding, IntepreterError, RenderOptions, ReplSession, SourceFile,
+};
+use std::{
+    fs::read_to_string,
+    io::{self, BufRead, Write},
+};
 
 fn main() {
-    interpret_code(
-        vec![SourceFile {
-            filename: "example.brick",
-            module_name: "main",
-            contents: read_to_string("example.brick").expect("file should be readable"),
-        }],
-        vec![(
-            "print",
-            Box::new(|_, values| {
-                println!("{:?}", values);
-                None
-            }),
-        )],
-    )
-    .unwrap();
+    if std::env::args().any(|arg| arg == "--repl") {
+        repl();
+    } else {
+        let result = interpret_code(
+            vec![SourceFile {
+                filename: "example.brick",
+                module_name: "main",
+                contents: read_to_string("example.brick").expect("file should be readable"),
+            }],
+            vec![("print", print_binding())],
+        );
+        if let Err(IntepreterError::CompileError(error)) = &result {
+            let options = render_options();
+            for diagnostic in error.diagnostics() {
+                eprintln!("{}\n", diagnostic.render(&options));
+            }
+            std::process::exit(1);
+        }
+        result.unwrap();
+    }
+}
+
+/**
+ * `--tab-width N` sets how wide tabs are when underlining errors
+ */
+fn render_options() -> RenderOptions {
+    let mut options = RenderOptions::default();
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--tab-width" {
+            options.tab_width = args
+                .next()
+                .and_then(|width| width.parse().ok())
+                .expect("--tab-width should be followed by a number");
+        }
+    }
+
+    options
+}
+
+fn print_binding() -> ExternBinding {
+    Box::new(|_, values| {
+        println!("{:?}", values);
+        None
+    })
+}
+
+/**
+ * Each line carries on from the ones before it, so everything they declared
+ * can still be used.
+ */
+fn repl() {
+    let stdin = io::stdin();
+    let mut session = ReplSession::new(vec![("print", print_binding())]);
+    let mut pending = String::new();
+
+    loop {
+        print!("{}", if pending.is_empty() { "> " } else { ". " });
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        pending.push_str(&line);
+        if !is_complete(&pending) {
+            continue;
+        }
+        let input = std::mem::take(&mut pending);
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        match session.evaluate(&input) {
+            Ok(results) => {
+                if !results.is_empty() {
+                    println!("{:?}", results);
+                }
+            }
+            Err(err) => println!("{err}"),
+        }
+    }
+}
+
+/**
+ * Whether the input has balanced brackets and no unterminated strings, so
+ * multi-line declarations can be typed in over several lines.
+ */
+fn is_complete(input: &str) -> bool {
+    let (chars, in_string) = code_chars(input);
+    let mut depth = 0;
+    for c in chars {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    !in_string && depth <= 0
+}
+
+/**
+ * The characters of the input that aren't in strings, chars or comments, and
+ * whether the input ends partway through a string
+ */
+fn code_chars(input: &str) -> (Vec<char>, bool) {
+    let mut code = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' if input[offset..].starts_with("\"\"\"") => {
+                chars.next();
+                chars.next();
+                let mut quotes = 0;
+                while quotes < 3 {
+                    match chars.next() {
+                        Some((_, '"')) => quotes += 1,
+                        Some(_) => quotes = 0,
+                        None => return (code, true),
+                    }
+                }
+            }
+            '"' | '\'' => loop {
+                match chars.next() {
+                    Some((_, '\\')) => {
+                        chars.next();
+                    }
+                    Some((_, end)) if end == c => break,
+                    Some(_) => {}
+                    None => return (code, true),
+                }
+            },
+            c => code.push(c),
+        }
+    }
+
+    (code, false)
 }
@@ -38,7 +38,9 @@ impl<'a> AstNode<'a> {
 
         match &self.value {
             Return(Some(child))
+            | Break(Some(child), _)
             | Yield(Some(child))
+            | Defer(child)
             | TakeRef(child)
             | TakeUnique(child)
             | ArrayLiteralLength(child, _)
@@ -49,9 +51,12 @@ impl<'a> AstNode<'a> {
             | Statement(child)
             | Deref(child)
             | UnaryExpr(_, child)
+            | IsVariant(child, _)
+            | Downcast(child, _)
             | ArrayType(child)
             | CellType(child)
             | Loop(child)
+            | LabeledLoop(_, child)
             | ReferenceCountLiteral(child)
             | CellLiteral(child)
             | BorrowDeclaration(_, child, _) => {
@@ -59,7 +64,6 @@ impl<'a> AstNode<'a> {
             }
             DictType(left, right)
             | BinExpr(_, left, right)
-            | While(left, right)
             | GeneratorType {
                 yield_ty: left,
                 param_ty: right,
@@ -67,6 +71,18 @@ impl<'a> AstNode<'a> {
                 callback(right);
                 callback(left);
             }
+            ArraySlice(collection, start, end) => {
+                callback(end);
+                callback(start);
+                callback(collection);
+            }
+            While(condition, step, body) => {
+                callback(body);
+                if let Some(step) = step {
+                    callback(step);
+                }
+                callback(condition);
+            }
             If(IfDeclaration {
                 condition,
                 if_branch,
@@ -78,17 +94,42 @@ impl<'a> AstNode<'a> {
                     callback(else_branch);
                 }
             }
+            IfLet(IfLetDeclaration {
+                value,
+                if_branch,
+                else_branch,
+                ..
+            }) => {
+                callback(value);
+                callback(if_branch);
+                if let Some(else_branch) = else_branch {
+                    callback(else_branch);
+                }
+            }
             ConstDeclaration {
                 type_hint,
                 value: child,
                 ..
             }
-            | Declaration(_, type_hint, child, _) => {
+            | Declaration(_, type_hint, Some(child), _) => {
                 if let Some(type_hint) = type_hint {
                     callback(type_hint);
                 }
                 callback(child);
             }
+            Declaration(_, type_hint, None, _) => {
+                if let Some(type_hint) = type_hint {
+                    callback(type_hint);
+                }
+            }
+            FunctionType { params, returns } => {
+                for param in params.iter() {
+                    callback(param);
+                }
+                if let Some(returns) = returns {
+                    callback(returns);
+                }
+            }
             ArrayLiteral(values) | Block(values) => {
                 for value in values.iter() {
                     callback(value);
@@ -125,9 +166,13 @@ impl<'a> AstNode<'a> {
                     callback(returns);
                 }
             }
-            ExternFunctionBinding(FunctionHeaderValue {
-                params, returns, ..
-            })
+            ExternFunctionBinding {
+                header:
+                    FunctionHeaderValue {
+                        params, returns, ..
+                    },
+                ..
+            }
             | RequiredFunction(FunctionHeaderValue {
                 params, returns, ..
             }) => {
@@ -140,12 +185,16 @@ impl<'a> AstNode<'a> {
             }
             StructDeclaration(StructDeclarationValue {
                 fields,
+                field_defaults,
                 associated_functions,
                 ..
             }) => {
                 for field in fields.iter() {
                     callback(field.ty);
                 }
+                for (_, default) in field_defaults.iter() {
+                    callback(default);
+                }
                 for node in associated_functions.iter() {
                     callback(node);
                 }
@@ -153,11 +202,16 @@ impl<'a> AstNode<'a> {
             InterfaceDeclaration(InterfaceDeclarationValue {
                 associated_functions: fields,
                 ..
+            })
+            | ImplBlock(ImplBlockValue {
+                associated_functions: fields,
+                ..
             }) => {
                 for field in fields.iter() {
                     callback(field);
                 }
             }
+            TypeAliasDeclaration { ty, .. } => callback(ty),
             UnionDeclaration(UnionDeclarationValue {
                 variants: fields, ..
             }) => {
@@ -175,7 +229,191 @@ impl<'a> AstNode<'a> {
                 }
             }
             Name { .. }
-            | Import(_)
+            | Import(..)
+            | Int(_)
+            | Float(_)
+            | Bool(_)
+            | Null
+            | CharLiteral(_)
+            | StringLiteral(_)
+            | Return(None)
+            | Break(None, _)
+            | Continue(_)
+            | Yield(None)
+            | VoidType => {}
+        }
+    }
+
+    pub fn children_mut(&mut self, mut callback: impl FnMut(&mut AstNode<'a>)) {
+        use AstNodeValue::*;
+
+        match &mut self.value {
+            Return(Some(child))
+            | Break(Some(child), _)
+            | Yield(Some(child))
+            | Defer(child)
+            | TakeRef(child)
+            | TakeUnique(child)
+            | ArrayLiteralLength(child, _)
+            | UniqueType(child)
+            | SharedType(child)
+            | NullableType(child)
+            | RcType(child)
+            | Statement(child)
+            | Deref(child)
+            | UnaryExpr(_, child)
+            | IsVariant(child, _)
+            | Downcast(child, _)
+            | ArrayType(child)
+            | CellType(child)
+            | Loop(child)
+            | LabeledLoop(_, child)
+            | ReferenceCountLiteral(child)
+            | CellLiteral(child)
+            | BorrowDeclaration(_, child, _) => {
+                callback(child);
+            }
+            DictType(left, right)
+            | BinExpr(_, left, right)
+            | GeneratorType {
+                yield_ty: left,
+                param_ty: right,
+            } => {
+                callback(right);
+                callback(left);
+            }
+            ArraySlice(collection, start, end) => {
+                callback(end);
+                callback(start);
+                callback(collection);
+            }
+            While(condition, step, body) => {
+                callback(body);
+                if let Some(step) = step {
+                    callback(step);
+                }
+                callback(condition);
+            }
+            If(IfDeclaration {
+                condition,
+                if_branch,
+                else_branch,
+            }) => {
+                callback(condition);
+                callback(if_branch);
+                if let Some(else_branch) = else_branch {
+                    callback(else_branch);
+                }
+            }
+            IfLet(IfLetDeclaration {
+                value,
+                if_branch,
+                else_branch,
+                ..
+            }) => {
+                callback(value);
+                callback(if_branch);
+                if let Some(else_branch) = else_branch {
+                    callback(else_branch);
+                }
+            }
+            ConstDeclaration {
+                type_hint,
+                value: child,
+                ..
+            }
+            | Declaration(_, type_hint, Some(child), _) => {
+                if let Some(type_hint) = type_hint {
+                    callback(type_hint);
+                }
+                callback(child);
+            }
+            Declaration(_, type_hint, None, _) => {
+                if let Some(type_hint) = type_hint {
+                    callback(type_hint);
+                }
+            }
+            FunctionType { params, returns } => {
+                for param in params.iter_mut() {
+                    callback(param);
+                }
+                if let Some(returns) = returns {
+                    callback(returns);
+                }
+            }
+            ArrayLiteral(values) | Block(values) => {
+                for value in values.iter_mut() {
+                    callback(value);
+                }
+            }
+            RecordLiteral { fields, .. } => {
+                for expression in fields.values_mut() {
+                    callback(expression);
+                }
+            }
+            DictLiteral(entries) => {
+                for (left, right) in entries.iter_mut() {
+                    callback(left);
+                    callback(right);
+                }
+            }
+            Call(function, parameters) => {
+                callback(function);
+                for expression in parameters.iter_mut() {
+                    callback(expression);
+                }
+            }
+            // Parameter and field types are shared references, so they can't be visited mutably
+            AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
+                body, returns, ..
+            }) => {
+                callback(body);
+                if let Some(returns) = returns {
+                    callback(returns);
+                }
+            }
+            ExternFunctionBinding {
+                header: FunctionHeaderValue { returns, .. },
+                ..
+            }
+            | RequiredFunction(FunctionHeaderValue { returns, .. }) => {
+                if let Some(returns) = returns {
+                    callback(returns);
+                }
+            }
+            StructDeclaration(StructDeclarationValue {
+                field_defaults,
+                associated_functions,
+                ..
+            }) => {
+                for (_, default) in field_defaults.iter_mut() {
+                    callback(default);
+                }
+                for node in associated_functions.iter_mut() {
+                    callback(node);
+                }
+            }
+            InterfaceDeclaration(InterfaceDeclarationValue {
+                associated_functions: fields,
+                ..
+            })
+            | ImplBlock(ImplBlockValue {
+                associated_functions: fields,
+                ..
+            }) => {
+                for field in fields.iter_mut() {
+                    callback(field);
+                }
+            }
+            UnionDeclaration(_) | TypeAliasDeclaration { .. } => {}
+            Match(case) => {
+                callback(case.value);
+                for case in case.cases.iter_mut() {
+                    callback(&mut case.body);
+                }
+            }
+            Name { .. }
+            | Import(..)
             | Int(_)
             | Float(_)
             | Bool(_)
@@ -183,10 +421,51 @@ impl<'a> AstNode<'a> {
             | CharLiteral(_)
             | StringLiteral(_)
             | Return(None)
+            | Break(None, _)
+            | Continue(_)
             | Yield(None)
             | VoidType => {}
         }
     }
+
+    /**
+     * Visit this node and everything under it, each node before its children. Children are
+     * visited in the same order `children` gives them, so every walk over a tree is the same.
+     */
+    pub fn walk(&'a self, mut callback: impl FnMut(&'a AstNode<'a>)) {
+        self.walk_recursive(&mut callback);
+    }
+
+    fn walk_recursive(&'a self, callback: &mut impl FnMut(&'a AstNode<'a>)) {
+        callback(self);
+        self.children(|child| child.walk_recursive(callback));
+    }
+
+    /**
+     * Visit this node and everything under it, each node after its children
+     */
+    pub fn walk_post_order(&'a self, mut callback: impl FnMut(&'a AstNode<'a>)) {
+        self.walk_post_order_recursive(&mut callback);
+    }
+
+    fn walk_post_order_recursive(&'a self, callback: &mut impl FnMut(&'a AstNode<'a>)) {
+        self.children(|child| child.walk_post_order_recursive(callback));
+        callback(self);
+    }
+
+    /**
+     * This node and everything under it, in the same order as `walk`
+     */
+    pub fn iter(&'a self) -> impl Iterator<Item = &'a AstNode<'a>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let start = stack.len();
+            node.children(|child| stack.push(child));
+            stack[start..].reverse();
+            Some(node)
+        })
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -201,6 +480,12 @@ pub struct FunctionDeclarationValue<'a> {
      */
     pub is_extern: bool,
     pub is_coroutine: bool,
+    /**
+     * Whether this function may be called from a constant, which evaluates it at compile time
+     */
+    pub is_const: bool,
+    /// Whether other modules can import this, rather than it being private to its own module
+    pub is_exported: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -214,8 +499,11 @@ pub struct FunctionHeaderValue<'a> {
 pub struct StructDeclarationValue<'a> {
     pub name: String,
     pub fields: Vec<NameAndType<'a>>,
+    /// The values that fields left out of a struct literal take, by field name
+    pub field_defaults: Vec<(String, AstNode<'a>)>,
     pub associated_functions: Vec<AstNode<'a>>,
     pub properties: Vec<String>,
+    pub is_exported: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -223,6 +511,7 @@ pub struct UnionDeclarationValue<'a> {
     pub name: String,
     pub variants: Vec<UnionDeclarationVariant<'a>>,
     pub properties: Vec<String>,
+    pub is_exported: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -235,6 +524,40 @@ pub enum UnionDeclarationVariant<'a> {
 pub struct InterfaceDeclarationValue<'a> {
     pub name: String,
     pub associated_functions: Vec<AstNode<'a>>,
+    pub is_exported: bool,
+}
+
+/**
+ * Associated functions declared apart from their struct, which are merged into the struct's own
+ */
+#[derive(Debug, PartialEq)]
+pub struct ImplBlockValue<'a> {
+    pub name: String,
+    pub name_provenance: SourceRange,
+    /// `impl Interface for Struct` declares that the struct conforms to the interface, which is
+    /// checked even if the struct is never converted to it
+    pub interface: Option<(String, SourceRange)>,
+    pub associated_functions: Vec<AstNode<'a>>,
+}
+
+/**
+ * The loop a `break` or `continue` names, instead of leaving the innermost one
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopLabel {
+    pub name: String,
+    /// How many loops out from the jump the labeled one is, where 0 is the innermost loop. This is
+    /// filled in once the labeled loop is parsed, so it stays empty for labels that don't exist.
+    pub depth: Option<usize>,
+}
+
+impl LoopLabel {
+    /**
+     * How many loops out a break or continue with this label jumps, where 0 is the innermost loop
+     */
+    pub fn jump_depth(label: &Option<LoopLabel>) -> usize {
+        label.as_ref().and_then(|label| label.depth).unwrap_or(0)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -244,6 +567,18 @@ pub struct IfDeclaration<'a> {
     pub else_branch: Option<&'a mut AstNode<'a>>,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct IfLetDeclaration<'a> {
+    /**
+     * A variant with a binding for unions, or just a binding name for nullables
+     */
+    pub pattern: MatchCaseVariant,
+    pub var_id: VariableID,
+    pub value: &'a mut AstNode<'a>,
+    pub if_branch: &'a mut AstNode<'a>,
+    pub else_branch: Option<&'a mut AstNode<'a>>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct MatchDeclaration<'a> {
     pub value: &'a mut AstNode<'a>,
@@ -270,15 +605,28 @@ pub struct MatchCaseVariant {
 pub enum AstNodeValue<'a> {
     // Statements
     FunctionDeclaration(FunctionDeclarationValue<'a>),
-    ExternFunctionBinding(FunctionHeaderValue<'a>),
+    ExternFunctionBinding {
+        header: FunctionHeaderValue<'a>,
+        is_exported: bool,
+    },
     StructDeclaration(StructDeclarationValue<'a>),
     UnionDeclaration(UnionDeclarationValue<'a>),
     InterfaceDeclaration(InterfaceDeclarationValue<'a>),
+    ImplBlock(ImplBlockValue<'a>),
+    /// Another name for a type, which is interchangeable with the type it stands for
+    TypeAliasDeclaration {
+        name: String,
+        ty: &'a AstNode<'a>,
+        is_exported: bool,
+    },
     RequiredFunction(FunctionHeaderValue<'a>),
+    /**
+     * A variable declared without a value has to be assigned before it's read
+     */
     Declaration(
         String,
         Option<&'a mut AstNode<'a>>,
-        &'a mut AstNode<'a>,
+        Option<&'a mut AstNode<'a>>,
         VariableID,
     ),
     BorrowDeclaration(String, &'a mut AstNode<'a>, VariableID),
@@ -288,9 +636,19 @@ pub enum AstNodeValue<'a> {
         value: &'a mut AstNode<'a>,
         variable_id: ConstantID,
     },
-    Import(Vec<String>),
+    /// The path to import, the names picked out of it if it ends in `.{a, b}`, and the local name
+    /// it's bound to if it ends in `as alias`
+    Import(Vec<String>, Option<Vec<String>>, Option<String>),
     Return(Option<&'a mut AstNode<'a>>),
+    /// Exit the innermost loop (or the labeled one), optionally making the value the result of a
+    /// `loop` expression
+    Break(Option<&'a mut AstNode<'a>>, Option<LoopLabel>),
+    /// Skip to the next iteration of the innermost loop (or the labeled one), running the step of
+    /// a `while` first
+    Continue(Option<LoopLabel>),
     Yield(Option<&'a mut AstNode<'a>>),
+    /// Run an expression when the enclosing block exits, however it exits
+    Defer(&'a mut AstNode<'a>),
     // Any non-specific expression that ends in ; is a statement
     Statement(&'a mut AstNode<'a>),
 
@@ -309,8 +667,16 @@ pub enum AstNodeValue<'a> {
     UnaryExpr(UnaryOp, &'a mut AstNode<'a>),
     BinExpr(BinOp, &'a mut AstNode<'a>, &'a mut AstNode<'a>),
     If(IfDeclaration<'a>),
-    While(&'a mut AstNode<'a>, &'a mut AstNode<'a>),
+    IfLet(IfLetDeclaration<'a>),
+    /// Condition, an optional step run after each iteration, and body
+    While(
+        &'a mut AstNode<'a>,
+        Option<&'a mut AstNode<'a>>,
+        &'a mut AstNode<'a>,
+    ),
     Loop(&'a mut AstNode<'a>),
+    /// A `while` or `loop` with a name that breaks and continues in nested loops can refer to
+    LabeledLoop(String, &'a mut AstNode<'a>),
     Call(&'a mut AstNode<'a>, Vec<AstNode<'a>>),
     TakeUnique(&'a mut AstNode<'a>),
     TakeRef(&'a mut AstNode<'a>),
@@ -321,11 +687,20 @@ pub enum AstNodeValue<'a> {
     DictLiteral(Vec<(AstNode<'a>, AstNode<'a>)>),
     ArrayLiteral(Vec<AstNode<'a>>),
     ArrayLiteralLength(&'a mut AstNode<'a>, &'a mut AstNode<'a>),
+    /// Collection, start, and end of `collection[start..end]`
+    ArraySlice(
+        &'a mut AstNode<'a>,
+        &'a mut AstNode<'a>,
+        &'a mut AstNode<'a>,
+    ),
     ReferenceCountLiteral(&'a mut AstNode<'a>),
     CellLiteral(&'a mut AstNode<'a>),
     Block(Vec<AstNode<'a>>),
     Deref(&'a mut AstNode<'a>),
     Match(MatchDeclaration<'a>),
+    IsVariant(&'a mut AstNode<'a>, String),
+    /// `value as Struct`, recovering the struct that an interface value was made from
+    Downcast(&'a mut AstNode<'a>, String),
 
     // Types
     // TODO: unify
@@ -341,6 +716,11 @@ pub enum AstNodeValue<'a> {
         yield_ty: &'a mut AstNode<'a>,
         param_ty: &'a mut AstNode<'a>,
     },
+    /// A pointer to a function with these parameters, returning void if there's no return type
+    FunctionType {
+        params: Vec<AstNode<'a>>,
+        returns: Option<&'a mut AstNode<'a>>,
+    },
 }
 
 impl<'a> AstNodeValue<'a> {
@@ -355,6 +735,11 @@ impl<'a> AstNodeValue<'a> {
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum UnaryOp {
     BooleanNot,
+    NullableUnwrap,
+    Negate,
+    BitNot,
+    /// Leaves a number unchanged
+    Plus,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -413,11 +798,31 @@ impl BinOp {
             BinOp::BooleanOr => BOOLEAN_OR,
         }
     }
+
+    pub fn is_left_associative(&self) -> bool {
+        !self.is_right_associative() && !self.is_comparison()
+    }
+
+    pub fn is_right_associative(&self) -> bool {
+        matches!(
+            self,
+            BinOp::NullCoalesce
+                | BinOp::Assignment
+                | BinOp::AddAssign
+                | BinOp::SubtractAssign
+                | BinOp::MultiplyAssign
+                | BinOp::DivideAssign
+        )
+    }
+
+    fn is_comparison(&self) -> bool {
+        self.binding_power() == COMPARE
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum ParseError {
-    #[error("unexpected token {0}, {1}")]
+    #[error("unexpected {}, {1}: {}", .0.value, .0.range)]
     UnexpectedToken(Box<Token>, &'static str),
     #[error("unexpected end of input at {0}, {1}")]
     UnexpectedEndOfInput(SourceMarker, &'static str),
@@ -427,6 +832,61 @@ pub enum ParseError {
     TokenError(#[from] LexError),
     #[error("unexpected top-level statement at {0}")]
     UnexpectedTopLevelStatement(SourceRange),
+    #[error("comparisons can't be chained, combine them with 'and' instead: {0}")]
+    ChainedComparison(SourceRange),
+    #[error("reference operators can't be stacked, add parentheses if this is intended: {0}")]
+    NestedReferenceOperator(SourceRange),
+    #[error("'*' dereferences a value, pointer types are written 'unique T' or 'ref T': {0}")]
+    DerefInTypePosition(SourceRange),
+    #[error("expected a value, found a type: {0}")]
+    TypeInValuePosition(SourceRange),
+    #[error("variables declared without a value need a type annotation: {0}")]
+    UninitializedWithoutType(SourceRange),
+}
+
+impl ParseError {
+    /**
+     * A stable identifier for this kind of error, for tools that can't rely on the message text.
+     * Codes are never reused or renumbered, new errors get new codes.
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken(_, _) => "E0001",
+            ParseError::UnexpectedEndOfInput(_, _) => "E0002",
+            ParseError::MissingTypeForParam(_) => "E0003",
+            ParseError::TokenError(_) => "E0004",
+            ParseError::UnexpectedTopLevelStatement(_) => "E0005",
+            ParseError::ChainedComparison(_) => "E0006",
+            ParseError::NestedReferenceOperator(_) => "E0007",
+            ParseError::DerefInTypePosition(_) => "E0008",
+            ParseError::TypeInValuePosition(_) => "E0009",
+            ParseError::UninitializedWithoutType(_) => "E0010",
+        }
+    }
+
+    /**
+     * The location the error is reported at. Errors found at a single point in the source get an
+     * empty range there.
+     */
+    pub fn primary_span(&self) -> Option<SourceRange> {
+        match self {
+            ParseError::UnexpectedToken(token, _) => Some(token.range.clone()),
+            ParseError::UnexpectedEndOfInput(marker, _)
+            | ParseError::MissingTypeForParam(marker) => Some(SourceRange::new(*marker, *marker)),
+            ParseError::TokenError(
+                LexError::UnexpectedStart(_, marker)
+                | LexError::IllegalNullByte(marker)
+                | LexError::UnterminatedLiteral(marker)
+                | LexError::IllegalEscapeSequence(marker),
+            ) => Some(SourceRange::new(*marker, *marker)),
+            ParseError::UnexpectedTopLevelStatement(provenance)
+            | ParseError::ChainedComparison(provenance)
+            | ParseError::NestedReferenceOperator(provenance)
+            | ParseError::DerefInTypePosition(provenance)
+            | ParseError::TypeInValuePosition(provenance)
+            | ParseError::UninitializedWithoutType(provenance) => Some(provenance.clone()),
+        }
+    }
 }
 
 type TokenIterInner<'a> = &'a mut dyn Iterator<Item = Result<Token, LexError>>;
@@ -442,7 +902,11 @@ pub fn parse<'a>(
 
     while let Some(lexeme) = peek_token_optional(&mut source)? {
         let cursor = lexeme.range.start();
-        let statement = statement(&mut source, arena, cursor)?;
+        let statement = if lexeme.value == TokenValue::Export {
+            exported_declaration(&mut source, arena, cursor)?
+        } else {
+            statement(&mut source, arena, cursor)?
+        };
 
         top_level_nodes.push(statement);
     }
@@ -450,6 +914,37 @@ pub fn parse<'a>(
     Ok(top_level_nodes)
 }
 
+/**
+ * Only top-level functions and types can be exported, everything else is private to its module
+ */
+fn exported_declaration<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    start: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let export = already_peeked_token(source)?;
+    let mut declaration = statement(source, context, export.range.end())?;
+    match &mut declaration.value {
+        AstNodeValue::FunctionDeclaration(FunctionDeclarationValue { is_exported, .. })
+        | AstNodeValue::StructDeclaration(StructDeclarationValue { is_exported, .. })
+        | AstNodeValue::UnionDeclaration(UnionDeclarationValue { is_exported, .. })
+        | AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue { is_exported, .. })
+        | AstNodeValue::ExternFunctionBinding { is_exported, .. }
+        | AstNodeValue::TypeAliasDeclaration { is_exported, .. } => {
+            *is_exported = true;
+        }
+        _ => {
+            return Err(ParseError::UnexpectedToken(
+                Box::new(export),
+                "only functions and types can be exported",
+            ))
+        }
+    }
+    declaration.provenance = SourceRange::new(start, declaration.provenance.end());
+
+    Ok(declaration)
+}
+
 fn add_node<'a>(context: &'a Arena<AstNode<'a>>, node: AstNode<'a>) -> &'a mut AstNode<'a> {
     context.alloc(node)
 }
@@ -460,7 +955,7 @@ fn statement<'a>(
     cursor: SourceMarker,
 ) -> Result<AstNode<'a>, ParseError> {
     Ok(
-        match peek_token(source, cursor, "expected let, fn, or expression")?.value {
+        match &peek_token(source, cursor, "expected let, fn, or expression")?.value {
             TokenValue::Let
             | TokenValue::Const
             | TokenValue::Borrow
@@ -471,13 +966,28 @@ fn statement<'a>(
             | TokenValue::Struct
             | TokenValue::Union
             | TokenValue::Interface
-            | TokenValue::Return => {
+            | TokenValue::Impl
+            | TokenValue::Return
+            | TokenValue::Break
+            | TokenValue::Continue
+            | TokenValue::Defer => {
                 let Token { range, value, .. } = already_peeked_token(source)?;
                 let cursor = range.end();
                 match value {
                     TokenValue::Let => variable_declaration(source, context, cursor)?,
                     TokenValue::Borrow => borrow_declaration(source, context, cursor)?,
-                    TokenValue::Const => const_declaration(source, context, cursor)?,
+                    TokenValue::Const => {
+                        if let Some(Token {
+                            value: TokenValue::Function,
+                            ..
+                        }) = peek_token_optional(source)?
+                        {
+                            let token = already_peeked_token(source)?;
+                            function_declaration(source, context, token.range.end(), false, true)?
+                        } else {
+                            const_declaration(source, context, cursor)?
+                        }
+                    }
                     TokenValue::Import => {
                         let statement = import_declaration(source, cursor)?;
                         if let Some(Token {
@@ -491,7 +1001,9 @@ fn statement<'a>(
                         statement
                     }
                     TokenValue::Extern => extern_function_declaration(source, context, cursor)?,
-                    TokenValue::Function => function_declaration(source, context, cursor, false)?,
+                    TokenValue::Function => {
+                        function_declaration(source, context, cursor, false, false)?
+                    }
                     TokenValue::Gen => {
                         let token = assert_next_lexeme_eq(
                             source,
@@ -500,11 +1012,12 @@ fn statement<'a>(
                             "expected fn after gen",
                         )?;
                         let cursor = token.range.end();
-                        function_declaration(source, context, cursor, true)?
+                        function_declaration(source, context, cursor, true, false)?
                     }
                     TokenValue::Struct => struct_declaration(source, context, cursor)?,
                     TokenValue::Union => union_declaration(source, context, cursor)?,
                     TokenValue::Interface => interface_declaration(source, context, cursor)?,
+                    TokenValue::Impl => impl_block(source, context, cursor)?,
                     TokenValue::Return => {
                         let statement = return_declaration(source, context, cursor)?;
                         if let Some(Token {
@@ -517,24 +1030,92 @@ fn statement<'a>(
 
                         statement
                     }
+                    TokenValue::Break => {
+                        let statement = break_declaration(source, context, range)?;
+                        if let Some(Token {
+                            value: TokenValue::Semicolon,
+                            ..
+                        }) = peek_token_optional(source)?
+                        {
+                            already_peeked_token(source)?;
+                        }
+
+                        statement
+                    }
+                    TokenValue::Continue => {
+                        let mut range = range;
+                        let mut label = None;
+                        if let Some(Token {
+                            value: TokenValue::Word(_),
+                            ..
+                        }) = peek_token_optional(source)?
+                        {
+                            let token = already_peeked_token(source)?;
+                            let TokenValue::Word(name) = token.value else {
+                                unreachable!()
+                            };
+                            range = SourceRange::new(range.start(), token.range.end());
+                            label = Some(LoopLabel { name, depth: None });
+                        }
+                        if let Some(Token {
+                            value: TokenValue::Semicolon,
+                            ..
+                        }) = peek_token_optional(source)?
+                        {
+                            already_peeked_token(source)?;
+                        }
+
+                        AstNode::new(AstNodeValue::Continue(label), range)
+                    }
+                    TokenValue::Defer => defer_declaration(source, context, range)?,
                     _ => unreachable!(),
                 }
             }
-            _ => {
-                let expr = expression(source, context, cursor, true)?;
+            // Like `for` in impl blocks, `type` is only a keyword when a name follows it, so it
+            // can still be used as a variable
+            TokenValue::Word(keyword) if keyword == "type" => {
+                let Token { range, value } = already_peeked_token(source)?;
                 if let Some(Token {
-                    value: TokenValue::Semicolon,
+                    value: TokenValue::Word(_),
                     ..
                 }) = peek_token_optional(source)?
                 {
-                    let token = already_peeked_token(source)?;
-                    let provenance = SourceRange::new(expr.provenance.start(), token.range.end());
-                    let expr = add_node(context, expr);
-                    AstNode::new(AstNodeValue::Statement(expr), provenance)
+                    type_alias_declaration(source, context, range.end())?
                 } else {
-                    expr
+                    let TokenValue::Word(word) = value else {
+                        unreachable!()
+                    };
+                    let start = range.start();
+                    let left = AstNode::new(AstNodeValue::name(word), range);
+                    let expr = expression_operators(source, context, left, start, 0, true)?;
+                    expression_statement(source, context, expr)?
                 }
             }
+            _ => {
+                let expr = expression(source, context, cursor, true)?;
+                expression_statement(source, context, expr)?
+            }
+        },
+    )
+}
+
+fn expression_statement<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    expr: AstNode<'a>,
+) -> Result<AstNode<'a>, ParseError> {
+    Ok(
+        if let Some(Token {
+            value: TokenValue::Semicolon,
+            ..
+        }) = peek_token_optional(source)?
+        {
+            let token = already_peeked_token(source)?;
+            let provenance = SourceRange::new(expr.provenance.start(), token.range.end());
+            let expr = add_node(context, expr);
+            AstNode::new(AstNodeValue::Statement(expr), provenance)
+        } else {
+            expr
         },
     )
 }
@@ -562,6 +1143,80 @@ fn return_declaration<'a>(
     Ok(AstNode::new(AstNodeValue::Return(Some(value)), provenance))
 }
 
+fn defer_declaration<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    keyword: SourceRange,
+) -> Result<AstNode<'a>, ParseError> {
+    let value = expression(source, context, keyword.end(), true)?;
+    let token = assert_next_lexeme_eq(
+        source,
+        TokenValue::Semicolon,
+        value.provenance.end(),
+        "expected ; after deferred expression",
+    )?;
+    let provenance = SourceRange::new(keyword.start(), token.range.end());
+    let value = add_node(context, value);
+
+    Ok(AstNode::new(AstNodeValue::Defer(value), provenance))
+}
+
+fn break_declaration<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    keyword: SourceRange,
+) -> Result<AstNode<'a>, ParseError> {
+    let next = peek_token(source, keyword.end(), "expected ; or value after break")?;
+    if matches!(next.value, TokenValue::Semicolon | TokenValue::CloseBracket) {
+        return Ok(AstNode::new(AstNodeValue::Break(None, None), keyword));
+    }
+    let (label, value) = if let TokenValue::Word(_) = next.value {
+        let Token { value, range } = already_peeked_token(source)?;
+        let TokenValue::Word(word) = value else {
+            unreachable!()
+        };
+        // `break outer 5` has a label and then a value. A word followed by anything that could
+        // continue it is the start of the value instead, and `break outer` alone is ambiguous until
+        // the labeled loop resolves it.
+        match peek_token_optional(source)? {
+            Some(next) if starts_labeled_break_value(&next.value) => {
+                let value = expression(source, context, range.end(), true)?;
+                let label = LoopLabel {
+                    name: word,
+                    depth: None,
+                };
+                (Some(label), value)
+            }
+            _ => {
+                let start = range.start();
+                let left = AstNode::new(AstNodeValue::name(word), range);
+                let value = expression_operators(source, context, left, start, 0, true)?;
+                (None, value)
+            }
+        }
+    } else {
+        (None, expression(source, context, keyword.end(), true)?)
+    };
+    let provenance = SourceRange::new(keyword.start(), value.provenance.end());
+    let value = add_node(context, value);
+
+    Ok(AstNode::new(
+        AstNodeValue::Break(Some(value), label),
+        provenance,
+    ))
+}
+
+/**
+ * Tokens that can start an expression, but can't continue one that's already been started
+ */
+fn starts_labeled_break_value(token: &TokenValue) -> bool {
+    !token.is_expression_boundary()
+        && !matches!(
+            token,
+            TokenValue::OpenParen | TokenValue::OpenSquare | TokenValue::OpenBracket
+        )
+}
+
 fn struct_declaration<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
@@ -571,20 +1226,57 @@ fn struct_declaration<'a>(
     let (name, provenance) = word(source, cursor, "expected name after 'struct'")?;
     cursor = provenance.end();
     let (properties, cursor) = property_list(source, cursor)?;
-    let (end, fields, associated_functions) =
+    let (end, fields, field_defaults, associated_functions) =
         interface_or_struct_body(source, context, cursor, false)?;
 
     Ok(AstNode::new(
         AstNodeValue::StructDeclaration(StructDeclarationValue {
             name,
             fields,
+            field_defaults,
             associated_functions,
             properties,
+            is_exported: false,
         }),
         SourceRange::new(start, end),
     ))
 }
 
+fn type_alias_declaration<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    cursor: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let start = cursor;
+    let (name, provenance) = word(source, cursor, "expected name after 'type'")?;
+    let cursor = assert_next_lexeme_eq(
+        source,
+        TokenValue::Assign,
+        provenance.end(),
+        "expected = after type alias name",
+    )?
+    .range
+    .end();
+    let ty = type_expression(source, context, cursor)?;
+    let end = assert_next_lexeme_eq(
+        source,
+        TokenValue::Semicolon,
+        ty.provenance.end(),
+        "expected ; after type alias",
+    )?
+    .range
+    .end();
+
+    Ok(AstNode::new(
+        AstNodeValue::TypeAliasDeclaration {
+            name,
+            ty: add_node(context, ty),
+            is_exported: false,
+        },
+        SourceRange::new(start, end),
+    ))
+}
+
 fn property_list(
     source: &mut TokenIter,
     mut cursor: SourceMarker,
@@ -622,24 +1314,33 @@ fn interface_declaration<'a>(
     cursor: SourceMarker,
 ) -> Result<AstNode<'a>, ParseError> {
     let (name, provenance) = word(source, cursor, "expected name after 'interface'")?;
-    let (end, _, associated_functions) =
+    let (end, _, _, associated_functions) =
         interface_or_struct_body(source, context, provenance.end(), true)?;
 
     Ok(AstNode::new(
         AstNodeValue::InterfaceDeclaration(InterfaceDeclarationValue {
             name,
             associated_functions,
+            is_exported: false,
         }),
         SourceRange::new(cursor, end),
     ))
 }
 
+/// Where the body ends, then its fields, their defaults, and its associated functions
+type StructBody<'a> = (
+    SourceMarker,
+    Vec<NameAndType<'a>>,
+    Vec<(String, AstNode<'a>)>,
+    Vec<AstNode<'a>>,
+);
+
 fn interface_or_struct_body<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
     cursor: SourceMarker,
     is_interface: bool,
-) -> Result<(SourceMarker, Vec<NameAndType<'a>>, Vec<AstNode<'a>>), ParseError> {
+) -> Result<StructBody<'a>, ParseError> {
     let mut cursor = assert_next_lexeme_eq(
         source,
         TokenValue::OpenBracket,
@@ -650,6 +1351,7 @@ fn interface_or_struct_body<'a>(
     .end();
 
     let mut fields = Vec::new();
+    let mut field_defaults = Vec::new();
     let mut associated_functions = Vec::new();
 
     loop {
@@ -667,52 +1369,10 @@ fn interface_or_struct_body<'a>(
         {
             let start = cursor;
             let token = already_peeked_token(source)?;
-            cursor = token.range.end();
-            let FunctionHeader {
-                name,
-                params,
-                returns,
-                end,
-            } = function_header(source, context, cursor)?;
+            let (function, end) =
+                associated_function(source, context, start, token.range.end(), is_interface)?;
             cursor = end;
-
-            let next = peek_token(source, cursor, "expected ',', }, or body")?;
-            if is_interface
-                && (next.value == TokenValue::Comma || next.value == TokenValue::CloseBracket)
-            {
-                if next.value == TokenValue::Comma {
-                    already_peeked_token(source)?;
-                }
-                associated_functions.push(AstNode::new(
-                    AstNodeValue::RequiredFunction(FunctionHeaderValue {
-                        name,
-                        params,
-                        returns,
-                    }),
-                    SourceRange::new(start, cursor),
-                ));
-            } else {
-                let token = assert_next_lexeme_eq(
-                    source,
-                    TokenValue::OpenBracket,
-                    cursor,
-                    "expected open bracket to start function body",
-                )?;
-                cursor = token.range.end();
-                let body = block(source, context, cursor)?;
-                cursor = body.provenance.end();
-                associated_functions.push(AstNode::new(
-                    AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
-                        name,
-                        params: params.into_iter().map(|p| (VariableID::new(), p)).collect(),
-                        returns,
-                        body: add_node(context, body),
-                        is_extern: false,
-                        is_coroutine: false,
-                    }),
-                    SourceRange::new(start, cursor),
-                ));
-            }
+            associated_functions.push(function);
         } else {
             if is_interface {
                 let next = already_peeked_token(source)?;
@@ -726,6 +1386,13 @@ fn interface_or_struct_body<'a>(
             cursor = range.end();
             let kind = type_hint.ok_or(ParseError::MissingTypeForParam(cursor))?;
             let kind = add_node(context, kind);
+            if peek_token(source, kind.provenance.end(), "expected , or } after field")?.value
+                == TokenValue::Assign
+            {
+                let cursor = already_peeked_token(source)?.range.end();
+                let default = expression(source, context, cursor, true)?;
+                field_defaults.push((name.clone(), default));
+            }
             fields.push(NameAndType {
                 name,
                 ty: kind,
@@ -745,7 +1412,133 @@ fn interface_or_struct_body<'a>(
         }
     }
 
-    Ok((cursor, fields, associated_functions))
+    Ok((cursor, fields, field_defaults, associated_functions))
+}
+
+/**
+ * An associated function after its `fn`, which is only allowed to leave off the body in interfaces
+ */
+fn associated_function<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    start: SourceMarker,
+    mut cursor: SourceMarker,
+    is_interface: bool,
+) -> Result<(AstNode<'a>, SourceMarker), ParseError> {
+    let FunctionHeader {
+        name,
+        params,
+        returns,
+        end,
+    } = function_header(source, context, cursor)?;
+    cursor = end;
+
+    let next = peek_token(source, cursor, "expected ',', }, or body")?;
+    if is_interface && (next.value == TokenValue::Comma || next.value == TokenValue::CloseBracket) {
+        if next.value == TokenValue::Comma {
+            already_peeked_token(source)?;
+        }
+        Ok((
+            AstNode::new(
+                AstNodeValue::RequiredFunction(FunctionHeaderValue {
+                    name,
+                    params,
+                    returns,
+                }),
+                SourceRange::new(start, cursor),
+            ),
+            cursor,
+        ))
+    } else {
+        let body = function_body(
+            source,
+            context,
+            cursor,
+            "expected open bracket or = to start function body",
+        )?;
+        cursor = body.provenance.end();
+        Ok((
+            AstNode::new(
+                AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
+                    name,
+                    params: params.into_iter().map(|p| (VariableID::new(), p)).collect(),
+                    returns,
+                    body: add_node(context, body),
+                    is_extern: false,
+                    is_coroutine: false,
+                    is_const: false,
+                    is_exported: false,
+                }),
+                SourceRange::new(start, cursor),
+            ),
+            cursor,
+        ))
+    }
+}
+
+fn impl_block<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    start: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let (mut name, mut name_provenance) = word(source, start, "expected type name after 'impl'")?;
+    let mut interface = None;
+    // Like `as` in imports, `for` is only special here
+    if let Some(Token {
+        value: TokenValue::Word(keyword),
+        ..
+    }) = peek_token_optional(source)?
+    {
+        if keyword == "for" {
+            let token = already_peeked_token(source)?;
+            let (struct_name, struct_provenance) = word(
+                source,
+                token.range.end(),
+                "expected struct name after 'for'",
+            )?;
+            interface = Some((
+                std::mem::replace(&mut name, struct_name),
+                std::mem::replace(&mut name_provenance, struct_provenance),
+            ));
+        }
+    }
+    let mut cursor = assert_next_lexeme_eq(
+        source,
+        TokenValue::OpenBracket,
+        name_provenance.end(),
+        "expected open bracket to start impl",
+    )?
+    .range
+    .end();
+
+    let mut associated_functions = Vec::new();
+    while !peek_for_closed(
+        source,
+        TokenValue::CloseBracket,
+        cursor,
+        "expected associated function or }",
+    )? {
+        let token = assert_next_lexeme_eq(
+            source,
+            TokenValue::Function,
+            cursor,
+            "expected associated function in impl",
+        )?;
+        let (function, end) =
+            associated_function(source, context, cursor, token.range.end(), false)?;
+        cursor = end;
+        associated_functions.push(function);
+    }
+
+    Ok(AstNode::new(
+        AstNodeValue::ImplBlock(ImplBlockValue {
+            name,
+            name_provenance,
+            interface,
+            associated_functions,
+        }),
+        SourceRange::new(start, cursor),
+    ))
 }
 
 fn union_declaration<'a>(
@@ -811,6 +1604,7 @@ fn union_declaration<'a>(
             name,
             variants,
             properties,
+            is_exported: false,
         }),
         provenance,
     ))
@@ -839,11 +1633,14 @@ fn extern_function_declaration<'a>(
     let next = next_token(source, end, "expected ; or { after extern fn decl")?;
     let (value, end) = match &next.value {
         TokenValue::Semicolon => (
-            AstNodeValue::ExternFunctionBinding(FunctionHeaderValue {
-                name,
-                params,
-                returns,
-            }),
+            AstNodeValue::ExternFunctionBinding {
+                header: FunctionHeaderValue {
+                    name,
+                    params,
+                    returns,
+                },
+                is_exported: false,
+            },
             next.range.end(),
         ),
         TokenValue::OpenBracket => {
@@ -857,6 +1654,8 @@ fn extern_function_declaration<'a>(
                     body: add_node(context, body),
                     is_extern: true,
                     is_coroutine: false,
+                    is_const: false,
+                    is_exported: false,
                 }),
                 end,
             )
@@ -878,6 +1677,7 @@ fn function_declaration<'a>(
     context: &'a Arena<AstNode<'a>>,
     start: SourceMarker,
     is_generator: bool,
+    is_const: bool,
 ) -> Result<AstNode<'a>, ParseError> {
     let FunctionHeader {
         name,
@@ -886,13 +1686,12 @@ fn function_declaration<'a>(
         end,
     } = function_header(source, context, start)?;
     let mut provenance = SourceRange::new(start, end);
-    let next_token = assert_next_lexeme_eq(
+    let body = function_body(
         source,
-        TokenValue::OpenBracket,
-        start,
-        "expected { after function declaration",
+        context,
+        end,
+        "expected { or = after function declaration",
     )?;
-    let body = block(source, context, next_token.range.end())?;
     provenance.set_end(body.provenance.end());
 
     Ok(AstNode::new(
@@ -903,11 +1702,43 @@ fn function_declaration<'a>(
             body: add_node(context, body),
             is_extern: false,
             is_coroutine: is_generator,
+            is_const,
+            is_exported: false,
         }),
         provenance,
     ))
 }
 
+/**
+ * Either a block, or `= expression;` as a shorthand for a block that only holds the expression
+ */
+fn function_body<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    cursor: SourceMarker,
+    reason: &'static str,
+) -> Result<AstNode<'a>, ParseError> {
+    let token = next_token(source, cursor, reason)?;
+    match token.value {
+        TokenValue::OpenBracket => block(source, context, token.range.end()),
+        TokenValue::Assign => {
+            let expr = expression(source, context, token.range.end(), true)?;
+            let end = assert_next_lexeme_eq(
+                source,
+                TokenValue::Semicolon,
+                expr.provenance.end(),
+                "expected ; after function body",
+            )?
+            .range
+            .end();
+            let provenance = SourceRange::new(expr.provenance.start(), end);
+
+            Ok(AstNode::new(AstNodeValue::Block(vec![expr]), provenance))
+        }
+        _ => Err(ParseError::UnexpectedToken(Box::new(token), reason)),
+    }
+}
+
 struct FunctionHeader<'a> {
     name: String,
     params: Vec<NameAndType<'a>>,
@@ -930,35 +1761,33 @@ fn function_header<'a>(
     )?;
     let mut cursor = next_token.range.end();
     let mut params = Vec::new();
-    loop {
-        let token = peek_token(source, cursor, "expected either parameters or close paren")?;
-        cursor = token.range.start();
-        match token.value {
-            TokenValue::CloseParen => break,
-            TokenValue::Comma => {
-                source.next();
-            }
-            _ => {
-                let (name, range, type_hint) =
-                    name_and_type_hint(source, context, cursor, "expected parameter")?;
-                cursor = range.end();
-                let kind = type_hint.ok_or(ParseError::MissingTypeForParam(cursor))?;
-                let kind = add_node(context, kind);
-                params.push(NameAndType {
-                    name,
-                    ty: kind,
-                    provenance: SourceRange::new(range.start(), kind.provenance.end()),
-                });
-            }
-        }
-    }
-    let next_token = assert_next_lexeme_eq(
+    let mut closed = peek_for_closed(
         source,
         TokenValue::CloseParen,
         cursor,
-        "expected closing parenthesis to end parameters",
+        "expected either parameters or close paren",
     )?;
-    cursor = next_token.range.end();
+    while !closed {
+        let (name, range, type_hint) =
+            name_and_type_hint(source, context, cursor, "expected parameter")?;
+        cursor = range.end();
+        let kind = type_hint.ok_or(ParseError::MissingTypeForParam(cursor))?;
+        let kind = add_node(context, kind);
+        params.push(NameAndType {
+            name,
+            ty: kind,
+            provenance: SourceRange::new(range.start(), kind.provenance.end()),
+        });
+
+        let (should_end, range) = comma_or_end_list(
+            source,
+            TokenValue::CloseParen,
+            kind.provenance.end(),
+            "expected comma or closing parenthesis to end parameters",
+        )?;
+        closed = should_end;
+        cursor = range.end();
+    }
     let returns = if let Token {
         value: TokenValue::Colon,
         range,
@@ -990,16 +1819,35 @@ fn import_declaration<'a>(
     let (name, provenance) = word(source, start, "expected word after 'import'")?;
     let mut cursor = provenance.end();
     let mut components = vec![name];
+    let mut names = None;
+    let mut alias = None;
 
     loop {
         let next = next_token(source, cursor, "expected . or ; in import declaration")?;
         cursor = next.range.end();
         match &next.value {
             TokenValue::Semicolon => break,
-            TokenValue::Period => {
-                let (name, provenance) = word(source, cursor, "expected word after . in import")?;
+            // `as` is only special here, so it doesn't take a keyword away from other code
+            TokenValue::Word(keyword) if keyword == "as" && names.is_none() && alias.is_none() => {
+                let (name, provenance) = word(source, cursor, "expected name after 'as'")?;
                 cursor = provenance.end();
-                components.push(name);
+                alias = Some(name);
+            }
+            TokenValue::Period if names.is_none() && alias.is_none() => {
+                if let Some(Ok(Token {
+                    value: TokenValue::OpenBracket,
+                    ..
+                })) = source.peek()
+                {
+                    let (imported, end) = import_name_list(source, cursor)?;
+                    cursor = end;
+                    names = Some(imported);
+                } else {
+                    let (name, provenance) =
+                        word(source, cursor, "expected word after . in import")?;
+                    cursor = provenance.end();
+                    components.push(name);
+                }
             }
             _ => {
                 return Err(ParseError::UnexpectedToken(
@@ -1011,11 +1859,59 @@ fn import_declaration<'a>(
     }
 
     Ok(AstNode::new(
-        AstNodeValue::Import(components),
+        AstNodeValue::Import(components, names, alias),
         SourceRange::new(start, provenance.end()),
     ))
 }
 
+/**
+ * The `{a, b}` at the end of an import, returning the names and the end of the closing brace
+ */
+fn import_name_list(
+    source: &mut TokenIter,
+    cursor: SourceMarker,
+) -> Result<(Vec<String>, SourceMarker), ParseError> {
+    let mut cursor = assert_next_lexeme_eq(
+        source,
+        TokenValue::OpenBracket,
+        cursor,
+        "expected { in import",
+    )?
+    .range
+    .end();
+    let mut names = Vec::new();
+    loop {
+        let next = next_token(source, cursor, "expected name or } in import")?;
+        cursor = next.range.end();
+        match next.value {
+            TokenValue::CloseBracket => break,
+            TokenValue::Word(name) => {
+                names.push(name);
+                let next = next_token(source, cursor, "expected , or } in import")?;
+                cursor = next.range.end();
+                match next.value {
+                    TokenValue::Comma => {}
+                    TokenValue::CloseBracket => break,
+                    _ => {
+                        return Err(ParseError::UnexpectedToken(
+                            Box::new(next),
+                            "expected , or } in import",
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(ParseError::UnexpectedToken(
+                    Box::new(next),
+                    "expected name or } in import",
+                ))
+            }
+        }
+    }
+
+    Ok((names, cursor))
+}
+
 fn variable_declaration<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
@@ -1027,16 +1923,30 @@ fn variable_declaration<'a>(
         cursor,
         "expected word after 'let' in declaration",
     )?;
-    let cursor = assert_next_lexeme_eq(
-        source,
-        TokenValue::Assign,
-        provenance.end(),
-        "expected = after let binding target",
-    )?
-    .range
-    .end();
-    let value = expression(source, context, cursor, true)?;
-    provenance.set_end(value.provenance.end());
+    let value = if matches!(
+        source.peek(),
+        Some(Ok(Token {
+            value: TokenValue::Semicolon,
+            ..
+        }))
+    ) {
+        if type_hint.is_none() {
+            return Err(ParseError::UninitializedWithoutType(provenance));
+        }
+        None
+    } else {
+        let cursor = assert_next_lexeme_eq(
+            source,
+            TokenValue::Assign,
+            provenance.end(),
+            "expected = after let binding target",
+        )?
+        .range
+        .end();
+        let value = expression(source, context, cursor, true)?;
+        provenance.set_end(value.provenance.end());
+        Some(add_node(context, value))
+    };
 
     let type_hint = type_hint.map(|type_hint| add_node(context, type_hint));
 
@@ -1048,7 +1958,7 @@ fn variable_declaration<'a>(
     )?;
 
     Ok(AstNode::new(
-        AstNodeValue::Declaration(name, type_hint, add_node(context, value), VariableID::new()),
+        AstNodeValue::Declaration(name, type_hint, value, VariableID::new()),
         provenance,
     ))
 }
@@ -1162,6 +2072,7 @@ fn type_expression<'a>(
     let next = next_token(source, cursor, "expected type")?;
     let node = match next.value {
         TokenValue::Void => AstNode::new(AstNodeValue::VoidType, next.range),
+        TokenValue::Asterisk => return Err(ParseError::DerefInTypePosition(next.range)),
         ptr @ (TokenValue::Unique | TokenValue::Ref) => {
             let subtype = type_expression(source, context, next.range.end())?;
             let end = subtype.provenance.end();
@@ -1277,6 +2188,53 @@ fn type_expression<'a>(
                 SourceRange::new(next.range.start(), token.range.end()),
             )
         }
+        TokenValue::Function => {
+            let token = assert_next_lexeme_eq(
+                source,
+                TokenValue::OpenParen,
+                next.range.end(),
+                "expected ( after fn in function type",
+            )?;
+            let mut end = token.range.end();
+            let mut params = Vec::new();
+            let mut closed = peek_for_closed(
+                source,
+                TokenValue::CloseParen,
+                end,
+                "expected ) or next parameter type",
+            )?;
+            while !closed {
+                let param = type_expression(source, context, end)?;
+                end = param.provenance.end();
+                params.push(param);
+
+                let (should_break, new_end) = comma_or_end_list(
+                    source,
+                    TokenValue::CloseParen,
+                    end,
+                    "expected comma or ) to end parameter types",
+                )?;
+                end = new_end.end();
+                closed = should_break;
+            }
+            let returns = if let Some(Token {
+                value: TokenValue::Colon,
+                ..
+            }) = peek_token_optional(source)?
+            {
+                let colon = already_peeked_token(source)?;
+                let returns = type_expression(source, context, colon.range.end())?;
+                end = returns.provenance.end();
+                Some(add_node(context, returns))
+            } else {
+                None
+            };
+
+            AstNode::new(
+                AstNodeValue::FunctionType { params, returns },
+                SourceRange::new(next.range.start(), end),
+            )
+        }
         TokenValue::Word(name) => match name.as_str() {
             "generator" => {
                 let token = assert_next_lexeme_eq(
@@ -1360,9 +2318,17 @@ fn expression_pratt<'a>(
     let Token { value, range } = next_token(source, start, "expected expression")?;
     let start = range.start();
     let cursor = range.end();
-    let mut left = match value {
-        // TODO: should this be treated as a unary operator instead?
-        TokenValue::Minus => {
+    let left = match value {
+        // Negative numbers are literals, and negating anything else is a unary operator
+        TokenValue::Minus
+            if matches!(
+                peek_token_optional(source)?,
+                Some(Token {
+                    value: TokenValue::Int(_),
+                    ..
+                })
+            ) =>
+        {
             let (int, range) = integer(source, cursor, "expected digit after negative sign")?;
             try_decimal(source, -(int as i64), range)?
         }
@@ -1390,6 +2356,34 @@ fn expression_pratt<'a>(
         // Atoms
         TokenValue::True => AstNode::new(AstNodeValue::Bool(true), range),
         TokenValue::False => AstNode::new(AstNodeValue::Bool(false), range),
+        TokenValue::Word(word)
+            if matches!(
+                peek_token_optional(source)?,
+                Some(Token {
+                    value: TokenValue::Colon,
+                    ..
+                })
+            ) =>
+        {
+            let colon = already_peeked_token(source)?;
+            labeled_loop(source, context, word, start, colon.range.end())?
+        }
+        // Like `type`, `matches` is only special when it's called, so it can still be a name. The
+        // right side of `.` and `?.` binds tighter than anything else, and is always a field or
+        // method rather than the predicate
+        TokenValue::Word(word)
+            if word == "matches"
+                && min_binding < NULL_CHAINING
+                && matches!(
+                    peek_token_optional(source)?,
+                    Some(Token {
+                        value: TokenValue::OpenParen,
+                        ..
+                    })
+                ) =>
+        {
+            matches_variant(source, context, start, cursor)?
+        }
         TokenValue::Word(word) => AstNode::new(AstNodeValue::name(word), range),
         TokenValue::Null => AstNode::new(AstNodeValue::Null, range),
         TokenValue::CharacterLiteral(c) => AstNode::new(AstNodeValue::CharLiteral(c), range),
@@ -1407,6 +2401,7 @@ fn expression_pratt<'a>(
                 AstNode::new(AstNodeValue::Yield(Some(inner)), range)
             }
         }
+        TokenValue::Void => return Err(ParseError::TypeInValuePosition(range)),
         // Prefix operator
         value => {
             let Some(((), right_binding)) = prefix_binding_power(&value) else {
@@ -1415,6 +2410,20 @@ fn expression_pratt<'a>(
                     "expected an expression",
                 ));
             };
+            if matches!(value, TokenValue::Ref | TokenValue::Unique) {
+                let next = peek_token(source, cursor, "expected value after reference operator")?;
+                match next.value {
+                    TokenValue::Ref | TokenValue::Unique => {
+                        let range = SourceRange::new(start, next.range.end());
+                        return Err(ParseError::NestedReferenceOperator(range));
+                    }
+                    TokenValue::Void => {
+                        let range = SourceRange::new(start, next.range.end());
+                        return Err(ParseError::TypeInValuePosition(range));
+                    }
+                    _ => {}
+                }
+            }
             let right = expression_pratt(source, context, cursor, right_binding, can_be_struct)?;
             let end = right.provenance.end();
             let right = add_node(context, right);
@@ -1424,6 +2433,9 @@ fn expression_pratt<'a>(
                     TokenValue::Unique => AstNodeValue::TakeUnique(right),
                     TokenValue::Asterisk => AstNodeValue::Deref(right),
                     TokenValue::Exclamation => AstNodeValue::UnaryExpr(UnaryOp::BooleanNot, right),
+                    TokenValue::Minus => AstNodeValue::UnaryExpr(UnaryOp::Negate, right),
+                    TokenValue::Plus => AstNodeValue::UnaryExpr(UnaryOp::Plus, right),
+                    TokenValue::Tilde => AstNodeValue::UnaryExpr(UnaryOp::BitNot, right),
                     other => unreachable!("prefix operator {:?}", other),
                 },
                 SourceRange::new(range.start(), end),
@@ -1431,6 +2443,20 @@ fn expression_pratt<'a>(
         }
     };
 
+    expression_operators(source, context, left, start, min_binding, can_be_struct)
+}
+
+/**
+ * Apply the postfix and infix operators that follow an expression that's already been parsed
+ */
+fn expression_operators<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    mut left: AstNode<'a>,
+    start: SourceMarker,
+    min_binding: u8,
+    can_be_struct: bool,
+) -> Result<AstNode<'a>, ParseError> {
     loop {
         let op = match peek_token_optional(source)? {
             None => break,
@@ -1444,6 +2470,13 @@ fn expression_pratt<'a>(
             if op.value == TokenValue::OpenBracket && !can_be_struct {
                 break;
             }
+            // A `!` after a block or on a later line starts a new statement that negates
+            // something, rather than unwrapping the value before it
+            if op.value == TokenValue::Exclamation
+                && (op.range.start_line != left.provenance.end_line || ends_in_block(&left))
+            {
+                break;
+            }
             let Token { value, range } = already_peeked_token(source)?;
 
             match value {
@@ -1480,78 +2513,83 @@ fn expression_pratt<'a>(
                 }
                 TokenValue::OpenSquare => {
                     let index = expression(source, context, range.end(), can_be_struct)?;
-                    let Token { range, .. } = assert_next_lexeme_eq(
-                        source,
-                        TokenValue::CloseSquare,
-                        index.provenance.end(),
-                        "expected ] to follow array index",
-                    )?;
-                    left = AstNode::new(
-                        AstNodeValue::BinExpr(
-                            BinOp::Index,
-                            add_node(context, left),
-                            add_node(context, index),
-                        ),
-                        SourceRange::new(start, range.end()),
-                    );
+                    if let Some(Token {
+                        value: TokenValue::DotDot,
+                        ..
+                    }) = peek_token_optional(source)?
+                    {
+                        let Token { range, .. } = already_peeked_token(source)?;
+                        let end = expression(source, context, range.end(), can_be_struct)?;
+                        let Token { range, .. } = assert_next_lexeme_eq(
+                            source,
+                            TokenValue::CloseSquare,
+                            end.provenance.end(),
+                            "expected ] to follow array slice",
+                        )?;
+                        left = AstNode::new(
+                            AstNodeValue::ArraySlice(
+                                add_node(context, left),
+                                add_node(context, index),
+                                add_node(context, end),
+                            ),
+                            SourceRange::new(start, range.end()),
+                        );
+                    } else {
+                        let Token { range, .. } = assert_next_lexeme_eq(
+                            source,
+                            TokenValue::CloseSquare,
+                            index.provenance.end(),
+                            "expected ] to follow array index",
+                        )?;
+                        left = AstNode::new(
+                            AstNodeValue::BinExpr(
+                                BinOp::Index,
+                                add_node(context, left),
+                                add_node(context, index),
+                            ),
+                            SourceRange::new(start, range.end()),
+                        );
+                    }
                 }
                 TokenValue::OpenBracket if can_be_struct => {
                     // TODO: extract struct literals into their own method
                     let mut end = range.end();
                     let mut fields = HashMap::new();
 
-                    loop {
-                        if TokenValue::CloseBracket
-                            == peek_token(source, end, "expected } or next field")?.value
-                        {
-                            source.next();
-                            break;
-                        }
-
+                    let mut closed = peek_for_closed(
+                        source,
+                        TokenValue::CloseBracket,
+                        end,
+                        "expected } or next field",
+                    )?;
+                    while !closed {
                         let (field, field_range) =
                             word(source, end, "expected field in struct literal")?;
-                        if let lex @ (TokenValue::Comma | TokenValue::CloseBracket) =
-                            &peek_token(source, end, "expected comma or } to end struct literal")?
-                                .value
-                        {
-                            let lex = lex.clone();
-                            source.next();
-                            let argument =
-                                AstNode::new(AstNodeValue::name(field.clone()), field_range);
-                            fields.insert(field, argument);
-                            if lex == TokenValue::CloseBracket {
-                                break;
-                            }
-                        } else {
-                            let token = assert_next_lexeme_eq(
-                                source,
-                                TokenValue::Colon,
-                                end,
-                                "expected colon after field name",
-                            )?;
-                            let cursor = token.range.end();
-                            let argument = expression(source, context, cursor, can_be_struct)?;
-                            end = argument.provenance.end();
-                            fields.insert(field, argument);
-
-                            if let TokenValue::Comma = peek_token(
+                        let argument = if TokenValue::Colon
+                            == peek_token(
                                 source,
-                                end,
-                                "expected comma or } to end struct literal",
+                                field_range.end(),
+                                "expected colon, comma or } after field name",
                             )?
                             .value
-                            {
-                                source.next();
-                            } else {
-                                assert_next_lexeme_eq(
-                                    source,
-                                    TokenValue::CloseBracket,
-                                    end,
-                                    "expected close parenthesis or comma after argument",
-                                )?;
-                                break;
-                            }
-                        }
+                        {
+                            let token = already_peeked_token(source)?;
+                            expression(source, context, token.range.end(), can_be_struct)?
+                        } else {
+                            // Shorthand for a field with the same name as a variable
+                            AstNode::new(AstNodeValue::name(field.clone()), field_range)
+                        };
+                        end = argument.provenance.end();
+                        fields.insert(field, argument);
+
+                        let (should_break, new_end) = comma_or_end_list(
+                            source,
+                            TokenValue::CloseBracket,
+                            end,
+                            "expected comma or } to end struct literal",
+                        )?;
+                        end = new_end.end();
+                        closed = should_break;
                     }
 
                     let AstNodeValue::Name { .. } = left.value else {
@@ -1566,6 +2604,28 @@ fn expression_pratt<'a>(
                         SourceRange::new(start, end),
                     );
                 }
+                TokenValue::Exclamation => {
+                    left = AstNode::new(
+                        AstNodeValue::UnaryExpr(UnaryOp::NullableUnwrap, add_node(context, left)),
+                        SourceRange::new(start, range.end()),
+                    );
+                }
+                TokenValue::Is => {
+                    let (variant, variant_range) =
+                        word(source, range.end(), "expected variant name after is")?;
+                    left = AstNode::new(
+                        AstNodeValue::IsVariant(add_node(context, left), variant),
+                        SourceRange::new(start, variant_range.end()),
+                    );
+                }
+                TokenValue::Word(_) => {
+                    let (name, name_range) =
+                        word(source, range.end(), "expected struct name after as")?;
+                    left = AstNode::new(
+                        AstNodeValue::Downcast(add_node(context, left), name),
+                        SourceRange::new(start, name_range.end()),
+                    );
+                }
                 token => unreachable!("postfix operator {:?}", token),
             }
 
@@ -1585,7 +2645,7 @@ fn expression_pratt<'a>(
             let right =
                 expression_pratt(source, context, current.end(), right_binding, can_be_struct)?;
 
-            let bin_op = match value {
+            let bin_op = match &value {
                 TokenValue::Assign => BinOp::Assignment,
                 TokenValue::PlusEquals => BinOp::AddAssign,
                 TokenValue::MinusEquals => BinOp::SubtractAssign,
@@ -1617,6 +2677,17 @@ fn expression_pratt<'a>(
                 SourceRange::new(start, end),
             );
 
+            if is_comparison(&value) {
+                if let Some(next) = peek_token_optional(source)? {
+                    if is_comparison(&next.value) {
+                        return Err(ParseError::ChainedComparison(SourceRange::new(
+                            start,
+                            next.range.end(),
+                        )));
+                    }
+                }
+            }
+
             continue;
         }
 
@@ -1626,35 +2697,62 @@ fn expression_pratt<'a>(
     Ok(left)
 }
 
-const ASSIGNMENT: u8 = 2;
-const NULL_COALESCE: u8 = ASSIGNMENT + 2;
+/*
+ * Each operator's binding power. Infix operators are left-associative, except for assignment and
+ * null coalescing, which are right-associative, and comparisons, which can't be chained at all.
+ */
+pub const ASSIGNMENT: u8 = 2;
+pub const NULL_COALESCE: u8 = ASSIGNMENT + 2;
 // bools
-const BOOLEAN_OR: u8 = NULL_COALESCE + 2;
-const BOOLEAN_AND: u8 = BOOLEAN_OR + 2;
-const BOOLEAN_NOT: u8 = BOOLEAN_AND + 1;
-const COMPARE: u8 = BOOLEAN_NOT + 2;
+pub const BOOLEAN_OR: u8 = NULL_COALESCE + 2;
+pub const BOOLEAN_AND: u8 = BOOLEAN_OR + 2;
+pub const BOOLEAN_NOT: u8 = BOOLEAN_AND + 1;
+pub const COMPARE: u8 = BOOLEAN_NOT + 2;
+pub const CAST: u8 = COMPARE + 1;
 // math
-const SUM: u8 = COMPARE + 2;
-const FACTOR: u8 = SUM + 2;
+pub const SUM: u8 = COMPARE + 2;
+pub const FACTOR: u8 = SUM + 2;
+pub const NEGATE: u8 = FACTOR + 1;
 // misc
-const CONCAT: u8 = FACTOR + 2;
-const REFERENCE: u8 = CONCAT + 1;
-const CALL: u8 = REFERENCE + 2;
-const NULL_CHAINING: u8 = CALL + 1;
-const DOT: u8 = NULL_CHAINING + 1;
+pub const CONCAT: u8 = FACTOR + 2;
+pub const REFERENCE: u8 = CONCAT + 1;
+pub const CALL: u8 = REFERENCE + 2;
+pub const NULL_CHAINING: u8 = CALL + 1;
+pub const DOT: u8 = NULL_CHAINING + 1;
 
 fn prefix_binding_power(op: &TokenValue) -> Option<((), u8)> {
     let res = match op {
         TokenValue::Ref | TokenValue::Unique | TokenValue::Asterisk => ((), REFERENCE),
         TokenValue::Exclamation => ((), BOOLEAN_NOT),
+        TokenValue::Minus | TokenValue::Plus | TokenValue::Tilde => ((), NEGATE),
         _ => return None,
     };
     Some(res)
 }
 
+/**
+ * Statements like `if` and `while` end at their closing bracket, so nothing after them continues
+ * the expression
+ */
+fn ends_in_block(node: &AstNode<'_>) -> bool {
+    matches!(
+        node.value,
+        AstNodeValue::If(_)
+            | AstNodeValue::IfLet(_)
+            | AstNodeValue::While(..)
+            | AstNodeValue::Loop(_)
+            | AstNodeValue::LabeledLoop(..)
+            | AstNodeValue::Block(_)
+            | AstNodeValue::Match(_)
+    )
+}
+
 fn postfix_binding_power(op: &TokenValue) -> Option<(u8, ())> {
     let res = match op {
         TokenValue::OpenParen | TokenValue::OpenSquare | TokenValue::OpenBracket => (CALL, ()),
+        TokenValue::Exclamation => (NULL_CHAINING, ()),
+        TokenValue::Is => (COMPARE, ()),
+        TokenValue::Word(keyword) if keyword == "as" => (CAST, ()),
         _ => return None,
     };
     Some(res)
@@ -1667,25 +2765,32 @@ fn infix_binding_power(op: &TokenValue) -> Option<(u8, u8)> {
         | TokenValue::MinusEquals
         | TokenValue::AsteriskEquals
         | TokenValue::ForwardSlashEquals => (ASSIGNMENT, ASSIGNMENT - 1),
-        TokenValue::LessThan
-        | TokenValue::GreaterThan
-        | TokenValue::GreaterEqualThan
-        | TokenValue::LessEqualThan
-        | TokenValue::NotEquals
-        | TokenValue::EqualTo => (COMPARE, COMPARE - 1),
-        TokenValue::Plus | TokenValue::Minus => (SUM, SUM - 1),
-        TokenValue::Asterisk | TokenValue::ForwardSlash => (FACTOR, FACTOR - 1),
-        TokenValue::Concat => (CONCAT, CONCAT - 1),
+        op if is_comparison(op) => (COMPARE, COMPARE + 1),
+        TokenValue::Plus | TokenValue::Minus => (SUM, SUM + 1),
+        TokenValue::Asterisk | TokenValue::ForwardSlash => (FACTOR, FACTOR + 1),
+        TokenValue::Concat => (CONCAT, CONCAT + 1),
         TokenValue::Period => (DOT - 1, DOT),
         TokenValue::NullChaining => (NULL_CHAINING - 1, NULL_CHAINING),
-        TokenValue::BooleanAnd => (BOOLEAN_AND, BOOLEAN_AND - 1),
-        TokenValue::BooleanOr => (BOOLEAN_OR, BOOLEAN_OR - 1),
+        TokenValue::BooleanAnd => (BOOLEAN_AND, BOOLEAN_AND + 1),
+        TokenValue::BooleanOr => (BOOLEAN_OR, BOOLEAN_OR + 1),
         TokenValue::NullCoalesce => (NULL_COALESCE, NULL_COALESCE - 1),
         _ => return None,
     };
     Some(res)
 }
 
+fn is_comparison(op: &TokenValue) -> bool {
+    matches!(
+        op,
+        TokenValue::LessThan
+            | TokenValue::GreaterThan
+            | TokenValue::GreaterEqualThan
+            | TokenValue::LessEqualThan
+            | TokenValue::NotEquals
+            | TokenValue::EqualTo
+    )
+}
+
 fn array_literal<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
@@ -1717,12 +2822,6 @@ fn array_literal<'a>(
             )?;
 
             while !closed {
-                let peeked = peek_token(source, end, "expected , or ]")?;
-                if TokenValue::CloseSquare == peeked.value {
-                    source.next();
-                    break;
-                }
-                end = peeked.range.end();
                 let expr = expression(source, context, end, can_be_struct)?;
                 end = expr.provenance.end();
                 children.push(expr);
@@ -1915,11 +3014,29 @@ fn if_or_while<'a>(
     is_if_or_while: TokenValue,
     cursor: SourceMarker,
 ) -> Result<AstNode<'a>, ParseError> {
+    if is_if_or_while == TokenValue::If
+        && peek_token(source, cursor, "expected predicate after if")?.value == TokenValue::Let
+    {
+        let token = already_peeked_token(source)?;
+        return if_let(source, context, token.range.end());
+    }
+
     let predicate = expression(source, context, cursor, false)?;
+    let mut cursor = predicate.provenance.end();
+    let step = if is_if_or_while == TokenValue::While
+        && peek_token(source, cursor, "expected { after predicate")?.value == TokenValue::Semicolon
+    {
+        let token = already_peeked_token(source)?;
+        let step = expression(source, context, token.range.end(), false)?;
+        cursor = step.provenance.end();
+        Some(add_node(context, step))
+    } else {
+        None
+    };
     let token = assert_next_lexeme_eq(
         source,
         TokenValue::OpenBracket,
-        predicate.provenance.end(),
+        cursor,
         "expected { after predicate",
     )?;
     let cursor = token.range.end();
@@ -1930,36 +3047,8 @@ fn if_or_while<'a>(
     let block_ptr = add_node(context, if_block);
 
     Ok(if is_if_or_while == TokenValue::If {
-        match peek_token_optional(source)? {
-            Some(Token {
-                value: TokenValue::Word(word),
-                ..
-            }) if word == "else" => {
-                let else_token = already_peeked_token(source)?;
-                let next_token = peek_token(
-                    source,
-                    else_token.range.end(),
-                    "expected { or if after else",
-                )?;
-                let else_block = match next_token.value {
-                    TokenValue::OpenBracket => {
-                        let token = already_peeked_token(source)?;
-                        block(source, context, token.range.end())?
-                    }
-                    TokenValue::If => {
-                        let token = already_peeked_token(source)?;
-                        let if_node =
-                            if_or_while(source, context, TokenValue::If, token.range.end())?;
-                        let provenance = if_node.provenance.clone();
-                        AstNode::new(AstNodeValue::Block(vec![if_node]), provenance)
-                    }
-                    _ => {
-                        return Err(ParseError::UnexpectedToken(
-                            Box::new(already_peeked_token(source)?),
-                            "expected { or if after else",
-                        ))
-                    }
-                };
+        match else_branch(source, context)? {
+            Some(else_block) => {
                 let provenance = SourceRange::new(cursor, else_block.provenance.end());
                 let else_ptr = add_node(context, else_block);
                 AstNode::new(
@@ -1971,7 +3060,7 @@ fn if_or_while<'a>(
                     provenance,
                 )
             }
-            _ => AstNode::new(
+            None => AstNode::new(
                 AstNodeValue::If(IfDeclaration {
                     condition: predicate_ptr,
                     if_branch: block_ptr,
@@ -1981,10 +3070,137 @@ fn if_or_while<'a>(
             ),
         }
     } else {
-        AstNode::new(AstNodeValue::While(predicate_ptr, block_ptr), provenance)
+        AstNode::new(
+            AstNodeValue::While(predicate_ptr, step, block_ptr),
+            provenance,
+        )
     })
 }
 
+/**
+ * Either `if let variant(binding) = union` or `if let binding = nullable`
+ */
+fn if_let<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    cursor: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let start = cursor;
+    let pattern = match_case_variant(source, cursor)?;
+    let token = assert_next_lexeme_eq(
+        source,
+        TokenValue::Assign,
+        pattern.provenance.end(),
+        "expected = after if let pattern",
+    )?;
+    let value = expression(source, context, token.range.end(), false)?;
+    let token = assert_next_lexeme_eq(
+        source,
+        TokenValue::OpenBracket,
+        value.provenance.end(),
+        "expected { after if let value",
+    )?;
+    let if_block = block(source, context, token.range.end())?;
+    let mut end = if_block.provenance.end();
+
+    let else_branch = else_branch(source, context)?.map(|else_block| {
+        end = else_block.provenance.end();
+        add_node(context, else_block)
+    });
+
+    Ok(AstNode::new(
+        AstNodeValue::IfLet(IfLetDeclaration {
+            pattern,
+            var_id: VariableID::new(),
+            value: add_node(context, value),
+            if_branch: add_node(context, if_block),
+            else_branch,
+        }),
+        SourceRange::new(start, end),
+    ))
+}
+
+fn else_branch<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+) -> Result<Option<AstNode<'a>>, ParseError> {
+    match peek_token_optional(source)? {
+        Some(Token {
+            value: TokenValue::Word(word),
+            ..
+        }) if word == "else" => {
+            let else_token = already_peeked_token(source)?;
+            let next_token = peek_token(
+                source,
+                else_token.range.end(),
+                "expected { or if after else",
+            )?;
+            let else_block = match next_token.value {
+                TokenValue::OpenBracket => {
+                    let token = already_peeked_token(source)?;
+                    block(source, context, token.range.end())?
+                }
+                TokenValue::If => {
+                    let token = already_peeked_token(source)?;
+                    let if_node = if_or_while(source, context, TokenValue::If, token.range.end())?;
+                    let provenance = if_node.provenance.clone();
+                    AstNode::new(AstNodeValue::Block(vec![if_node]), provenance)
+                }
+                _ => {
+                    return Err(ParseError::UnexpectedToken(
+                        Box::new(already_peeked_token(source)?),
+                        "expected { or if after else",
+                    ))
+                }
+            };
+            Ok(Some(else_block))
+        }
+        _ => Ok(None),
+    }
+}
+
+/**
+ * `matches(value, variant)`, which is the same test as `value is variant`
+ */
+fn matches_variant<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    start: SourceMarker,
+    cursor: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let cursor = assert_next_lexeme_eq(
+        source,
+        TokenValue::OpenParen,
+        cursor,
+        "expected ( after matches",
+    )?
+    .range
+    .end();
+    let value = expression(source, context, cursor, true)?;
+    let cursor = assert_next_lexeme_eq(
+        source,
+        TokenValue::Comma,
+        value.provenance.end(),
+        "expected , after matched value",
+    )?
+    .range
+    .end();
+    let (variant, variant_range) = word(source, cursor, "expected variant name in matches")?;
+    let end = assert_next_lexeme_eq(
+        source,
+        TokenValue::CloseParen,
+        variant_range.end(),
+        "expected ) after variant name",
+    )?
+    .range
+    .end();
+
+    Ok(AstNode::new(
+        AstNodeValue::IsVariant(context.alloc(value), variant),
+        SourceRange::new(start, end),
+    ))
+}
+
 fn match_statement<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
@@ -2052,17 +3268,32 @@ fn match_case_statement<'a>(
         cursor = already_peeked_token(source)?.range.end();
         let body = block(source, context, cursor)?;
         cursor = body.provenance.end();
+        // Block arms don't need a comma, but may have one
+        if let Some(Token {
+            value: TokenValue::Comma,
+            range,
+        }) = peek_token_optional(source)?
+        {
+            cursor = range.end();
+            source.next();
+        }
         body
     } else {
         let expr = expression(source, context, cursor, true)?;
-        cursor = assert_next_lexeme_eq(
-            source,
-            TokenValue::Comma,
-            expr.provenance.end(),
-            "expected , after variant expression",
-        )?
-        .range
-        .end();
+        cursor = expr.provenance.end();
+        // The last arm may leave off its comma
+        if peek_token(source, cursor, "expected , after variant expression")?.value
+            != TokenValue::CloseBracket
+        {
+            cursor = assert_next_lexeme_eq(
+                source,
+                TokenValue::Comma,
+                cursor,
+                "expected , after variant expression",
+            )?
+            .range
+            .end();
+        }
         expr
     };
 
@@ -2078,8 +3309,8 @@ fn match_case_variant(
     source: &mut TokenIter,
     mut cursor: SourceMarker,
 ) -> Result<MatchCaseVariant, ParseError> {
-    let start = cursor;
     let (name, range) = word(source, cursor, "expected name of case variant")?;
+    let start = range.start();
     cursor = range.end();
 
     let mut bindings = Vec::new();
@@ -2128,6 +3359,66 @@ fn parse_loop<'a>(
     Ok(AstNode::new(AstNodeValue::Loop(body), provenance))
 }
 
+fn labeled_loop<'a>(
+    source: &mut TokenIter,
+    context: &'a Arena<AstNode<'a>>,
+    label: String,
+    start: SourceMarker,
+    cursor: SourceMarker,
+) -> Result<AstNode<'a>, ParseError> {
+    let token = next_token(source, cursor, "expected a loop after a label")?;
+    let mut inner = match token.value {
+        TokenValue::While => if_or_while(source, context, TokenValue::While, token.range.end())?,
+        TokenValue::Loop => parse_loop(source, context, token.range.end())?,
+        _ => {
+            return Err(ParseError::UnexpectedToken(
+                Box::new(token),
+                "expected a loop after a label",
+            ))
+        }
+    };
+    inner.children_mut(|child| resolve_loop_label(child, &label, 0));
+    let provenance = SourceRange::new(start, inner.provenance.end());
+    let inner = add_node(context, inner);
+
+    Ok(AstNode::new(
+        AstNodeValue::LabeledLoop(label, inner),
+        provenance,
+    ))
+}
+
+/**
+ * Point the breaks and continues that name this label at the loop, now that we know how deeply
+ * each one is nested inside it. A `break` with a value that happens to be the label's name is
+ * taken to be the label.
+ */
+fn resolve_loop_label(node: &mut AstNode<'_>, label: &str, depth: usize) {
+    match &mut node.value {
+        AstNodeValue::Break(Some(value), None) if matches!(&value.value, AstNodeValue::Name { value, .. } if value == label) =>
+        {
+            node.value = AstNodeValue::Break(
+                None,
+                Some(LoopLabel {
+                    name: label.to_string(),
+                    depth: Some(depth),
+                }),
+            );
+        }
+        AstNodeValue::Break(_, Some(jump_label)) | AstNodeValue::Continue(Some(jump_label))
+            if jump_label.name == label =>
+        {
+            jump_label.depth.get_or_insert(depth);
+            node.children_mut(|child| resolve_loop_label(child, label, depth));
+        }
+        // Inner functions can't jump out to loops in the function they're declared in
+        AstNodeValue::FunctionDeclaration(_) => {}
+        AstNodeValue::While(_, _, _) | AstNodeValue::Loop(_) => {
+            node.children_mut(|child| resolve_loop_label(child, label, depth + 1))
+        }
+        _ => node.children_mut(|child| resolve_loop_label(child, label, depth)),
+    }
+}
+
 fn block<'a>(
     source: &mut TokenIter,
     context: &'a Arena<AstNode<'a>>,
@@ -2343,3 +3634,557 @@ fn skim_off_comments(source: &mut TokenIter) {
         source.next().unwrap().unwrap();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::tokenizer::lex;
+
+    fn parse_source<'a>(
+        arena: &'a Arena<AstNode<'a>>,
+        source: &str,
+    ) -> Result<Vec<AstNode<'a>>, ParseError> {
+        parse(arena, lex("test", source.to_string()))
+    }
+
+    fn declared_type<'a>(nodes: &'a [AstNode<'a>]) -> &'a AstNodeValue<'a> {
+        let AstNodeValue::Declaration(_, Some(ty), _, _) = &nodes[0].value else {
+            panic!("expected a typed declaration, found {:?}", nodes[0].value);
+        };
+        &ty.value
+    }
+
+    fn statement_value<'a>(nodes: &'a [AstNode<'a>]) -> &'a AstNodeValue<'a> {
+        let AstNodeValue::Statement(inner) = &nodes[0].value else {
+            panic!("expected a statement, found {:?}", nodes[0].value);
+        };
+        &inner.value
+    }
+
+    /**
+     * Compare two trees while ignoring where their nodes came from in the source
+     */
+    fn assert_same_tree<'a>(left: &'a AstNode<'a>, right: &'a AstNode<'a>) {
+        assert_eq!(
+            std::mem::discriminant(&left.value),
+            std::mem::discriminant(&right.value),
+            "{:?} != {:?}",
+            left.value,
+            right.value
+        );
+        match (&left.value, &right.value) {
+            (AstNodeValue::Name { value: left, .. }, AstNodeValue::Name { value: right, .. }) => {
+                assert_eq!(left, right)
+            }
+            (AstNodeValue::BinExpr(left, _, _), AstNodeValue::BinExpr(right, _, _)) => {
+                assert_eq!(left, right)
+            }
+            (AstNodeValue::Int(left), AstNodeValue::Int(right)) => assert_eq!(left, right),
+            _ => {}
+        }
+        let mut left_children = Vec::new();
+        left.children(|child| left_children.push(child));
+        let mut right_children = Vec::new();
+        right.children(|child| right_children.push(child));
+        assert_eq!(left_children.len(), right_children.len());
+        for (left, right) in left_children.into_iter().zip(right_children) {
+            assert_same_tree(left, right);
+        }
+    }
+
+    #[test]
+    fn walk_reaches_every_node_once() {
+        fn count_recursive<'a>(node: &'a AstNode<'a>) -> usize {
+            let mut count = 1;
+            node.children(|child| count += count_recursive(child));
+            count
+        }
+
+        let arena = Arena::new();
+        let nodes = parse_source(
+            &arena,
+            r#"
+struct Point {
+    x: i32,
+    y: i32,
+}
+fn sum(points: ref list[Point]): i32 {
+    let total = 0;
+    let index = 0;
+    while index < points.len() {
+        total += points[index].x + points[index].y;
+        index += 1;
+    }
+    if total > 100 { 100 } else { total }
+}
+let points = list[Point { x: 1, y: 2 }, Point { x: -3, y: 4 }];
+sum(ref points)
+"#,
+        )
+        .unwrap();
+
+        for node in nodes.iter() {
+            let mut pre_order = Vec::new();
+            node.walk(|node| pre_order.push(node as *const AstNode));
+            let mut post_order = Vec::new();
+            node.walk_post_order(|node| post_order.push(node as *const AstNode));
+            let iterated: Vec<_> = node.iter().map(|node| node as *const AstNode).collect();
+
+            assert_eq!(pre_order.len(), count_recursive(node));
+            let unique: HashSet<_> = pre_order.iter().collect();
+            assert_eq!(unique.len(), pre_order.len());
+            assert_eq!(pre_order[0], node as *const AstNode);
+            assert_eq!(*post_order.last().unwrap(), node as *const AstNode);
+            assert_eq!(iterated, pre_order);
+            let post_order: HashSet<_> = post_order.iter().collect();
+            assert_eq!(post_order, unique);
+        }
+    }
+
+    #[test]
+    fn unique_array_type() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "let x: unique list[i32] = y;").unwrap();
+
+        let AstNodeValue::UniqueType(inner) = declared_type(&nodes) else {
+            panic!("expected unique type");
+        };
+        let AstNodeValue::ArrayType(element) = &inner.value else {
+            panic!("expected array type inside unique");
+        };
+        assert!(matches!(&element.value, AstNodeValue::Name { value, .. } if value == "i32"));
+    }
+
+    #[test]
+    fn pointer_to_pointer_type() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "let x: unique ref i32 = y;").unwrap();
+
+        let AstNodeValue::UniqueType(inner) = declared_type(&nodes) else {
+            panic!("expected unique type");
+        };
+        assert!(matches!(&inner.value, AstNodeValue::SharedType(_)));
+    }
+
+    #[test]
+    fn dot_binds_tighter_than_take_reference() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "unique a.b;").unwrap();
+
+        let AstNodeValue::TakeUnique(inner) = statement_value(&nodes) else {
+            panic!("expected take unique");
+        };
+        assert!(matches!(
+            &inner.value,
+            AstNodeValue::BinExpr(BinOp::Dot, _, _)
+        ));
+    }
+
+    #[test]
+    fn parenthesized_nested_reference() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "ref (unique x);").unwrap();
+
+        let AstNodeValue::TakeRef(inner) = statement_value(&nodes) else {
+            panic!("expected take ref");
+        };
+        assert!(matches!(&inner.value, AstNodeValue::TakeUnique(_)));
+    }
+
+    #[test]
+    fn while_step() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "while i < 10; i += 1 { continue; }").unwrap();
+
+        let AstNodeValue::While(condition, Some(step), body) = &nodes[0].value else {
+            panic!("expected while with a step, found {:?}", nodes[0].value);
+        };
+        assert!(matches!(
+            &condition.value,
+            AstNodeValue::BinExpr(BinOp::LessThan, _, _)
+        ));
+        assert!(matches!(
+            &step.value,
+            AstNodeValue::BinExpr(BinOp::AddAssign, _, _)
+        ));
+        let AstNodeValue::Block(contents) = &body.value else {
+            panic!("expected block body");
+        };
+        assert!(matches!(&contents[0].value, AstNodeValue::Continue(None)));
+    }
+
+    #[test]
+    fn labeled_loop_jumps() {
+        let arena = Arena::new();
+        let nodes = parse_source(
+            &arena,
+            "outer: while true { loop { break outer; } continue outer; }",
+        )
+        .unwrap();
+
+        let AstNodeValue::LabeledLoop(label, inner) = &nodes[0].value else {
+            panic!("expected labeled loop, found {:?}", nodes[0].value);
+        };
+        assert_eq!(label, "outer");
+        let AstNodeValue::While(_, None, body) = &inner.value else {
+            panic!("expected while, found {:?}", inner.value);
+        };
+        let AstNodeValue::Block(contents) = &body.value else {
+            panic!("expected block body");
+        };
+        let AstNodeValue::Loop(inner_body) = &contents[0].value else {
+            panic!("expected nested loop, found {:?}", contents[0].value);
+        };
+        let AstNodeValue::Block(inner_contents) = &inner_body.value else {
+            panic!("expected block body");
+        };
+        let label = |depth| {
+            Some(LoopLabel {
+                name: "outer".to_string(),
+                depth: Some(depth),
+            })
+        };
+        assert_eq!(inner_contents[0].value, AstNodeValue::Break(None, label(1)));
+        assert_eq!(contents[1].value, AstNodeValue::Continue(label(0)));
+    }
+
+    #[test]
+    fn labeled_break_with_value() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "outer: loop { loop { break outer 5; } }").unwrap();
+
+        let AstNodeValue::LabeledLoop(_, inner) = &nodes[0].value else {
+            panic!("expected labeled loop, found {:?}", nodes[0].value);
+        };
+        let mut breaks = Vec::new();
+        inner.walk(|node| {
+            if let AstNodeValue::Break(value, label) = &node.value {
+                breaks.push((value, label));
+            }
+        });
+        let [(Some(value), Some(label))] = &breaks[..] else {
+            panic!("expected one labeled break with a value, found {breaks:?}");
+        };
+        assert_eq!(value.value, AstNodeValue::Int(5));
+        assert_eq!(
+            label,
+            &LoopLabel {
+                name: "outer".to_string(),
+                depth: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn function_type() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "let f: fn(i32, bool): i32? = add;").unwrap();
+
+        let AstNodeValue::Declaration(_, Some(type_hint), _, _) = &nodes[0].value else {
+            panic!("expected typed declaration, found {:?}", nodes[0].value);
+        };
+        let AstNodeValue::FunctionType {
+            params,
+            returns: Some(returns),
+        } = &type_hint.value
+        else {
+            panic!("expected function type, found {:?}", type_hint.value);
+        };
+        assert_eq!(params.len(), 2);
+        assert!(matches!(&returns.value, AstNodeValue::NullableType(_)));
+    }
+
+    #[test]
+    fn import_names_in_braces() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "import self.helper.{double, Pair,};").unwrap();
+        let AstNodeValue::Import(path, Some(names), None) = &nodes[0].value else {
+            panic!("expected an import of names, found {:?}", nodes[0].value);
+        };
+        assert_eq!(path, &["self", "helper"]);
+        assert_eq!(names, &["double", "Pair"]);
+    }
+
+    #[test]
+    fn method_chain_across_lines() {
+        let arena = Arena::new();
+        let single_line = parse_source(&arena, "counter.add(2).add(10).get()").unwrap();
+        let multi_line = parse_source(
+            &arena,
+            "counter
+    .add(2)
+    .add(10)
+    .get()",
+        )
+        .unwrap();
+        assert_eq!(single_line.len(), 1);
+        assert_eq!(multi_line.len(), 1);
+        assert!(matches!(&multi_line[0].value, AstNodeValue::Call(_, _)));
+        assert_same_tree(&single_line[0], &multi_line[0]);
+    }
+
+    #[test]
+    fn import_alias() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "import self.really.long.name as short;").unwrap();
+        let AstNodeValue::Import(path, None, Some(alias)) = &nodes[0].value else {
+            panic!("expected an aliased import, found {:?}", nodes[0].value);
+        };
+        assert_eq!(path, &["self", "really", "long", "name"]);
+        assert_eq!(alias, "short");
+    }
+
+    #[test]
+    fn unary_operators() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "-x * 2").unwrap();
+        let AstNodeValue::BinExpr(BinOp::Multiply, left, _) = &nodes[0].value else {
+            panic!("expected a multiplication, found {:?}", nodes[0].value);
+        };
+        assert!(matches!(
+            &left.value,
+            AstNodeValue::UnaryExpr(UnaryOp::Negate, _)
+        ));
+
+        let nodes = parse_source(&arena, "~x.y").unwrap();
+        let AstNodeValue::UnaryExpr(UnaryOp::BitNot, operand) = &nodes[0].value else {
+            panic!("expected a bitwise not, found {:?}", nodes[0].value);
+        };
+        assert!(matches!(
+            &operand.value,
+            AstNodeValue::BinExpr(BinOp::Dot, _, _)
+        ));
+
+        let nodes = parse_source(&arena, "+x").unwrap();
+        assert!(matches!(
+            &nodes[0].value,
+            AstNodeValue::UnaryExpr(UnaryOp::Plus, _)
+        ));
+
+        // Negative numbers are still literals
+        let nodes = parse_source(&arena, "-5").unwrap();
+        assert!(matches!(&nodes[0].value, AstNodeValue::Int(-5)));
+    }
+
+    #[test]
+    fn matches_is_a_variant_test() {
+        let arena = Arena::new();
+        let matched = parse_source(&arena, "matches(a.b, Cons) and c").unwrap();
+        let is = parse_source(&arena, "a.b is Cons and c").unwrap();
+        assert_same_tree(&matched[0], &is[0]);
+
+        let AstNodeValue::BinExpr(BinOp::BooleanAnd, left, _) = &matched[0].value else {
+            panic!("expected a boolean and, found {:?}", matched[0].value);
+        };
+        assert!(matches!(&left.value, AstNodeValue::IsVariant(_, variant) if variant == "Cons"));
+        assert!(parse_source(&arena, "matches(a)").is_err());
+    }
+
+    #[test]
+    fn not_after_block_starts_a_statement() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "if c { a }\n!b").unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert!(matches!(
+            &nodes[1].value,
+            AstNodeValue::UnaryExpr(UnaryOp::BooleanNot, _)
+        ));
+    }
+
+    #[test]
+    fn downcast_binds_tighter_than_comparison() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "shape.inner as Circle == null").unwrap();
+        let AstNodeValue::BinExpr(BinOp::EqualTo, left, _) = &nodes[0].value else {
+            panic!("expected a comparison, found {:?}", nodes[0].value);
+        };
+        let AstNodeValue::Downcast(value, name) = &left.value else {
+            panic!("expected a downcast, found {:?}", left.value);
+        };
+        assert_eq!(name, "Circle");
+        assert!(matches!(
+            &value.value,
+            AstNodeValue::BinExpr(BinOp::Dot, _, _)
+        ));
+    }
+
+    #[test]
+    fn export_marks_declarations() {
+        let arena = Arena::new();
+        let nodes =
+            parse_source(&arena, "export fn a() {}\nfn b() {}\nexport struct C {}").unwrap();
+        assert!(matches!(
+            &nodes[0].value,
+            AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
+                is_exported: true,
+                ..
+            })
+        ));
+        assert!(matches!(
+            &nodes[1].value,
+            AstNodeValue::FunctionDeclaration(FunctionDeclarationValue {
+                is_exported: false,
+                ..
+            })
+        ));
+        assert!(matches!(
+            &nodes[2].value,
+            AstNodeValue::StructDeclaration(StructDeclarationValue {
+                is_exported: true,
+                ..
+            })
+        ));
+        assert!(parse_source(&arena, "export let x = 1;").is_err());
+        assert!(parse_source(&arena, "fn a() {\n    export fn b() {}\n}").is_err());
+    }
+
+    #[test]
+    fn struct_field_defaults() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "struct A {\n    x: i32 = 5,\n    y: i32,\n}").unwrap();
+        let AstNodeValue::StructDeclaration(decl) = &nodes[0].value else {
+            panic!("expected a struct, found {:?}", nodes[0].value);
+        };
+        assert_eq!(decl.fields.len(), 2);
+        assert_eq!(decl.field_defaults.len(), 1);
+        assert_eq!(decl.field_defaults[0].0, "x");
+        assert!(matches!(
+            decl.field_defaults[0].1.value,
+            AstNodeValue::Int(5)
+        ));
+    }
+
+    #[test]
+    fn defer_statement() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "defer cleanup(x);\nlet y = 1;").unwrap();
+        assert_eq!(nodes.len(), 2);
+        let AstNodeValue::Defer(deferred) = &nodes[0].value else {
+            panic!("expected a defer, found {:?}", nodes[0].value);
+        };
+        assert!(matches!(&deferred.value, AstNodeValue::Call(_, _)));
+
+        assert!(parse_source(&arena, "defer cleanup(x)").is_err());
+    }
+
+    #[test]
+    fn uninitialized_declaration() {
+        let arena = Arena::new();
+        let nodes = parse_source(&arena, "let x: i32;").unwrap();
+        assert!(matches!(
+            &nodes[0].value,
+            AstNodeValue::Declaration(_, Some(_), None, _)
+        ));
+
+        let result = parse_source(&arena, "let x;");
+        assert!(matches!(
+            result,
+            Err(ParseError::UninitializedWithoutType(_))
+        ));
+    }
+
+    #[test]
+    fn nested_reference_operator() {
+        let arena = Arena::new();
+        let result = parse_source(&arena, "let y = unique unique x;");
+
+        assert!(matches!(
+            result,
+            Err(ParseError::NestedReferenceOperator(_))
+        ));
+    }
+
+    #[test]
+    fn deref_in_type_position() {
+        let arena = Arena::new();
+        let result = parse_source(&arena, "let x: *i32 = y;");
+
+        assert!(matches!(result, Err(ParseError::DerefInTypePosition(_))));
+    }
+
+    #[test]
+    fn type_in_value_position() {
+        let arena = Arena::new();
+        let result = parse_source(&arena, "let x = unique void;");
+
+        assert!(matches!(result, Err(ParseError::TypeInValuePosition(_))));
+    }
+
+    fn unexpected_token_message(source: &str) -> String {
+        let arena = Arena::new();
+        let Err(error @ ParseError::UnexpectedToken(_, _)) = parse_source(&arena, source) else {
+            panic!("{source} should fail with an unexpected token");
+        };
+
+        error.to_string()
+    }
+
+    #[test]
+    fn unexpected_tokens_are_named() {
+        assert_eq!(
+            unexpected_token_message("let x = };"),
+            "unexpected '}', expected an expression: test@1:9"
+        );
+        assert_eq!(
+            unexpected_token_message("let 42 = x;"),
+            "unexpected integer 42, expected word after 'let' in declaration: test@1:5 - 1:6"
+        );
+        assert_eq!(
+            unexpected_token_message("struct { x: i32 }"),
+            "unexpected '{', expected name after 'struct': test@1:8"
+        );
+        assert_eq!(
+            unexpected_token_message("union Shape foo"),
+            "unexpected identifier 'foo', expected open bracket to start variants: test@1:13 - 1:15"
+        );
+        assert_eq!(
+            unexpected_token_message("fn while() {}"),
+            "unexpected keyword 'while', expected name after 'fn': test@1:4 - 1:8"
+        );
+        assert_eq!(
+            unexpected_token_message("import self.'a';"),
+            "unexpected character 'a', expected word after . in import: test@1:13 - 1:15"
+        );
+    }
+
+    #[test]
+    fn error_codes_are_stable_and_unique() {
+        let marker = SourceMarker::new("test", "", 1, 1);
+        let range = SourceRange::new(marker, marker);
+        let errors = [
+            (
+                ParseError::UnexpectedToken(
+                    Box::new(Token {
+                        value: TokenValue::Semicolon,
+                        range: range.clone(),
+                    }),
+                    "",
+                ),
+                "E0001",
+            ),
+            (ParseError::UnexpectedEndOfInput(marker, ""), "E0002"),
+            (ParseError::MissingTypeForParam(marker), "E0003"),
+            (
+                ParseError::TokenError(LexError::IllegalNullByte(marker)),
+                "E0004",
+            ),
+            (
+                ParseError::UnexpectedTopLevelStatement(range.clone()),
+                "E0005",
+            ),
+            (ParseError::ChainedComparison(range.clone()), "E0006"),
+            (ParseError::NestedReferenceOperator(range.clone()), "E0007"),
+            (ParseError::DerefInTypePosition(range.clone()), "E0008"),
+            (ParseError::TypeInValuePosition(range.clone()), "E0009"),
+            (ParseError::UninitializedWithoutType(range.clone()), "E0010"),
+        ];
+        let mut seen = std::collections::HashSet::new();
+        for (error, code) in errors.iter() {
+            assert_eq!(error.code(), *code, "{error:?}");
+            assert!(seen.insert(error.code()), "{} is used twice", error.code());
+            assert_eq!(error.primary_span(), Some(range.clone()), "{error:?}");
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use std::fmt;
 
 #[derive(Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SourceRange {
     source_name: &'static str,
+    #[cfg_attr(feature = "serde", serde(skip))]
     source_text: &'static str,
     pub start_line: u32,
     pub start_offset: u32,
@@ -40,6 +42,10 @@ impl SourceRange {
         }
     }
 
+    pub fn source_name(&self) -> &'static str {
+        self.source_name
+    }
+
     pub fn set_end(&mut self, end: SourceMarker) {
         self.end_line = end.line;
         self.end_offset = end.offset;
@@ -55,6 +61,24 @@ impl SourceRange {
         &self.source_text[start..end]
     }
 
+    /**
+     * The text of each line the range touches, without the newlines
+     */
+    pub fn line_texts(&self) -> impl Iterator<Item = &'static str> {
+        self.source_text
+            .lines()
+            .skip(self.start_line as usize - 1)
+            .take((self.end_line + 1).saturating_sub(self.start_line) as usize)
+    }
+
+    /**
+     * The text of the line just before the range, if it doesn't start on the first line
+     */
+    pub fn preceding_line_text(&self) -> Option<&'static str> {
+        let index = (self.start_line as usize).checked_sub(2)?;
+        self.source_text.lines().nth(index)
+    }
+
     pub fn contains(&self, line: u32, char: u32) -> bool {
         line >= self.start_line
             && line <= self.end_line
@@ -98,7 +122,6 @@ impl fmt::Display for SourceRange {
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct SourceMarker {
     source_name: &'static str,
-    #[allow(dead_code)]
     source_text: &'static str,
     line: u32,
     offset: u32,
@@ -132,6 +155,42 @@ impl SourceMarker {
 
         index + (self.offset as usize)
     }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /**
+     * The text of the line the marker is on, without the newline
+     */
+    pub fn line_text(&self) -> &'static str {
+        self.source_text
+            .lines()
+            .nth(self.line as usize - 1)
+            .unwrap_or("")
+    }
+
+    /**
+     * The column the marker is shown at, counting from 1, if each tab advances to the next multiple
+     * of the tab width. The offset itself counts a tab as a single character.
+     */
+    pub fn display_column(&self, tab_width: u32) -> u32 {
+        self.line_text()
+            .chars()
+            .take(self.offset.saturating_sub(1) as usize)
+            .fold(1, |column, ch| next_display_column(column, ch, tab_width))
+    }
+}
+
+/**
+ * The display column after the given character, if it started at the given column
+ */
+pub(crate) fn next_display_column(column: u32, ch: char, tab_width: u32) -> u32 {
+    if ch == '\t' && tab_width > 0 {
+        column + tab_width - (column - 1) % tab_width
+    } else {
+        column + 1
+    }
 }
 
 impl fmt::Debug for SourceMarker {
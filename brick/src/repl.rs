@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use typed_arena::Arena;
+
+use crate::{
+    borrowck::{self, BlockLiveness},
+    hir::{lower_module_continuing, HirNode},
+    id::{AnyID, ConstantID, FunctionID},
+    interpreter::{Function, VmState, VM},
+    linear_ir::{layout_types, LinearContext},
+    parse_file, top_level_unwind_error,
+    type_validator::validate_types,
+    typecheck::{typecheck_continuing, FieldDefaults, TopLevelScope},
+    CompileError, DeclarationContext, ExternBinding, IntepreterError, SourceRange, TargetConfig,
+    Value,
+};
+
+const MODULE_NAME: &str = "main";
+const FILENAME: &str = "repl";
+/// How much room string literals typed into the session have, in bytes
+const CONSTANT_DATA_CAPACITY: usize = 1024 * 1024;
+
+/**
+ * Runs code one line at a time, where each line carries on from the ones before it. Everything a
+ * line declares, from functions and types to variables and constants, can be used by the lines
+ * after it, and each line only runs once.
+ *
+ * ```
+ * use brick::{ReplSession, Value};
+ *
+ * let mut session = ReplSession::new(Vec::new());
+ * session.evaluate("let numbers = list[1, 2];").unwrap();
+ * session.evaluate("numbers.push(3);").unwrap();
+ * assert_eq!(session.evaluate("numbers.len()").unwrap(), vec![Value::Size(3)]);
+ * ```
+ */
+pub struct ReplSession {
+    declarations: DeclarationContext,
+    declared_names: HashMap<(&'static str, String), SourceRange>,
+    scope: TopLevelScope,
+    constant_values: HashMap<ConstantID, HirNode>,
+    field_defaults: FieldDefaults,
+    /// How the lines so far left the top-level variables
+    liveness: BlockLiveness,
+    functions: HashMap<FunctionID, Function>,
+    /// Extern bindings whose functions haven't been declared yet
+    bindings: HashMap<String, ExternBinding>,
+    constant_data: Vec<u8>,
+    string_literal_offsets: HashMap<String, usize>,
+    vm: VmState,
+}
+
+impl ReplSession {
+    /**
+     * The bindings are hooked up to the extern functions of the same name once a line declares
+     * them
+     */
+    pub fn new(bindings: Vec<(&str, ExternBinding)>) -> ReplSession {
+        ReplSession {
+            declarations: DeclarationContext::new(&[]).expect("declaring nothing can't fail"),
+            declared_names: HashMap::new(),
+            scope: TopLevelScope::default(),
+            constant_values: HashMap::new(),
+            field_defaults: HashMap::new(),
+            liveness: BlockLiveness::default(),
+            functions: HashMap::new(),
+            bindings: bindings
+                .into_iter()
+                .map(|(name, binding)| (name.to_string(), binding))
+                .collect(),
+            constant_data: Vec::new(),
+            string_literal_offsets: HashMap::new(),
+            vm: VmState::new(CONSTANT_DATA_CAPACITY),
+        }
+    }
+
+    /**
+     * Compile and run a line, returning the value of its last expression if it has one. A line
+     * that fails to compile or run is undone, so the session carries on as if it was never
+     * entered.
+     */
+    pub fn evaluate(&mut self, line: &str) -> Result<Vec<Value>, IntepreterError> {
+        let arena = Arena::new();
+        let files = [(MODULE_NAME, parse_file(&arena, FILENAME, line.to_string())?)];
+
+        let mut declarations = self.declarations.clone();
+        let mut declared_names = self.declared_names.clone();
+        declarations
+            .extend_module(&files, &mut declared_names)
+            .map_err(CompileError::from)?;
+        validate_types(&declarations).map_err(CompileError::from)?;
+
+        let mut scope = self.scope.clone();
+        let mut types = typecheck_continuing(&files[0].1, MODULE_NAME, &declarations, &mut scope)
+            .map_err(CompileError::from)?;
+        let mut field_defaults = self.field_defaults.clone();
+        field_defaults.extend(std::mem::take(&mut types.field_defaults));
+        let mut constant_values = self.constant_values.clone();
+        let mut module =
+            lower_module_continuing(types, &declarations, &field_defaults, &mut constant_values);
+
+        let persistent: HashSet<_> = scope
+            .names
+            .values()
+            .filter_map(|(id, _)| match id {
+                AnyID::Variable(id) => Some(*id),
+                _ => None,
+            })
+            .collect();
+        let mut liveness = self.liveness.clone();
+        borrowck::borrow_check_continuing(&declarations, &mut module, &persistent, &mut liveness)
+            .map_err(CompileError::from)?;
+        // Constant function calls aren't folded ahead of time, since the line is about to run
+        // anyway
+
+        let target = TargetConfig::native();
+        let mut layouts = HashMap::new();
+        layout_types(
+            &declarations.id_to_decl,
+            &mut layouts,
+            target.byte_size,
+            target.pointer_size,
+        );
+        let mut linear_context = LinearContext {
+            layouts: &layouts,
+            constant_data_region: &mut self.constant_data,
+            string_literal_offsets: std::mem::take(&mut self.string_literal_offsets),
+            indirect_function_types: &mut HashMap::new(),
+            byte_size: target.byte_size,
+            pointer_size: target.pointer_size,
+            checked_arithmetic: false,
+            module: declarations.new_file(),
+            invalid_lvalues: Vec::new(),
+        };
+        let functions: Vec<_> = module
+            .functions
+            .into_iter()
+            .map(|function| linear_context.linearize_function(&declarations, function))
+            .collect();
+        let statements = linear_context.linearize_nodes(vec![module.top_level_statements]);
+        let invalid_lvalue = linear_context.invalid_lvalues.first().cloned();
+        // String literals are only ever added, so the ones from a line that fails are kept
+        self.string_literal_offsets = linear_context.string_literal_offsets;
+        self.vm
+            .extend_constant_data(&self.constant_data[self.vm.constant_data_len()..]);
+        if let Some(provenance) = invalid_lvalue {
+            return Err(CompileError::InvalidLvalue(provenance).into());
+        }
+
+        let mut added_functions = Vec::new();
+        for function in functions {
+            added_functions.push(function.id);
+            self.functions.insert(function.id, Function::Ir(function));
+        }
+        for (name, id) in declarations.extern_function_bindings.iter() {
+            if let Some(binding) = self.bindings.remove(name) {
+                added_functions.push(*id);
+                self.functions.insert(*id, Function::Extern(binding));
+            }
+        }
+
+        let checkpoint = self.vm.checkpoint();
+        match VM::evaluate_continuing(layouts, &self.functions, &mut self.vm, &statements) {
+            Ok(results) => {
+                self.declarations = declarations;
+                self.declared_names = declared_names;
+                self.scope = scope;
+                self.constant_values = constant_values;
+                self.field_defaults = field_defaults;
+                self.liveness = liveness;
+
+                Ok(results)
+            }
+            Err(unwind) => {
+                self.vm.restore(checkpoint);
+                for id in added_functions {
+                    if let Some(Function::Extern(binding)) = self.functions.remove(&id) {
+                        let name = declarations
+                            .extern_function_bindings
+                            .iter()
+                            .find(|(_, bound)| *bound == id)
+                            .map(|(name, _)| name.clone())
+                            .unwrap();
+                        self.bindings.insert(name, binding);
+                    }
+                }
+
+                Err(top_level_unwind_error(unwind))
+            }
+        }
+    }
+}
@@ -46,6 +46,7 @@ pub enum TokenValue {
 
     // Misc operators
     Period,
+    DotDot,
     Concat,
 
     // Nullability
@@ -59,6 +60,7 @@ pub enum TokenValue {
     Semicolon,
     QuestionMark,
     Exclamation,
+    Tilde,
     CaseRocket,
     VerticalPipe,
 
@@ -85,16 +87,22 @@ pub enum TokenValue {
     Unique,
     Ref,
     Return,
+    Break,
+    Continue,
+    Defer,
     Extern,
+    Export,
     Null,
     Dict,
     List,
     Rc,
     Cell,
     Interface,
+    Impl,
     Yield,
     Void,
     Case,
+    Is,
     Borrow,
     Const,
 
@@ -113,6 +121,7 @@ impl TokenValue {
             | TokenValue::OpenParen
             | TokenValue::OpenBracket
             | TokenValue::CharacterLiteral(_)
+            | TokenValue::Tilde
             | TokenValue::OpenSquare
             | TokenValue::If
             | TokenValue::While
@@ -146,6 +155,7 @@ impl TokenValue {
             | TokenValue::BooleanAnd
             | TokenValue::BooleanOr
             | TokenValue::Period
+            | TokenValue::DotDot
             | TokenValue::Concat
             | TokenValue::NullCoalesce
             | TokenValue::NullChaining
@@ -156,6 +166,7 @@ impl TokenValue {
             | TokenValue::QuestionMark
             | TokenValue::Exclamation
             | TokenValue::CaseRocket
+            | TokenValue::Is
             | TokenValue::VerticalPipe
             | TokenValue::CloseParen
             | TokenValue::CloseBracket
@@ -169,8 +180,13 @@ impl TokenValue {
             | TokenValue::Struct
             | TokenValue::Union
             | TokenValue::Return
+            | TokenValue::Break
+            | TokenValue::Continue
+            | TokenValue::Defer
             | TokenValue::Extern
+            | TokenValue::Export
             | TokenValue::Interface
+            | TokenValue::Impl
             | TokenValue::Void
             | TokenValue::LineComment(_) => true,
         }
@@ -181,71 +197,79 @@ impl fmt::Display for TokenValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use TokenValue::*;
         match self {
-            Word(word) => write!(f, "word {}", word),
-            Int(int) => write!(f, "int {}", int),
-            CharacterLiteral(c) => write!(f, "character literal {}", c),
-            StringLiteral(s) => write!(f, "string literal {}", s),
-            Plus => write!(f, "+"),
-            Minus => write!(f, "-"),
-            Asterisk => write!(f, "*"),
-            ForwardSlash => write!(f, "/"),
-            Assign => write!(f, "="),
-            PlusEquals => write!(f, "+="),
-            MinusEquals => write!(f, "-="),
-            AsteriskEquals => write!(f, "*="),
-            ForwardSlashEquals => write!(f, "/="),
-            Semicolon => write!(f, ";"),
-            Comma => write!(f, ","),
-            Colon => write!(f, ":"),
-            Period => write!(f, "."),
-            Concat => write!(f, "++"),
-            OpenParen => write!(f, "("),
-            CloseParen => write!(f, ")"),
-            OpenBracket => write!(f, "{{"),
-            CloseBracket => write!(f, "}}"),
-            OpenSquare => write!(f, "["),
-            CloseSquare => write!(f, "]"),
-            LessThan => write!(f, "<"),
-            GreaterThan => write!(f, ">"),
-            LessEqualThan => write!(f, "<="),
-            GreaterEqualThan => write!(f, ">="),
-            EqualTo => write!(f, "=="),
-            NotEquals => write!(f, "!="),
-            BooleanAnd => write!(f, "and"),
-            BooleanOr => write!(f, "or"),
-            QuestionMark => write!(f, "?"),
-            NullCoalesce => write!(f, "??"),
-            NullChaining => write!(f, "?."),
-            Exclamation => write!(f, "!"),
-            CaseRocket => write!(f, "=>"),
-            VerticalPipe => write!(f, "|"),
-            Let => write!(f, "keyword let"),
-            Const => write!(f, "keyword const"),
-            Borrow => write!(f, "keyword borrow"),
-            If => write!(f, "keyword if"),
-            While => write!(f, "keyword while"),
-            Loop => write!(f, "keyword loop"),
-            True => write!(f, "keyword true"),
-            False => write!(f, "keyword false"),
-            Function => write!(f, "keyword fn"),
-            Gen => write!(f, "keyword gen"),
-            Import => write!(f, "keyword import"),
-            Struct => write!(f, "keyword struct"),
-            Union => write!(f, "keyword union"),
-            Unique => write!(f, "keyword unique"),
-            Ref => write!(f, "keyword ref"),
-            Return => write!(f, "keyword return"),
-            Extern => write!(f, "keyword extern"),
-            Null => write!(f, "keyword null"),
-            Dict => write!(f, "keyword dict"),
-            Rc => write!(f, "keyword rc"),
-            Cell => write!(f, "keyword cell"),
-            List => write!(f, "keyword list"),
-            Interface => write!(f, "keyword interface"),
-            Yield => write!(f, "keyword yield"),
-            Void => write!(f, "keyword void"),
-            Case => write!(f, "keyword case"),
-            LineComment(comment) => write!(f, "// {}", comment),
+            Word(word) => write!(f, "identifier '{word}'"),
+            Int(int) => write!(f, "integer {int}"),
+            CharacterLiteral(c) => write!(f, "character {c:?}"),
+            StringLiteral(s) => write!(f, "string {s:?}"),
+            Plus => write!(f, "'+'"),
+            Minus => write!(f, "'-'"),
+            Asterisk => write!(f, "'*'"),
+            ForwardSlash => write!(f, "'/'"),
+            Assign => write!(f, "'='"),
+            PlusEquals => write!(f, "'+='"),
+            MinusEquals => write!(f, "'-='"),
+            AsteriskEquals => write!(f, "'*='"),
+            ForwardSlashEquals => write!(f, "'/='"),
+            Semicolon => write!(f, "';'"),
+            Comma => write!(f, "','"),
+            Colon => write!(f, "':'"),
+            Period => write!(f, "'.'"),
+            DotDot => write!(f, "'..'"),
+            Concat => write!(f, "'++'"),
+            OpenParen => write!(f, "'('"),
+            CloseParen => write!(f, "')'"),
+            OpenBracket => write!(f, "'{{'"),
+            CloseBracket => write!(f, "'}}'"),
+            OpenSquare => write!(f, "'['"),
+            CloseSquare => write!(f, "']'"),
+            LessThan => write!(f, "'<'"),
+            GreaterThan => write!(f, "'>'"),
+            LessEqualThan => write!(f, "'<='"),
+            GreaterEqualThan => write!(f, "'>='"),
+            EqualTo => write!(f, "'=='"),
+            NotEquals => write!(f, "'!='"),
+            BooleanAnd => write!(f, "keyword 'and'"),
+            BooleanOr => write!(f, "keyword 'or'"),
+            QuestionMark => write!(f, "'?'"),
+            NullCoalesce => write!(f, "'??'"),
+            NullChaining => write!(f, "'?.'"),
+            Exclamation => write!(f, "'!'"),
+            Tilde => write!(f, "'~'"),
+            CaseRocket => write!(f, "'=>'"),
+            VerticalPipe => write!(f, "'|'"),
+            Let => write!(f, "keyword 'let'"),
+            Const => write!(f, "keyword 'const'"),
+            Borrow => write!(f, "keyword 'borrow'"),
+            If => write!(f, "keyword 'if'"),
+            While => write!(f, "keyword 'while'"),
+            Loop => write!(f, "keyword 'loop'"),
+            True => write!(f, "keyword 'true'"),
+            False => write!(f, "keyword 'false'"),
+            Function => write!(f, "keyword 'fn'"),
+            Gen => write!(f, "keyword 'gen'"),
+            Import => write!(f, "keyword 'import'"),
+            Struct => write!(f, "keyword 'struct'"),
+            Union => write!(f, "keyword 'union'"),
+            Unique => write!(f, "keyword 'unique'"),
+            Ref => write!(f, "keyword 'ref'"),
+            Return => write!(f, "keyword 'return'"),
+            Break => write!(f, "keyword 'break'"),
+            Continue => write!(f, "keyword 'continue'"),
+            Defer => write!(f, "keyword 'defer'"),
+            Extern => write!(f, "keyword 'extern'"),
+            Export => write!(f, "keyword 'export'"),
+            Null => write!(f, "keyword 'null'"),
+            Dict => write!(f, "keyword 'dict'"),
+            Rc => write!(f, "keyword 'rc'"),
+            Cell => write!(f, "keyword 'cell'"),
+            List => write!(f, "keyword 'list'"),
+            Interface => write!(f, "keyword 'interface'"),
+            Impl => write!(f, "keyword 'impl'"),
+            Yield => write!(f, "keyword 'yield'"),
+            Void => write!(f, "keyword 'void'"),
+            Case => write!(f, "keyword 'case'"),
+            Is => write!(f, "keyword 'is'"),
+            LineComment(_) => write!(f, "comment"),
         }
     }
 }
@@ -262,6 +286,10 @@ pub enum LexError {
     IllegalEscapeSequence(SourceMarker),
 }
 
+/**
+ * Errors don't end the stream: after reporting one, lexing picks back up with the next character
+ * (or after the bad literal), so callers like the language server can keep going past a typo.
+ */
 pub fn lex<'a>(
     source_name: &'static str,
     source_text: String,
@@ -332,6 +360,47 @@ impl<T: Iterator<Item = char>> TokenIterator<T> {
             (ch, start) => Ok((ch, SourceRange::new(start, start))),
         }
     }
+
+    /**
+     * Everything up to the closing triple quote is taken verbatim, including
+     * newlines and backslashes
+     */
+    fn raw_string_literal(
+        &mut self,
+        start: SourceMarker,
+    ) -> Result<(String, SourceMarker), LexError> {
+        let mut string = String::new();
+        let mut quotes = 0;
+        loop {
+            let ch = self
+                .source
+                .next()
+                .ok_or(LexError::UnterminatedLiteral(start))?;
+            if ch == '\n' {
+                self.line += 1;
+                self.offset = 0;
+            } else {
+                self.offset += 1;
+            }
+            string.push(ch);
+
+            if ch == '"' {
+                quotes += 1;
+                if quotes == 3 {
+                    string.truncate(string.len() - 3);
+                    let end = SourceMarker::new(
+                        self.source_name,
+                        self.source_text,
+                        self.line,
+                        self.offset,
+                    );
+                    return Ok((string, end));
+                }
+            } else {
+                quotes = 0;
+            }
+        }
+    }
 }
 
 impl<T: Iterator<Item = char>> Iterator for TokenIterator<T> {
@@ -372,18 +441,24 @@ impl<T: Iterator<Item = char>> Iterator for TokenIterator<T> {
                         "unique" => TokenValue::Unique,
                         "ref" => TokenValue::Ref,
                         "return" => TokenValue::Return,
+                        "break" => TokenValue::Break,
+                        "continue" => TokenValue::Continue,
+                        "defer" => TokenValue::Defer,
                         "extern" => TokenValue::Extern,
+                        "export" => TokenValue::Export,
                         "null" => TokenValue::Null,
                         "dict" => TokenValue::Dict,
                         "list" => TokenValue::List,
                         "rc" => TokenValue::Rc,
                         "cell" => TokenValue::Cell,
                         "interface" => TokenValue::Interface,
+                        "impl" => TokenValue::Impl,
                         "and" => TokenValue::BooleanAnd,
                         "or" => TokenValue::BooleanOr,
                         "yield" => TokenValue::Yield,
                         "void" => TokenValue::Void,
                         "case" => TokenValue::Case,
+                        "is" => TokenValue::Is,
                         _ => TokenValue::Word(word),
                     }
                 }
@@ -511,7 +586,14 @@ impl<T: Iterator<Item = char>> Iterator for TokenIterator<T> {
                 ',' => TokenValue::Comma,
                 ';' => TokenValue::Semicolon,
                 ':' => TokenValue::Colon,
-                '.' => TokenValue::Period,
+                '.' => {
+                    if let Some('.') = self.source.peek() {
+                        end = Some(self.next_char().unwrap().1);
+                        TokenValue::DotDot
+                    } else {
+                        TokenValue::Period
+                    }
+                }
                 '(' => TokenValue::OpenParen,
                 ')' => TokenValue::CloseParen,
                 '{' => TokenValue::OpenBracket,
@@ -519,10 +601,17 @@ impl<T: Iterator<Item = char>> Iterator for TokenIterator<T> {
                 '[' => TokenValue::OpenSquare,
                 ']' => TokenValue::CloseSquare,
                 '|' => TokenValue::VerticalPipe,
+                '~' => TokenValue::Tilde,
                 '\0' => return Some(Err(LexError::IllegalNullByte(start))),
                 '\'' => {
                     let (value, idx) = match self.next_char_literal(start) {
                         Ok(val) => val,
+                        Err(err @ LexError::IllegalEscapeSequence(_)) => {
+                            if self.source.peek() == Some(&'\'') {
+                                self.next_char();
+                            }
+                            return Some(Err(err));
+                        }
                         Err(err) => return Some(Err(err)),
                     };
                     // TODO: handle escape sequences
@@ -533,15 +622,40 @@ impl<T: Iterator<Item = char>> Iterator for TokenIterator<T> {
 
                     TokenValue::CharacterLiteral(value)
                 }
+                '"' if self.source.peek() == Some(&'"') => {
+                    let (_, quote_end) = self.next_char().unwrap();
+                    if self.source.peek() == Some(&'"') {
+                        self.next_char();
+                        let (string, string_end) = match self.raw_string_literal(start) {
+                            Ok(val) => val,
+                            Err(err) => return Some(Err(err)),
+                        };
+                        end = Some(string_end);
+                        TokenValue::StringLiteral(string)
+                    } else {
+                        end = Some(quote_end);
+                        TokenValue::StringLiteral(String::new())
+                    }
+                }
                 '"' => {
                     let mut string = String::new();
+                    let mut escape_error = None;
                     loop {
                         let (next, idx) = match self.next_char_literal(start) {
                             Ok(val) => val,
+                            // Keep going to the closing quote, so the rest of the string isn't
+                            // lexed as code
+                            Err(err @ LexError::IllegalEscapeSequence(_)) => {
+                                escape_error.get_or_insert(err);
+                                continue;
+                            }
                             Err(err) => return Some(Err(err)),
                         };
                         end = Some(idx.end());
                         if next == '"' {
+                            if let Some(err) = escape_error {
+                                return Some(Err(err));
+                            }
                             break TokenValue::StringLiteral(string);
                         }
                         string.push(next);
@@ -578,4 +692,127 @@ mod test {
             vec![If, Let, True, False, Function, Word("word".to_string())]
         );
     }
+
+    #[test]
+    fn comparison_operators() {
+        let result = lex("test", "a<=b>=c==d!=e<f>g=h".to_string())
+            .map(|token| token.map(|token| token.value))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                Word("a".to_string()),
+                LessEqualThan,
+                Word("b".to_string()),
+                GreaterEqualThan,
+                Word("c".to_string()),
+                EqualTo,
+                Word("d".to_string()),
+                NotEquals,
+                Word("e".to_string()),
+                LessThan,
+                Word("f".to_string()),
+                GreaterThan,
+                Word("g".to_string()),
+                Assign,
+                Word("h".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn assignment_before_comparison() {
+        let result = lex("test", "x = = y ==y".to_string())
+            .map(|token| token.map(|token| token.value))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                Word("x".to_string()),
+                Assign,
+                Assign,
+                Word("y".to_string()),
+                EqualTo,
+                Word("y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_string() {
+        let source = "\"\"\"first \"line\"\nsecond \\n line\"\"\" word";
+        let result = lex("test", source.to_string())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            result[0].value,
+            StringLiteral("first \"line\"\nsecond \\n line".to_string())
+        );
+        assert_eq!(result[1].value, Word("word".to_string()));
+        assert!(result[1].range.contains(2, 19));
+    }
+
+    #[test]
+    fn empty_string() {
+        let result = lex("test", "\"\" word".to_string())
+            .map(|token| token.map(|token| token.value))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![StringLiteral(String::new()), Word("word".to_string())]
+        );
+    }
+
+    #[test]
+    fn continues_after_unexpected_character() {
+        let result = lex("test", "let x = 1 $ 2;".to_string())
+            .map(|token| token.map(|token| token.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(result.len(), 7);
+        assert!(matches!(result[4], Err(LexError::UnexpectedStart('$', _))));
+        let result = result
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                Let,
+                Word("x".to_string()),
+                Assign,
+                Int(1),
+                Int(2),
+                Semicolon
+            ]
+        );
+    }
+
+    #[test]
+    fn continues_after_illegal_escape() {
+        let result = lex("test", "\"a \\q b\" word '\\q' other".to_string())
+            .map(|token| token.map(|token| token.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(result.len(), 4);
+        assert!(matches!(result[0], Err(LexError::IllegalEscapeSequence(_))));
+        assert!(matches!(&result[1], Ok(Word(word)) if word == "word"));
+        assert!(matches!(result[2], Err(LexError::IllegalEscapeSequence(_))));
+        assert!(matches!(&result[3], Ok(Word(word)) if word == "other"));
+    }
+
+    #[test]
+    fn unterminated_raw_string() {
+        let result =
+            lex("test", "\"\"\"never closed\"\"".to_string()).collect::<Result<Vec<_>, _>>();
+
+        assert!(matches!(result, Err(LexError::UnterminatedLiteral(_))));
+    }
 }
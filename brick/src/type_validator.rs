@@ -11,7 +11,7 @@ use crate::{
 pub enum TypeValidationError {
     #[error("{}", print_multi_errors(&.0[..]))]
     MultiError(Vec<TypeValidationError>),
-    #[error("illegal return of a reference from a function: {0}")]
+    #[error("illegal return of a reference from a function, only functions with exactly one reference parameter can return a reference into it: {0}")]
     ReferenceReturn(SourceRange),
     #[error("drop may not be defined on normal types: {0}")]
     DropOnNormalType(SourceRange),
@@ -172,7 +172,14 @@ fn validate_drop(
 fn validate_fn(fn_ty: &FuncType) -> Result<(), TypeValidationError> {
     let mut result = Ok(());
 
-    if matches!(&fn_ty.returns, ExpressionType::Pointer(_, _)) {
+    // The returned reference borrows from the reference parameter, so there has to be exactly
+    // one for callers to know what stays borrowed
+    let reference_params = fn_ty
+        .params
+        .iter()
+        .filter(|param| matches!(param, ExpressionType::Pointer(_, _)))
+        .count();
+    if matches!(&fn_ty.returns, ExpressionType::Pointer(_, _)) && reference_params != 1 {
         merge_results(
             &mut result,
             Err(TypeValidationError::ReferenceReturn(
@@ -1,19 +1,28 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
 
 use thiserror::Error;
 
 use crate::{
-    declaration_context::{resolve_type_expr, DeclarationContext, FileDeclarations, TypeID},
-    id::{AnyID, FunctionID},
+    declaration_context::{
+        find_local_functions, resolve_type_expr, DeclarationContext, FileDeclarations,
+        IntrinsicFunction, TypeID,
+    },
+    definite_assignment::check_definite_assignment,
+    id::{AnyID, ConstantID, FunctionID},
     multi_error::{merge_results, merge_results_or_value, print_multi_errors, MultiError},
     parser::{
-        AstNode, AstNodeValue, BinOp, FunctionDeclarationValue, IfDeclaration,
-        InterfaceDeclarationValue, MatchDeclaration, StructDeclarationValue, UnaryOp,
+        AstNode, AstNodeValue, BinOp, FunctionDeclarationValue, IfDeclaration, IfLetDeclaration,
+        ImplBlockValue, InterfaceDeclarationValue, LoopLabel, MatchDeclaration,
+        StructDeclarationValue, UnaryOp,
     },
     provenance::SourceRange,
 };
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ExpressionType {
     Void,
     Unreachable,
@@ -68,8 +77,9 @@ impl ExpressionType {
             | ExpressionType::TypeParameterReference(_)
             | ExpressionType::Collection(_)
             | ExpressionType::Generator { .. }
-            | ExpressionType::ReferenceToFunction(_)
-            | ExpressionType::FunctionReference { .. } => true,
+            | ExpressionType::ReferenceToFunction(_) => true,
+            // Function pointers are plain values, with nothing to free
+            ExpressionType::FunctionReference { .. } => false,
         }
     }
 
@@ -91,7 +101,7 @@ impl ExpressionType {
         }
     }
 
-    fn resolve_generics(&mut self, bindings: &[ExpressionType]) {
+    pub(crate) fn resolve_generics(&mut self, bindings: &[ExpressionType]) {
         match self {
             ExpressionType::Void
             | ExpressionType::Unreachable
@@ -135,6 +145,7 @@ impl ExpressionType {
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CollectionType {
     Array(Box<ExpressionType>),
     Dict(Box<ExpressionType>, Box<ExpressionType>),
@@ -144,12 +155,14 @@ pub enum CollectionType {
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PointerKind {
     Shared,
     Unique,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TypeDeclaration {
     Struct(StructType),
     Interface(InterfaceType),
@@ -229,11 +242,7 @@ impl TypeDeclaration {
                     .clone()
                     .unwrap_or(ExpressionType::Void),
             ))),
-            TypeDeclaration::Module(lhs_type) => {
-                lhs_type.exports.get(field).cloned().ok_or_else(|| {
-                    TypecheckError::FieldNotPresent(field.to_string(), provenance.clone())
-                })
-            }
+            TypeDeclaration::Module(lhs_type) => lhs_type.exported(field, provenance),
         }
     }
 
@@ -247,21 +256,52 @@ impl TypeDeclaration {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ModuleType {
     pub id: TypeID,
     pub exports: HashMap<String, ExpressionType>,
+    /// The names in `exports` that other modules can use, the rest are private to this one
+    pub exported: HashSet<String>,
+}
+
+impl ModuleType {
+    /**
+     * Look up a name from outside the module, which only sees what it exported
+     */
+    pub fn exported(
+        &self,
+        name: &str,
+        provenance: &SourceRange,
+    ) -> Result<ExpressionType, TypecheckError> {
+        let ty = self
+            .exports
+            .get(name)
+            .ok_or_else(|| TypecheckError::FieldNotPresent(name.to_string(), provenance.clone()))?;
+        if !self.exported.contains(name) {
+            return Err(TypecheckError::NotExported(
+                name.to_string(),
+                provenance.clone(),
+            ));
+        }
+
+        Ok(ty.clone())
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct StructType {
     pub id: TypeID,
     pub fields: HashMap<String, ExpressionType>,
+    /// Fields that struct literals may leave out
+    pub defaulted_fields: HashSet<String>,
     pub associated_functions: HashMap<String, FunctionID>,
     pub is_affine: bool,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FuncType {
     pub id: FunctionID,
     pub type_param_count: usize,
@@ -269,10 +309,12 @@ pub struct FuncType {
     pub returns: ExpressionType,
     pub is_associated: bool,
     pub is_coroutine: bool,
+    pub is_const: bool,
     pub provenance: Option<SourceRange>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct UnionType {
     pub id: TypeID,
     pub variant_order: Vec<String>,
@@ -280,13 +322,15 @@ pub struct UnionType {
     pub is_affine: bool,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct InterfaceType {
     pub id: TypeID,
     pub associated_functions: HashMap<String, FunctionID>,
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PrimitiveType {
     Char,
     Int32,
@@ -303,11 +347,11 @@ pub enum TypecheckError {
     MultiError(Vec<TypecheckError>),
     #[error("arithmetic")]
     ArithmeticMismatch(SourceRange),
-    #[error("mismatched types at {provenance}: received {received:?}, expected {expected:?}")]
+    #[error("mismatched types at {provenance}: expected {expected}, received {received}")]
     TypeMismatch {
         provenance: SourceRange,
-        expected: ExpressionType,
-        received: ExpressionType,
+        expected: String,
+        received: String,
     },
     #[error("declaration for {0:?} not found")]
     NameNotFound(SourceRange),
@@ -321,8 +365,14 @@ pub enum TypecheckError {
     NoNullDeclarations(SourceRange),
     #[error("expected nullable left-hand-side to ?? operator: {0}")]
     ExpectedNullableLHS(SourceRange),
+    #[error("expected nullable value to unwrap with ! operator: {0}")]
+    ExpectedNullableUnwrap(SourceRange),
     #[error("cannot yield outside of a generator: {0}")]
     CannotYield(SourceRange),
+    #[error("cannot break outside of a loop: {0}")]
+    BreakOutsideLoop(SourceRange),
+    #[error("cannot continue outside of a loop: {0}")]
+    ContinueOutsideLoop(SourceRange),
     #[error("illegal left hand side of assignment: {0}")]
     IllegalAssignmentLHS(SourceRange),
     #[error("illegal lhs of dot operator: {0}")]
@@ -331,6 +381,14 @@ pub enum TypecheckError {
     MustReturnGenerator(SourceRange),
     #[error("argument to case statement must be a union: {0}")]
     CaseStatementRequiresUnion(SourceRange),
+    #[error("left side of is must be a union: {0}")]
+    IsRequiresUnion(SourceRange),
+    #[error("value matched against a variant in if let must be a union: {0}")]
+    IfLetRequiresUnion(SourceRange),
+    #[error("value bound without a variant in if let must be nullable: {0}")]
+    IfLetRequiresNullable(SourceRange),
+    #[error("union has no variant named {0}: {1}")]
+    VariantNotPresent(String, SourceRange),
     #[error("right side of dot operator must be a name: {0}")]
     IllegalDotRHS(SourceRange),
     #[error("variant doesn't match previous count of bindings: {0}")]
@@ -345,7 +403,7 @@ pub enum TypecheckError {
     IllegalFirstClassReference(SourceRange),
     #[error("right hand side of 'borrow' statement must be a reference: {0}")]
     IllegalNonRefBorrow(SourceRange),
-    #[error("right hand side of 'borrow' statement must be a valid lvalue: {0}")]
+    #[error("can only borrow a valid lvalue: {0}")]
     IllegalNonLvalueBorrow(SourceRange),
     #[error("illegal reference inside data type: {0}")]
     IllegalReferenceInsideDataType(SourceRange),
@@ -367,6 +425,199 @@ pub enum TypecheckError {
     FileNotFound(SourceRange, String),
     #[error("non-constant value in const: {0}")]
     NonConstantInConst(SourceRange),
+    #[error("only const fns can be called in a const context: {0}")]
+    NonConstFunctionInConst(SourceRange),
+    #[error("const fn parameters and return values must be primitives: {0}")]
+    NonPrimitiveConstFunction(SourceRange),
+    #[error("type has infinite size, it contains itself without indirection ({}): {0}", .1.join(" -> "))]
+    InfinitelySizedType(SourceRange, Vec<String>),
+    #[error("only arrays of copyable values can be sliced: {0}")]
+    IllegalSlice(SourceRange),
+    #[error("variant {0} is already matched by an earlier case: {1}")]
+    DuplicateMatchVariant(String, SourceRange),
+    #[error("variable {0} is read before it's assigned on every path: {1}")]
+    UseBeforeAssignment(String, SourceRange),
+    #[error("module doesn't declare anything named {0}: {1}")]
+    ImportNotFound(String, SourceRange),
+    #[error("can't return, break or continue out of a deferred expression: {0}")]
+    JumpOutOfDefer(SourceRange),
+    #[error("{0} is already declared at {1}: {2}")]
+    DuplicateDeclaration(String, SourceRange, SourceRange),
+    #[error("{0} is private to its module, declare it with 'export' to use it elsewhere: {1}")]
+    NotExported(String, SourceRange),
+    #[error("no enclosing loop is labeled {0}: {1}")]
+    UnknownLoopLabel(String, SourceRange),
+    #[error("{0} can reach the end of its body without returning a value: {1}")]
+    MissingReturn(String, SourceRange),
+    #[error("type alias refers to itself ({}): {0}", .1.join(" -> "))]
+    TypeAliasCycle(SourceRange, Vec<String>),
+    #[error("impl for {0}, which isn't a struct declared in this module: {1}")]
+    ImplForUnknownStruct(String, SourceRange),
+    #[error("{0} from {1} isn't implemented, or its signature doesn't match: {2}")]
+    MissingInterfaceMethod(String, String, SourceRange),
+    #[error("{0} isn't an interface: {1}")]
+    NotAnInterface(String, SourceRange),
+    #[error("nested functions can't capture {0} from the function around them: {1}")]
+    CapturedLocal(String, SourceRange),
+    #[error("only interfaces can be downcast: {0}")]
+    DowncastRequiresInterface(SourceRange),
+    #[error("{0} isn't a struct that implements {1}: {2}")]
+    InvalidDowncastTarget(String, String, SourceRange),
+    #[error("only arrays of primitive values can be searched with contains: {0}")]
+    IllegalArrayContains(SourceRange),
+    #[error("a returned reference has to point into the function's reference parameter: {0}")]
+    ReferenceNotFromParameter(SourceRange),
+    #[error("{0} can't be declared inside a function: {1}")]
+    IllegalLocalDeclaration(String, SourceRange),
+    #[error("functions can't be declared inside associated functions: {0}")]
+    FunctionInsideAssociatedFunction(SourceRange),
+    #[error("array length is the negative constant {0}: {1}")]
+    NegativeArrayLength(i64, SourceRange),
+}
+
+impl TypecheckError {
+    /**
+     * A stable identifier for this kind of error, for tools that can't rely on the message text.
+     * Codes are never reused or renumbered, new errors get new codes.
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypecheckError::MultiError(_) => "E0100",
+            TypecheckError::ArithmeticMismatch(_) => "E0101",
+            TypecheckError::TypeMismatch { .. } => "E0102",
+            TypecheckError::NameNotFound(_) => "E0103",
+            TypecheckError::CantCall(_) => "E0104",
+            TypecheckError::WrongArgsCount(_) => "E0105",
+            TypecheckError::MissingField(_) => "E0106",
+            TypecheckError::NoNullDeclarations(_) => "E0107",
+            TypecheckError::ExpectedNullableLHS(_) => "E0108",
+            TypecheckError::ExpectedNullableUnwrap(_) => "E0109",
+            TypecheckError::CannotYield(_) => "E0110",
+            TypecheckError::BreakOutsideLoop(_) => "E0111",
+            TypecheckError::ContinueOutsideLoop(_) => "E0112",
+            TypecheckError::IllegalAssignmentLHS(_) => "E0113",
+            TypecheckError::IllegalDotLHS(_) => "E0114",
+            TypecheckError::MustReturnGenerator(_) => "E0115",
+            TypecheckError::CaseStatementRequiresUnion(_) => "E0116",
+            TypecheckError::IsRequiresUnion(_) => "E0117",
+            TypecheckError::IfLetRequiresUnion(_) => "E0118",
+            TypecheckError::IfLetRequiresNullable(_) => "E0119",
+            TypecheckError::VariantNotPresent(_, _) => "E0120",
+            TypecheckError::IllegalDotRHS(_) => "E0121",
+            TypecheckError::BindingCountDoesntMatch(_) => "E0122",
+            TypecheckError::BindingNameDoesntMatch(_) => "E0123",
+            TypecheckError::DereferenceNonPointer(_) => "E0124",
+            TypecheckError::NonExhaustiveCase(_) => "E0125",
+            TypecheckError::IllegalFirstClassReference(_) => "E0126",
+            TypecheckError::IllegalNonRefBorrow(_) => "E0127",
+            TypecheckError::IllegalNonLvalueBorrow(_) => "E0128",
+            TypecheckError::IllegalReferenceInsideDataType(_) => "E0129",
+            TypecheckError::UnknownProperty(_, _) => "E0130",
+            TypecheckError::FieldNotPresent(_, _) => "E0131",
+            TypecheckError::NonStructDeclStructLiteral(_) => "E0132",
+            TypecheckError::CantAssignToReference(_) => "E0133",
+            TypecheckError::IllegalSharedRefMutation(_) => "E0134",
+            TypecheckError::IllegalImport(_) => "E0135",
+            TypecheckError::ImportPathMustBeModule(_) => "E0136",
+            TypecheckError::FileNotFound(_, _) => "E0137",
+            TypecheckError::NonConstantInConst(_) => "E0138",
+            TypecheckError::NonConstFunctionInConst(_) => "E0139",
+            TypecheckError::NonPrimitiveConstFunction(_) => "E0140",
+            TypecheckError::InfinitelySizedType(_, _) => "E0141",
+            TypecheckError::IllegalSlice(_) => "E0142",
+            TypecheckError::DuplicateMatchVariant(_, _) => "E0143",
+            TypecheckError::UseBeforeAssignment(_, _) => "E0144",
+            TypecheckError::ImportNotFound(_, _) => "E0145",
+            TypecheckError::JumpOutOfDefer(_) => "E0146",
+            TypecheckError::DuplicateDeclaration(_, _, _) => "E0147",
+            TypecheckError::NotExported(_, _) => "E0148",
+            TypecheckError::UnknownLoopLabel(_, _) => "E0149",
+            TypecheckError::MissingReturn(_, _) => "E0150",
+            TypecheckError::TypeAliasCycle(_, _) => "E0151",
+            TypecheckError::ImplForUnknownStruct(_, _) => "E0152",
+            TypecheckError::MissingInterfaceMethod(_, _, _) => "E0153",
+            TypecheckError::NotAnInterface(_, _) => "E0154",
+            TypecheckError::CapturedLocal(_, _) => "E0155",
+            TypecheckError::DowncastRequiresInterface(_) => "E0156",
+            TypecheckError::InvalidDowncastTarget(_, _, _) => "E0157",
+            TypecheckError::IllegalArrayContains(_) => "E0158",
+            TypecheckError::ReferenceNotFromParameter(_) => "E0159",
+            TypecheckError::IllegalLocalDeclaration(_, _) => "E0160",
+            TypecheckError::FunctionInsideAssociatedFunction(_) => "E0161",
+            TypecheckError::NegativeArrayLength(_, _) => "E0162",
+        }
+    }
+
+    /**
+     * The location the error is reported at. A group of errors reports the first one's location.
+     */
+    pub fn primary_span(&self) -> Option<SourceRange> {
+        match self {
+            TypecheckError::MultiError(errors) => errors.first()?.primary_span(),
+            TypecheckError::TypeMismatch { provenance, .. }
+            | TypecheckError::VariantNotPresent(_, provenance)
+            | TypecheckError::UnknownProperty(_, provenance)
+            | TypecheckError::DuplicateMatchVariant(_, provenance)
+            | TypecheckError::UseBeforeAssignment(_, provenance)
+            | TypecheckError::ImportNotFound(_, provenance)
+            | TypecheckError::DuplicateDeclaration(_, _, provenance)
+            | TypecheckError::NotExported(_, provenance)
+            | TypecheckError::UnknownLoopLabel(_, provenance)
+            | TypecheckError::MissingReturn(_, provenance)
+            | TypecheckError::ImplForUnknownStruct(_, provenance)
+            | TypecheckError::NotAnInterface(_, provenance)
+            | TypecheckError::CapturedLocal(_, provenance)
+            | TypecheckError::IllegalLocalDeclaration(_, provenance)
+            | TypecheckError::InvalidDowncastTarget(_, _, provenance)
+            | TypecheckError::MissingInterfaceMethod(_, _, provenance)
+            | TypecheckError::FieldNotPresent(_, provenance)
+            | TypecheckError::FileNotFound(provenance, _)
+            | TypecheckError::InfinitelySizedType(provenance, _)
+            | TypecheckError::TypeAliasCycle(provenance, _)
+            | TypecheckError::ArithmeticMismatch(provenance)
+            | TypecheckError::JumpOutOfDefer(provenance)
+            | TypecheckError::NameNotFound(provenance)
+            | TypecheckError::CantCall(provenance)
+            | TypecheckError::WrongArgsCount(provenance)
+            | TypecheckError::MissingField(provenance)
+            | TypecheckError::NoNullDeclarations(provenance)
+            | TypecheckError::ExpectedNullableLHS(provenance)
+            | TypecheckError::ExpectedNullableUnwrap(provenance)
+            | TypecheckError::CannotYield(provenance)
+            | TypecheckError::BreakOutsideLoop(provenance)
+            | TypecheckError::ContinueOutsideLoop(provenance)
+            | TypecheckError::IllegalAssignmentLHS(provenance)
+            | TypecheckError::IllegalDotLHS(provenance)
+            | TypecheckError::MustReturnGenerator(provenance)
+            | TypecheckError::CaseStatementRequiresUnion(provenance)
+            | TypecheckError::IsRequiresUnion(provenance)
+            | TypecheckError::DowncastRequiresInterface(provenance)
+            | TypecheckError::FunctionInsideAssociatedFunction(provenance)
+            | TypecheckError::NegativeArrayLength(_, provenance)
+            | TypecheckError::IfLetRequiresUnion(provenance)
+            | TypecheckError::IfLetRequiresNullable(provenance)
+            | TypecheckError::IllegalDotRHS(provenance)
+            | TypecheckError::BindingCountDoesntMatch(provenance)
+            | TypecheckError::BindingNameDoesntMatch(provenance)
+            | TypecheckError::DereferenceNonPointer(provenance)
+            | TypecheckError::NonExhaustiveCase(provenance)
+            | TypecheckError::IllegalFirstClassReference(provenance)
+            | TypecheckError::IllegalNonRefBorrow(provenance)
+            | TypecheckError::IllegalNonLvalueBorrow(provenance)
+            | TypecheckError::IllegalReferenceInsideDataType(provenance)
+            | TypecheckError::NonStructDeclStructLiteral(provenance)
+            | TypecheckError::CantAssignToReference(provenance)
+            | TypecheckError::IllegalSharedRefMutation(provenance)
+            | TypecheckError::IllegalImport(provenance)
+            | TypecheckError::ImportPathMustBeModule(provenance)
+            | TypecheckError::NonConstantInConst(provenance)
+            | TypecheckError::NonConstFunctionInConst(provenance)
+            | TypecheckError::NonPrimitiveConstFunction(provenance)
+            | TypecheckError::IllegalSlice(provenance)
+            | TypecheckError::IllegalArrayContains(provenance)
+            | TypecheckError::ReferenceNotFromParameter(provenance) => Some(provenance.clone()),
+        }
+    }
 }
 
 impl MultiError for TypecheckError {
@@ -387,8 +638,33 @@ struct TypecheckContext<'a> {
     top_level_type_names: HashMap<&'a str, TypeID>,
     top_level_function_names: HashMap<&'a str, FunctionID>,
     top_level_name_to_expr_type: HashMap<String, (AnyID, ExpressionType)>,
+    constant_values: RefCell<HashMap<ConstantID, ConstValue>>,
+    /// Shared by the whole module, including the function bodies local structs are declared in
+    field_defaults: &'a RefCell<FieldDefaults>,
+    /// Variables from the functions that the current one is nested inside, which it can see but
+    /// can't capture
+    enclosing_locals: HashSet<String>,
+    /// Whether the current function returns a reference, so its blocks may produce one
+    returns_reference: bool,
 }
 
+/**
+ * The compile-time value of a constant, if it's simple enough to resolve
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+}
+
+/**
+ * The value and type of each struct's defaulted fields, by field name
+ */
+pub type FieldDefaults = HashMap<TypeID, HashMap<String, (ConstValue, ExpressionType)>>;
+
 impl<'a> TypecheckContext<'a> {
     fn id_to_decl(&self) -> &HashMap<TypeID, TypeDeclaration> {
         &self.declarations.id_to_decl
@@ -397,12 +673,52 @@ impl<'a> TypecheckContext<'a> {
     fn decl(&self, id: &TypeID) -> Option<&TypeDeclaration> {
         self.declarations.id_to_decl.get(id)
     }
+
+    /**
+     * A context for a function body, where the types and functions declared inside it shadow the
+     * module's
+     */
+    fn with_locals(
+        &self,
+        local_types: Option<&'a HashMap<String, TypeID>>,
+        local_functions: Option<&'a HashMap<String, FunctionID>>,
+    ) -> TypecheckContext<'a> {
+        let mut top_level_type_names = self.top_level_type_names.clone();
+        let mut top_level_function_names = self.top_level_function_names.clone();
+        let mut top_level_name_to_expr_type = self.top_level_name_to_expr_type.clone();
+        for (name, ty_id) in local_types.into_iter().flatten() {
+            top_level_type_names.insert(name.as_str(), *ty_id);
+            top_level_name_to_expr_type.insert(
+                name.clone(),
+                ((*ty_id).into(), ExpressionType::ReferenceToType(*ty_id)),
+            );
+        }
+        for (name, fn_id) in local_functions.into_iter().flatten() {
+            top_level_function_names.insert(name.as_str(), *fn_id);
+            top_level_name_to_expr_type.insert(
+                name.clone(),
+                ((*fn_id).into(), ExpressionType::ReferenceToFunction(*fn_id)),
+            );
+        }
+
+        TypecheckContext {
+            declarations: self.declarations,
+            top_level_type_names,
+            top_level_function_names,
+            top_level_name_to_expr_type,
+            constant_values: RefCell::new(self.constant_values.borrow().clone()),
+            field_defaults: self.field_defaults,
+            enclosing_locals: self.enclosing_locals.clone(),
+            returns_reference: false,
+        }
+    }
 }
 
 pub struct TypecheckedFile<'ast, 'decl> {
     pub functions: Vec<TypecheckedFunction<'ast>>,
     pub top_level_statements: Vec<&'ast AstNode<'ast>>,
     pub module: &'decl FileDeclarations,
+    pub field_defaults: FieldDefaults,
 }
 
 #[derive(Clone, Debug)]
@@ -412,11 +728,40 @@ pub struct TypecheckedFunction<'a> {
     pub func: &'a FunctionDeclarationValue<'a>,
 }
 
+/**
+ * What the top level of a file declared, for a file that carries on from it in the same module,
+ * like the next line typed into a REPL
+ */
+#[derive(Clone, Default)]
+pub(crate) struct TopLevelScope {
+    /// The variables and constants declared at the top level, by name
+    pub names: HashMap<String, (AnyID, ExpressionType)>,
+    constant_values: HashMap<ConstantID, ConstValue>,
+}
+
 // TODO: pass import namespace in
 pub fn typecheck<'ast, 'decl>(
     file: &'ast [AstNode<'ast>],
     current_module_name: &str,
     declarations: &'decl DeclarationContext,
+) -> Result<TypecheckedFile<'ast, 'decl>, TypecheckError> {
+    typecheck_continuing(
+        file,
+        current_module_name,
+        declarations,
+        &mut TopLevelScope::default(),
+    )
+}
+
+/**
+ * Typecheck a file whose top level can see what earlier files left in the scope. If it typechecks,
+ * the variables and constants it declares are added to the scope.
+ */
+pub(crate) fn typecheck_continuing<'ast, 'decl>(
+    file: &'ast [AstNode<'ast>],
+    current_module_name: &str,
+    declarations: &'decl DeclarationContext,
+    scope: &mut TopLevelScope,
 ) -> Result<TypecheckedFile<'ast, 'decl>, TypecheckError> {
     let mut top_level_type_names = HashMap::new();
     let mut top_level_function_names = HashMap::new();
@@ -442,6 +787,12 @@ pub fn typecheck<'ast, 'decl>(
             | ExpressionType::Generator { .. }
             | ExpressionType::FunctionReference { .. }
             | ExpressionType::InstanceOf(_) => unreachable!(),
+            // Aliases only stand in for types, they can't be used as values
+            ExpressionType::ReferenceToType(ty_id)
+                if declarations.type_aliases.contains_key(ty_id) =>
+            {
+                top_level_type_names.insert(name.as_str(), *ty_id);
+            }
             ExpressionType::ReferenceToType(ty_id) => {
                 let value = &declarations.id_to_decl[ty_id];
                 top_level_type_names.insert(name.as_str(), *ty_id);
@@ -476,6 +827,9 @@ pub fn typecheck<'ast, 'decl>(
             ExpressionType::ReferenceToType(ty_id) => {
                 let ty_id = *ty_id;
                 top_level_type_names.insert(name, ty_id);
+                if declarations.type_aliases.contains_key(&ty_id) {
+                    continue;
+                }
                 top_level_name_to_expr_type.insert(
                     name.to_string(),
                     (ty_id.into(), ExpressionType::ReferenceToType(ty_id)),
@@ -485,25 +839,42 @@ pub fn typecheck<'ast, 'decl>(
         }
     }
 
+    let field_defaults = RefCell::new(HashMap::new());
     let mut context = TypecheckContext {
         declarations,
         top_level_name_to_expr_type,
         top_level_function_names,
         top_level_type_names,
+        constant_values: RefCell::new(scope.constant_values.clone()),
+        field_defaults: &field_defaults,
+        enclosing_locals: HashSet::new(),
+        returns_reference: false,
     };
 
     // Insert all the constants
-    let mut constants = HashMap::new();
+    let (mut constants, mut top_level_scope): (HashMap<_, _>, HashMap<_, _>) = scope
+        .names
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .partition(|(_, (id, _))| matches!(id, AnyID::Constant(_)));
     for statement in file {
         if let AstNodeValue::ConstDeclaration { .. } = &statement.value {
-            typecheck_const(&context, &[], &mut constants, statement)?;
+            typecheck_const(
+                &context,
+                &[&context.top_level_name_to_expr_type],
+                &mut constants,
+                statement,
+            )?;
         }
     }
-    context.top_level_name_to_expr_type.extend(constants);
+    context.top_level_name_to_expr_type.extend(
+        constants
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
 
     let mut functions = Vec::new();
     let mut top_level_statements = Vec::new();
-    let mut top_level_scope = HashMap::new();
 
     for statement in file {
         merge_results(
@@ -518,11 +889,17 @@ pub fn typecheck<'ast, 'decl>(
         );
     }
     results?;
+    check_definite_assignment(&functions, &top_level_statements)?;
+
+    top_level_scope.extend(constants);
+    scope.names = top_level_scope;
+    scope.constant_values = context.constant_values.take();
 
     Ok(TypecheckedFile {
         functions,
         top_level_statements,
         module,
+        field_defaults: field_defaults.take(),
     })
 }
 
@@ -543,6 +920,7 @@ fn typecheck_node<'a>(
                 name: func.name.clone(),
                 func,
             });
+            hoist_local_functions(context.declarations, *func_id, func, functions);
         }
         AstNodeValue::StructDeclaration(StructDeclarationValue {
             name,
@@ -553,6 +931,11 @@ fn typecheck_node<'a>(
             name,
             associated_functions,
             ..
+        })
+        | AstNodeValue::ImplBlock(ImplBlockValue {
+            name,
+            associated_functions,
+            ..
         }) => {
             let ty_id = &context.top_level_type_names[name.as_str()];
             let (TypeDeclaration::Struct(StructType {
@@ -586,11 +969,32 @@ fn typecheck_node<'a>(
                 })
                 .collect::<Result<Vec<_>, _>>()?;
             functions.extend(associated_functions.iter().cloned());
+            if let AstNodeValue::ImplBlock(ImplBlockValue {
+                interface: Some((interface, provenance)),
+                ..
+            }) = &statement.value
+            {
+                check_interface_conformance(
+                    context,
+                    associated_functions_ty,
+                    interface,
+                    provenance,
+                )?;
+            }
+            if let AstNodeValue::StructDeclaration(decl) = &statement.value {
+                typecheck_field_defaults(
+                    context,
+                    &[&context.top_level_name_to_expr_type],
+                    top_level_scope,
+                    decl,
+                )?;
+            }
         }
         // These nodes don't execute anything and therefore don't need to be typechecked
-        AstNodeValue::Import(_)
+        AstNodeValue::Import(..)
         | AstNodeValue::UnionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_) => {}
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ExternFunctionBinding { .. } => {}
         // Constants are extracted and type-checked earlier in the process, but still need
         // to be present so we can locate them in the HIR
         AstNodeValue::ConstDeclaration { .. } => {
@@ -604,6 +1008,7 @@ fn typecheck_node<'a>(
                 context,
                 None,
             )?;
+            typecheck_orphan_breaks(statement)?;
             top_level_statements.push(statement);
         }
     }
@@ -611,11 +1016,76 @@ fn typecheck_node<'a>(
     Ok(())
 }
 
+/**
+ * Report each of the interface's functions that the struct doesn't implement, for structs that
+ * declare their conformance instead of relying on being structurally assignable
+ */
+fn check_interface_conformance(
+    context: &TypecheckContext,
+    struct_functions: &HashMap<String, FunctionID>,
+    interface_name: &str,
+    provenance: &SourceRange,
+) -> Result<(), TypecheckError> {
+    let Some(interface_id) = context.top_level_type_names.get(interface_name) else {
+        return Err(TypecheckError::NameNotFound(provenance.clone()));
+    };
+    // Type aliases don't have declarations, so an alias of an interface isn't one either
+    let Some(TypeDeclaration::Interface(interface)) =
+        context.declarations.id_to_decl.get(interface_id)
+    else {
+        return Err(TypecheckError::NotAnInterface(
+            interface_name.to_string(),
+            provenance.clone(),
+        ));
+    };
+
+    let mut required: Vec<_> = interface.associated_functions.iter().collect();
+    required.sort();
+    let mut result = Ok(());
+    for (name, required_id) in required {
+        let implemented = struct_functions.get(name).is_some_and(|provided_id| {
+            implements_function(
+                &context.declarations.id_to_func[required_id],
+                &context.declarations.id_to_func[provided_id],
+            )
+        });
+        if !implemented {
+            merge_results(
+                &mut result,
+                Err(TypecheckError::MissingInterfaceMethod(
+                    name.clone(),
+                    interface_name.to_string(),
+                    provenance.clone(),
+                )),
+            );
+        }
+    }
+
+    result
+}
+
 fn typecheck_function<'a>(
     context: &TypecheckContext,
     function: &'a FunctionDeclarationValue<'a>,
     function_type: &FuncType,
 ) -> Result<(), TypecheckError> {
+    let local_context;
+    let local_types = context.declarations.local_type_names.get(&function_type.id);
+    let local_functions = context
+        .declarations
+        .local_function_names
+        .get(&function_type.id);
+    let returns_reference = function_type.returns.is_reference();
+    let context = if local_types.is_some() || local_functions.is_some() || returns_reference {
+        local_context = TypecheckContext {
+            returns_reference,
+            ..context.with_locals(local_types, local_functions)
+        };
+        &local_context
+    } else {
+        context
+    };
+
     let parameters = function
         .params
         .iter()
@@ -624,7 +1094,7 @@ fn typecheck_function<'a>(
         .collect();
 
     if function.is_coroutine {
-        let ExpressionType::Generator { param_ty, .. } = &function_type.returns else {
+        let ExpressionType::Generator { yield_ty, param_ty } = &function_type.returns else {
             return Err(TypecheckError::MustReturnGenerator(
                 function_type.provenance.clone().unwrap(),
             ));
@@ -638,12 +1108,17 @@ fn typecheck_function<'a>(
         )?;
 
         if return_ty != &ExpressionType::Void && return_ty != &ExpressionType::Unreachable {
-            return Err(TypecheckError::TypeMismatch {
-                provenance: function.body.provenance.clone(),
-                expected: ExpressionType::Void,
-                received: return_ty.clone(),
-            });
+            return Err(type_mismatch(
+                context.declarations,
+                function.body.provenance.clone(),
+                &ExpressionType::Void,
+                return_ty,
+            ));
         }
+
+        typecheck_yields(context, yield_ty, function.body)?;
+        // Returning finishes the generator, so there's nothing to return
+        typecheck_returns(context, &ExpressionType::Void, function.body)?;
     } else {
         let return_ty = typecheck_expression(
             function.body,
@@ -652,19 +1127,81 @@ fn typecheck_function<'a>(
             context,
             None,
         )?;
-        assert_assignable_to(
-            context.declarations,
-            &function.body.provenance,
-            &function_type.returns,
-            return_ty,
-        )?;
+        if return_ty == &ExpressionType::Void && function_type.returns != ExpressionType::Void {
+            // The body doesn't produce a value, so every path through it has to return one
+            if !always_returns(function.body) {
+                return Err(TypecheckError::MissingReturn(
+                    function.name.clone(),
+                    function.body.provenance.clone(),
+                ));
+            }
+        } else {
+            assert_assignable_to(
+                context.declarations,
+                &function.body.provenance,
+                &function_type.returns,
+                return_ty,
+            )?;
+        }
 
         typecheck_returns(context, &function_type.returns, function.body)?;
+        if returns_reference {
+            let parameter = function
+                .params
+                .iter()
+                .zip(function_type.params.iter())
+                .find_map(|((id, _), param)| param.is_reference().then_some((*id).into()))
+                .expect("type validation requires a reference parameter");
+            validate_reference_returns(function.body, parameter)?;
+        }
+    }
+
+    typecheck_orphan_breaks(function.body)?;
+
+    if function.is_const {
+        // Const fns are folded into a literal, so they have to produce one
+        if !function_type
+            .params
+            .iter()
+            .chain(std::iter::once(&function_type.returns))
+            .all(|ty| matches!(ty, ExpressionType::Primitive(_)))
+        {
+            return Err(TypecheckError::NonPrimitiveConstFunction(
+                function_type.provenance.clone().unwrap(),
+            ));
+        }
+        validate_const_fn_body(context, function.body)?;
     }
 
     Ok(())
 }
 
+/**
+ * Functions declared inside another function are lowered like any other function, so they're
+ * added to the file's functions along with the ones nested inside of them
+ */
+fn hoist_local_functions<'a>(
+    declarations: &DeclarationContext,
+    func_id: FunctionID,
+    func: &'a FunctionDeclarationValue<'a>,
+    functions: &mut Vec<TypecheckedFunction<'a>>,
+) {
+    let Some(local_names) = declarations.local_function_names.get(&func_id) else {
+        return;
+    };
+    let mut local_functions = Vec::new();
+    find_local_functions(func.body, &mut local_functions);
+    for (local_func, _) in local_functions {
+        let local_id = local_names[local_func.name.as_str()];
+        functions.push(TypecheckedFunction {
+            id: local_id,
+            name: local_func.name.clone(),
+            func: local_func,
+        });
+        hoist_local_functions(declarations, local_id, local_func, functions);
+    }
+}
+
 fn typecheck_expression<'a>(
     node: &'a AstNode<'a>,
     outer_scopes: &[&HashMap<String, (AnyID, ExpressionType)>],
@@ -673,15 +1210,62 @@ fn typecheck_expression<'a>(
     generator_input_ty: Option<&ExpressionType>,
 ) -> Result<&'a ExpressionType, TypecheckError> {
     let ty = match &node.value {
-        AstNodeValue::FunctionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_)
+        // Local structs were hoisted to the whole function when declarations were collected
+        AstNodeValue::StructDeclaration(
+            decl @ StructDeclarationValue {
+                associated_functions,
+                ..
+            },
+        ) if associated_functions.is_empty() => {
+            typecheck_field_defaults(context, outer_scopes, current_scope, decl)?;
+            ExpressionType::Void
+        }
+        // Local functions were hoisted too, but their bodies are checked here where it's known
+        // which variables they'd be capturing
+        AstNodeValue::FunctionDeclaration(func) => {
+            let Some(func_id) = context.top_level_function_names.get(func.name.as_str()) else {
+                return Err(TypecheckError::FunctionInsideAssociatedFunction(
+                    node.provenance.clone(),
+                ));
+            };
+            let func_type = &context.declarations.id_to_func[func_id];
+            let mut nested_context = context.with_locals(None, None);
+            nested_context.enclosing_locals.extend(
+                outer_scopes
+                    .iter()
+                    .flat_map(|scope| scope.keys())
+                    .chain(current_scope.keys())
+                    .cloned(),
+            );
+            typecheck_function(&nested_context, func, func_type)?;
+
+            ExpressionType::Void
+        }
+        AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::RequiredFunction(_)
-        | AstNodeValue::StructDeclaration(_)
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
-        | AstNodeValue::Import(_) => {
+        | AstNodeValue::Import(..) => {
             unimplemented!("Can't do this inside a function");
         }
+        AstNodeValue::ImplBlock(_) => {
+            return Err(TypecheckError::IllegalLocalDeclaration(
+                "impl blocks".to_string(),
+                node.provenance.clone(),
+            ));
+        }
+        AstNodeValue::StructDeclaration(_) => {
+            return Err(TypecheckError::IllegalLocalDeclaration(
+                "structs with associated functions".to_string(),
+                node.provenance.clone(),
+            ));
+        }
+        AstNodeValue::TypeAliasDeclaration { .. } => {
+            return Err(TypecheckError::IllegalLocalDeclaration(
+                "type aliases".to_string(),
+                node.provenance.clone(),
+            ));
+        }
         AstNodeValue::UniqueType(_)
         | AstNodeValue::VoidType
         | AstNodeValue::SharedType(_)
@@ -690,7 +1274,8 @@ fn typecheck_expression<'a>(
         | AstNodeValue::RcType(_)
         | AstNodeValue::DictType(_, _)
         | AstNodeValue::NullableType(_)
-        | AstNodeValue::GeneratorType { .. } => {
+        | AstNodeValue::GeneratorType { .. }
+        | AstNodeValue::FunctionType { .. } => {
             panic!("illegal type expression in function body");
         }
         AstNodeValue::Statement(inner) => {
@@ -703,7 +1288,26 @@ fn typecheck_expression<'a>(
             )?;
             ExpressionType::Void
         }
-        AstNodeValue::Declaration(name, type_hint, value, variable_id) => {
+        AstNodeValue::Declaration(name, Some(type_hint), None, variable_id) => {
+            let hint_ty = resolve_type_expr(
+                &context.top_level_type_names,
+                &context.declarations.type_aliases,
+                type_hint,
+            )?;
+            if matches!(hint_ty, ExpressionType::Pointer(_, _)) {
+                return Err(TypecheckError::IllegalFirstClassReference(
+                    node.provenance.clone(),
+                ));
+            }
+            type_hint.ty.set(hint_ty.clone()).unwrap();
+            current_scope.insert(name.clone(), ((*variable_id).into(), hint_ty));
+
+            ExpressionType::Void
+        }
+        AstNodeValue::Declaration(_, None, None, _) => {
+            unreachable!("parser rejects declarations without a value or a type")
+        }
+        AstNodeValue::Declaration(name, type_hint, Some(value), variable_id) => {
             let value_ty = typecheck_expression(
                 value,
                 outer_scopes,
@@ -722,7 +1326,11 @@ fn typecheck_expression<'a>(
             }
 
             if let Some(type_hint) = type_hint {
-                let hint_ty = resolve_type_expr(&context.top_level_type_names, type_hint)?;
+                let hint_ty = resolve_type_expr(
+                    &context.top_level_type_names,
+                    &context.declarations.type_aliases,
+                    type_hint,
+                )?;
                 if matches!(hint_ty, ExpressionType::Pointer(_, _)) {
                     merge_results(
                         &mut result,
@@ -767,23 +1375,16 @@ fn typecheck_expression<'a>(
             )?;
             current_scope.insert(name.clone(), ((*variable_id).into(), value_ty.clone()));
 
-            let mut results = Ok(());
-            if let AstNodeValue::TakeRef(child) | AstNodeValue::TakeUnique(child) = &value.value {
-                if !validate_lvalue(child) {
-                    merge_results(
-                        &mut results,
-                        Err(TypecheckError::IllegalNonLvalueBorrow(
-                            node.provenance.clone(),
-                        )),
-                    );
-                }
-            } else {
-                merge_results(
-                    &mut results,
-                    Err(TypecheckError::IllegalNonRefBorrow(node.provenance.clone())),
-                );
+            // Taking a reference already checked that it points at an lvalue, and a returned
+            // reference borrows from the reference that was passed in
+            let is_borrow = match &value.value {
+                AstNodeValue::TakeRef(_) | AstNodeValue::TakeUnique(_) => true,
+                AstNodeValue::Call(_, _) => value_ty.is_reference(),
+                _ => false,
             };
-            results?;
+            if !is_borrow {
+                return Err(TypecheckError::IllegalNonRefBorrow(node.provenance.clone()));
+            }
 
             ExpressionType::Void
         }
@@ -803,6 +1404,70 @@ fn typecheck_expression<'a>(
 
             ExpressionType::Unreachable
         }
+        AstNodeValue::Break(value, label) => {
+            if let Some(value) = value {
+                let value_ty = typecheck_expression(
+                    value,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                );
+                // `break name` is taken as a label if there's a loop with that name, so if there's
+                // no variable either it was probably meant as one
+                match (value_ty, &value.value) {
+                    (
+                        Err(TypecheckError::NameNotFound(_)),
+                        AstNodeValue::Name { value: name, .. },
+                    ) if label.is_none() => {
+                        return Err(TypecheckError::UnknownLoopLabel(
+                            name.clone(),
+                            node.provenance.clone(),
+                        ));
+                    }
+                    (value_ty, _) => {
+                        value_ty?;
+                    }
+                }
+            }
+            if let Some(LoopLabel { name, depth: None }) = label {
+                return Err(TypecheckError::UnknownLoopLabel(
+                    name.clone(),
+                    node.provenance.clone(),
+                ));
+            }
+
+            ExpressionType::Unreachable
+        }
+        AstNodeValue::Continue(Some(LoopLabel { name, depth: None })) => {
+            return Err(TypecheckError::UnknownLoopLabel(
+                name.clone(),
+                node.provenance.clone(),
+            ));
+        }
+        AstNodeValue::Continue(_) => ExpressionType::Unreachable,
+        AstNodeValue::Defer(deferred) => {
+            let deferred_ty = typecheck_expression(
+                deferred,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            assert_assignable_to(
+                context.declarations,
+                &deferred.provenance,
+                &ExpressionType::Void,
+                deferred_ty,
+            )?;
+            let mut jumps = Vec::new();
+            deferred_jumps(deferred, 0, &mut jumps);
+            if let Some(jump) = jumps.first() {
+                return Err(TypecheckError::JumpOutOfDefer(jump.provenance.clone()));
+            }
+
+            ExpressionType::Void
+        }
         AstNodeValue::Yield(yielded) => {
             let Some(yield_ctx_ty) = generator_input_ty else {
                 return Err(TypecheckError::CannotYield(node.provenance.clone()));
@@ -824,7 +1489,20 @@ fn typecheck_expression<'a>(
             referenced_id,
         } => {
             let (ref_id, expr) = resolve_name(name, current_scope, outer_scopes)
-                .ok_or_else(|| TypecheckError::NameNotFound(node.provenance.clone()))?;
+                .or_else(|| {
+                    let intrinsic = context.declarations.global_intrinsics.get(name.as_str())?;
+                    Some((
+                        AnyID::Function(intrinsic.fn_id),
+                        ExpressionType::ReferenceToFunction(intrinsic.fn_id),
+                    ))
+                })
+                .ok_or_else(|| {
+                    if context.enclosing_locals.contains(name) {
+                        TypecheckError::CapturedLocal(name.clone(), node.provenance.clone())
+                    } else {
+                        TypecheckError::NameNotFound(node.provenance.clone())
+                    }
+                })?;
             referenced_id
                 .set(ref_id)
                 .expect("each node should be visited once");
@@ -880,14 +1558,22 @@ fn typecheck_expression<'a>(
                             ExpressionType::InstanceOf(*id)
                         }
                     }
-                    TypeDeclaration::Module(module) => module.exports[name].clone(),
+                    TypeDeclaration::Module(module) => module.exported(name, &right.provenance)?,
                     // TODO: static functions on structs?
                     TypeDeclaration::Struct(_) | TypeDeclaration::Interface(_) => {
                         return Err(TypecheckError::IllegalDotLHS(left.provenance.clone()));
                     }
                 },
-                ExpressionType::Collection(CollectionType::Array(_item_ty)) => {
+                ExpressionType::Collection(CollectionType::Array(item_ty)) => {
                     if let Some(ty) = context.declarations.array_intrinsics.get(name.as_str()) {
+                        // Elements are compared the same way dictionary keys are
+                        if ty.intrinsic_fn == IntrinsicFunction::ArrayContains
+                            && !matches!(item_ty.as_ref(), ExpressionType::Primitive(_))
+                        {
+                            return Err(TypecheckError::IllegalArrayContains(
+                                node.provenance.clone(),
+                            ));
+                        }
                         ExpressionType::ReferenceToFunction(ty.fn_id)
                     } else {
                         todo!("array methods")
@@ -914,6 +1600,16 @@ fn typecheck_expression<'a>(
                         todo!("rc methods")
                     }
                 }
+                ExpressionType::Primitive(prim) => {
+                    if let Some(ty) = context.declarations.primitive_intrinsic(*prim, name) {
+                        ExpressionType::ReferenceToFunction(ty.fn_id)
+                    } else {
+                        return Err(TypecheckError::FieldNotPresent(
+                            name.clone(),
+                            node.provenance.clone(),
+                        ));
+                    }
+                }
                 _ => {
                     return Err(TypecheckError::IllegalDotLHS(left.provenance.clone()));
                 }
@@ -993,7 +1689,8 @@ fn typecheck_expression<'a>(
                 context,
                 generator_input_ty,
             )?;
-            match collection_ty {
+            // Indexing auto-dereferences, like field access
+            match fully_dereference(collection_ty) {
                 ExpressionType::Collection(CollectionType::Array(item_ty)) => {
                     let index_ty = typecheck_expression(
                         index,
@@ -1031,6 +1728,39 @@ fn typecheck_expression<'a>(
                 ty => todo!("can't index {:?}", ty),
             }
         }
+        AstNodeValue::ArraySlice(collection, start, end) => {
+            let collection_ty = typecheck_expression(
+                collection,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            // Slices are copies, so duplicating affine items would leave two owners
+            let ExpressionType::Collection(CollectionType::Array(item_ty)) = &collection_ty else {
+                return Err(TypecheckError::IllegalSlice(collection.provenance.clone()));
+            };
+            if item_ty.is_affine(context.id_to_decl()) {
+                return Err(TypecheckError::IllegalSlice(collection.provenance.clone()));
+            }
+            for bound in [start, end] {
+                let bound_ty = typecheck_expression(
+                    bound,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                )?;
+                assert_assignable_to(
+                    context.declarations,
+                    &bound.provenance,
+                    &ExpressionType::Primitive(PrimitiveType::PointerSize),
+                    bound_ty,
+                )?;
+            }
+
+            collection_ty.clone()
+        }
         AstNodeValue::BinExpr(
             BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide,
             left,
@@ -1043,7 +1773,7 @@ fn typecheck_expression<'a>(
                 context,
                 generator_input_ty,
             )?;
-            let ExpressionType::Primitive(_) = fully_dereference(left) else {
+            let ExpressionType::Primitive(left) = fully_dereference(left) else {
                 return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
             };
             let right = typecheck_expression(
@@ -1053,15 +1783,27 @@ fn typecheck_expression<'a>(
                 context,
                 generator_input_ty,
             )?;
-            let ExpressionType::Primitive(_) = fully_dereference(right) else {
+            let ExpressionType::Primitive(right) = fully_dereference(right) else {
                 return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
             };
-            if is_assignable_to(context.declarations, None, left, right) {
-                left.clone()
-            } else if is_assignable_to(context.declarations, None, right, left) {
-                right.clone()
-            } else {
-                return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
+            // Mixing a size with an i32, like `arr.len() - 1`, always produces a size no matter
+            // which side it's on, and the i32 is cast up to match
+            match (left, right) {
+                (PrimitiveType::PointerSize, PrimitiveType::Int32)
+                | (PrimitiveType::Int32, PrimitiveType::PointerSize) => {
+                    ExpressionType::Primitive(PrimitiveType::PointerSize)
+                }
+                (left, right) => {
+                    let left = ExpressionType::Primitive(*left);
+                    let right = ExpressionType::Primitive(*right);
+                    if is_assignable_to(context.declarations, None, &left, &right) {
+                        left
+                    } else if is_assignable_to(context.declarations, None, &right, &left) {
+                        right
+                    } else {
+                        return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
+                    }
+                }
             }
         }
         AstNodeValue::BinExpr(BinOp::BooleanAnd | BinOp::BooleanOr, left, right) => {
@@ -1241,11 +1983,18 @@ fn typecheck_expression<'a>(
                 return Err(TypecheckError::ExpectedNullableLHS(left.provenance.clone()));
             };
 
-            assert_assignable_to(context.declarations, &right.provenance, ty, right_ty)?;
+            if is_assignable_to(context.declarations, None, left_ty, right_ty)
+                && !is_assignable_to(context.declarations, None, ty, right_ty)
+            {
+                // Falling back to something that might be null might still be null
+                left_ty.clone()
+            } else {
+                assert_assignable_to(context.declarations, &right.provenance, ty, right_ty)?;
 
-            ty.as_ref().clone()
+                ty.as_ref().clone()
+            }
         }
-        AstNodeValue::While(condition, body) => {
+        AstNodeValue::While(condition, step, body) => {
             let condition_ty = typecheck_expression(
                 condition,
                 outer_scopes,
@@ -1258,24 +2007,32 @@ fn typecheck_expression<'a>(
                 condition_deref,
                 ExpressionType::Primitive(PrimitiveType::Bool)
             ) {
-                return Err(TypecheckError::TypeMismatch {
-                    provenance: condition.provenance.clone(),
-                    received: condition_ty.clone(),
-                    expected: ExpressionType::Primitive(PrimitiveType::Bool),
-                });
+                return Err(type_mismatch(
+                    context.declarations,
+                    condition.provenance.clone(),
+                    &ExpressionType::Primitive(PrimitiveType::Bool),
+                    condition_ty,
+                ));
             }
 
-            typecheck_expression(
-                body,
-                outer_scopes,
-                current_scope,
-                context,
-                generator_input_ty,
-            )?;
+            if let Some(step) = step {
+                let step_ty = typecheck_expression(
+                    step,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                )?;
+                if !matches!(step_ty, ExpressionType::Void) {
+                    return Err(type_mismatch(
+                        context.declarations,
+                        step.provenance.clone(),
+                        &ExpressionType::Void,
+                        step_ty,
+                    ));
+                }
+            }
 
-            ExpressionType::Void
-        }
-        AstNodeValue::Loop(body) => {
             typecheck_expression(
                 body,
                 outer_scopes,
@@ -1283,8 +2040,46 @@ fn typecheck_expression<'a>(
                 context,
                 generator_input_ty,
             )?;
-            ExpressionType::Unreachable
+
+            let mut breaks = Vec::new();
+            loop_breaks(body, 0, &mut breaks);
+            let mut result = Ok(());
+            for break_node in breaks {
+                if let AstNodeValue::Break(Some(value), _) = &break_node.value {
+                    merge_results(
+                        &mut result,
+                        Err(type_mismatch(
+                            context.declarations,
+                            break_node.provenance.clone(),
+                            &ExpressionType::Void,
+                            value.ty.get().unwrap(),
+                        )),
+                    );
+                }
+            }
+            result?;
+
+            ExpressionType::Void
+        }
+        AstNodeValue::Loop(body) => {
+            typecheck_expression(
+                body,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+
+            loop_result_ty(context, body)?
         }
+        AstNodeValue::LabeledLoop(_, inner) => typecheck_expression(
+            inner,
+            outer_scopes,
+            current_scope,
+            context,
+            generator_input_ty,
+        )?
+        .clone(),
         AstNodeValue::If(IfDeclaration {
             condition,
             if_branch,
@@ -1302,11 +2097,12 @@ fn typecheck_expression<'a>(
                 condition_deref,
                 ExpressionType::Primitive(PrimitiveType::Bool)
             ) {
-                return Err(TypecheckError::TypeMismatch {
-                    provenance: condition.provenance.clone(),
-                    received: condition_ty.clone(),
-                    expected: ExpressionType::Primitive(PrimitiveType::Bool),
-                });
+                return Err(type_mismatch(
+                    context.declarations,
+                    condition.provenance.clone(),
+                    &ExpressionType::Primitive(PrimitiveType::Bool),
+                    condition_ty,
+                ));
             }
 
             let if_ty = typecheck_expression(
@@ -1329,22 +2125,89 @@ fn typecheck_expression<'a>(
                 })
                 .transpose()?;
 
-            match else_ty {
-                Some(else_ty) => {
-                    if is_assignable_to(context.declarations, None, if_ty, else_ty) {
-                        if_ty.clone()
-                    } else {
-                        assert_assignable_to(
-                            context.declarations,
-                            &if_branch.provenance,
-                            else_ty,
-                            if_ty,
-                        )?;
-                        else_ty.clone()
-                    }
+            if_result_ty(context, if_branch, if_ty, else_ty)?
+        }
+        AstNodeValue::IfLet(IfLetDeclaration {
+            pattern,
+            var_id,
+            value,
+            if_branch,
+            else_branch,
+        }) => {
+            let input_ty = typecheck_expression(
+                value,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            let (binding_name, binding_ty) = if pattern.bindings.is_empty() {
+                let ExpressionType::Nullable(inner_ty) = input_ty else {
+                    return Err(TypecheckError::IfLetRequiresNullable(
+                        value.provenance.clone(),
+                    ));
+                };
+                pattern.ty.set(Some(inner_ty.as_ref().clone())).unwrap();
+                (&pattern.name, inner_ty.as_ref().clone())
+            } else {
+                let Some(TypeDeclaration::Union(union_ty)) = shallow_dereference(input_ty)
+                    .type_id()
+                    .map(|ty_id| &context.id_to_decl()[ty_id])
+                else {
+                    return Err(TypecheckError::IfLetRequiresUnion(value.provenance.clone()));
+                };
+                let Some(variant_ty) = union_ty.variants.get(&pattern.name) else {
+                    return Err(TypecheckError::VariantNotPresent(
+                        pattern.name.clone(),
+                        pattern.provenance.clone(),
+                    ));
+                };
+                let Some(variant_ty) = variant_ty else {
+                    return Err(TypecheckError::BindingCountDoesntMatch(
+                        pattern.provenance.clone(),
+                    ));
+                };
+                if pattern.bindings.len() != 1 {
+                    return Err(TypecheckError::BindingCountDoesntMatch(
+                        pattern.provenance.clone(),
+                    ));
                 }
-                None => ExpressionType::Void,
-            }
+                pattern.ty.set(Some(variant_ty.clone())).unwrap();
+                let binding_ty = if let ExpressionType::Pointer(ptr_ty, _) = input_ty {
+                    ExpressionType::Pointer(*ptr_ty, Box::new(variant_ty.clone()))
+                } else {
+                    variant_ty.clone()
+                };
+                (&pattern.bindings[0], binding_ty)
+            };
+
+            let mut scopes: Vec<&HashMap<_, _>> = Vec::with_capacity(outer_scopes.len() + 1);
+            scopes.push(current_scope);
+            scopes.extend_from_slice(outer_scopes);
+            let mut child_scope = HashMap::new();
+            child_scope.insert(binding_name.clone(), ((*var_id).into(), binding_ty));
+
+            let if_ty = typecheck_expression(
+                if_branch,
+                &scopes,
+                &mut child_scope,
+                context,
+                generator_input_ty,
+            )?;
+            let else_ty = else_branch
+                .as_ref()
+                .map(|else_branch| {
+                    typecheck_expression(
+                        else_branch,
+                        outer_scopes,
+                        current_scope,
+                        context,
+                        generator_input_ty,
+                    )
+                })
+                .transpose()?;
+
+            if_result_ty(context, if_branch, if_ty, else_ty)?
         }
         AstNodeValue::Match(MatchDeclaration { value, cases }) => {
             let input_ty = typecheck_expression(
@@ -1364,11 +2227,23 @@ fn typecheck_expression<'a>(
             };
             let mut return_type = None;
             let mut results = Ok(());
-            let mut variants_matched_against = HashSet::new();
+            let mut variants_matched_against = HashMap::new();
             for case in cases.iter() {
                 let mut binding = BindingState::Uninit;
                 for variant in case.variants.iter() {
-                    variants_matched_against.insert(&variant.name);
+                    // Only the first arm for a variant can ever run
+                    if variants_matched_against
+                        .insert(&variant.name, &variant.provenance)
+                        .is_some()
+                    {
+                        merge_results(
+                            &mut results,
+                            Err(TypecheckError::DuplicateMatchVariant(
+                                variant.name.clone(),
+                                variant.provenance.clone(),
+                            )),
+                        );
+                    }
                     let mut variant_ty = union_ty.variants[&variant.name].as_ref();
                     if !variant.bindings.is_empty() && variant.bindings[0] == "_" {
                         variant_ty = None;
@@ -1443,24 +2318,29 @@ fn typecheck_expression<'a>(
                     context,
                     generator_input_ty,
                 )?;
-                if let Some(return_type) = &return_type {
-                    merge_results(
-                        &mut results,
-                        assert_assignable_to(
-                            context.declarations,
-                            &case.body.provenance,
-                            return_type,
-                            body_ty,
-                        ),
-                    );
-                } else {
-                    return_type = Some(body_ty.clone());
+                match &return_type {
+                    // An arm that never finishes, like one that returns early, doesn't decide the
+                    // type of the others
+                    None | Some(ExpressionType::Unreachable) => {
+                        return_type = Some(body_ty.clone());
+                    }
+                    Some(return_type) => {
+                        merge_results(
+                            &mut results,
+                            assert_assignable_to(
+                                context.declarations,
+                                &case.body.provenance,
+                                return_type,
+                                body_ty,
+                            ),
+                        );
+                    }
                 }
             }
             if !union_ty
                 .variants
                 .keys()
-                .all(|variant_name| variants_matched_against.contains(variant_name))
+                .all(|variant_name| variants_matched_against.contains_key(variant_name))
             {
                 merge_results(
                     &mut results,
@@ -1482,6 +2362,68 @@ fn typecheck_expression<'a>(
 
             return_type
         }
+        AstNodeValue::IsVariant(value, variant) => {
+            let input_ty = typecheck_expression(
+                value,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            let Some(TypeDeclaration::Union(union_ty)) = shallow_dereference(input_ty)
+                .type_id()
+                .map(|ty_id| &context.id_to_decl()[ty_id])
+            else {
+                return Err(TypecheckError::IsRequiresUnion(value.provenance.clone()));
+            };
+            if !union_ty.variants.contains_key(variant) {
+                return Err(TypecheckError::VariantNotPresent(
+                    variant.clone(),
+                    node.provenance.clone(),
+                ));
+            }
+
+            ExpressionType::Primitive(PrimitiveType::Bool)
+        }
+        AstNodeValue::Downcast(value, name) => {
+            let input_ty = typecheck_expression(
+                value,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            let interface_decl = match input_ty {
+                ExpressionType::InstanceOf(ty_id) => context.decl(ty_id),
+                _ => None,
+            };
+            let Some(TypeDeclaration::Interface(interface_ty)) = interface_decl else {
+                return Err(TypecheckError::DowncastRequiresInterface(
+                    value.provenance.clone(),
+                ));
+            };
+            let interface_name = context
+                .declarations
+                .type_name(&interface_ty.id)
+                .unwrap_or_default()
+                .to_string();
+            let Some(struct_id) = context.top_level_type_names.get(name.as_str()) else {
+                return Err(TypecheckError::NameNotFound(node.provenance.clone()));
+            };
+            // The interface's pointer has to lead to this struct for it to be recovered
+            let struct_ty = ExpressionType::InstanceOf(*struct_id);
+            if !matches!(context.decl(struct_id), Some(TypeDeclaration::Struct(_)))
+                || !is_assignable_to(context.declarations, None, input_ty, &struct_ty)
+            {
+                return Err(TypecheckError::InvalidDowncastTarget(
+                    name.clone(),
+                    interface_name,
+                    node.provenance.clone(),
+                ));
+            }
+
+            ExpressionType::Nullable(Box::new(struct_ty))
+        }
         AstNodeValue::Block(children) => {
             let mut scopes: Vec<&HashMap<_, _>> = Vec::with_capacity(outer_scopes.len() + 1);
             scopes.push(current_scope);
@@ -1504,29 +2446,83 @@ fn typecheck_expression<'a>(
                     expr_ty = new_ty.clone();
                 }
 
-                if index == children.len() - 1 {
-                    if expr_ty.is_reference() {
-                        merge_results(
-                            &mut result,
-                            Err(TypecheckError::IllegalFirstClassReference(
-                                node.provenance.clone(),
-                            )),
-                        );
-                    }
-                } else if expr_ty != ExpressionType::Void && expr_ty != ExpressionType::Unreachable
+                // Only the final expression is the block's value: any earlier value is evaluated
+                // for its side effects and then discarded during lowering
+                if index == children.len() - 1
+                    && expr_ty.is_reference()
+                    && !context.returns_reference
                 {
                     merge_results(
                         &mut result,
-                        Err(TypecheckError::TypeMismatch {
-                            provenance: child.provenance.clone(),
-                            expected: ExpressionType::Void,
-                            received: expr_ty.clone(),
-                        }),
+                        Err(TypecheckError::IllegalFirstClassReference(
+                            node.provenance.clone(),
+                        )),
                     );
                 }
             }
+            result?;
+
             expr_ty
         }
+        AstNodeValue::Call(func, args)
+            if is_builtin(func, "typename", current_scope, outer_scopes) =>
+        {
+            let [arg] = &args[..] else {
+                return Err(TypecheckError::WrongArgsCount(node.provenance.clone()));
+            };
+            typecheck_expression(
+                arg,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            func.ty
+                .set(ExpressionType::Void)
+                .expect("each node should be visited once");
+            ExpressionType::Collection(CollectionType::String)
+        }
+        AstNodeValue::Call(func, args)
+            if is_builtin(func, "assert", current_scope, outer_scopes) =>
+        {
+            let (condition, message) = match &args[..] {
+                [condition] => (condition, None),
+                [condition, message] => (condition, Some(message)),
+                _ => return Err(TypecheckError::WrongArgsCount(node.provenance.clone())),
+            };
+            let condition_ty = typecheck_expression(
+                condition,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            assert_assignable_to(
+                context.declarations,
+                &condition.provenance,
+                &ExpressionType::Primitive(PrimitiveType::Bool),
+                condition_ty,
+            )?;
+            if let Some(message) = message {
+                let message_ty = typecheck_expression(
+                    message,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                )?;
+                assert_assignable_to(
+                    context.declarations,
+                    &message.provenance,
+                    &ExpressionType::Collection(CollectionType::String),
+                    message_ty,
+                )?;
+            }
+            func.ty
+                .set(ExpressionType::Void)
+                .expect("each node should be visited once");
+            ExpressionType::Void
+        }
         AstNodeValue::Call(func, args) => {
             match fully_dereference(typecheck_expression(
                 func,
@@ -1540,14 +2536,20 @@ fn typecheck_expression<'a>(
                     let mut generic_args =
                         vec![ExpressionType::Unreachable; func_ty.type_param_count];
 
+                    let mut has_primitive_receiver = false;
                     let params = if func_ty.is_associated {
                         if let AstNodeValue::BinExpr(BinOp::NullChaining | BinOp::Dot, lhs, _) =
                             &func.value
                         {
+                            let receiver_ty = lhs.ty.get().expect("type info to be filled in");
+                            has_primitive_receiver = matches!(
+                                fully_dereference(receiver_ty),
+                                ExpressionType::Primitive(_)
+                            );
                             find_generic_bindings(
                                 &mut generic_args[..],
                                 &func_ty.params[0],
-                                lhs.ty.get().expect("type info to be filled in"),
+                                receiver_ty,
                             );
                         }
 
@@ -1567,6 +2569,16 @@ fn typecheck_expression<'a>(
                             context,
                             generator_input_ty,
                         )?;
+                        // Numeric methods on an i32 widen to a size argument, the same way
+                        // arithmetic does, so an index can be compared against a length
+                        if has_primitive_receiver
+                            && param == &ExpressionType::TypeParameterReference(0)
+                            && generic_args[0] == ExpressionType::Primitive(PrimitiveType::Int32)
+                            && fully_dereference(arg_ty)
+                                == &ExpressionType::Primitive(PrimitiveType::PointerSize)
+                        {
+                            generic_args[0] = ExpressionType::Primitive(PrimitiveType::PointerSize);
+                        }
                         find_generic_bindings(&mut generic_args[..], param, arg_ty);
                         if !is_assignable_to(
                             context.declarations,
@@ -1574,11 +2586,12 @@ fn typecheck_expression<'a>(
                             param,
                             arg_ty,
                         ) {
-                            return Err(TypecheckError::TypeMismatch {
-                                provenance: arg.provenance.clone(),
-                                received: arg_ty.clone(),
-                                expected: param.clone(),
-                            });
+                            return Err(type_mismatch(
+                                context.declarations,
+                                arg.provenance.clone(),
+                                param,
+                                arg_ty,
+                            ));
                         }
                     }
 
@@ -1604,7 +2617,8 @@ fn typecheck_expression<'a>(
                             context,
                             generator_input_ty,
                         )?;
-                        // TODO: generic generators?
+                        // Generator values always have concrete types: any generics are
+                        // resolved when the generator function itself is called
                         assert_assignable_to(
                             context.declarations,
                             &arg.provenance,
@@ -1618,6 +2632,9 @@ fn typecheck_expression<'a>(
                     parameters,
                     returns,
                 } => {
+                    if parameters.len() != args.len() {
+                        return Err(TypecheckError::WrongArgsCount(node.provenance.clone()));
+                    }
                     let mut results = Ok(());
                     for (arg, param) in args.iter().zip(parameters.iter()) {
                         let arg_ty = typecheck_expression(
@@ -1659,13 +2676,16 @@ fn typecheck_expression<'a>(
 
             match context.decl(ty_id).unwrap() {
                 TypeDeclaration::Struct(struct_type) => {
-                    if struct_type.fields.len() != fields.len() {
+                    if struct_type.fields.len() < fields.len() {
                         return Err(TypecheckError::WrongArgsCount(node.provenance.clone()));
                     }
 
                     let mut results = Ok(());
                     for (name, param_field) in struct_type.fields.iter() {
                         let Some(arg_field) = fields.get(name) else {
+                            if struct_type.defaulted_fields.contains(name) {
+                                continue;
+                            }
                             return Err(TypecheckError::MissingField(node.provenance.clone()));
                         };
                         match typecheck_expression(
@@ -1693,6 +2713,15 @@ fn typecheck_expression<'a>(
                     }
 
                     results?;
+                    if let Some(name) = fields
+                        .keys()
+                        .find(|name| !struct_type.fields.contains_key(*name))
+                    {
+                        return Err(TypecheckError::FieldNotPresent(
+                            name.clone(),
+                            node.provenance.clone(),
+                        ));
+                    }
 
                     ExpressionType::InstanceOf(*ty_id)
                 }
@@ -1770,32 +2799,27 @@ fn typecheck_expression<'a>(
             )?;
             ExpressionType::Collection(CollectionType::Cell(Box::new(inner_ty.clone())))
         }
-        AstNodeValue::TakeUnique(inner) => ExpressionType::Pointer(
-            PointerKind::Unique,
-            Box::new(
-                typecheck_expression(
-                    inner,
-                    outer_scopes,
-                    current_scope,
-                    context,
-                    generator_input_ty,
-                )?
-                .clone(),
-            ),
-        ),
-        AstNodeValue::TakeRef(inner) => ExpressionType::Pointer(
-            PointerKind::Shared,
-            Box::new(
-                typecheck_expression(
-                    inner,
-                    outer_scopes,
-                    current_scope,
-                    context,
-                    generator_input_ty,
-                )?
-                .clone(),
-            ),
-        ),
+        AstNodeValue::TakeUnique(inner) | AstNodeValue::TakeRef(inner) => {
+            let inner_ty = typecheck_expression(
+                inner,
+                outer_scopes,
+                current_scope,
+                context,
+                generator_input_ty,
+            )?;
+            // A reference to a temporary would dangle once the temporary is gone
+            let kind = if let AstNodeValue::TakeUnique(_) = &node.value {
+                PointerKind::Unique
+            } else {
+                PointerKind::Shared
+            };
+            if !validate_lvalue(inner) {
+                return Err(TypecheckError::IllegalNonLvalueBorrow(
+                    node.provenance.clone(),
+                ));
+            }
+            ExpressionType::Pointer(kind, Box::new(inner_ty.clone()))
+        }
         AstNodeValue::Deref(inner) => {
             let ty = typecheck_expression(
                 inner,
@@ -1818,7 +2842,7 @@ fn typecheck_expression<'a>(
                 todo!("how to typecheck 0-length collections?");
             }
             let mut iter = items.iter();
-            let ty = typecheck_expression(
+            let mut ty = typecheck_expression(
                 iter.next().unwrap(),
                 outer_scopes,
                 current_scope,
@@ -1826,6 +2850,7 @@ fn typecheck_expression<'a>(
                 generator_input_ty,
             )?
             .clone();
+            // The array holds the widest of its elements, and the narrower ones are converted
             for remaining in iter {
                 let this_ty = typecheck_expression(
                     remaining,
@@ -1834,13 +2859,7 @@ fn typecheck_expression<'a>(
                     context,
                     generator_input_ty,
                 )?;
-                if &ty != this_ty {
-                    return Err(TypecheckError::TypeMismatch {
-                        provenance: remaining.provenance.clone(),
-                        received: this_ty.clone(),
-                        expected: ty,
-                    });
-                }
+                ty = common_type(context, &remaining.provenance, &ty, this_ty)?;
             }
             ExpressionType::Collection(CollectionType::Array(Box::new(ty)))
         }
@@ -1865,6 +2884,14 @@ fn typecheck_expression<'a>(
                 &ExpressionType::Primitive(PrimitiveType::PointerSize),
                 length_ty,
             )?;
+            if let Some(ConstValue::Int(length_value)) = const_eval(context, length) {
+                if length_value < 0 {
+                    return Err(TypecheckError::NegativeArrayLength(
+                        length_value,
+                        length.provenance.clone(),
+                    ));
+                }
+            }
             ExpressionType::Collection(CollectionType::Array(Box::new(value_ty.clone())))
         }
         AstNodeValue::UnaryExpr(op, child) => match op {
@@ -1884,6 +2911,51 @@ fn typecheck_expression<'a>(
                 )?;
                 ExpressionType::Primitive(PrimitiveType::Bool)
             }
+            UnaryOp::Negate | UnaryOp::BitNot | UnaryOp::Plus => {
+                let child_ty = typecheck_expression(
+                    child,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                )?;
+                let ExpressionType::Primitive(prim) = child_ty else {
+                    return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
+                };
+                let allowed = match op {
+                    UnaryOp::Negate => matches!(
+                        prim,
+                        PrimitiveType::Int32
+                            | PrimitiveType::Int64
+                            | PrimitiveType::Float32
+                            | PrimitiveType::Float64
+                    ),
+                    UnaryOp::BitNot => matches!(
+                        prim,
+                        PrimitiveType::Int32 | PrimitiveType::Int64 | PrimitiveType::PointerSize
+                    ),
+                    _ => !matches!(prim, PrimitiveType::Bool | PrimitiveType::Char),
+                };
+                if !allowed {
+                    return Err(TypecheckError::ArithmeticMismatch(node.provenance.clone()));
+                }
+                child_ty.clone()
+            }
+            UnaryOp::NullableUnwrap => {
+                let child_ty = typecheck_expression(
+                    child,
+                    outer_scopes,
+                    current_scope,
+                    context,
+                    generator_input_ty,
+                )?;
+                let ExpressionType::Nullable(ty) = child_ty else {
+                    return Err(TypecheckError::ExpectedNullableUnwrap(
+                        child.provenance.clone(),
+                    ));
+                };
+                ty.as_ref().clone()
+            }
         },
     };
 
@@ -1929,34 +3001,321 @@ fn typecheck_returns<'a>(
         if !is_assignable_to(context.declarations, None, expected_ty, return_ty) {
             merge_results(
                 &mut results,
-                Err(TypecheckError::TypeMismatch {
-                    provenance: current.provenance.clone(),
-                    expected: expected_ty.clone(),
-                    received: return_ty.clone(),
-                }),
+                Err(type_mismatch(
+                    context.declarations,
+                    current.provenance.clone(),
+                    expected_ty,
+                    return_ty,
+                )),
             );
         }
     }
-    current.children(|child| {
-        merge_results(&mut results, typecheck_returns(context, expected_ty, child))
-    });
+    // Nested functions return from themselves, not from the function around them
+    if !matches!(current.value, AstNodeValue::FunctionDeclaration(_)) {
+        current.children(|child| {
+            merge_results(&mut results, typecheck_returns(context, expected_ty, child))
+        });
+    }
 
     results
 }
 
-fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
-    match &lhs.value {
-        // TODO: remove this if I add auto-deref later
-        AstNodeValue::Name { .. } => {
-            validate_assignment_lhs_ty(lhs.ty.get().unwrap(), &lhs.provenance)
-        }
-        AstNodeValue::Deref(inner) => {
-            let Some(ExpressionType::Pointer(kind, _)) = inner.ty.get() else {
-                unreachable!()
-            };
-            let mut errors = if *kind == PointerKind::Shared {
-                Err(TypecheckError::IllegalSharedRefMutation(
-                    lhs.provenance.clone(),
+fn typecheck_yields<'a>(
+    context: &TypecheckContext,
+    expected_ty: &ExpressionType,
+    current: &'a AstNode<'a>,
+) -> Result<(), TypecheckError> {
+    let mut results = Ok(());
+    if let AstNodeValue::Yield(child) = &current.value {
+        let yield_ty = child
+            .as_ref()
+            .map(|child| child.ty.get().unwrap())
+            .unwrap_or(&ExpressionType::Void);
+        if !is_assignable_to(context.declarations, None, expected_ty, yield_ty) {
+            merge_results(
+                &mut results,
+                Err(type_mismatch(
+                    context.declarations,
+                    current.provenance.clone(),
+                    expected_ty,
+                    yield_ty,
+                )),
+            );
+        }
+    }
+    if !matches!(current.value, AstNodeValue::FunctionDeclaration(_)) {
+        current.children(|child| {
+            merge_results(&mut results, typecheck_yields(context, expected_ty, child))
+        });
+    }
+
+    results
+}
+
+/**
+ * Collect the breaks and continues that jump out of the loop with the given body. Ones inside of
+ * a nested loop belong to that loop instead, unless they're labeled with a loop further out.
+ */
+fn loop_breaks<'a>(current: &'a AstNode<'a>, depth: usize, breaks: &mut Vec<&'a AstNode<'a>>) {
+    match &current.value {
+        AstNodeValue::Break(_, label) | AstNodeValue::Continue(label)
+            if LoopLabel::jump_depth(label) == depth =>
+        {
+            breaks.push(current)
+        }
+        AstNodeValue::While(_, _, _) | AstNodeValue::Loop(_) => {
+            current.children(|child| loop_breaks(child, depth + 1, breaks));
+            return;
+        }
+        AstNodeValue::FunctionDeclaration(_) => return,
+        _ => {}
+    }
+    current.children(|child| loop_breaks(child, depth, breaks));
+}
+
+/**
+ * A loop only finishes when it's broken out of, so its value comes from its breaks. A loop that
+ * is never broken out of doesn't finish at all.
+ */
+fn loop_result_ty<'a>(
+    context: &TypecheckContext,
+    body: &'a AstNode<'a>,
+) -> Result<ExpressionType, TypecheckError> {
+    let mut breaks = Vec::new();
+    loop_breaks(body, 0, &mut breaks);
+
+    let mut result_ty: Option<ExpressionType> = None;
+    for break_node in breaks {
+        let AstNodeValue::Break(value, _) = &break_node.value else {
+            continue;
+        };
+        let break_ty = value
+            .as_ref()
+            .map(|value| value.ty.get().unwrap())
+            .unwrap_or(&ExpressionType::Void);
+        result_ty = Some(match result_ty {
+            Some(result_ty) => common_type(context, &break_node.provenance, &result_ty, break_ty)?,
+            None => break_ty.clone(),
+        });
+    }
+
+    Ok(result_ty.unwrap_or(ExpressionType::Unreachable))
+}
+
+/**
+ * Deferred expressions run while their block is already being exited, so they can't start another
+ * exit of their own. Breaks and continues are fine as long as they stay inside a loop in the
+ * deferred expression, so the number of loops entered so far is tracked.
+ */
+fn deferred_jumps<'a>(current: &'a AstNode<'a>, loops: usize, jumps: &mut Vec<&'a AstNode<'a>>) {
+    match &current.value {
+        AstNodeValue::Return(_) => jumps.push(current),
+        AstNodeValue::Break(_, label) | AstNodeValue::Continue(label)
+            if LoopLabel::jump_depth(label) >= loops =>
+        {
+            jumps.push(current)
+        }
+        AstNodeValue::While(_, _, _) | AstNodeValue::Loop(_) => {
+            current.children(|child| deferred_jumps(child, loops + 1, jumps));
+            return;
+        }
+        AstNodeValue::FunctionDeclaration(_) => return,
+        _ => {}
+    }
+    current.children(|child| deferred_jumps(child, loops, jumps));
+}
+
+/**
+ * A returned reference has to point into the function's reference parameter, which the caller
+ * keeps borrowed while they use the result. Anything else could point into a local that's gone
+ * once the function returns.
+ */
+fn validate_reference_returns<'a>(
+    body: &'a AstNode<'a>,
+    parameter: AnyID,
+) -> Result<(), TypecheckError> {
+    let mut results = validate_reference_origin(body, parameter);
+    find_returned_references(body, parameter, &mut results);
+
+    results
+}
+
+fn find_returned_references<'a>(
+    current: &'a AstNode<'a>,
+    parameter: AnyID,
+    results: &mut Result<(), TypecheckError>,
+) {
+    match &current.value {
+        AstNodeValue::Return(Some(returned)) => {
+            merge_results(results, validate_reference_origin(returned, parameter));
+        }
+        AstNodeValue::FunctionDeclaration(_) => return,
+        _ => {}
+    }
+    current.children(|child| find_returned_references(child, parameter, results));
+}
+
+fn validate_reference_origin(node: &AstNode<'_>, parameter: AnyID) -> Result<(), TypecheckError> {
+    match &node.value {
+        AstNodeValue::Block(children) => match children.last() {
+            Some(last) => validate_reference_origin(last, parameter),
+            None => Ok(()),
+        },
+        AstNodeValue::If(IfDeclaration {
+            if_branch,
+            else_branch: Some(else_branch),
+            ..
+        }) => {
+            let mut results = validate_reference_origin(if_branch, parameter);
+            merge_results(
+                &mut results,
+                validate_reference_origin(else_branch, parameter),
+            );
+            results
+        }
+        AstNodeValue::Name { .. }
+        | AstNodeValue::TakeRef(_)
+        | AstNodeValue::TakeUnique(_)
+        | AstNodeValue::Call(_, _)
+            if is_inside_parameter(node, parameter) =>
+        {
+            Ok(())
+        }
+        _ if !node.ty.get().is_some_and(ExpressionType::is_reference) => Ok(()),
+        _ => Err(TypecheckError::ReferenceNotFromParameter(
+            node.provenance.clone(),
+        )),
+    }
+}
+
+/**
+ * Whether the place is the parameter itself, or somewhere within what it points to
+ */
+fn is_inside_parameter(place: &AstNode<'_>, parameter: AnyID) -> bool {
+    match &place.value {
+        AstNodeValue::Name { referenced_id, .. } => referenced_id.get() == Some(&parameter),
+        AstNodeValue::TakeRef(inner)
+        | AstNodeValue::TakeUnique(inner)
+        | AstNodeValue::Deref(inner)
+        | AstNodeValue::BinExpr(BinOp::Dot | BinOp::Index, inner, _) => {
+            is_inside_parameter(inner, parameter)
+        }
+        // Calls can only return references into what they were given
+        AstNodeValue::Call(func, args) => {
+            let receiver = match &func.value {
+                AstNodeValue::BinExpr(BinOp::Dot, lhs, _)
+                    if !matches!(lhs.ty.get(), Some(ExpressionType::ReferenceToType(_))) =>
+                {
+                    Some(&**lhs)
+                }
+                _ => None,
+            };
+            receiver
+                .into_iter()
+                .chain(
+                    args.iter()
+                        .filter(|arg| arg.ty.get().is_some_and(ExpressionType::is_reference)),
+                )
+                .all(|input| is_inside_parameter(input, parameter))
+        }
+        _ => false,
+    }
+}
+
+/**
+ * Breaks and continues that aren't inside of any loop have nowhere to go
+ */
+fn typecheck_orphan_breaks<'a>(node: &'a AstNode<'a>) -> Result<(), TypecheckError> {
+    let mut breaks = Vec::new();
+    loop_breaks(node, 0, &mut breaks);
+
+    let mut result = Ok(());
+    for break_node in breaks {
+        let provenance = break_node.provenance.clone();
+        merge_results(
+            &mut result,
+            Err(match break_node.value {
+                AstNodeValue::Continue(_) => TypecheckError::ContinueOutsideLoop(provenance),
+                _ => TypecheckError::BreakOutsideLoop(provenance),
+            }),
+        );
+    }
+
+    result
+}
+
+/**
+ * Whether every path through the node returns before reaching its end. Anything the typechecker
+ * already found never finishes, like a loop without a break, counts as returning.
+ */
+fn always_returns(node: &AstNode<'_>) -> bool {
+    if node.ty.get() == Some(&ExpressionType::Unreachable) {
+        return true;
+    }
+    match &node.value {
+        AstNodeValue::Return(_) => true,
+        // Anything after a statement that always returns can't be reached
+        AstNodeValue::Block(statements) => statements.iter().any(always_returns),
+        AstNodeValue::Statement(inner) => always_returns(inner),
+        AstNodeValue::If(IfDeclaration {
+            if_branch,
+            else_branch: Some(else_branch),
+            ..
+        }) => always_returns(if_branch) && always_returns(else_branch),
+        AstNodeValue::Match(case) => case.cases.iter().all(|case| always_returns(&case.body)),
+        _ => false,
+    }
+}
+
+/**
+ * An if only has a type when both branches agree on it
+ */
+fn if_result_ty(
+    context: &TypecheckContext,
+    if_branch: &AstNode<'_>,
+    if_ty: &ExpressionType,
+    else_ty: Option<&ExpressionType>,
+) -> Result<ExpressionType, TypecheckError> {
+    match else_ty {
+        Some(else_ty) => common_type(context, &if_branch.provenance, if_ty, else_ty),
+        None => Ok(ExpressionType::Void),
+    }
+}
+
+/**
+ * Find the type that both branches of a conditional can be unified into. If either branch
+ * doesn't produce a value (such as an `else if` chain with no final `else`), the conditional
+ * is only usable as a statement.
+ */
+fn common_type(
+    context: &TypecheckContext,
+    provenance: &SourceRange,
+    left: &ExpressionType,
+    right: &ExpressionType,
+) -> Result<ExpressionType, TypecheckError> {
+    if matches!(left, ExpressionType::Void) || matches!(right, ExpressionType::Void) {
+        Ok(ExpressionType::Void)
+    } else if is_assignable_to(context.declarations, None, left, right) {
+        Ok(left.clone())
+    } else {
+        assert_assignable_to(context.declarations, provenance, right, left)?;
+        Ok(right.clone())
+    }
+}
+
+fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
+    match &lhs.value {
+        // Field access auto-dereferences, so a name may be the base of a write through pointers
+        AstNodeValue::Name { .. } => {
+            validate_assignment_lhs_ty(lhs.ty.get().unwrap(), &lhs.provenance)
+        }
+        AstNodeValue::Deref(inner) => {
+            let Some(ExpressionType::Pointer(kind, _)) = inner.ty.get() else {
+                unreachable!()
+            };
+            let mut errors = if *kind == PointerKind::Shared {
+                Err(TypecheckError::IllegalSharedRefMutation(
+                    lhs.provenance.clone(),
                 ))
             } else {
                 Ok(())
@@ -1964,20 +3323,36 @@ fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
             merge_results(&mut errors, validate_assignment_lhs(inner));
             errors
         }
-        AstNodeValue::BinExpr(BinOp::Dot | BinOp::Index, lhs, _) => validate_assignment_lhs(lhs),
+        AstNodeValue::BinExpr(BinOp::Dot | BinOp::Index, base, _) => {
+            let mut errors = validate_assignment_lhs(base);
+            // A field or element that holds a pointer is auto-dereferenced just like a name is, so
+            // each link in the chain must allow mutation (names are checked above)
+            if !matches!(&base.value, AstNodeValue::Name { .. }) {
+                merge_results(
+                    &mut errors,
+                    validate_assignment_lhs_ty(base.ty.get().unwrap(), &base.provenance),
+                );
+            }
+            errors
+        }
 
         AstNodeValue::BinExpr(_, _, _)
         | AstNodeValue::FunctionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_)
+        | AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::StructDeclaration(_)
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
         | AstNodeValue::RequiredFunction(_)
         | AstNodeValue::Declaration(_, _, _, _)
         | AstNodeValue::ConstDeclaration { .. }
-        | AstNodeValue::Import(_)
+        | AstNodeValue::Import(..)
         | AstNodeValue::Return(_)
+        | AstNodeValue::Break(_, _)
+        | AstNodeValue::Continue(_)
         | AstNodeValue::Yield(_)
+        | AstNodeValue::Defer(_)
         | AstNodeValue::Statement(_)
         | AstNodeValue::Int(_)
         | AstNodeValue::Float(_)
@@ -1986,9 +3361,13 @@ fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
         | AstNodeValue::StringLiteral(_)
         | AstNodeValue::Null
         | AstNodeValue::If(_)
-        | AstNodeValue::While(_, _)
+        | AstNodeValue::IfLet(_)
+        | AstNodeValue::While(_, _, _)
         | AstNodeValue::Match(_)
+        | AstNodeValue::IsVariant(_, _)
+        | AstNodeValue::Downcast(_, _)
         | AstNodeValue::Loop(_)
+        | AstNodeValue::LabeledLoop(_, _)
         | AstNodeValue::Call(_, _)
         | AstNodeValue::TakeUnique(_)
         | AstNodeValue::TakeRef(_)
@@ -1996,6 +3375,7 @@ fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
         | AstNodeValue::DictLiteral(_)
         | AstNodeValue::ArrayLiteral(_)
         | AstNodeValue::ArrayLiteralLength(_, _)
+        | AstNodeValue::ArraySlice(_, _, _)
         | AstNodeValue::Block(_)
         | AstNodeValue::UniqueType(_)
         | AstNodeValue::VoidType
@@ -2006,6 +3386,7 @@ fn validate_assignment_lhs(lhs: &AstNode<'_>) -> Result<(), TypecheckError> {
         | AstNodeValue::UnaryExpr(_, _)
         | AstNodeValue::NullableType(_)
         | AstNodeValue::GeneratorType { .. }
+        | AstNodeValue::FunctionType { .. }
         | AstNodeValue::BorrowDeclaration(..)
         | AstNodeValue::ReferenceCountLiteral(_)
         | AstNodeValue::CellType(_)
@@ -2033,14 +3414,20 @@ fn typecheck_const<'a>(
     };
     let value_ty = typecheck_expression(value, outer_scopes, current_scope, context, None)?;
     let mut result = Ok(());
-    if !validate_is_const(value) {
-        merge_results(
-            &mut result,
-            Err(TypecheckError::NonConstantInConst(value.provenance.clone())),
-        );
+    if let Some(const_value) = const_eval(context, value) {
+        context
+            .constant_values
+            .borrow_mut()
+            .insert(*variable_id, const_value);
+    } else {
+        merge_results(&mut result, validate_is_const(context, value));
     }
     if let Some(type_hint) = type_hint {
-        let hint_ty = resolve_type_expr(&context.top_level_type_names, type_hint)?;
+        let hint_ty = resolve_type_expr(
+            &context.top_level_type_names,
+            &context.declarations.type_aliases,
+            type_hint,
+        )?;
         merge_results(
             &mut result,
             assert_assignable_to(context.declarations, &value.provenance, &hint_ty, value_ty),
@@ -2059,28 +3446,122 @@ fn typecheck_const<'a>(
     Ok(node.ty.get().expect("just set"))
 }
 
-fn validate_is_const(node: &AstNode) -> bool {
+/**
+ * Resolve a literal or a reference to a simple constant to its value. Must be called after the
+ * node has been typechecked, so names are already resolved.
+ */
+fn const_eval(context: &TypecheckContext, node: &AstNode) -> Option<ConstValue> {
     match &node.value {
+        AstNodeValue::Int(x) => Some(ConstValue::Int(*x)),
+        AstNodeValue::Float(x) => Some(ConstValue::Float(*x)),
+        AstNodeValue::Bool(x) => Some(ConstValue::Bool(*x)),
+        AstNodeValue::CharLiteral(x) => Some(ConstValue::Char(*x)),
+        AstNodeValue::StringLiteral(x) => Some(ConstValue::String(x.clone())),
+        AstNodeValue::Name { referenced_id, .. } => match referenced_id.get()? {
+            AnyID::Constant(id) => context.constant_values.borrow().get(id).cloned(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/**
+ * Struct literals that leave out a field get its default in their place, so defaults have to be
+ * known at compile time
+ */
+fn typecheck_field_defaults<'a>(
+    context: &TypecheckContext,
+    outer_scopes: &[&HashMap<String, (AnyID, ExpressionType)>],
+    current_scope: &mut HashMap<String, (AnyID, ExpressionType)>,
+    decl: &'a StructDeclarationValue<'a>,
+) -> Result<(), TypecheckError> {
+    if decl.field_defaults.is_empty() {
+        return Ok(());
+    }
+    let ty_id = context.top_level_type_names[decl.name.as_str()];
+    let Some(TypeDeclaration::Struct(struct_type)) = context.decl(&ty_id) else {
+        unreachable!()
+    };
+
+    let mut result = Ok(());
+    let mut values = HashMap::new();
+    for (name, default) in decl.field_defaults.iter() {
+        let default_ty =
+            match typecheck_expression(default, outer_scopes, current_scope, context, None) {
+                Ok(default_ty) => default_ty,
+                Err(err) => {
+                    merge_results(&mut result, Err(err));
+                    continue;
+                }
+            };
+        merge_results(
+            &mut result,
+            assert_assignable_to(
+                context.declarations,
+                &default.provenance,
+                &struct_type.fields[name],
+                default_ty,
+            ),
+        );
+        match const_eval(context, default) {
+            Some(value) => {
+                values.insert(name.clone(), (value, default_ty.clone()));
+            }
+            None => merge_results(
+                &mut result,
+                Err(TypecheckError::NonConstantInConst(
+                    default.provenance.clone(),
+                )),
+            ),
+        }
+    }
+    result?;
+    context.field_defaults.borrow_mut().insert(ty_id, values);
+
+    Ok(())
+}
+
+fn validate_is_const(context: &TypecheckContext, node: &AstNode) -> Result<(), TypecheckError> {
+    match &node.value {
+        AstNodeValue::Call(func, args) => {
+            let mut result = validate_const_fn_call(context, node, func);
+            for arg in args.iter() {
+                merge_results(&mut result, validate_is_const(context, arg));
+            }
+            result
+        }
+        // Constants that couldn't be resolved to a simple value, like calls to const fns
+        AstNodeValue::Name { referenced_id, .. }
+            if matches!(referenced_id.get(), Some(AnyID::Constant(_))) =>
+        {
+            Ok(())
+        }
         AstNodeValue::FunctionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_)
+        | AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::StructDeclaration(_)
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
         | AstNodeValue::RequiredFunction(_)
         | AstNodeValue::Declaration(_, _, _, _)
         | AstNodeValue::BorrowDeclaration(_, _, _)
         | AstNodeValue::ConstDeclaration { .. }
-        | AstNodeValue::Import(_)
+        | AstNodeValue::Import(..)
         | AstNodeValue::Return(_)
+        | AstNodeValue::Break(_, _)
+        | AstNodeValue::Continue(_)
         | AstNodeValue::Yield(_)
+        | AstNodeValue::Defer(_)
         | AstNodeValue::Null
         | AstNodeValue::Statement(_)
         | AstNodeValue::UnaryExpr(_, _)
         | AstNodeValue::BinExpr(_, _, _)
         | AstNodeValue::If(_)
-        | AstNodeValue::While(_, _)
+        | AstNodeValue::IfLet(_)
+        | AstNodeValue::While(_, _, _)
         | AstNodeValue::Loop(_)
-        | AstNodeValue::Call(_, _)
+        | AstNodeValue::LabeledLoop(_, _)
         | AstNodeValue::TakeUnique(_)
         | AstNodeValue::TakeRef(_)
         | AstNodeValue::ReferenceCountLiteral(_)
@@ -2088,6 +3569,8 @@ fn validate_is_const(node: &AstNode) -> bool {
         | AstNodeValue::Block(_)
         | AstNodeValue::Deref(_)
         | AstNodeValue::Match(_)
+        | AstNodeValue::IsVariant(_, _)
+        | AstNodeValue::Downcast(_, _)
         | AstNodeValue::VoidType
         | AstNodeValue::UniqueType(_)
         | AstNodeValue::SharedType(_)
@@ -2097,7 +3580,10 @@ fn validate_is_const(node: &AstNode) -> bool {
         | AstNodeValue::NullableType(_)
         | AstNodeValue::CellType(_)
         | AstNodeValue::GeneratorType { .. }
-        | AstNodeValue::Name { .. } => false,
+        | AstNodeValue::FunctionType { .. }
+        | AstNodeValue::Name { .. } => {
+            Err(TypecheckError::NonConstantInConst(node.provenance.clone()))
+        }
         AstNodeValue::Int(_)
         | AstNodeValue::Float(_)
         | AstNodeValue::Bool(_)
@@ -2106,10 +3592,120 @@ fn validate_is_const(node: &AstNode) -> bool {
         | AstNodeValue::RecordLiteral { .. }
         | AstNodeValue::DictLiteral(_)
         | AstNodeValue::ArrayLiteral(_)
-        | AstNodeValue::ArrayLiteralLength(_, _) => true,
+        | AstNodeValue::ArrayLiteralLength(_, _)
+        | AstNodeValue::ArraySlice(_, _, _) => Ok(()),
+    }
+}
+
+/**
+ * Only const fns can be run at compile time
+ */
+fn validate_const_fn_call(
+    context: &TypecheckContext,
+    call: &AstNode,
+    func: &AstNode,
+) -> Result<(), TypecheckError> {
+    let AstNodeValue::Name { referenced_id, .. } = &func.value else {
+        return Err(TypecheckError::NonConstantInConst(call.provenance.clone()));
+    };
+    match referenced_id.get() {
+        Some(AnyID::Function(id)) if context.declarations.id_to_func[id].is_const => Ok(()),
+        Some(AnyID::Function(_)) => Err(TypecheckError::NonConstFunctionInConst(
+            call.provenance.clone(),
+        )),
+        _ => Err(TypecheckError::NonConstantInConst(call.provenance.clone())),
     }
 }
 
+/**
+ * A const fn may compute whatever it likes with its own locals, but it can't reach state outside
+ * of itself or call functions that might.
+ */
+fn validate_const_fn_body<'a>(
+    context: &TypecheckContext,
+    node: &'a AstNode<'a>,
+) -> Result<(), TypecheckError> {
+    let mut result = match &node.value {
+        AstNodeValue::Call(func, args) => {
+            // Builtins like assert aren't declared anywhere, and are fine to run at compile time
+            let is_builtin = matches!(
+                &func.value,
+                AstNodeValue::Name { referenced_id, .. } if referenced_id.get().is_none()
+            );
+            let mut result = if is_builtin {
+                Ok(())
+            } else {
+                validate_const_fn_call(context, node, func)
+            };
+            for arg in args.iter() {
+                merge_results(&mut result, validate_const_fn_body(context, arg));
+            }
+            return result;
+        }
+        AstNodeValue::TakeUnique(_)
+        | AstNodeValue::TakeRef(_)
+        | AstNodeValue::Deref(_)
+        | AstNodeValue::BorrowDeclaration(_, _, _)
+        | AstNodeValue::ReferenceCountLiteral(_)
+        | AstNodeValue::CellLiteral(_)
+        | AstNodeValue::Yield(_) => {
+            return Err(TypecheckError::NonConstantInConst(node.provenance.clone()));
+        }
+        AstNodeValue::FunctionDeclaration(_)
+        | AstNodeValue::ExternFunctionBinding { .. }
+        | AstNodeValue::StructDeclaration(_)
+        | AstNodeValue::UnionDeclaration(_)
+        | AstNodeValue::InterfaceDeclaration(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
+        | AstNodeValue::RequiredFunction(_)
+        | AstNodeValue::Declaration(_, _, _, _)
+        | AstNodeValue::ConstDeclaration { .. }
+        | AstNodeValue::Import(..)
+        | AstNodeValue::Return(_)
+        | AstNodeValue::Break(_, _)
+        | AstNodeValue::Continue(_)
+        | AstNodeValue::Defer(_)
+        | AstNodeValue::Null
+        | AstNodeValue::Statement(_)
+        | AstNodeValue::UnaryExpr(_, _)
+        | AstNodeValue::BinExpr(_, _, _)
+        | AstNodeValue::If(_)
+        | AstNodeValue::IfLet(_)
+        | AstNodeValue::While(_, _, _)
+        | AstNodeValue::Loop(_)
+        | AstNodeValue::LabeledLoop(_, _)
+        | AstNodeValue::Block(_)
+        | AstNodeValue::Match(_)
+        | AstNodeValue::IsVariant(_, _)
+        | AstNodeValue::Downcast(_, _)
+        | AstNodeValue::VoidType
+        | AstNodeValue::UniqueType(_)
+        | AstNodeValue::SharedType(_)
+        | AstNodeValue::ArrayType(_)
+        | AstNodeValue::DictType(_, _)
+        | AstNodeValue::RcType(_)
+        | AstNodeValue::NullableType(_)
+        | AstNodeValue::CellType(_)
+        | AstNodeValue::GeneratorType { .. }
+        | AstNodeValue::FunctionType { .. }
+        | AstNodeValue::Name { .. }
+        | AstNodeValue::Int(_)
+        | AstNodeValue::Float(_)
+        | AstNodeValue::Bool(_)
+        | AstNodeValue::CharLiteral(_)
+        | AstNodeValue::StringLiteral(_)
+        | AstNodeValue::RecordLiteral { .. }
+        | AstNodeValue::DictLiteral(_)
+        | AstNodeValue::ArrayLiteral(_)
+        | AstNodeValue::ArrayLiteralLength(_, _)
+        | AstNodeValue::ArraySlice(_, _, _) => Ok(()),
+    };
+    node.children(|child| merge_results(&mut result, validate_const_fn_body(context, child)));
+
+    result
+}
+
 fn ensure_no_assignment_to_reference(
     lhs: &ExpressionType,
     provenance: &SourceRange,
@@ -2125,13 +3721,13 @@ fn ensure_no_assignment_to_reference(
         ExpressionType::Collection(_)
         | ExpressionType::InstanceOf(_)
         | ExpressionType::Primitive(_)
-        | ExpressionType::Generator { .. } => Ok(()),
+        | ExpressionType::Generator { .. }
+        | ExpressionType::FunctionReference { .. } => Ok(()),
         ExpressionType::Nullable(inner) => ensure_no_assignment_to_reference(inner, provenance),
         ExpressionType::Pointer(_, _) => {
             Err(TypecheckError::CantAssignToReference(provenance.clone()))
         }
         ExpressionType::TypeParameterReference(_) => todo!(),
-        ExpressionType::FunctionReference { .. } => todo!(),
     }
 }
 
@@ -2151,19 +3747,23 @@ fn validate_assignment_lhs_ty(
         | ExpressionType::InstanceOf(_)
         | ExpressionType::Primitive(_)
         | ExpressionType::Generator { .. }
-        | ExpressionType::Pointer(PointerKind::Unique, _) => Ok(()),
+        | ExpressionType::FunctionReference { .. } => Ok(()),
+        // Every layer that gets auto-dereferenced must allow mutation
+        ExpressionType::Pointer(PointerKind::Unique, inner) => match inner.as_ref() {
+            ExpressionType::Pointer(_, _) => validate_assignment_lhs_ty(inner, provenance),
+            _ => Ok(()),
+        },
         ExpressionType::Nullable(inner) => validate_assignment_lhs_ty(inner, provenance),
         ExpressionType::Pointer(PointerKind::Shared, _) => {
             Err(TypecheckError::IllegalSharedRefMutation(provenance.clone()))
         }
         ExpressionType::TypeParameterReference(_) => todo!(),
-        ExpressionType::FunctionReference { .. } => todo!(),
     }
 }
 
 fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
     match &lvalue.value {
-        // TODO: remove this if I add auto-deref later
+        // Field access auto-dereferences, so a name may be the base of a write through pointers
         AstNodeValue::Name { .. } => true,
         AstNodeValue::Deref(inner) => {
             let Some(ExpressionType::Pointer(kind, _)) = inner.ty.get() else {
@@ -2175,16 +3775,21 @@ fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
 
         AstNodeValue::BinExpr(_, _, _)
         | AstNodeValue::FunctionDeclaration(_)
-        | AstNodeValue::ExternFunctionBinding(_)
+        | AstNodeValue::ExternFunctionBinding { .. }
         | AstNodeValue::StructDeclaration(_)
         | AstNodeValue::UnionDeclaration(_)
         | AstNodeValue::InterfaceDeclaration(_)
+        | AstNodeValue::TypeAliasDeclaration { .. }
+        | AstNodeValue::ImplBlock(_)
         | AstNodeValue::RequiredFunction(_)
         | AstNodeValue::Declaration(_, _, _, _)
         | AstNodeValue::ConstDeclaration { .. }
-        | AstNodeValue::Import(_)
+        | AstNodeValue::Import(..)
         | AstNodeValue::Return(_)
+        | AstNodeValue::Break(_, _)
+        | AstNodeValue::Continue(_)
         | AstNodeValue::Yield(_)
+        | AstNodeValue::Defer(_)
         | AstNodeValue::Statement(_)
         | AstNodeValue::Int(_)
         | AstNodeValue::Float(_)
@@ -2193,9 +3798,13 @@ fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
         | AstNodeValue::StringLiteral(_)
         | AstNodeValue::Null
         | AstNodeValue::If(_)
-        | AstNodeValue::While(_, _)
+        | AstNodeValue::IfLet(_)
+        | AstNodeValue::While(_, _, _)
         | AstNodeValue::Match(_)
+        | AstNodeValue::IsVariant(_, _)
+        | AstNodeValue::Downcast(_, _)
         | AstNodeValue::Loop(_)
+        | AstNodeValue::LabeledLoop(_, _)
         | AstNodeValue::Call(_, _)
         | AstNodeValue::TakeUnique(_)
         | AstNodeValue::TakeRef(_)
@@ -2203,6 +3812,7 @@ fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
         | AstNodeValue::DictLiteral(_)
         | AstNodeValue::ArrayLiteral(_)
         | AstNodeValue::ArrayLiteralLength(_, _)
+        | AstNodeValue::ArraySlice(_, _, _)
         | AstNodeValue::Block(_)
         | AstNodeValue::UniqueType(_)
         | AstNodeValue::VoidType
@@ -2213,6 +3823,7 @@ fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
         | AstNodeValue::UnaryExpr(_, _)
         | AstNodeValue::NullableType(_)
         | AstNodeValue::GeneratorType { .. }
+        | AstNodeValue::FunctionType { .. }
         | AstNodeValue::BorrowDeclaration(..)
         | AstNodeValue::ReferenceCountLiteral(_)
         | AstNodeValue::CellType(_)
@@ -2220,6 +3831,158 @@ fn validate_lvalue(lvalue: &AstNode<'_>) -> bool {
     }
 }
 
+/**
+ * Builtins are called like functions but handled by the compiler, unless the program has declared
+ * something with the same name:
+ * - `typename(x)` is resolved at compile time to the name of the argument's type
+ * - `assert(cond)` and `assert(cond, message)` abort if the condition is false
+ */
+fn is_builtin(
+    func: &AstNode<'_>,
+    builtin: &str,
+    current_scope: &HashMap<String, (AnyID, ExpressionType)>,
+    outer_scopes: &[&HashMap<String, (AnyID, ExpressionType)>],
+) -> bool {
+    matches!(&func.value, AstNodeValue::Name { value, .. }
+        if value == builtin && resolve_name(value, current_scope, outer_scopes).is_none())
+}
+
+/**
+ * The name of a type as `typename` reports it. Compound types are spelled like source syntax,
+ * but primitives use their long names (`int32` rather than `i32`), a named function is just
+ * `fn`, and type parameters are `T` followed by their index.
+ */
+pub fn type_name(declarations: &DeclarationContext, ty: &ExpressionType) -> String {
+    match ty {
+        ExpressionType::Void => "void".to_string(),
+        ExpressionType::Unreachable => "unreachable".to_string(),
+        ExpressionType::Primitive(prim) => match prim {
+            PrimitiveType::Char => "char",
+            PrimitiveType::Int32 => "int32",
+            PrimitiveType::Float32 => "float32",
+            PrimitiveType::Int64 => "int64",
+            PrimitiveType::Float64 => "float64",
+            PrimitiveType::Bool => "bool",
+            PrimitiveType::PointerSize => "size",
+        }
+        .to_string(),
+        ExpressionType::InstanceOf(id) | ExpressionType::ReferenceToType(id) => declarations
+            .type_name(id)
+            .unwrap_or("<anonymous>")
+            .to_string(),
+        ExpressionType::ReferenceToFunction(_) => "fn".to_string(),
+        ExpressionType::Pointer(PointerKind::Shared, inner) => {
+            format!("ref {}", type_name(declarations, inner))
+        }
+        ExpressionType::Pointer(PointerKind::Unique, inner) => {
+            format!("unique {}", type_name(declarations, inner))
+        }
+        ExpressionType::Collection(CollectionType::Array(inner)) => {
+            format!("list[{}]", type_name(declarations, inner))
+        }
+        ExpressionType::Collection(CollectionType::Dict(key, value)) => format!(
+            "dict[{}, {}]",
+            type_name(declarations, key),
+            type_name(declarations, value)
+        ),
+        ExpressionType::Collection(CollectionType::ReferenceCounter(inner)) => {
+            format!("rc[{}]", type_name(declarations, inner))
+        }
+        ExpressionType::Collection(CollectionType::Cell(inner)) => {
+            format!("cell[{}]", type_name(declarations, inner))
+        }
+        ExpressionType::Collection(CollectionType::String) => "string".to_string(),
+        ExpressionType::Null => "null".to_string(),
+        ExpressionType::Nullable(inner) => format!("{}?", type_name(declarations, inner)),
+        ExpressionType::TypeParameterReference(idx) => format!("T{idx}"),
+        ExpressionType::Generator { yield_ty, param_ty } => format!(
+            "generator[{}, {}]",
+            type_name(declarations, yield_ty),
+            type_name(declarations, param_ty)
+        ),
+        ExpressionType::FunctionReference {
+            parameters,
+            returns,
+        } => {
+            let parameters: Vec<_> = parameters
+                .iter()
+                .map(|param| type_name(declarations, param))
+                .collect();
+            format!(
+                "fn({}): {}",
+                parameters.join(", "),
+                type_name(declarations, returns)
+            )
+        }
+    }
+}
+
+/**
+ * The name of a type as it's shown in error messages, which is terser than the source syntax
+ */
+pub fn display_type(ty: &ExpressionType, declarations: &DeclarationContext) -> String {
+    match ty {
+        ExpressionType::ReferenceToFunction(id) => match declarations.id_to_func.get(id) {
+            Some(func) => display_function_type(&func.params, &func.returns, declarations),
+            None => "fn".to_string(),
+        },
+        ExpressionType::Pointer(PointerKind::Shared, inner) => {
+            format!("shared {}", display_type(inner, declarations))
+        }
+        ExpressionType::Pointer(PointerKind::Unique, inner) => {
+            format!("unique {}", display_type(inner, declarations))
+        }
+        ExpressionType::Collection(CollectionType::Array(inner)) => {
+            format!("[{}]", display_type(inner, declarations))
+        }
+        ExpressionType::Collection(CollectionType::Dict(key, value)) => format!(
+            "{{{}: {}}}",
+            display_type(key, declarations),
+            display_type(value, declarations)
+        ),
+        ExpressionType::Collection(CollectionType::ReferenceCounter(inner)) => {
+            format!("rc[{}]", display_type(inner, declarations))
+        }
+        ExpressionType::Collection(CollectionType::Cell(inner)) => {
+            format!("cell[{}]", display_type(inner, declarations))
+        }
+        ExpressionType::Nullable(inner) => format!("{}?", display_type(inner, declarations)),
+        ExpressionType::Generator { yield_ty, param_ty } => format!(
+            "generator[{}, {}]",
+            display_type(yield_ty, declarations),
+            display_type(param_ty, declarations)
+        ),
+        ExpressionType::FunctionReference {
+            parameters,
+            returns,
+        } => display_function_type(parameters, returns, declarations),
+        ExpressionType::Void
+        | ExpressionType::Unreachable
+        | ExpressionType::Primitive(_)
+        | ExpressionType::InstanceOf(_)
+        | ExpressionType::ReferenceToType(_)
+        | ExpressionType::Collection(CollectionType::String)
+        | ExpressionType::Null
+        | ExpressionType::TypeParameterReference(_) => type_name(declarations, ty),
+    }
+}
+
+fn display_function_type(
+    parameters: &[ExpressionType],
+    returns: &ExpressionType,
+    declarations: &DeclarationContext,
+) -> String {
+    let parameters: Vec<_> = parameters
+        .iter()
+        .map(|param| display_type(param, declarations))
+        .collect();
+    format!(
+        "fn({}): {}",
+        parameters.join(", "),
+        display_type(returns, declarations)
+    )
+}
+
 fn resolve_name(
     name: &str,
     current_scope: &HashMap<String, (AnyID, ExpressionType)>,
@@ -2298,12 +4061,25 @@ fn find_generic_bindings(
             find_generic_bindings(generic_args, left_yield_ty, right_yield_ty);
             find_generic_bindings(generic_args, left_param_ty, right_param_ty);
         }
-        (ExpressionType::FunctionReference { .. }, _)
-        | (_, ExpressionType::FunctionReference { .. }) => {
-            todo!("function references only exist within the compiler")
+        (
+            ExpressionType::FunctionReference {
+                parameters: left_params,
+                returns: left_returns,
+            },
+            ExpressionType::FunctionReference {
+                parameters: right_params,
+                returns: right_returns,
+            },
+        ) => {
+            for (left_param, right_param) in left_params.iter().zip(right_params.iter()) {
+                find_generic_bindings(generic_args, left_param, right_param);
+            }
+            find_generic_bindings(generic_args, left_returns, right_returns);
         }
-        (ExpressionType::ReferenceToFunction(_), _)
-        | (_, ExpressionType::ReferenceToFunction(_)) => {
+        (ExpressionType::FunctionReference { .. }, _)
+        | (_, ExpressionType::FunctionReference { .. })
+        | (_, ExpressionType::ReferenceToFunction(_)) => {}
+        (ExpressionType::ReferenceToFunction(_), _) => {
             todo!("first class functions")
         }
         (ExpressionType::Pointer(_, inner), rhs) => {
@@ -2322,6 +4098,20 @@ fn find_generic_bindings(
     }
 }
 
+/**
+ * Whether a struct's associated function can stand in for an interface's
+ */
+fn implements_function(required: &FuncType, provided: &FuncType) -> bool {
+    // Ignore the first argument to both associated functions -
+    // the type will differ because it's a self param
+    let (Some((_, required_params)), Some((_, provided_params))) =
+        (required.params.split_first(), provided.params.split_first())
+    else {
+        return false;
+    };
+    required_params == provided_params && required.returns == provided.returns
+}
+
 pub fn is_assignable_to(
     context: &DeclarationContext,
     generic_args: Option<&[ExpressionType]>,
@@ -2400,15 +4190,7 @@ pub fn is_assignable_to(
                     let Some(rhs_ty) = rhs_assoc.get(name) else {
                         return false;
                     };
-                    let lhs = &context.id_to_func[lhs_ty];
-                    let rhs = &context.id_to_func[rhs_ty];
-                    // Ignore the first argument to both associated functions -
-                    // the type will differ because it's a self param
-                    lhs.params[1..]
-                        .iter()
-                        .zip(rhs.params[1..].iter())
-                        .all(|(lhs, rhs)| lhs == rhs)
-                        && lhs.returns == rhs.returns
+                    implements_function(&context.id_to_func[lhs_ty], &context.id_to_func[rhs_ty])
                 }),
                 (Interface(_), Interface(_)) => left == right,
                 (Interface(_), Union(_)) => todo!(),
@@ -2450,14 +4232,43 @@ pub fn is_assignable_to(
         (Collection(CollectionType::String), Collection(CollectionType::String)) => true,
         (Collection(_), Collection(_)) => false,
 
-        (ExpressionType::FunctionReference { .. }, _)
-        | (_, ExpressionType::FunctionReference { .. }) => {
-            todo!("function references only exist within the compiler")
+        // Signatures are compared exactly, so fill in any generics they mention first
+        (FunctionReference { .. }, FunctionReference { .. } | ReferenceToFunction(_))
+            if generic_args.is_some() =>
+        {
+            let mut left = left.clone();
+            left.resolve_generics(generic_args.unwrap());
+            is_assignable_to(context, None, &left, right)
         }
-        (ExpressionType::ReferenceToFunction(_), _)
-        | (_, ExpressionType::ReferenceToFunction(_)) => {
-            todo!("first class functions")
+        (
+            FunctionReference {
+                parameters: left_params,
+                returns: left_returns,
+            },
+            FunctionReference {
+                parameters: right_params,
+                returns: right_returns,
+            },
+        ) => left_params == right_params && left_returns == right_returns,
+        // Methods, generics and coroutines don't have a single plain signature to point to
+        (
+            FunctionReference {
+                parameters,
+                returns,
+            },
+            ReferenceToFunction(id),
+        ) => {
+            let func = &context.id_to_func[id];
+            !func.is_associated
+                && !func.is_coroutine
+                && func.type_param_count == 0
+                && func.params == *parameters
+                && func.returns == **returns
         }
+        (FunctionReference { .. }, _)
+        | (_, FunctionReference { .. })
+        | (_, ReferenceToFunction(_)) => false,
+        (ReferenceToFunction(_), _) => todo!("first class functions"),
 
         (ReferenceToType(_), _) | (_, ReferenceToType(_)) => todo!("{:?} = {:?}", left, right),
     }
@@ -2488,10 +4299,241 @@ fn assert_assignable_to(
     if is_assignable_to(declarations, None, lhs, rhs) {
         Ok(())
     } else {
-        Err(TypecheckError::TypeMismatch {
-            provenance: provenance.clone(),
-            received: lhs.clone(),
-            expected: rhs.clone(),
-        })
+        Err(type_mismatch(declarations, provenance.clone(), lhs, rhs))
+    }
+}
+
+fn type_mismatch(
+    declarations: &DeclarationContext,
+    provenance: SourceRange,
+    expected: &ExpressionType,
+    received: &ExpressionType,
+) -> TypecheckError {
+    TypecheckError::TypeMismatch {
+        provenance,
+        expected: display_type(expected, declarations),
+        received: display_type(received, declarations),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::SourceMarker;
+
+    fn range() -> SourceRange {
+        let marker = SourceMarker::new("test", "", 1, 1);
+        SourceRange::new(marker, marker)
+    }
+
+    #[test]
+    fn generics_bind_through_function_references() {
+        let param = ExpressionType::FunctionReference {
+            parameters: vec![ExpressionType::TypeParameterReference(0)],
+            returns: Box::new(ExpressionType::TypeParameterReference(1)),
+        };
+        let argument = ExpressionType::FunctionReference {
+            parameters: vec![ExpressionType::Primitive(PrimitiveType::Int32)],
+            returns: Box::new(ExpressionType::Primitive(PrimitiveType::Float32)),
+        };
+        let mut generic_args = vec![ExpressionType::Unreachable; 2];
+
+        find_generic_bindings(&mut generic_args[..], &param, &argument);
+
+        assert_eq!(
+            generic_args,
+            vec![
+                ExpressionType::Primitive(PrimitiveType::Int32),
+                ExpressionType::Primitive(PrimitiveType::Float32),
+            ]
+        );
+    }
+
+    #[test]
+    fn generic_call_binds_through_variant_constructor() {
+        let arena = typed_arena::Arena::new();
+        let source = r#"
+union Number {
+    int(i32),
+    float(f32),
+}
+extern fn apply(make: fn(i32): Number, value: i32): Number;
+apply(Number.int, 1)
+"#;
+        let files = [(
+            "main",
+            crate::parse_file(&arena, "main.brick", source.to_string()).unwrap(),
+        )];
+        let mut declarations = DeclarationContext::new(&files).unwrap();
+        // Only intrinsics can be generic, and none of them take a function, so stand one in
+        let apply = declarations
+            .id_to_func
+            .values_mut()
+            .find(|func| func.provenance.is_some())
+            .unwrap();
+        apply.type_param_count = 2;
+        apply.params = vec![
+            ExpressionType::FunctionReference {
+                parameters: vec![ExpressionType::TypeParameterReference(0)],
+                returns: Box::new(ExpressionType::TypeParameterReference(1)),
+            },
+            ExpressionType::TypeParameterReference(0),
+        ];
+        apply.returns = ExpressionType::TypeParameterReference(1);
+
+        let file = typecheck(&files[0].1, "main", &declarations).unwrap();
+
+        let call = file.top_level_statements.last().unwrap();
+        let Some(ExpressionType::InstanceOf(id)) = call.ty.get() else {
+            panic!("expected a Number, found {:?}", call.ty.get());
+        };
+        assert_eq!(declarations.type_name(id), Some("Number"));
+    }
+
+    #[test]
+    fn error_codes_are_stable_and_unique() {
+        let errors = [
+            (
+                TypecheckError::MultiError(vec![TypecheckError::NameNotFound(range())]),
+                "E0100",
+            ),
+            (TypecheckError::ArithmeticMismatch(range()), "E0101"),
+            (
+                TypecheckError::TypeMismatch {
+                    provenance: range(),
+                    expected: "void".to_string(),
+                    received: "null".to_string(),
+                },
+                "E0102",
+            ),
+            (TypecheckError::NameNotFound(range()), "E0103"),
+            (TypecheckError::CantCall(range()), "E0104"),
+            (TypecheckError::WrongArgsCount(range()), "E0105"),
+            (TypecheckError::MissingField(range()), "E0106"),
+            (TypecheckError::NoNullDeclarations(range()), "E0107"),
+            (TypecheckError::ExpectedNullableLHS(range()), "E0108"),
+            (TypecheckError::ExpectedNullableUnwrap(range()), "E0109"),
+            (TypecheckError::CannotYield(range()), "E0110"),
+            (TypecheckError::BreakOutsideLoop(range()), "E0111"),
+            (TypecheckError::ContinueOutsideLoop(range()), "E0112"),
+            (TypecheckError::IllegalAssignmentLHS(range()), "E0113"),
+            (TypecheckError::IllegalDotLHS(range()), "E0114"),
+            (TypecheckError::MustReturnGenerator(range()), "E0115"),
+            (TypecheckError::CaseStatementRequiresUnion(range()), "E0116"),
+            (TypecheckError::IsRequiresUnion(range()), "E0117"),
+            (TypecheckError::IfLetRequiresUnion(range()), "E0118"),
+            (TypecheckError::IfLetRequiresNullable(range()), "E0119"),
+            (
+                TypecheckError::VariantNotPresent("a".to_string(), range()),
+                "E0120",
+            ),
+            (TypecheckError::IllegalDotRHS(range()), "E0121"),
+            (TypecheckError::BindingCountDoesntMatch(range()), "E0122"),
+            (TypecheckError::BindingNameDoesntMatch(range()), "E0123"),
+            (TypecheckError::DereferenceNonPointer(range()), "E0124"),
+            (TypecheckError::NonExhaustiveCase(range()), "E0125"),
+            (TypecheckError::IllegalFirstClassReference(range()), "E0126"),
+            (TypecheckError::IllegalNonRefBorrow(range()), "E0127"),
+            (TypecheckError::IllegalNonLvalueBorrow(range()), "E0128"),
+            (
+                TypecheckError::IllegalReferenceInsideDataType(range()),
+                "E0129",
+            ),
+            (
+                TypecheckError::UnknownProperty("a".to_string(), range()),
+                "E0130",
+            ),
+            (
+                TypecheckError::FieldNotPresent("a".to_string(), range()),
+                "E0131",
+            ),
+            (TypecheckError::NonStructDeclStructLiteral(range()), "E0132"),
+            (TypecheckError::CantAssignToReference(range()), "E0133"),
+            (TypecheckError::IllegalSharedRefMutation(range()), "E0134"),
+            (TypecheckError::IllegalImport(range()), "E0135"),
+            (TypecheckError::ImportPathMustBeModule(range()), "E0136"),
+            (
+                TypecheckError::FileNotFound(range(), "a".to_string()),
+                "E0137",
+            ),
+            (TypecheckError::NonConstantInConst(range()), "E0138"),
+            (TypecheckError::NonConstFunctionInConst(range()), "E0139"),
+            (TypecheckError::NonPrimitiveConstFunction(range()), "E0140"),
+            (
+                TypecheckError::InfinitelySizedType(range(), Vec::new()),
+                "E0141",
+            ),
+            (TypecheckError::IllegalSlice(range()), "E0142"),
+            (
+                TypecheckError::DuplicateMatchVariant("a".to_string(), range()),
+                "E0143",
+            ),
+            (
+                TypecheckError::UseBeforeAssignment("a".to_string(), range()),
+                "E0144",
+            ),
+            (
+                TypecheckError::ImportNotFound("a".to_string(), range()),
+                "E0145",
+            ),
+            (TypecheckError::JumpOutOfDefer(range()), "E0146"),
+            (
+                TypecheckError::DuplicateDeclaration("a".to_string(), range(), range()),
+                "E0147",
+            ),
+            (
+                TypecheckError::NotExported("a".to_string(), range()),
+                "E0148",
+            ),
+            (
+                TypecheckError::UnknownLoopLabel("a".to_string(), range()),
+                "E0149",
+            ),
+            (
+                TypecheckError::MissingReturn("a".to_string(), range()),
+                "E0150",
+            ),
+            (TypecheckError::TypeAliasCycle(range(), Vec::new()), "E0151"),
+            (
+                TypecheckError::ImplForUnknownStruct("a".to_string(), range()),
+                "E0152",
+            ),
+            (
+                TypecheckError::MissingInterfaceMethod("a".to_string(), "b".to_string(), range()),
+                "E0153",
+            ),
+            (
+                TypecheckError::NotAnInterface("a".to_string(), range()),
+                "E0154",
+            ),
+            (
+                TypecheckError::CapturedLocal("a".to_string(), range()),
+                "E0155",
+            ),
+            (TypecheckError::DowncastRequiresInterface(range()), "E0156"),
+            (
+                TypecheckError::InvalidDowncastTarget("a".to_string(), "b".to_string(), range()),
+                "E0157",
+            ),
+            (TypecheckError::IllegalArrayContains(range()), "E0158"),
+            (TypecheckError::ReferenceNotFromParameter(range()), "E0159"),
+            (
+                TypecheckError::IllegalLocalDeclaration("a".to_string(), range()),
+                "E0160",
+            ),
+            (
+                TypecheckError::FunctionInsideAssociatedFunction(range()),
+                "E0161",
+            ),
+            (TypecheckError::NegativeArrayLength(-1, range()), "E0162"),
+        ];
+        let mut seen = HashSet::new();
+        for (error, code) in errors.iter() {
+            assert_eq!(error.code(), *code, "{error:?}");
+            assert!(seen.insert(error.code()), "{} is used twice", error.code());
+            assert_eq!(error.primary_span(), Some(range()), "{error:?}");
+        }
     }
 }
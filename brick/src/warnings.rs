@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::{
+    id::{AnyID, FunctionID, VariableID},
+    parser::{AstNode, AstNodeValue, BinOp},
+    typecheck::TypecheckedFile,
+    ExpressionType, PrimitiveType, SourceRange,
+};
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CompileWarning {
+    #[error("parameter {0} is never used: {1}")]
+    UnusedParameter(String, SourceRange),
+    #[error("function {0} is never called: {1}")]
+    UnusedFunction(String, SourceRange),
+    #[error("value at the end of a loop body is discarded: {0}")]
+    DiscardedLoopValue(SourceRange),
+    #[error("floats are compared exactly, so rounding can make equal-looking values differ; compare their difference to a small epsilon instead, or mark the line with `// allow(float_equality)`: {0}")]
+    FloatEquality(SourceRange),
+}
+
+impl CompileWarning {
+    pub fn provenance(&self) -> &SourceRange {
+        match self {
+            CompileWarning::UnusedParameter(_, provenance)
+            | CompileWarning::UnusedFunction(_, provenance)
+            | CompileWarning::DiscardedLoopValue(provenance)
+            | CompileWarning::FloatEquality(provenance) => provenance,
+        }
+    }
+}
+
+/**
+ * The private functions a module defines and every function it refers to. Whether a function is
+ * dead can only be decided once every module has been checked.
+ */
+pub struct FunctionUsage {
+    defined: Vec<(FunctionID, String, SourceRange)>,
+    referenced: HashSet<FunctionID>,
+}
+
+pub fn find_unused_parameters(file: &TypecheckedFile<'_, '_>) -> Vec<CompileWarning> {
+    let mut warnings = Vec::new();
+    for function in file.functions.iter() {
+        let mut referenced = HashSet::new();
+        find_referenced_variables(function.func.body, &mut referenced);
+        for (var_id, param) in function.func.params.iter() {
+            if param.name == "self" || param.name.starts_with('_') {
+                continue;
+            }
+            if !referenced.contains(var_id) {
+                warnings.push(CompileWarning::UnusedParameter(
+                    param.name.clone(),
+                    param.provenance.clone(),
+                ));
+            }
+        }
+    }
+
+    warnings
+}
+
+fn find_referenced_variables<'a>(node: &'a AstNode<'a>, referenced: &mut HashSet<VariableID>) {
+    walk_function_body(node, &mut |node| {
+        if let AstNodeValue::Name { referenced_id, .. } = &node.value {
+            if let Some(AnyID::Variable(var_id)) = referenced_id.get() {
+                referenced.insert(*var_id);
+            }
+        }
+    });
+}
+
+/**
+ * Loops evaluate to void (or to the value they break with), so a value left at the end of their
+ * body is thrown away every iteration
+ */
+pub fn find_discarded_loop_values(file: &TypecheckedFile<'_, '_>) -> Vec<CompileWarning> {
+    let mut warnings = Vec::new();
+    for statement in file.top_level_statements.iter() {
+        find_discarded_loop_values_in(statement, &mut warnings);
+    }
+    for function in file.functions.iter() {
+        find_discarded_loop_values_in(function.func.body, &mut warnings);
+    }
+
+    warnings
+}
+
+fn find_discarded_loop_values_in<'a>(node: &'a AstNode<'a>, warnings: &mut Vec<CompileWarning>) {
+    walk_function_body(node, &mut |node| {
+        let (AstNodeValue::While(_, _, body) | AstNodeValue::Loop(body)) = &node.value else {
+            return;
+        };
+        let discards_value = !matches!(
+            body.ty.get(),
+            None | Some(ExpressionType::Void | ExpressionType::Unreachable)
+        );
+        if discards_value {
+            let tail = match &body.value {
+                AstNodeValue::Block(statements) => statements.last().unwrap_or(body),
+                _ => body,
+            };
+            warnings.push(CompileWarning::DiscardedLoopValue(tail.provenance.clone()));
+        }
+    });
+}
+
+/**
+ * Exact comparison of floats is usually a mistake, but not always, so the warning is skipped for
+ * comparisons with an allow comment on their line or the line before
+ */
+pub fn find_float_equality(file: &TypecheckedFile<'_, '_>) -> Vec<CompileWarning> {
+    let mut warnings = Vec::new();
+    for statement in file.top_level_statements.iter() {
+        find_float_equality_in(statement, &mut warnings);
+    }
+    for function in file.functions.iter() {
+        find_float_equality_in(function.func.body, &mut warnings);
+    }
+
+    warnings
+}
+
+const ALLOW_FLOAT_EQUALITY: &str = "allow(float_equality)";
+
+fn find_float_equality_in<'a>(node: &'a AstNode<'a>, warnings: &mut Vec<CompileWarning>) {
+    walk_function_body(node, &mut |node| {
+        let AstNodeValue::BinExpr(BinOp::EqualTo | BinOp::NotEquals, left, right) = &node.value
+        else {
+            return;
+        };
+        let is_float = |operand: &AstNode<'_>| {
+            matches!(
+                operand.ty.get(),
+                Some(ExpressionType::Primitive(
+                    PrimitiveType::Float32 | PrimitiveType::Float64
+                ))
+            )
+        };
+        if !is_float(left) || !is_float(right) {
+            return;
+        }
+        let is_allowed = node
+            .provenance
+            .line_texts()
+            .chain(node.provenance.preceding_line_text())
+            .filter_map(line_comment)
+            .any(|comment| comment.contains(ALLOW_FLOAT_EQUALITY));
+        if !is_allowed {
+            warnings.push(CompileWarning::FloatEquality(node.provenance.clone()));
+        }
+    });
+}
+
+/**
+ * The text after a `//` on a single line, skipping over string and character literals so their
+ * contents aren't mistaken for comments
+ */
+fn line_comment(line: &str) -> Option<&str> {
+    let mut rest = line;
+    loop {
+        let start = rest.find(['/', '"', '\''])?;
+        let literal = &rest[start..];
+        rest = if let Some(comment) = literal.strip_prefix("//") {
+            return Some(comment);
+        } else if let Some(raw) = literal.strip_prefix("\"\"\"") {
+            &raw[raw.find("\"\"\"")? + 3..]
+        } else if let Some(string) = literal.strip_prefix('"') {
+            &string[string.find('"')? + 1..]
+        } else if let Some(ch) = literal.strip_prefix('\'') {
+            let ch = ch.strip_prefix('\\').unwrap_or(ch);
+            let mut chars = ch.chars();
+            chars.next();
+            chars.as_str().strip_prefix('\'').unwrap_or(chars.as_str())
+        } else {
+            &literal[1..]
+        };
+    }
+}
+
+pub fn function_usage<'a>(ast: &'a [AstNode<'a>], file: &TypecheckedFile<'a, '_>) -> FunctionUsage {
+    let mut defined = Vec::new();
+    for statement in ast.iter() {
+        let AstNodeValue::FunctionDeclaration(func) = &statement.value else {
+            continue;
+        };
+        // Extern functions are called by the host, and main is the entry point
+        if func.is_extern || func.name == "main" {
+            continue;
+        }
+        if let Some(function) = file
+            .functions
+            .iter()
+            .find(|function| std::ptr::eq(function.func, func))
+        {
+            defined.push((function.id, func.name.clone(), statement.provenance.clone()));
+        }
+    }
+
+    let mut referenced = HashSet::new();
+    for statement in file.top_level_statements.iter() {
+        find_referenced_functions(statement, None, &mut referenced);
+    }
+    for function in file.functions.iter() {
+        find_referenced_functions(function.func.body, Some(function.id), &mut referenced);
+    }
+
+    FunctionUsage {
+        defined,
+        referenced,
+    }
+}
+
+fn find_referenced_functions<'a>(
+    node: &'a AstNode<'a>,
+    current_function: Option<FunctionID>,
+    referenced: &mut HashSet<FunctionID>,
+) {
+    walk_function_body(node, &mut |node| {
+        // Recursive calls don't keep a function alive
+        if let Some(ExpressionType::ReferenceToFunction(fn_id)) = node.ty.get() {
+            if Some(*fn_id) != current_function {
+                referenced.insert(*fn_id);
+            }
+        }
+    });
+}
+
+pub fn find_unused_functions(usages: &[FunctionUsage]) -> Vec<CompileWarning> {
+    let referenced: HashSet<_> = usages
+        .iter()
+        .flat_map(|usage| usage.referenced.iter().copied())
+        .collect();
+
+    usages
+        .iter()
+        .flat_map(|usage| usage.defined.iter())
+        .filter(|(fn_id, _, _)| !referenced.contains(fn_id))
+        .map(|(_, name, provenance)| {
+            CompileWarning::UnusedFunction(name.clone(), provenance.clone())
+        })
+        .collect()
+}
+
+/**
+ * Visit everything in a function body except the functions nested inside it, which are in the
+ * file's functions and get visited on their own
+ */
+fn walk_function_body<'a>(node: &'a AstNode<'a>, callback: &mut impl FnMut(&'a AstNode<'a>)) {
+    callback(node);
+    node.children(|child| {
+        if !matches!(child.value, AstNodeValue::FunctionDeclaration(_)) {
+            walk_function_body(child, callback);
+        }
+    });
+}
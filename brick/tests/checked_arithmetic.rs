@@ -0,0 +1,105 @@
+use brick::{
+    interpret_code_with_options, CompileError, CompileOptions, IntepreterError, SourceFile, Value,
+};
+
+fn run(source: &str, checked_arithmetic: bool) -> Result<Vec<Value>, IntepreterError> {
+    let (results, _memory) = interpret_code_with_options(
+        vec![SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: source.to_string(),
+        }],
+        Vec::new(),
+        &CompileOptions { checked_arithmetic },
+    )?;
+
+    Ok(results)
+}
+
+#[test]
+fn max_plus_one_traps_when_checked() {
+    let result = run("let max = 2147483647; max + 1", true);
+    assert!(
+        matches!(result, Err(IntepreterError::Overflow)),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn max_plus_one_wraps_when_unchecked() {
+    let results = run("let max = 2147483647; max + 1", false).unwrap();
+    assert_eq!(results, vec![Value::Int32(i32::MIN)]);
+}
+
+#[test]
+fn overflows_trap_when_checked() {
+    for source in [
+        "let min = -2147483647 - 1; min - 1",
+        "let x = 1; x - (-2147483647 - 1)",
+        "let big = 65536; big * big",
+        "let min = -2147483647 - 1; min * -1",
+        "let min = -2147483647 - 1; -1 * min",
+        "let x: i64 = 65536; let big = x * x; big * big",
+        "let x: i64 = 65536; let big = x * x * x * 16384; big + big",
+    ] {
+        let result = run(source, true);
+        assert!(
+            matches!(result, Err(IntepreterError::Overflow)),
+            "{source}: {result:?}"
+        );
+    }
+}
+
+#[test]
+fn results_in_range_are_unchanged_when_checked() {
+    for (source, expected) in [
+        ("let max = 2147483647; max - 1 + 1", Value::Int32(i32::MAX)),
+        (
+            "let min = -2147483647 - 1; min + 1",
+            Value::Int32(i32::MIN + 1),
+        ),
+        ("let x = -3; x * 7", Value::Int32(-21)),
+        ("let x = 0; x * -2147483647", Value::Int32(0)),
+        ("let x = -1; x * 2147483647", Value::Int32(-i32::MAX)),
+        (
+            "let x: i64 = 65536; let big = x * x * x * 16384; big - 1 + big",
+            Value::Int64(i64::MAX),
+        ),
+    ] {
+        assert_eq!(run(source, true).unwrap(), vec![expected], "{source}");
+    }
+}
+
+#[test]
+fn floats_are_never_checked() {
+    let results = run("let x = 3.5; x * 2.0", true).unwrap();
+    assert_eq!(results, vec![Value::Float32(7.0)]);
+}
+
+const OVERFLOWING_CONST: &str = "
+const fn increment(x: i32): i32 {
+    x + 1
+}
+const WRAPPED = increment(2147483647);
+WRAPPED
+";
+
+#[test]
+fn const_overflow_fails_to_compile_when_checked() {
+    let result = run(OVERFLOWING_CONST, true);
+    assert!(
+        matches!(
+            result,
+            Err(IntepreterError::CompileError(
+                CompileError::ConstEvaluationFailed(_)
+            ))
+        ),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn const_overflow_wraps_when_unchecked() {
+    let results = run(OVERFLOWING_CONST, false).unwrap();
+    assert_eq!(results, vec![Value::Int32(i32::MIN)]);
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use brick::{check_types_with_stats, SourceFile};
+
+fn many_functions(count: usize) -> String {
+    let mut contents = String::new();
+    for i in 0..count {
+        contents.push_str(&format!(
+            "fn f{i}(x: i32): i32 {{\n    let total = 0;\n    let i = 0;\n    while i < x; i += 1 {{\n        total += i * {i};\n    }}\n    total\n}}\n"
+        ));
+    }
+    contents.push_str("f0(3)\n");
+
+    contents
+}
+
+#[test]
+fn every_phase_is_recorded() {
+    let (_, stats) = check_types_with_stats(vec![SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: many_functions(200),
+    }])
+    .unwrap();
+
+    assert_eq!(stats.modules.len(), 1);
+    let module = &stats.modules[0];
+    assert_eq!(module.module_name, "main");
+    for (phase, duration) in [
+        ("lexing", module.lexing),
+        ("parsing", module.parsing),
+        ("typechecking", module.typechecking),
+        ("lowering", module.lowering),
+        ("resolution", stats.resolution),
+        ("borrow checking", stats.borrow_checking),
+    ] {
+        assert!(duration > Duration::ZERO, "{phase} wasn't recorded");
+    }
+
+    // With one module nothing runs in parallel, so the phases make up most of the total
+    let phase_total = stats.phase_total();
+    assert!(phase_total <= stats.total, "{stats:?}");
+    assert!(phase_total >= stats.total / 2, "{stats:?}");
+}
+
+#[test]
+fn modules_are_recorded_in_order() {
+    let (_, stats) = check_types_with_stats(vec![
+        SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: "import self.helper.{double};\ndouble(2)".to_string(),
+        },
+        SourceFile {
+            filename: "helper.brick",
+            module_name: "helper",
+            contents: "export fn double(x: i32): i32 { x * 2 }".to_string(),
+        },
+    ])
+    .unwrap();
+
+    let names: Vec<_> = stats
+        .modules
+        .iter()
+        .map(|module| module.module_name)
+        .collect();
+    assert_eq!(names, ["main", "helper"]);
+}
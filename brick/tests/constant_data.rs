@@ -0,0 +1,60 @@
+use brick::{lower_code, LinearNode, LinearNodeValue, SourceFile, TargetConfig};
+
+fn lower(source: &str) -> (Vec<usize>, Vec<u8>) {
+    let results = lower_code(
+        vec![SourceFile {
+            module_name: "main",
+            filename: "main.brick",
+            contents: source.to_string(),
+        }],
+        &TargetConfig::native(),
+    )
+    .unwrap();
+    let mut offsets = Vec::new();
+    let mut find_offsets = |node: &LinearNode| {
+        if let LinearNodeValue::ConstantDataAddress(offset) = &node.value {
+            offsets.push(*offset);
+        }
+    };
+    for statement in results.statements.iter() {
+        statement.visit_recursive(&mut find_offsets);
+    }
+    for function in results.functions.iter() {
+        for node in function.body.iter() {
+            node.visit_recursive(&mut find_offsets);
+        }
+    }
+
+    (offsets, results.constant_data)
+}
+
+#[test]
+fn identical_string_literals_share_data() {
+    let (offsets, data) = lower(
+        r#"
+fn greeting(): string {
+    "hello"
+}
+let a = "hello";
+let b = greeting();
+"hello"
+"#,
+    );
+    assert_eq!(offsets, vec![0, 0, 0]);
+    assert_eq!(data, b"hello");
+}
+
+#[test]
+fn different_string_literals_are_stored_separately() {
+    let (mut offsets, data) = lower(
+        r#"
+let a = "hello";
+let b = "world";
+let c = "hello";
+"#,
+    );
+    offsets.sort();
+    offsets.dedup();
+    assert_eq!(offsets, vec![0, 5]);
+    assert_eq!(data, b"helloworld");
+}
@@ -0,0 +1,67 @@
+use brick::{lower_code, LinearNode, LinearNodeValue, RuntimeFunction, SourceFile, TargetConfig};
+
+fn count_string_concats(source: &str) -> usize {
+    let results = lower_code(
+        vec![SourceFile {
+            module_name: "main",
+            filename: "main.brick",
+            contents: source.to_string(),
+        }],
+        &TargetConfig::native(),
+    )
+    .unwrap();
+    let mut count = 0;
+    let mut count_node = |node: &LinearNode| {
+        if let LinearNodeValue::RuntimeCall(RuntimeFunction::StringConcat, _) = &node.value {
+            count += 1;
+        }
+    };
+    for statement in results.statements.iter() {
+        statement.visit_recursive(&mut count_node);
+    }
+    for function in results.functions.iter() {
+        for node in function.body.iter() {
+            node.visit_recursive(&mut count_node);
+        }
+    }
+
+    count
+}
+
+#[test]
+fn literal_concat_is_folded() {
+    assert_eq!(count_string_concats(r#""a" ++ "b""#), 0);
+}
+
+#[test]
+fn chained_literal_concat_is_folded() {
+    assert_eq!(count_string_concats(r#""a" ++ "b" ++ "c""#), 0);
+}
+
+#[test]
+fn constant_concat_is_folded() {
+    assert_eq!(
+        count_string_concats(
+            r#"
+const GREETING = "hello";
+GREETING ++ " world"
+"#
+        ),
+        0
+    );
+}
+
+#[test]
+fn runtime_concat_is_kept() {
+    assert_eq!(
+        count_string_concats(
+            r#"
+fn greet(name: string): string {
+    "hello " ++ name
+}
+greet("world")
+"#
+        ),
+        1
+    );
+}
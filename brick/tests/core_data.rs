@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::bail;
 use brick::{check_types, interpret_code, SourceFile, Value};
@@ -40,23 +43,7 @@ fn data() {
             let counter = *counter.lock().unwrap();
             look_for_value(&mut results, &memory[..], expected, counter)
         },
-        [
-            // Coroutines temporarily don't compile
-            "coroutine/count_up.brick",
-            "coroutine/echo.brick",
-            "coroutine/infinite.brick",
-            "coroutine/multiple_generators.brick",
-            "coroutine/mutable_ref.brick",
-            "coroutine/mutable_ref_repeated.brick",
-            "coroutine/nested_coroutines.brick",
-            "coroutine/other_functions.brick",
-            "coroutine/regression_test_branch_in_yielding_loop.brick",
-            "coroutine/yield_basic.brick",
-            "coroutine/yield_once.brick",
-            "coroutine/yield_twice.brick",
-        ]
-        .into_iter()
-        .collect(),
+        HashSet::new(),
     );
 }
 
@@ -0,0 +1,269 @@
+use assert_matches::assert_matches;
+use brick::{
+    check_types, recheck_types, typecheck_module, CompilationResults, DeclarationContext,
+    DeclarationRef, ExpressionType, ModuleUpdate, PrimitiveType, SourceFile, TypeDeclaration,
+};
+use typed_arena::Arena;
+
+fn with_declarations(modules: &[(&'static str, &str)], callback: impl FnOnce(CompilationResults)) {
+    let arena = Arena::new();
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|(name, source)| {
+            (
+                *name,
+                brick::parse_file(&arena, name, source.to_string()).unwrap(),
+            )
+        })
+        .collect();
+    callback(typecheck_module(&modules[..]).unwrap());
+}
+
+const GEOMETRY: &str = r#"
+struct Point {
+    y: f32,
+    x: f32,
+}
+
+union Shape {
+    Circle(f32),
+    Empty,
+}
+
+fn origin(): Point {
+    Point { x: 0.0, y: 0.0 }
+}
+
+fn scale(point: Point, factor: f32): Point {
+    Point { x: point.x * factor, y: point.y * factor }
+}
+"#;
+
+const MAIN: &str = r#"
+fn area(radius: f32): f32 {
+    radius * radius * 3.0
+}
+
+area(1.0)
+"#;
+
+#[test]
+fn exported_functions() {
+    with_declarations(&[("geometry", GEOMETRY), ("main", MAIN)], |results| {
+        let functions = results.declarations.exported_functions();
+        let names: Vec<_> = functions
+            .iter()
+            .map(|func| (func.module, func.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("geometry", "origin"),
+                ("geometry", "scale"),
+                ("main", "area")
+            ]
+        );
+
+        let area = &functions[2].signature;
+        assert_eq!(
+            area.params,
+            vec![ExpressionType::Primitive(PrimitiveType::Float32)]
+        );
+        assert_eq!(
+            area.returns,
+            ExpressionType::Primitive(PrimitiveType::Float32)
+        );
+        assert_eq!(functions[1].signature.params.len(), 2);
+    });
+}
+
+#[test]
+fn struct_fields() {
+    with_declarations(&[("geometry", GEOMETRY)], |results| {
+        let fields = results.declarations.type_fields("Point").unwrap();
+        let fields: Vec<_> = fields.iter().map(|field| (field.name, field.ty)).collect();
+        let float = ExpressionType::Primitive(PrimitiveType::Float32);
+        assert_eq!(fields, vec![("x", &float), ("y", &float)]);
+    });
+}
+
+#[test]
+fn union_variants() {
+    with_declarations(&[("geometry", GEOMETRY)], |results| {
+        let variants = results.declarations.type_fields("geometry.Shape").unwrap();
+        let variants: Vec<_> = variants
+            .iter()
+            .map(|variant| (variant.name, variant.ty))
+            .collect();
+        assert_eq!(
+            variants,
+            vec![
+                ("Circle", &ExpressionType::Primitive(PrimitiveType::Float32)),
+                ("Empty", &ExpressionType::Void),
+            ]
+        );
+    });
+}
+
+#[test]
+fn lookup() {
+    with_declarations(&[("geometry", GEOMETRY), ("main", MAIN)], |results| {
+        let declarations = &results.declarations;
+        assert_matches!(
+            declarations.lookup("Point"),
+            Some(DeclarationRef::Type(TypeDeclaration::Struct(_)))
+        );
+        assert_matches!(
+            declarations.lookup("main.area"),
+            Some(DeclarationRef::Function(func)) if func.params.len() == 1
+        );
+        assert_matches!(declarations.lookup("main.Point"), None);
+        assert_matches!(declarations.lookup("missing"), None);
+        assert_matches!(declarations.type_fields("origin"), None);
+    });
+}
+
+const HELPER: &str = r#"
+export struct Counter {
+    count: i32,
+}
+
+export fn double(x: i32): i32 {
+    x * 2
+}
+
+fn private_helper(x: i32): i32 {
+    x
+}
+"#;
+
+/**
+ * Declare main and helper, then replace helper's declarations with the new source. Main's syntax
+ * tree is gone by the time of the update, so it can't be resolved again.
+ */
+fn update_helper(new_helper: &str) -> (DeclarationContext, Result<ModuleUpdate, String>) {
+    let mut declarations = {
+        let arena = Arena::new();
+        let files = [
+            (
+                "main",
+                brick::parse_file(
+                    &arena,
+                    "main",
+                    "import self.helper.{double};\ndouble(2)".to_string(),
+                )
+                .unwrap(),
+            ),
+            (
+                "helper",
+                brick::parse_file(&arena, "helper", HELPER.to_string()).unwrap(),
+            ),
+        ];
+        DeclarationContext::new(&files[..]).unwrap()
+    };
+
+    let arena = Arena::new();
+    let files = [(
+        "helper",
+        brick::parse_file(&arena, "helper", new_helper.to_string()).unwrap(),
+    )];
+    let update = declarations
+        .update_module(&files[..])
+        .map_err(|err| err.to_string());
+
+    (declarations, update)
+}
+
+#[test]
+fn updating_function_body_is_contained() {
+    let new_helper = HELPER.replace("x * 2", "\n\n    x + x");
+    let (declarations, update) = update_helper(new_helper.as_str());
+    assert_eq!(update, Ok(ModuleUpdate::Contained));
+
+    let Some(DeclarationRef::Function(double)) = declarations.lookup("helper.double") else {
+        panic!("double is still declared");
+    };
+    assert_eq!(double.provenance.as_ref().unwrap().end_line, 10);
+    assert_eq!(
+        declarations.files["main"].imports()["double"],
+        ExpressionType::ReferenceToFunction(double.id)
+    );
+}
+
+#[test]
+fn updating_private_signature_is_contained() {
+    let new_helper = HELPER.replace("private_helper(x: i32): i32", "private_helper(x: i64): i64");
+    let (_, update) = update_helper(new_helper.as_str());
+    assert_eq!(update, Ok(ModuleUpdate::Contained));
+}
+
+#[test]
+fn updating_exported_signature_changes_signatures() {
+    let new_helper = HELPER.replace("double(x: i32): i32", "double(x: i64): i64");
+    let (_, update) = update_helper(new_helper.as_str());
+    assert_eq!(update, Ok(ModuleUpdate::SignaturesChanged));
+}
+
+#[test]
+fn updating_struct_changes_signatures() {
+    let new_helper = HELPER.replace("count: i32", "count: i64");
+    let (_, update) = update_helper(new_helper.as_str());
+    assert_eq!(update, Ok(ModuleUpdate::SignaturesChanged));
+}
+
+#[test]
+fn removing_export_changes_signatures() {
+    let new_helper = HELPER.replace("export fn double", "fn double");
+    let (_, update) = update_helper(new_helper.as_str());
+    assert_eq!(update, Ok(ModuleUpdate::SignaturesChanged));
+}
+
+#[test]
+fn invalid_update_fails() {
+    let new_helper = HELPER.replace("count: i32", "count: Missing");
+    let (_, update) = update_helper(new_helper.as_str());
+    assert!(update.is_err());
+}
+
+#[test]
+fn empty_update_is_contained() {
+    let (mut declarations, _) = update_helper(HELPER);
+    assert_eq!(
+        declarations
+            .update_module(&[])
+            .map_err(|err| err.to_string()),
+        Ok(ModuleUpdate::Contained)
+    );
+    assert!(declarations.lookup("helper.double").is_some());
+}
+
+fn sources(helper: &str) -> Vec<SourceFile> {
+    vec![
+        SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: "import self.helper.{double};\ndouble(2)".to_string(),
+        },
+        SourceFile {
+            filename: "helper.brick",
+            module_name: "helper",
+            contents: helper.to_string(),
+        },
+    ]
+}
+
+#[test]
+fn rechecking_contained_edit() {
+    let CompilationResults { declarations, .. } = check_types(sources(HELPER)).unwrap();
+    let new_helper = HELPER.replace("x * 2", "x + x");
+    let results = recheck_types(sources(new_helper.as_str()), "helper", declarations).unwrap();
+    assert!(results.modules.contains_key("main"));
+    assert!(results.modules.contains_key("helper"));
+}
+
+#[test]
+fn rechecking_signature_change_checks_other_modules() {
+    let CompilationResults { declarations, .. } = check_types(sources(HELPER)).unwrap();
+    let new_helper = HELPER.replace("export fn double", "fn double");
+    assert!(recheck_types(sources(new_helper.as_str()), "helper", declarations).is_err());
+}
@@ -0,0 +1,182 @@
+use brick::{check_types, CompileError, RenderOptions, SourceFile};
+
+fn compile_error(contents: &str) -> CompileError {
+    let Err(error) = check_types(vec![SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: contents.to_string(),
+    }]) else {
+        panic!("{contents} should fail to compile");
+    };
+
+    error
+}
+
+#[test]
+fn parse_error_code() {
+    let CompileError::ParseError(error) = compile_error("let x = 1 < 2 < 3;") else {
+        panic!("expected a parse error");
+    };
+    assert_eq!(error.code(), "E0006");
+    assert_eq!(error.primary_span().unwrap().start_line, 1);
+}
+
+#[test]
+fn typecheck_error_code() {
+    let CompileError::TypecheckError(error) = compile_error("let x = 1;\nlet y: bool = x;") else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0102");
+    assert_eq!(error.primary_span().unwrap().start_line, 2);
+}
+
+#[test]
+fn diagnostics_flatten_typecheck_errors() {
+    let error = compile_error("fn a(): i32 { true }\nfn b(): bool { 1 }");
+    let diagnostics = error.diagnostics();
+    assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+    for diagnostic in diagnostics.iter() {
+        assert_eq!(diagnostic.code, Some("E0102"));
+        assert_eq!(
+            diagnostic.span.as_ref().unwrap().source_name(),
+            "main.brick"
+        );
+    }
+}
+
+#[test]
+fn duplicate_match_variant_points_at_redundant_arm() {
+    let error = compile_error(
+        r#"union TwoWay {
+    one(i32),
+    two(i32),
+}
+case TwoWay.two(2) {
+    one(val) => val,
+    two(val) => 1000,
+    one(val) => 0,
+}"#,
+    );
+    let diagnostics = error.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!(diagnostics[0].code, Some("E0143"));
+    assert_eq!(diagnostics[0].span.as_ref().unwrap().start_line, 8);
+}
+
+#[test]
+fn import_of_missing_name_is_an_error() {
+    let Err(error) = check_types(vec![
+        SourceFile {
+            filename: "helper.brick",
+            module_name: "helper",
+            contents: "export fn double(x: i32): i32 { x * 2 }".to_string(),
+        },
+        SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: "import self.helper.{double, triple};\ndouble(1)".to_string(),
+        },
+    ]) else {
+        panic!("importing a missing name should fail to compile");
+    };
+    let CompileError::TypecheckError(error) = error else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0145");
+    assert!(error.to_string().contains("triple"), "{error}");
+}
+
+#[test]
+fn import_of_private_name_is_an_error() {
+    let Err(error) = check_types(vec![
+        SourceFile {
+            filename: "helper.brick",
+            module_name: "helper",
+            contents: "export fn double(x: i32): i32 { x * 2 }\nfn triple(x: i32): i32 { x * 3 }"
+                .to_string(),
+        },
+        SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: "import self.helper.{double, triple};\ndouble(1)".to_string(),
+        },
+    ]) else {
+        panic!("importing a private name should fail to compile");
+    };
+    let CompileError::TypecheckError(error) = error else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0148");
+    assert!(error.to_string().contains("triple"), "{error}");
+}
+
+#[test]
+fn return_out_of_defer_is_an_error() {
+    let CompileError::TypecheckError(error) =
+        compile_error("fn f(): i32 {\n    defer {\n        return 1;\n    };\n    2\n}")
+    else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0146");
+    assert_eq!(error.primary_span().unwrap().start_line, 3);
+}
+
+#[test]
+fn duplicate_function_is_an_error() {
+    let CompileError::TypecheckError(error) =
+        compile_error("fn value(): i32 { 1 }\nfn value(): i32 { 2 }\nvalue()")
+    else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0147");
+    assert_eq!(error.primary_span().unwrap().start_line, 2);
+    assert!(error.to_string().contains("value"), "{error}");
+}
+
+#[test]
+fn function_colliding_with_struct_is_an_error() {
+    let CompileError::TypecheckError(error) =
+        compile_error("struct Point { x: i32 }\nfn Point(): i32 { 1 }")
+    else {
+        panic!("expected a typecheck error");
+    };
+    assert_eq!(error.code(), "E0147");
+    assert_eq!(error.primary_span().unwrap().start_line, 2);
+}
+
+#[test]
+fn rendered_underline_accounts_for_tabs() {
+    let error = compile_error("let x = 1;\n\t\tlet y: bool = x;");
+    let diagnostics = error.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    let span = diagnostics[0].span.as_ref().unwrap();
+    // Offsets still count each tab as one character
+    assert_eq!(span.start_offset, 10);
+
+    let rendered = diagnostics[0].render(&RenderOptions::default());
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[3], "2 |         let y: bool = x;");
+    assert_eq!(lines[4], "  |                ^^^^");
+
+    let rendered = diagnostics[0].render(&RenderOptions { tab_width: 2 });
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[3], "2 |     let y: bool = x;");
+    assert_eq!(lines[4], "  |            ^^^^");
+}
+
+#[test]
+fn rendered_underline_spans_multiple_lines() {
+    let error = compile_error("fn f(): bool {\n    1 }");
+    let diagnostics = error.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    let span = diagnostics[0].span.as_ref().unwrap();
+    assert_eq!((span.start_line, span.end_line), (1, 2));
+
+    let rendered = diagnostics[0].render(&RenderOptions::default());
+    let lines: Vec<_> = rendered.lines().collect();
+    assert_eq!(lines[3], "1 |   fn f(): bool {");
+    assert_eq!(lines[4], "  |  ______________^");
+    assert_eq!(lines[5], "2 | |     1 }");
+    assert_eq!(lines[6], "  | |_______^");
+    assert_eq!(lines.len(), 7);
+}
@@ -1,7 +1,10 @@
 use std::sync::{Arc, Mutex};
 
 use assert_matches::assert_matches;
-use brick::{interpret_code, ExternBinding, IntepreterError, SourceFile, Value};
+use brick::{
+    check_types, interpret_code, run_program, run_program_capturing_output, AbortReason,
+    ExternBinding, HirNode, IntepreterError, SourceFile, Value,
+};
 
 static mut INCR_VALUE: i32 = 0;
 
@@ -75,7 +78,6 @@ x
 }
 
 #[test]
-#[should_panic]
 fn externally_driven_coroutine() {
     let results = Arc::new(Mutex::new(Vec::new()));
 
@@ -209,3 +211,198 @@ obj["hello"].Number
     .unwrap();
     assert_eq!(&result[..], &[Value::Float64(1024.0)]);
 }
+
+#[test]
+fn run_program_errors() {
+    assert_matches!(
+        run_program("let x: i32 = \"five\";"),
+        Err(IntepreterError::CompileError(_))
+    );
+    assert_matches!(
+        run_program("let x: i32? = null; x!"),
+        Err(IntepreterError::Abort(AbortReason::NullUnwrap))
+    );
+}
+
+#[test]
+fn run_program_abort_reasons() {
+    let cases = [
+        ("let x: i32? = null; x!", AbortReason::NullUnwrap),
+        (
+            "let array = list[1, 2, 3]; array[5]",
+            AbortReason::ArrayIndexOutOfBounds,
+        ),
+        (
+            "let entries = dict{ [1]: 30 }; entries[2]",
+            AbortReason::DictKeyNotFound,
+        ),
+        (
+            "let value = 300; value.to_char()",
+            AbortReason::CharOutOfRange,
+        ),
+        ("let x = 2; x.pow(-1)", AbortReason::NegativeExponent),
+        (
+            "gen fn once(): generator[i32, void] { yield 1; return; } let seq = once(); seq(); seq()",
+            AbortReason::GeneratorFinished,
+        ),
+        (
+            "gen fn once(): generator[i32, void] { yield 1; } let seq = once(); seq(); seq()",
+            AbortReason::GeneratorFinished,
+        ),
+    ];
+    for (source, expected) in cases {
+        let Err(IntepreterError::Abort(reason)) = run_program(source) else {
+            panic!("{source} should abort");
+        };
+        assert_eq!(reason, expected, "{source}");
+    }
+}
+
+#[test]
+fn run_program_assertions() {
+    assert_eq!(
+        run_program("assert(1 + 1 == 2); 3").unwrap(),
+        vec![Value::Int32(3)]
+    );
+    let Err(IntepreterError::AssertionFailed {
+        message,
+        provenance,
+    }) = run_program("let x = 1;\nassert(x == 2, \"x should be \" ++ \"two\");")
+    else {
+        panic!("assertion should fail");
+    };
+    assert_eq!(message.as_deref(), Some("x should be two"));
+    assert_eq!(provenance.start_line, 2);
+    assert_matches!(
+        run_program("assert(false);"),
+        Err(IntepreterError::AssertionFailed { message: None, .. })
+    );
+}
+
+#[test]
+fn run_program_output() {
+    let (result, output) = run_program_capturing_output(
+        r#"
+extern fn print(str: string);
+fn greet(name: string) {
+    print("hello " ++ name);
+}
+greet("world");
+print("done");
+"#,
+    )
+    .unwrap();
+    assert!(result.is_empty());
+    assert_eq!(output, "hello world\ndone\n");
+}
+
+#[test]
+fn raw_multiline_string() {
+    let (_, output) = run_program_capturing_output(
+        r#"
+extern fn print(str: string);
+let template = """
+first line
+    "second" \n line
+""";
+print(template);
+"#,
+    )
+    .unwrap();
+    assert_eq!(output, "\nfirst line\n    \"second\" \\n line\n\n");
+}
+
+#[test]
+fn interface_dispatch() {
+    let (result, output) = run_program_capturing_output(
+        r#"
+extern fn print(str: string);
+
+interface Named {
+    fn name(self: ref Named): string,
+}
+
+struct Cat {
+    lives: i32,
+
+    fn name(self: ref Cat): string {
+        "cat"
+    }
+}
+
+struct Dog {
+    good: bool,
+
+    fn name(self: ref Dog): string {
+        "dog"
+    }
+}
+
+fn announce(named: Named) {
+    print(named.name());
+}
+
+let cat = Cat { lives: 9 };
+let dog = Dog { good: true };
+announce(dog);
+announce(cat);
+"#,
+    )
+    .unwrap();
+    assert!(result.is_empty());
+    assert_eq!(output, "dog\ncat\n");
+}
+
+fn function_hir(contents: &str) -> HirNode {
+    let mut results = check_types(vec![SourceFile {
+        module_name: "main",
+        filename: "main.brick",
+        contents: contents.to_string(),
+    }])
+    .unwrap();
+    let mut module = results.modules.remove("main").unwrap();
+    assert_eq!(module.functions.len(), 1);
+
+    module.functions.remove(0).body
+}
+
+/**
+ * Compare everything but IDs and provenance, which are different every time code is lowered
+ */
+fn assert_same_hir(left: &HirNode, right: &HirNode) {
+    assert_eq!(
+        std::mem::discriminant(&left.value),
+        std::mem::discriminant(&right.value),
+        "{left:?} != {right:?}"
+    );
+    assert_eq!(left.ty, right.ty);
+    let mut left_children = Vec::new();
+    left.children(|child| left_children.push(child));
+    let mut right_children = Vec::new();
+    right.children(|child| right_children.push(child));
+    assert_eq!(left_children.len(), right_children.len());
+    for (left, right) in left_children.iter().zip(right_children.iter()) {
+        assert_same_hir(left, right);
+    }
+}
+
+#[test]
+fn expression_body_lowers_like_block() {
+    let expression_body = function_hir("fn double(x: i32): i32 = x * 2;");
+    let block_body = function_hir("fn double(x: i32): i32 { x * 2 }");
+
+    assert_same_hir(&expression_body, &block_body);
+}
+
+#[test]
+fn expression_body_call() {
+    let result = run_program(
+        r#"
+fn double(x: i32): i32 = x * 2;
+fn quadruple(x: i32): i32 = double(double(x));
+quadruple(3)
+"#,
+    )
+    .unwrap();
+    assert_eq!(result, vec![Value::Int32(12)]);
+}
@@ -0,0 +1,129 @@
+use std::{cell::RefCell, rc::Rc};
+
+use brick::{ExternBinding, IntepreterError, ReplSession, Value};
+
+fn evaluate(session: &mut ReplSession, line: &str) -> Vec<Value> {
+    session
+        .evaluate(line)
+        .unwrap_or_else(|err| panic!("{line} failed: {err}"))
+}
+
+/**
+ * A session with `extern fn record(value: i32)` bound, along with every value recorded so far
+ */
+fn recording_session() -> (ReplSession, Rc<RefCell<Vec<i32>>>) {
+    let recorded = Rc::new(RefCell::new(Vec::new()));
+    let values = recorded.clone();
+    let record: ExternBinding = Box::new(move |_, params| {
+        let [Value::Int32(value)] = &params[..] else {
+            unreachable!("record takes an i32, not {params:?}");
+        };
+        values.borrow_mut().push(*value);
+        None
+    });
+
+    (ReplSession::new(vec![("record", record)]), recorded)
+}
+
+#[test]
+fn variables_persist() {
+    let mut session = ReplSession::new(Vec::new());
+    assert_eq!(evaluate(&mut session, "let x = 1;"), vec![]);
+    assert_eq!(evaluate(&mut session, "x + 1"), vec![Value::Int32(2)]);
+    evaluate(&mut session, "x = x + 10;");
+    assert_eq!(evaluate(&mut session, "x"), vec![Value::Int32(11)]);
+}
+
+#[test]
+fn lists_persist() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "let numbers = list[1, 2];");
+    evaluate(&mut session, "numbers.push(3);");
+    evaluate(
+        &mut session,
+        "let total = 0; let i = 0; while i < 3 { total += numbers[i]; i += 1; }",
+    );
+    assert_eq!(
+        evaluate(&mut session, "numbers.len()"),
+        vec![Value::Size(3)]
+    );
+    assert_eq!(evaluate(&mut session, "total"), vec![Value::Int32(6)]);
+}
+
+#[test]
+fn structs_persist() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "struct Point { x: i32, y: i32 = 4 }");
+    evaluate(&mut session, "let p = Point { x: 3 };");
+    evaluate(&mut session, "p.x = p.x * 2;");
+    assert_eq!(evaluate(&mut session, "p.x + p.y"), vec![Value::Int32(10)]);
+}
+
+#[test]
+fn functions_and_constants_persist() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "const OFFSET: i32 = 5;");
+    evaluate(&mut session, "fn shift(n: i32): i32 { n + OFFSET }");
+    evaluate(&mut session, "fn twice(n: i32): i32 { shift(shift(n)) }");
+    assert_eq!(
+        evaluate(&mut session, "twice(OFFSET)"),
+        vec![Value::Int32(15)]
+    );
+}
+
+#[test]
+fn strings_persist() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "let greeting = \"hello\";");
+    evaluate(&mut session, "let message = greeting ++ \" world\";");
+    let results = evaluate(&mut session, "message");
+    assert_eq!(results[1], Value::Size("hello world".len()));
+}
+
+#[test]
+fn lines_run_once() {
+    let (mut session, recorded) = recording_session();
+    evaluate(&mut session, "extern fn record(value: i32);");
+    evaluate(&mut session, "let x = 1; record(x);");
+    evaluate(&mut session, "record(x + 1);");
+    evaluate(&mut session, "record(x + 2);");
+    assert_eq!(*recorded.borrow(), vec![1, 2, 3]);
+}
+
+#[test]
+fn moved_variables_stay_moved() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "let numbers = list[1, 2];");
+    evaluate(&mut session, "let moved = numbers;");
+    assert!(matches!(
+        session.evaluate("numbers.len()"),
+        Err(IntepreterError::CompileError(_))
+    ));
+    assert_eq!(evaluate(&mut session, "moved.len()"), vec![Value::Size(2)]);
+}
+
+#[test]
+fn failed_lines_are_undone() {
+    let mut session = ReplSession::new(Vec::new());
+    evaluate(&mut session, "let x = 1;");
+    assert!(session.evaluate("let y = x + ;").is_err());
+    assert!(session
+        .evaluate("x = 5; let y = 2; assert(false);")
+        .is_err());
+    assert!(session.evaluate("y").is_err());
+    assert_eq!(evaluate(&mut session, "x"), vec![Value::Int32(1)]);
+    // Names from a line that didn't compile are free to be declared again
+    assert!(session.evaluate("fn f(): i32 { missing }").is_err());
+    evaluate(&mut session, "fn f(): i32 { 2 }");
+}
+
+#[test]
+fn bindings_survive_failed_lines() {
+    let (mut session, recorded) = recording_session();
+    assert!(session
+        .evaluate("extern fn record(value: i32); record(1); assert(false);")
+        .is_err());
+    evaluate(&mut session, "extern fn record(value: i32);");
+    evaluate(&mut session, "record(2);");
+    assert_eq!(*recorded.borrow(), vec![1, 2]);
+}
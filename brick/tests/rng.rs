@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+
+use brick::{interpret_code, SourceFile, Value, RNG_DEFAULT_STATE};
+
+/**
+ * Plain xorshift64, kept separate from the interpreter so the two can't drift together
+ */
+fn reference_outputs(seed: u64, count: usize) -> Vec<i32> {
+    let mut state = if seed == 0 { RNG_DEFAULT_STATE } else { seed };
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 33) as i32
+        })
+        .collect()
+}
+
+fn interpreted_outputs(source: &str) -> Vec<i32> {
+    let outputs = Arc::new(Mutex::new(Vec::new()));
+    let recorded = outputs.clone();
+    interpret_code(
+        vec![SourceFile {
+            module_name: "main",
+            filename: "main.brick",
+            contents: source.to_string(),
+        }],
+        vec![(
+            "record",
+            Box::new(move |_, values| {
+                let [Value::Int32(value)] = &values[..] else {
+                    panic!("expected a single i32, got {values:?}");
+                };
+                recorded.lock().unwrap().push(*value);
+                None
+            }),
+        )],
+    )
+    .unwrap();
+
+    let outputs = outputs.lock().unwrap().clone();
+    outputs
+}
+
+#[test]
+fn seeded_outputs_match_reference() {
+    let outputs = interpreted_outputs(
+        r#"
+extern fn record(value: i32);
+rng_seed(12345);
+let i = 0;
+while i < 5; i += 1 {
+    record(rng_next());
+}
+"#,
+    );
+    assert_eq!(outputs, reference_outputs(12345, 5));
+}
+
+#[test]
+fn reseeding_repeats_sequence() {
+    let outputs = interpreted_outputs(
+        r#"
+extern fn record(value: i32);
+rng_seed(7);
+record(rng_next());
+record(rng_next());
+rng_seed(7);
+record(rng_next());
+record(rng_next());
+"#,
+    );
+    assert_eq!(outputs[..2], outputs[2..]);
+    assert_eq!(outputs[..2], reference_outputs(7, 2)[..]);
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "serde")]
+
+use brick::{check_types, SourceFile};
+use serde_json::Value;
+
+fn serialize(contents: &str) -> Value {
+    let results = check_types(vec![SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: contents.to_string(),
+    }])
+    .unwrap();
+
+    serde_json::to_value(&results).unwrap()
+}
+
+#[test]
+fn functions_and_types_are_serialized() {
+    let json = serialize(
+        "struct Point { x: i32, y: i32 }\nfn sum(point: Point): i32 { point.x + point.y }\nsum(Point { x: 1, y: 2 })",
+    );
+
+    let functions = json["modules"]["main"]["functions"].as_array().unwrap();
+    assert_eq!(functions.len(), 1);
+    let sum = &functions[0];
+    assert_eq!(sum["name"], "sum");
+    assert!(sum["id"].is_u64(), "{sum}");
+    assert!(sum["body"]["id"].is_u64(), "{sum}");
+    assert_eq!(
+        sum["body"]["ty"],
+        serde_json::json!({ "Primitive": "Int32" })
+    );
+    assert_eq!(sum["body"]["provenance"]["source_name"], "main.brick");
+
+    let declarations = &json["declarations"];
+    let point = declarations["id_to_decl"]
+        .as_object()
+        .unwrap()
+        .values()
+        .find_map(|decl| decl.get("Struct"))
+        .unwrap();
+    assert_eq!(
+        point["fields"]["x"],
+        serde_json::json!({ "Primitive": "Int32" })
+    );
+    let sum_ty = &declarations["id_to_func"][sum["id"].to_string()];
+    assert_eq!(
+        sum_ty["returns"],
+        serde_json::json!({ "Primitive": "Int32" })
+    );
+    assert_eq!(
+        sum_ty["params"][0],
+        serde_json::json!({ "InstanceOf": point["id"] })
+    );
+}
+
+#[test]
+fn warnings_are_serialized() {
+    let json = serialize("fn unused(x: i32) {}\n");
+
+    let warnings = json["warnings"].as_array().unwrap();
+    assert!(warnings
+        .iter()
+        .any(|warning| warning["UnusedParameter"][0] == "x"));
+}
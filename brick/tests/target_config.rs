@@ -0,0 +1,72 @@
+use brick::{lower_code, DeclarationRef, SourceFile, TargetConfig};
+
+fn type_size(source: &str, name: &str, target: &TargetConfig) -> usize {
+    let results = lower_code(
+        vec![SourceFile {
+            filename: "main.brick",
+            module_name: "main",
+            contents: source.to_string(),
+        }],
+        target,
+    )
+    .unwrap();
+    let Some(DeclarationRef::Type(ty)) = results.declarations.lookup(name) else {
+        panic!("{name} isn't a type");
+    };
+
+    results.type_layouts[&ty.id()].size
+}
+
+#[test]
+fn pointer_size_changes_struct_layout() {
+    let source = "struct Inventory {
+    items: list[i32],
+    label: string,
+    open: bool,
+}";
+
+    let size_32 = type_size(source, "Inventory", &TargetConfig::WASM32);
+    let size_64 = type_size(source, "Inventory", &TargetConfig::WASM64);
+
+    assert!(size_64 > size_32, "{size_64} should be more than {size_32}");
+}
+
+#[test]
+fn structs_without_pointers_are_the_same_size() {
+    let source = "struct Point {
+    x: i32,
+    y: i32,
+}";
+
+    assert_eq!(
+        type_size(source, "Point", &TargetConfig::WASM32),
+        type_size(source, "Point", &TargetConfig::WASM64)
+    );
+}
+
+#[test]
+fn unions_fit_their_largest_variant_exactly() {
+    let source = "struct Flags {
+    a: bool,
+    b: bool,
+    c: bool,
+    d: bool,
+    e: bool,
+    f: bool,
+    g: bool,
+}
+
+union Setting {
+    toggle(bool),
+    flags(Flags),
+}";
+
+    for target in [TargetConfig::WASM32, TargetConfig::WASM64] {
+        let flags = type_size(source, "Flags", &target);
+        assert_eq!(flags, 7 * target.byte_size);
+        assert_eq!(
+            type_size(source, "Setting", &target),
+            target.pointer_size + flags
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use brick::{check_types, SourceFile};
+
+fn mismatch_message(contents: &str) -> String {
+    let Err(error) = check_types(vec![SourceFile {
+        filename: "main.brick",
+        module_name: "main",
+        contents: contents.to_string(),
+    }]) else {
+        panic!("{contents} should fail to compile");
+    };
+    let message = error.to_string();
+    assert!(message.contains("mismatched types"), "{message}");
+
+    message
+}
+
+#[test]
+fn declared_type_uses_source_name() {
+    let message = mismatch_message("struct Point { x: i32 }\nlet p: Point = 5;");
+    assert!(
+        message.contains("expected Point, received int32"),
+        "{message}"
+    );
+}
+
+#[test]
+fn primitive_types() {
+    let message = mismatch_message("let x: bool = 1.5;");
+    assert!(
+        message.contains("expected bool, received float32"),
+        "{message}"
+    );
+}
+
+#[test]
+fn array_type() {
+    let message = mismatch_message("let x: list[i32] = true;");
+    assert!(
+        message.contains("expected [int32], received bool"),
+        "{message}"
+    );
+}
+
+#[test]
+fn dict_type() {
+    let message = mismatch_message("let x: dict[string, list[i32]] = true;");
+    assert!(
+        message.contains("expected {string: [int32]}, received bool"),
+        "{message}"
+    );
+}
+
+#[test]
+fn nullable_type() {
+    let message = mismatch_message("let x: i32? = true;");
+    assert!(
+        message.contains("expected int32?, received bool"),
+        "{message}"
+    );
+}
+
+#[test]
+fn pointer_types() {
+    let message = mismatch_message("fn take(x: unique i32) {}\ntake(true);");
+    assert!(
+        message.contains("expected unique int32, received bool"),
+        "{message}"
+    );
+
+    let message = mismatch_message("fn take(x: ref i32) {}\ntake(true);");
+    assert!(
+        message.contains("expected shared int32, received bool"),
+        "{message}"
+    );
+}
+
+#[test]
+fn function_types() {
+    let message = mismatch_message("fn add(a: i32, b: i32): i32 { a + b }\nlet x: bool = add;");
+    assert!(
+        message.contains("expected bool, received fn(int32, int32): int32"),
+        "{message}"
+    );
+}
@@ -0,0 +1,41 @@
+use brick::{run_program, Value, ValueConversionError};
+
+#[test]
+fn accessors_reject_other_kinds() {
+    assert_eq!(Value::Int32(1).as_float(), None);
+    assert_eq!(Value::Float64(1.0).as_int(), None);
+    assert_eq!(Value::Byte(2).as_bool(), None);
+    assert_eq!(Value::Int64(1).as_size(), None);
+}
+
+#[test]
+fn try_from_converts_results() {
+    let results = run_program("let x: i64 = 40; x + 2").unwrap();
+    let value: i64 = results[0].clone().try_into().unwrap();
+    assert_eq!(value, 42);
+
+    let results = run_program("1 == 1").unwrap();
+    let value: bool = results[0].clone().try_into().unwrap();
+    assert!(value);
+}
+
+#[test]
+fn try_from_reports_mismatch() {
+    let result: Result<i64, _> = Value::Int32(3).try_into();
+    assert_eq!(
+        result,
+        Err(ValueConversionError {
+            expected: "i64",
+            found: Value::Int32(3),
+        })
+    );
+}
+
+#[test]
+fn as_string_requires_pointer_and_length() {
+    assert_eq!(Value::as_string(&[Value::Int32(1)], &[]), None);
+    assert_eq!(
+        Value::as_string(&[Value::Size(0), Value::Size(4)], b"brick"),
+        Some("bric")
+    );
+}
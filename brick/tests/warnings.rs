@@ -0,0 +1,232 @@
+use assert_matches::assert_matches;
+use brick::{typecheck_module, CompilationResults, CompileWarning};
+use typed_arena::Arena;
+
+fn warnings(modules: &[(&'static str, &str)]) -> Vec<CompileWarning> {
+    let arena = Arena::new();
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|(name, source)| {
+            (
+                *name,
+                brick::parse_file(&arena, name, source.to_string()).unwrap(),
+            )
+        })
+        .collect();
+    let CompilationResults { warnings, .. } = typecheck_module(&modules[..]).unwrap();
+
+    warnings
+}
+
+#[test]
+fn unused_parameter() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+fn first(a: i32, b: i32): i32 {
+    a
+}
+first(1, 2)
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::UnusedParameter(name, _)] if name == "b");
+}
+
+#[test]
+fn underscore_parameter() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+fn first(a: i32, _b: i32): i32 {
+    a
+}
+first(1, 2)
+"#,
+    )]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn uncalled_function() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+fn called(): i32 {
+    1
+}
+fn uncalled(): i32 {
+    uncalled() + 1
+}
+called()
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::UnusedFunction(name, _)] if name == "uncalled");
+}
+
+#[test]
+fn function_called_from_other_module() {
+    let warnings = warnings(&[
+        (
+            "main",
+            r#"
+import self.other.helper;
+helper()
+"#,
+        ),
+        (
+            "other",
+            r#"
+export fn helper(): i32 {
+    1
+}
+"#,
+        ),
+    ]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn extern_and_main_functions() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+extern fn exported(): i32 {
+    1
+}
+fn main() {}
+"#,
+    )]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn function_called_through_module() {
+    let warnings = warnings(&[
+        (
+            "main",
+            r#"
+import self.other;
+other.helper()
+"#,
+        ),
+        (
+            "other",
+            r#"
+export fn helper(): i32 {
+    1
+}
+"#,
+        ),
+    ]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn loop_body_discards_value() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let x = 0;
+while x < 10 {
+    x += 1;
+    x + 1
+}
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::DiscardedLoopValue(provenance)] if provenance.start_line == 5);
+}
+
+#[test]
+fn loop_body_ending_in_statement() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let x = 0;
+loop {
+    x += 1;
+    if x > 10 {
+        break;
+    }
+}
+while x < 20 {
+    x += 1;
+}
+"#,
+    )]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn float_equality() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let a = 0.1 + 0.2;
+let b = 0.3;
+if a == b {
+    1
+} else {
+    2
+}
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::FloatEquality(provenance)] if provenance.start_line == 4);
+}
+
+#[test]
+fn float_inequality() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+fn differs(a: f64, b: f64): bool {
+    a != b
+}
+differs(1.0, 2.0)
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::FloatEquality(provenance)] if provenance.start_line == 3);
+}
+
+#[test]
+fn int_equality() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let a = 1;
+let b = 2;
+a == b
+"#,
+    )]);
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn allowed_float_equality() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let a = 0.5;
+let b = 0.5;
+// allow(float_equality)
+let same = a == b;
+let different = a != b; // allow(float_equality)
+same and !different
+"#,
+    )]);
+    assert!(warnings.is_empty(), "{warnings:?}");
+}
+
+#[test]
+fn float_equality_allow_in_string() {
+    let warnings = warnings(&[(
+        "main",
+        r#"
+let a = 0.5;
+let b = 0.5;
+let note = "// allow(float_equality)";
+let same = a == b; let other = "allow(float_equality)";
+same
+"#,
+    )]);
+    assert_matches!(&warnings[..], [CompileWarning::FloatEquality(provenance)] if provenance.start_line == 5);
+}
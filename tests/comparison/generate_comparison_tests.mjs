@@ -7,7 +7,7 @@ for (let i = -5; i <= 5; i++) {
     save(i, j, "<", "ls", i < j);
     save(i, j, "<=", "lse", i <= j);
     save(i, j, ">", "gr", i > j);
-    save(i, j, ">=", "gr", i >= j);
+    save(i, j, ">=", "gre", i >= j);
   }
 }
 